@@ -0,0 +1,91 @@
+/*
+ * The C API of verilator_debugger, to annotate the instructions of a simulation with the source code of the elfs
+ * from C or C++, i.e. from a Verilator testbench.
+ *
+ * Link with libverilator_debugger.a (and -lpthread -ldl -lm) or libverilator_debugger.so, built by cargo build
+ * --release. The calls failing return NULL or -1 and vd_last_error tells why.
+ */
+#ifndef VERILATOR_DEBUGGER_H
+#define VERILATOR_DEBUGGER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The time and the cycle of the records when the log doesn't tell them. */
+#define VD_UNKNOWN UINT64_MAX
+
+/* The hart of the records when the log doesn't tell it. */
+#define VD_UNKNOWN_HART UINT32_MAX
+
+typedef struct VdDebugger VdDebugger;
+
+/* The source location of an address, the strings are NULL when they aren't known. */
+typedef struct VdLocation {
+    const char *file;
+    /* The line in the file, 0 when it isn't known. */
+    uint32_t line;
+    /* The function that contains the address, the one the code was inlined into. */
+    const char *function;
+    /* The line of code, NULL if the source isn't available. */
+    const char *code;
+    /* The function symbol and the offset of the address when the code isn't available, i.e. uart_init+0x24. */
+    const char *symbol;
+    /* 1 if the address is out of the elfs. */
+    int external;
+} VdLocation;
+
+/* An instruction of the log with its source location. */
+typedef struct VdRecord {
+    uint64_t pc;
+    /* VD_UNKNOWN_HART when the log doesn't tell. */
+    uint32_t hart;
+    /* VD_UNKNOWN when the log doesn't tell. */
+    uint64_t time;
+    /* VD_UNKNOWN when the log doesn't tell. */
+    uint64_t cycle;
+    /* The log line the record was parsed from. */
+    const char *line;
+    /* The decoded instruction, NULL if the log doesn't have it. */
+    const char *instruction;
+    VdLocation location;
+} VdRecord;
+
+/*
+ * Open elfs to annotate the addresses of their instructions and optionally a log to read its records, the format of
+ * the log is detected from its first lines.
+ *
+ * Returns the debugger to close with vd_close, NULL on error.
+ */
+VdDebugger *vd_open(const char *const *elf_files, size_t elf_count, const char *log_file);
+
+/*
+ * Get the source location of an address, i.e. the pc of the core in a testbench. The strings of the location are
+ * valid until the next call with the debugger.
+ *
+ * Returns 0, -1 on error.
+ */
+int vd_annotate_address(VdDebugger *vd, uint64_t addr, VdLocation *location);
+
+/*
+ * Read the next record of the log in the elfs, with its source location. The strings of the record are valid until
+ * the next call with the debugger.
+ *
+ * Returns 1 when a record is read, 0 at the end of the log, -1 on error.
+ */
+int vd_next_record(VdDebugger *vd, VdRecord *record);
+
+/* Get the error of the last call that failed on this thread, NULL if no call failed. */
+const char *vd_last_error(void);
+
+/* Close a debugger, NULL is ignored. */
+void vd_close(VdDebugger *vd);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use crate::callstack::Frame;
+use crate::elf::SymbolMap;
+use crate::error::{Error, Result};
+use crate::isa::{self, InstrClass};
+use crate::symbolizer::SrcLocation;
+use crate::trace::{DataAccess, TraceRecord};
+
+/**
+ * A location whose executions are reported, an address, the entry of a function or a line of code.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint{
+    Addr(u64),
+    /// The name of the function, with or without its namespaces.
+    Function(String),
+    /// The end of the path of the file, i.e. main.c or src/main.c, and the line.
+    Line { file: String, line: u32 },
+}
+
+impl Breakpoint {
+    /**
+     * Check if an instruction is at the breakpoint.
+     *
+     * @param pc: The address of the instruction.
+     * @param location: The source location of the address, a line and a function also match the code inlined from
+     * them.
+     * @param functions: The function symbols of the elfs, a function is entered at the address of its symbol.
+     */
+    pub fn matches(&self, pc: u64, location: &SrcLocation, functions: &SymbolMap) -> bool{
+        match self {
+            Breakpoint::Addr(addr) => pc == *addr,
+            Breakpoint::Function(name) => {
+                let named = |function: &str| function == name || function.rsplit("::").next() == Some(name);
+                // The functions inlined into others have no symbol, the code inlined from them matches instead.
+                let inlined = std::iter::once(location).chain(&location.inlined_at).take(location.inlined_at.len());
+                functions.lookup(pc).is_some_and(|(symbol, offset)| offset == 0 && named(&symbol.name))
+                    || inlined.filter_map(|frame| frame.function.as_deref()).any(named)
+            },
+            Breakpoint::Line { file, line } => std::iter::once(location).chain(&location.inlined_at).any(|frame| {
+                frame.line == Some(*line) && frame.file.as_deref().is_some_and(|path| Path::new(path).ends_with(file))
+            }),
+        }
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    /**
+     * Parse an address in hex with 0x, a FILE:LINE or else the name of a function.
+     */
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err>{
+        if let Some(digits) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+            return u64::from_str_radix(digits, 16).map(Breakpoint::Addr)
+                .map_err(|err| format!("Invalid hex address {}: {}", value, err));
+        }
+        if let Some((file, line)) = value.rsplit_once(':').filter(|(file, _)| !file.is_empty()) {
+            if let Ok(line) = line.parse::<u32>() {
+                return Ok(Breakpoint::Line { file: file.to_string(), line });
+            }
+        }
+        match value.is_empty() {
+            true => Err(String::from("Expected an address, a function or a FILE:LINE, i.e. main.c:42")),
+            false => Ok(Breakpoint::Function(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Addr(addr) => write!(f, "0x{:x}", addr),
+            Breakpoint::Function(name) => write!(f, "{}", name),
+            Breakpoint::Line { file, line } => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+/**
+ * An execution of a breakpoint.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointHit{
+    /// The number of the breakpoint, from 1 in the order they were given.
+    pub number: usize,
+    pub breakpoint: Breakpoint,
+    /// The number of executions of the breakpoint so far, this one included.
+    pub count: u64,
+}
+
+impl BreakpointHit {
+    /**
+     * Describe the execution, i.e. Breakpoint 1 (main.c:42) hit 3 at cycle 1234 of hart 0.
+     *
+     * @param record: The first instruction of the execution.
+     */
+    pub fn describe(&self, record: &TraceRecord) -> String{
+        format!("Breakpoint {} ({}) hit {}{}", self.number, self.breakpoint, self.count, position(record))
+    }
+}
+
+/**
+ * Get when and where an instruction executed, i.e. at cycle 1234 of hart 0, empty when the log doesn't tell.
+ */
+fn position(record: &TraceRecord) -> String{
+    let mut text = match (record.cycle, record.time) {
+        (Some(cycle), _) => format!(" at cycle {}", cycle),
+        (None, Some(time)) => format!(" at time {}", time),
+        (None, None) => String::new(),
+    };
+    if let Some(hart) = record.hart {
+        text.push_str(&format!(" of hart {}", hart));
+    }
+    text
+}
+
+/**
+ * Get the frames of a call stack from the innermost like the backtrace of GDB, i.e. #1 0x20000124 in main, each with
+ * the address its function is at, the call of the frame below it.
+ *
+ * @param record: The instruction the innermost function is at.
+ * @param stack: The call stack from the outermost function.
+ */
+pub fn backtrace(record: &TraceRecord, stack: &[Frame]) -> Vec<String>{
+    let addrs = std::iter::once(Some(record.pc)).chain(stack.iter().rev().map(|frame| frame.call_pc));
+    addrs.zip(stack.iter().rev()).enumerate().map(|(level, (addr, frame))| match addr {
+        Some(addr) => format!("#{} 0x{:x} in {}", level, addr, frame.function),
+        None => format!("#{} ?? in {}", level, frame.function),
+    }).collect()
+}
+
+/**
+ * Find the executions of the breakpoints along the trace.
+ *
+ * A run of instructions at a breakpoint is one execution, i.e. the instructions of a line, a new one starts when the
+ * hart jumps back to the breakpoint.
+ */
+#[derive(Debug, Default)]
+pub struct BreakpointSet{
+    breakpoints: Vec<Breakpoint>,
+    /// The number of executions of each breakpoint.
+    counts: Vec<u64>,
+    /// The address of the last record of each hart and the breakpoint it was at.
+    harts: HashMap<Option<u32>, (u64, Option<usize>)>,
+}
+
+impl BreakpointSet {
+    /**
+     * Constructor.
+     *
+     * @param breakpoints: The breakpoints, an instruction at several of them is an execution of the first one.
+     */
+    pub fn new(breakpoints: Vec<Breakpoint>) -> BreakpointSet{
+        let counts = vec![0; breakpoints.len()];
+        BreakpointSet { breakpoints, counts, harts: HashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.breakpoints.is_empty()
+    }
+
+    /**
+     * Move the hart of a record to the record, the records must be given in the order of the trace.
+     *
+     * @param record: The traced instruction.
+     * @param location: The source location of its address.
+     * @param functions: The function symbols of the elfs.
+     * @return The execution the record starts, None if it isn't at a breakpoint or continues the execution of the
+     * record before it.
+     */
+    pub fn update(&mut self, record: &TraceRecord, location: &SrcLocation, functions: &SymbolMap) -> Option<BreakpointHit>{
+        let index = self.breakpoints.iter().position(|breakpoint| breakpoint.matches(record.pc, location, functions));
+        let last = self.harts.insert(record.hart, (record.pc, index));
+        let index = index?;
+        if last.is_some_and(|(pc, last)| last == Some(index) && pc < record.pc) {
+            return None;
+        }
+        self.counts[index] += 1;
+        Some(BreakpointHit { number: index + 1, breakpoint: self.breakpoints[index].clone(), count: self.counts[index] })
+    }
+
+    /**
+     * Get the breakpoints with their number of executions, in the order they were given.
+     */
+    pub fn counts(&self) -> impl Iterator<Item = (&Breakpoint, u64)>{
+        self.breakpoints.iter().zip(self.counts.iter().copied())
+    }
+}
+
+/**
+ * A location of data whose loads and stores are reported, an address or a data symbol of the elfs.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watchpoint{
+    /// The address of one byte.
+    Addr(u64),
+    /// The name of a global variable or buffer, its whole size is watched.
+    Symbol(String),
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    /**
+     * Parse an address in hex with 0x or else the name of a data symbol.
+     */
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err>{
+        if let Some(digits) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+            return u64::from_str_radix(digits, 16).map(Watchpoint::Addr)
+                .map_err(|err| format!("Invalid hex address {}: {}", value, err));
+        }
+        match value.is_empty() {
+            true => Err(String::from("Expected an address or a data symbol, i.e. 0x10000000 or BUFFER")),
+            false => Ok(Watchpoint::Symbol(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watchpoint::Addr(addr) => write!(f, "0x{:x}", addr),
+            Watchpoint::Symbol(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/**
+ * A load or a store of a watchpoint.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchHit{
+    /// The number of the watchpoint, from 1 in the order they were given.
+    pub number: usize,
+    pub watchpoint: Watchpoint,
+    pub access: DataAccess,
+    /// The number of accesses of the watchpoint so far, this one included.
+    pub count: u64,
+}
+
+impl WatchHit {
+    /**
+     * Describe the access, i.e. Watchpoint 1 (BUFFER) hit 2 by a store of 0x3 to 0x10000001 at cycle 18.
+     *
+     * @param record: The instruction that accessed the watchpoint.
+     */
+    pub fn describe(&self, record: &TraceRecord) -> String{
+        let direction = if self.access.kind == InstrClass::Store { "to" } else { "from" };
+        let value = self.access.value.map_or(String::new(), |value| format!(" of 0x{:x}", value));
+        format!("Watchpoint {} ({}) hit {} by a {}{} {} 0x{:x}{}", self.number, self.watchpoint, self.count,
+            self.access.kind.name(), value, direction, self.access.addr, position(record))
+    }
+}
+
+/**
+ * Find the loads and the stores of the watchpoints along the trace, from the data addresses of the log.
+ */
+#[derive(Debug, Default)]
+pub struct WatchSet{
+    /// The watchpoints with the first address and the end address, excluded, they cover.
+    watchpoints: Vec<(Watchpoint, u64, u64)>,
+    /// The number of accesses of each watchpoint.
+    counts: Vec<u64>,
+}
+
+impl WatchSet {
+    /**
+     * Constructor.
+     *
+     * @param watchpoints: The watchpoints, an access to several of them is an access of the first one.
+     * @param data_symbols: The data symbols of the elfs, for the watchpoints that are symbols.
+     * @return The set, error if a symbol isn't in the elfs.
+     */
+    pub fn new(watchpoints: Vec<Watchpoint>, data_symbols: &SymbolMap) -> Result<WatchSet>{
+        let watchpoints = watchpoints.into_iter().map(|watchpoint| {
+            let (addr, size) = match &watchpoint {
+                Watchpoint::Addr(addr) => (*addr, 1),
+                Watchpoint::Symbol(name) => data_symbols.find(name).map(|symbol| (symbol.addr, symbol.size.max(1)))
+                    .ok_or_else(|| Error::Config(format!("No data symbol {} in the elfs", name)))?,
+            };
+            Ok((watchpoint, addr, addr + size))
+        }).collect::<Result<Vec<(Watchpoint, u64, u64)>>>()?;
+        let counts = vec![0; watchpoints.len()];
+        Ok(WatchSet { watchpoints, counts })
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.watchpoints.is_empty()
+    }
+
+    /**
+     * Check if a record accesses one of the watchpoints.
+     *
+     * @param record: The traced instruction, the bytes it accesses are known from the mnemonics of RISC-V, one byte
+     * is assumed otherwise.
+     * @return The access, None if the record doesn't access memory or none of the watchpoints.
+     */
+    pub fn update(&mut self, record: &TraceRecord) -> Option<WatchHit>{
+        let access = record.data_access()?;
+        let end = access.addr.saturating_add(isa::access_size(record).unwrap_or(1));
+        let index = self.watchpoints.iter().position(|(_, start, stop)| access.addr < *stop && end > *start)?;
+        self.counts[index] += 1;
+        Some(WatchHit { number: index + 1, watchpoint: self.watchpoints[index].0.clone(), access, count: self.counts[index] })
+    }
+
+    /**
+     * Get the watchpoints with their number of accesses, in the order they were given.
+     */
+    pub fn counts(&self) -> impl Iterator<Item = (&Watchpoint, u64)>{
+        self.watchpoints.iter().map(|(watchpoint, _, _)| watchpoint).zip(self.counts.iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::ElfSymbol;
+
+    fn record(hart: Option<u32>, cycle: u64, pc: u64, instruction: &str, access: &str) -> TraceRecord{
+        TraceRecord {
+            line: format!("{}\t{:08x}\t{}\t{}", cycle, pc, instruction, access),
+            hart,
+            time: None,
+            cycle: Some(cycle),
+            pc,
+            opcode: None,
+            instruction: Some(instruction.to_string()),
+            notes: Vec::new(),
+        }
+    }
+
+    fn location(file: &str, line: u32, function: &str) -> SrcLocation{
+        SrcLocation { file: Some(file.to_string()), line: Some(line), function: Some(function.to_string()), inlined_at: Vec::new() }
+    }
+
+    fn symbols(symbols: &[(&str, u64, u64)]) -> SymbolMap{
+        SymbolMap::new(symbols.iter().map(|(name, addr, size)| ElfSymbol { name: name.to_string(), addr: *addr, size: *size }).collect())
+    }
+
+    #[test]
+    fn breakpoints_parse(){
+        assert_eq!("0x20000054".parse(), Ok(Breakpoint::Addr(0x20000054)));
+        assert_eq!("src/main.rs:59".parse(), Ok(Breakpoint::Line { file: String::from("src/main.rs"), line: 59 }));
+        assert_eq!("fw::checksum".parse(), Ok(Breakpoint::Function(String::from("fw::checksum"))));
+        assert!("0xg".parse::<Breakpoint>().is_err());
+        assert!("".parse::<Breakpoint>().is_err());
+    }
+
+    #[test]
+    fn breakpoints_match_functions_at_their_symbol_and_lines_through_inlining(){
+        let functions = symbols(&[("fw::checksum", 0x20000054, 52)]);
+        let checksum = location("/tmp/fw/src/main.rs", 59, "fw::checksum");
+        let function = Breakpoint::Function(String::from("checksum"));
+        assert!(function.matches(0x20000054, &checksum, &functions));
+        assert!(!function.matches(0x20000058, &checksum, &functions));
+        let mut inlined = location("/rustc/library/core/src/ptr/mod.rs", 2091, "core::ptr::read_volatile");
+        inlined.inlined_at.push(checksum);
+        let line = Breakpoint::Line { file: String::from("src/main.rs"), line: 59 };
+        assert!(line.matches(0x20000058, &inlined, &functions));
+        assert!(!Breakpoint::Line { file: String::from("main.rs"), line: 60 }.matches(0x20000058, &inlined, &functions));
+        assert!(Breakpoint::Function(String::from("read_volatile")).matches(0x20000058, &inlined, &functions));
+    }
+
+    #[test]
+    fn breakpoint_hits_count_the_executions_per_hart(){
+        let functions = symbols(&[]);
+        let line = location("/tmp/fw/src/main.rs", 45, "fw::delay");
+        let other = location("/tmp/fw/src/main.rs", 46, "fw::delay");
+        let mut set = BreakpointSet::new(vec!["main.rs:45".parse().unwrap(), Breakpoint::Addr(0x20000040)]);
+        let hits: Vec<Option<(usize, u64)>> = [
+            (record(Some(0), 1, 0x2000003e, "addi x10,x10,-1", ""), &line),
+            // The rest of the line is the same execution, the other hart starts its own.
+            (record(Some(0), 2, 0x20000040, "nop", ""), &line),
+            (record(Some(1), 3, 0x2000003e, "addi x10,x10,-1", ""), &line),
+            (record(Some(0), 4, 0x20000044, "bnez x10,-6", ""), &other),
+            // The jump back to the line is a new execution.
+            (record(Some(0), 5, 0x2000003e, "addi x10,x10,-1", ""), &line),
+            (record(Some(1), 6, 0x20000040, "c.nop", ""), &other),
+        ].iter().map(|(record, location)| set.update(record, location, &functions).map(|hit| (hit.number, hit.count))).collect();
+        assert_eq!(hits, [Some((1, 1)), None, Some((1, 2)), None, Some((1, 3)), Some((2, 1))]);
+        let counts: Vec<(String, u64)> = set.counts().map(|(breakpoint, count)| (breakpoint.to_string(), count)).collect();
+        assert_eq!(counts, [(String::from("main.rs:45"), 3), (String::from("0x20000040"), 1)]);
+        let hit = BreakpointHit { number: 1, breakpoint: "main.rs:45".parse().unwrap(), count: 3 };
+        assert_eq!(hit.describe(&record(Some(0), 5, 0x2000003e, "nop", "")), "Breakpoint 1 (main.rs:45) hit 3 at cycle 5 of hart 0");
+    }
+
+    #[test]
+    fn watch_hits_are_the_accesses_in_the_range_of_the_symbol(){
+        let data = symbols(&[("fw::BUFFER", 0x10000000, 64), ("fw::COUNT", 0x10000040, 4)]);
+        let mut set = WatchSet::new(vec!["BUFFER".parse().unwrap(), Watchpoint::Addr(0x10000fec)], &data).unwrap();
+        let hits: Vec<Option<(usize, u64, u64)>> = [
+            record(None, 1, 0x20000096, "sb x11,0(x10)", "x11:0x00000041 PA:0x10000000 store:0x00000041"),
+            // A word stored across the end of the buffer accesses it, the word after it doesn't.
+            record(None, 2, 0x2000009c, "sw x11,62(x10)", "x11:0x00000007 PA:0x1000003e store:0x00000007"),
+            record(None, 3, 0x200000a2, "lw x12,64(x10)", "x12=0x00000007 PA:0x10000040 load:0x00000007"),
+            record(None, 4, 0x200000a6, "c.swsp x1,12(x2)", "x1:0x200000d2 PA:0x10000fec store:0x200000d2"),
+            record(None, 5, 0x200000a8, "addi x10,x10,1", "x10=0x10000001"),
+            record(None, 6, 0x200000aa, "lbu x12,63(x0)", "x12=0x00000007 PA:0x1000003f load:0x00000007"),
+        ].iter().map(|record| set.update(record).map(|hit| (hit.number, hit.count, hit.access.addr))).collect();
+        assert_eq!(hits, [Some((1, 1, 0x10000000)), Some((1, 2, 0x1000003e)), None, Some((2, 1, 0x10000fec)), None,
+            Some((1, 3, 0x1000003f))]);
+        assert_eq!(data.symbolize(0x1000003e).as_deref(), Some("fw::BUFFER+0x3e"));
+        let counts: Vec<u64> = set.counts().map(|(_, count)| count).collect();
+        assert_eq!(counts, [3, 1]);
+        let store = record(None, 2, 0x2000009c, "sw x11,62(x10)", "x11:0x00000007 PA:0x1000003e store:0x00000007");
+        let hit = set.update(&store).unwrap();
+        assert_eq!(hit.describe(&store), "Watchpoint 1 (BUFFER) hit 4 by a store of 0x7 to 0x1000003e at cycle 2");
+        assert!(matches!(WatchSet::new(vec![Watchpoint::Symbol(String::from("MISSING"))], &data), Err(Error::Config(_))));
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use crate::isa::{Arch, Transfer};
+use crate::trace::TraceRecord;
+
+/**
+ * A function of the call stack.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame{
+    pub function: String,
+    /// The address of the call that entered the function, None for the functions the trace started in.
+    pub call_pc: Option<u64>,
+}
+
+/**
+ * The call stack of one hart.
+ */
+#[derive(Debug, Default)]
+struct HartStack{
+    frames: Vec<Frame>,
+    /// The transfer of the last instruction and its address, it decides how the stack changes at the next one.
+    transfer: Option<(Transfer, u64)>,
+}
+
+/**
+ * Rebuild the call stacks of the harts by following the calls and the returns of the trace.
+ *
+ * The stacks are approximate: when the function of an instruction doesn't match the top of the stack, i.e. after a
+ * tail call, a trap or a return that was missed, the top of the stack is replaced so the stack resynchronizes.
+ */
+#[derive(Debug, Default)]
+pub struct CallStack{
+    harts: HashMap<Option<u32>, HartStack>,
+    /// The architecture of the harts, to find their calls and returns.
+    arch: Arch,
+}
+
+impl CallStack {
+    /**
+     * Constructor.
+     *
+     * @param arch: The architecture of the harts.
+     */
+    pub fn new(arch: Arch) -> CallStack{
+        CallStack { arch, ..CallStack::default() }
+    }
+
+    /**
+     * Move the stack of the hart of a record to the record, the records must be given in the order of the trace.
+     *
+     * @param record: The traced instruction.
+     * @param function: The function the instruction belongs to, the one its code was inlined into if any.
+     * @return The stack of the hart from the outermost function, the last frame is the function of the record.
+     */
+    pub fn update(&mut self, record: &TraceRecord, function: &str) -> &[Frame]{
+        let hart = self.harts.entry(record.hart).or_default();
+        match hart.transfer.take() {
+            Some((Transfer::Call, call_pc)) if !hart.frames.is_empty() => {
+                hart.frames.push(Frame { function: function.to_string(), call_pc: Some(call_pc) });
+            },
+            transfer => {
+                if matches!(transfer, Some((Transfer::Return, _))) && hart.frames.len() > 1 {
+                    hart.frames.pop();
+                }
+                match hart.frames.last_mut() {
+                    Some(top) if top.function == function => (),
+                    Some(top) => top.function = function.to_string(),
+                    None => hart.frames.push(Frame { function: function.to_string(), call_pc: None }),
+                }
+            },
+        }
+        hart.transfer = Transfer::of(record, self.arch).map(|transfer| (transfer, record.pc));
+        &hart.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOP: &str = "00000013";
+    /// jal ra,+0x100.
+    const CALL: &str = "100000ef";
+    /// jal x0,+0x100.
+    const TAIL_CALL: &str = "1000006f";
+    /// jalr x0,0(ra).
+    const RET: &str = "00008067";
+
+    fn record(hart: Option<u32>, pc: u64, opcode: &str) -> TraceRecord{
+        TraceRecord {
+            line: format!("{:08x}\t{}", pc, opcode),
+            hart,
+            time: None,
+            cycle: None,
+            pc,
+            opcode: Some(opcode.to_string()),
+            instruction: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /**
+     * Get the functions of a stack and the addresses of their calls.
+     */
+    fn frames(stack: &[Frame]) -> Vec<(&str, Option<u64>)>{
+        stack.iter().map(|frame| (frame.function.as_str(), frame.call_pc)).collect()
+    }
+
+    #[test]
+    fn nested_calls_push_and_returns_pop(){
+        let mut stack = CallStack::new(Arch::Riscv);
+        assert_eq!(frames(stack.update(&record(None, 0x100, NOP), "main")), [("main", None)]);
+        assert_eq!(frames(stack.update(&record(None, 0x104, CALL), "main")), [("main", None)]);
+        assert_eq!(frames(stack.update(&record(None, 0x204, CALL), "outer")), [("main", None), ("outer", Some(0x104))]);
+        assert_eq!(frames(stack.update(&record(None, 0x304, RET), "inner")),
+            [("main", None), ("outer", Some(0x104)), ("inner", Some(0x204))]);
+        assert_eq!(frames(stack.update(&record(None, 0x208, RET), "outer")), [("main", None), ("outer", Some(0x104))]);
+        assert_eq!(frames(stack.update(&record(None, 0x108, NOP), "main")), [("main", None)]);
+        // The function the trace started in isn't popped by a return.
+        stack.update(&record(None, 0x10c, RET), "main");
+        assert_eq!(frames(stack.update(&record(None, 0x10, NOP), "_start")), [("_start", None)]);
+    }
+
+    #[test]
+    fn tail_calls_replace_the_caller(){
+        let mut stack = CallStack::new(Arch::Riscv);
+        stack.update(&record(None, 0x104, CALL), "main");
+        assert_eq!(frames(stack.update(&record(None, 0x204, TAIL_CALL), "outer")), [("main", None), ("outer", Some(0x104))]);
+        // The callee of the tail call returns to the caller of the function that made it.
+        assert_eq!(frames(stack.update(&record(None, 0x304, RET), "tail")), [("main", None), ("tail", Some(0x104))]);
+        assert_eq!(frames(stack.update(&record(None, 0x108, NOP), "main")), [("main", None)]);
+    }
+
+    #[test]
+    fn harts_have_their_own_stack(){
+        let mut stack = CallStack::new(Arch::Riscv);
+        stack.update(&record(Some(0), 0x104, CALL), "main");
+        stack.update(&record(Some(1), 0x104, NOP), "main");
+        assert_eq!(frames(stack.update(&record(Some(0), 0x204, CALL), "outer")), [("main", None), ("outer", Some(0x104))]);
+        // The call of hart 1 is followed by an instruction of hart 0, it doesn't enter a function of hart 0.
+        stack.update(&record(Some(1), 0x108, CALL), "main");
+        assert_eq!(frames(stack.update(&record(Some(0), 0x304, RET), "inner")),
+            [("main", None), ("outer", Some(0x104)), ("inner", Some(0x204))]);
+        assert_eq!(frames(stack.update(&record(Some(1), 0x208, NOP), "outer")), [("main", None), ("outer", Some(0x108))]);
+        assert_eq!(frames(stack.update(&record(Some(0), 0x208, NOP), "outer")), [("main", None), ("outer", Some(0x104))]);
+    }
+}
@@ -0,0 +1,919 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::str::FromStr;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{ArgAction, ArgGroup, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use verilator_debugger::{Arch, Breakpoint, ColorChoice, Compression, Csr, LayoutParser, OutputFormat, PathMap, RegexParser, SplitBy, TraceField, TraceFormat, TraceLayout, TraceParser, Watchpoint};
+use verilator_debugger::debugger::STDIO_PATH;
+use verilator_debugger::{elf, isa};
+use verilator_debugger::toolchain::{self, Toolchain};
+use crate::config_file::{OutputSection, ToolchainSection, TraceSection};
+use crate::failure::{Failure, Reporter};
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success, the logs are identical for diff
+  1  the logs diverge
+  2  invalid arguments, options or configuration file
+  3  an elf is missing or can't be used
+  4  no toolchain is found or one of its tools fails
+  5  a log is missing, can't be read or its format is unknown
+  6  the output can't be written";
+
+/// The reports of the stats command, counted over the whole log.
+const STATS_FORMATS: [OutputFormat; 11] = [OutputFormat::Stats, OutputFormat::Profile, OutputFormat::ProfileJson,
+    OutputFormat::Folded, OutputFormat::PerfScript, OutputFormat::ChromeTrace, OutputFormat::Speedscope, OutputFormat::Dot,
+    OutputFormat::Branches, OutputFormat::Stack, OutputFormat::Loops];
+
+/// The reports of the coverage command.
+const COVERAGE_FORMATS: [OutputFormat; 2] = [OutputFormat::Coverage, OutputFormat::Lcov];
+
+/// Annotate Verilator trace logs with the source code of the simulated elf.
+///
+/// The annotate command is assumed when no command is given.
+#[derive(Parser, Debug)]
+#[command(version, about, arg_required_else_help = true, after_help = EXIT_CODES)]
+pub struct Cli{
+    /// Path to a configuration file [default: ./varilator_debugger.toml when present].
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// Report the failures on stderr as json lines with their kind, exit code and message, i.e.
+    /// {"kind":"elf","exit_code":3,"message":"..."}, instead of as text.
+    #[arg(long, global = true)]
+    pub errors_json: bool,
+
+    /// Show what is skipped or unknown, i.e. the addresses without a source and the source files that can't be read,
+    /// -vv also shows each line of the log or of objdump that was skipped.
+    #[arg(long, short = 'v', global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only print the warnings and a summary at the end, without the progress bar and the status messages, for the
+    /// logs of CI.
+    #[arg(long, short = 'q', global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command{
+    /// Annotate the log with the source code that produced each traced instruction.
+    Annotate(Box<AnnotateArgs>),
+    /// Count where the instructions and the cycles of the log were spent, overall, per function and per call stack,
+    /// for the profilers and the flame graphs.
+    Stats(StatsArgs),
+    /// Report the share of the functions and of the source lines of the elfs that the log executed.
+    Coverage(CoverageArgs),
+    /// Print the global variable or buffer of the elfs each address is in.
+    SymbolAt(SymbolAtArgs),
+    /// Compare the instructions in the elfs of two logs, i.e. the RTL and an ISS, and show where they diverge.
+    Diff(DiffArgs),
+    /// Load the elfs once and answer the queries typed about addresses, functions and lines, with the cycles at
+    /// which they executed in a log.
+    Repl(ReplArgs),
+    /// Answer the queries about addresses, functions and lines given on the command line, like the repl does, for the
+    /// scripts.
+    Query(QueryArgs),
+    /// Index a log and serve a web page to search its instructions, browse its functions and see how often each
+    /// source line executed, instead of writing an annotated file.
+    Serve(ServeArgs),
+    /// Print the completion script of a shell, with the commands, the options and the values of the options like
+    /// --format and --trace-format, i.e. completions bash > ~/.local/share/bash-completion/completions/verilator_debugger.
+    Completions(CompletionsArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs{
+    /// The shell the script is written for.
+    #[arg(value_name = "SHELL")]
+    pub shell: Shell,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// Path to the Verilator trace log, - for stdin. The logs compressed with gzip or zstd are decompressed.
+    #[arg(long, value_name = "PATH")]
+    pub log: String,
+
+    /// Path to the file that will receive the report, - for stdout.
+    #[arg(long, short, value_name = "PATH", default_value = STDIO_PATH)]
+    pub output: String,
+
+    /// The report.
+    #[arg(long, value_name = "NAME", default_value = "stats", value_parser = PossibleValuesParser::new(STATS_FORMATS.map(|format| format.name())).map(|name| name.parse::<OutputFormat>().unwrap()))]
+    pub format: OutputFormat,
+
+    /// Only count the instructions from this cycle on.
+    #[arg(long, value_name = "CYCLE")]
+    pub from_cycle: Option<u64>,
+
+    /// Only count the instructions up to this cycle, the rest of the log isn't read.
+    #[arg(long, value_name = "CYCLE")]
+    pub to_cycle: Option<u64>,
+
+    /// Only count the instructions of this hart, repeat it for more harts. The instructions of the logs without hart
+    /// ids are those of hart 0.
+    #[arg(long, value_name = "N")]
+    pub hart: Vec<u32>,
+
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    #[command(flatten)]
+    pub toolchain: ToolchainArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CoverageArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// Path to the Verilator trace log, - for stdin. The logs compressed with gzip or zstd are decompressed.
+    #[arg(long, value_name = "PATH")]
+    pub log: String,
+
+    /// Path to the file that will receive the report, - for stdout.
+    #[arg(long, short, value_name = "PATH", default_value = STDIO_PATH)]
+    pub output: String,
+
+    /// The report, lcov writes a tracefile for genhtml.
+    #[arg(long, value_name = "NAME", default_value = "coverage", value_parser = PossibleValuesParser::new(COVERAGE_FORMATS.map(|format| format.name())).map(|name| name.parse::<OutputFormat>().unwrap()))]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    #[command(flatten)]
+    pub toolchain: ToolchainArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// The locations to look up, an address in hex, a function or a FILE:LINE, i.e. 0x20000514 uart_init main.c:42.
+    #[arg(value_name = "LOCATION", required = true, value_parser = Breakpoint::from_str)]
+    pub locations: Vec<Breakpoint>,
+
+    /// The log whose executions of the locations are shown.
+    #[arg(long, value_name = "PATH")]
+    pub log: Option<String>,
+
+    /// Format of the log [default: detected from its first lines].
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(TraceFormat::names()).map(|name| name.parse::<TraceFormat>().unwrap()))]
+    pub trace_format: Option<TraceFormat>,
+
+    /// Show N source lines before and after the line of the locations.
+    #[arg(long, short = 'C', value_name = "N", default_value_t = 0)]
+    pub context: u32,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+
+    #[command(flatten)]
+    pub toolchain: ToolchainArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// The log whose executions of the locations are shown, it can also be set with the log command.
+    #[arg(long, value_name = "PATH")]
+    pub log: Option<String>,
+
+    /// Format of the log [default: detected from its first lines].
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(TraceFormat::names()).map(|name| name.parse::<TraceFormat>().unwrap()))]
+    pub trace_format: Option<TraceFormat>,
+
+    /// Show N source lines before and after the line of the locations.
+    #[arg(long, short = 'C', value_name = "N", default_value_t = 0)]
+    pub context: u32,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+
+    #[command(flatten)]
+    pub toolchain: ToolchainArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// Path to the log, it's read once when the server starts.
+    #[arg(long, value_name = "PATH")]
+    pub log: String,
+
+    /// Format of the log [default: detected from its first lines].
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(TraceFormat::names()).map(|name| name.parse::<TraceFormat>().unwrap()))]
+    pub trace_format: Option<TraceFormat>,
+
+    /// The port the page is served on.
+    #[arg(long, value_name = "PORT", default_value_t = 8080)]
+    pub port: u16,
+
+    /// The address the server listens on, i.e. 0.0.0.0 to open the page from other machines.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1")]
+    pub address: String,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+
+    #[command(flatten)]
+    pub toolchain: ToolchainArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// The first log.
+    #[arg(value_name = "LEFT")]
+    pub left: String,
+
+    /// The second log.
+    #[arg(value_name = "RIGHT")]
+    pub right: String,
+
+    /// Format of the first log [default: detected from its first lines].
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(TraceFormat::names()).map(|name| name.parse::<TraceFormat>().unwrap()))]
+    pub left_format: Option<TraceFormat>,
+
+    /// Format of the second log [default: detected from its first lines].
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(TraceFormat::names()).map(|name| name.parse::<TraceFormat>().unwrap()))]
+    pub right_format: Option<TraceFormat>,
+
+    /// Also compare the values written to the registers, for the instructions whose write-backs both logs show, i.e.
+    /// Ibex against the Spike commit log.
+    #[arg(long)]
+    pub writes: bool,
+
+    /// Don't compare the writes to this register, repeat it for more registers.
+    #[arg(long, value_name = "REG", value_parser = parse_register, requires = "writes")]
+    pub ignore_register: Vec<String>,
+
+    /// Don't compare the values read from or written to this CSR, by name or number, i.e. mcycle for the timers
+    /// and the counters that differ between the RTL and the ISS. Repeat it for more CSRs.
+    #[arg(long, value_name = "CSR", value_parser = parse_csr, requires = "writes")]
+    pub ignore_csr: Vec<u16>,
+
+    /// Show the last N instructions the logs have in common before the divergence.
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub before: usize,
+
+    /// Show N source lines before and after the line of the instructions that diverge.
+    #[arg(long, short = 'C', value_name = "N", default_value_t = 0)]
+    pub context: u32,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+
+    #[command(flatten)]
+    pub toolchain: ToolchainArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct SymbolAtArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// The addresses to look up, in hex.
+    #[arg(value_name = "ADDR", required = true, value_parser = parse_addr)]
+    pub addrs: Vec<u64>,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("stops").multiple(true)))]
+pub struct AnnotateArgs{
+    #[command(flatten)]
+    pub elf: ElfArgs,
+
+    /// First address of the image in the log, in hex, instead of the one found from the entry point of the elf.
+    /// Repeat it in the order of the --elf.
+    #[arg(long, value_name = "ADDR", value_parser = parse_addr)]
+    pub start_addr: Vec<u64>,
+
+    /// Address after the end of the image in the log, in hex, instead of the one found from the entry point of the elf.
+    /// Repeat it in the order of the --elf.
+    #[arg(long, value_name = "ADDR", value_parser = parse_addr)]
+    pub end_addr: Vec<u64>,
+
+    /// Keep the instructions out of the ranges of the elfs, annotated as [external: 0x...], instead of dropping
+    /// them, i.e. the jumps into a boot rom, a debug stub or a garbage address.
+    #[arg(long)]
+    pub keep_external: bool,
+
+    /// Path to the Verilator trace log, - for stdin, or a fifo of mkfifo the simulation writes into. The logs
+    /// compressed with gzip or zstd are decompressed.
+    #[arg(long, value_name = "PATH", required_unless_present_any = ["log_path", "listen"], conflicts_with = "log_path")]
+    pub log: Option<String>,
+
+    /// Path to the file that will receive the output, - for stdout, the directory of the files with --split-by
+    /// [default: parsed_<log>, stdout when reading stdin].
+    #[arg(long, short, value_name = "PATH", conflicts_with = "output_path")]
+    pub output: Option<String>,
+
+    /// Same as --log.
+    #[arg(value_name = "LOG")]
+    pub log_path: Option<String>,
+
+    /// Same as --output.
+    #[arg(value_name = "OUTPUT")]
+    pub output_path: Option<String>,
+
+    /// Compress the output with gzip or zstd, the extension is added to the default output path.
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(Compression::names()).map(|name| name.parse::<Compression>().unwrap()))]
+    pub compress: Option<Compression>,
+
+    /// Write the annotated output of each executed function into its own file of the output directory, named after
+    /// the function, and the index of the files into index.txt.
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(SplitBy::names()).map(|name| name.parse::<SplitBy>().unwrap()))]
+    pub split_by: Option<SplitBy>,
+
+    /// Format of the output [default: text].
+    #[arg(long, value_parser = PossibleValuesParser::new(OutputFormat::names()).map(|name| name.parse::<OutputFormat>().unwrap()))]
+    pub format: Option<OutputFormat>,
+
+    /// When to highlight the source locations and code with colors [default: auto].
+    #[arg(long, env = "VERILATOR_DEBUGGER_COLOR", value_parser = PossibleValuesParser::new(ColorChoice::names()).map(|name| name.parse::<ColorChoice>().unwrap()))]
+    pub color: Option<ColorChoice>,
+
+    /// Show N source lines before and after the executed line [default: 0].
+    #[arg(long, short = 'C', value_name = "N")]
+    pub context: Option<u32>,
+
+    #[command(flatten)]
+    pub sources: SourceArgs,
+
+    /// Only show the instructions from this cycle on, the lines without a cycle are hidden.
+    #[arg(long, value_name = "CYCLE")]
+    pub from_cycle: Option<u64>,
+
+    /// Only show the instructions up to this cycle, the rest of the log isn't read.
+    #[arg(long, value_name = "CYCLE")]
+    pub to_cycle: Option<u64>,
+
+    /// Only show the instructions from this time on, the lines without a time are hidden.
+    #[arg(long, value_name = "TIME")]
+    pub from_time: Option<u64>,
+
+    /// Only show the instructions up to this time, the rest of the log isn't read.
+    #[arg(long, value_name = "TIME")]
+    pub to_time: Option<u64>,
+
+    /// Only show the instructions of this hart, repeat it for more harts. The instructions of the logs without hart
+    /// ids are those of hart 0.
+    #[arg(long, value_name = "N")]
+    pub hart: Vec<u32>,
+
+    /// Only show the instructions whose source file is in this file or directory, i.e. src/drivers/, repeat it for
+    /// more paths. The paths match the start of the source paths or any of their parts.
+    #[arg(long, value_name = "PATH")]
+    pub only_path: Vec<String>,
+
+    /// Hide the instructions whose source file is in this file or directory, i.e. vendor/, repeat it for more paths.
+    #[arg(long, value_name = "PATH")]
+    pub exclude_path: Vec<String>,
+
+    /// Only show the instructions executed in this function or in the code inlined into it, repeat it for more
+    /// functions. Rust and C++ names match with or without their namespaces.
+    #[arg(long, value_name = "NAME")]
+    pub function: Vec<String>,
+
+    /// Show N instructions before and after each run of instructions in the --function.
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "function")]
+    pub function_context: u32,
+
+    /// Only show the executions of this breakpoint, an address in hex, the entry of a function or a FILE:LINE, i.e.
+    /// main.c:42, each after a banner with its cycle and its call stack. Repeat it for more breakpoints.
+    #[arg(long, value_name = "LOCATION", group = "stops", conflicts_with = "function", value_parser = Breakpoint::from_str)]
+    pub break_at: Vec<Breakpoint>,
+
+    /// Only show the loads and the stores of this data, an address in hex or a global variable or buffer of the
+    /// elfs, i.e. BUFFER, each after a banner with the access and the value, for the logs with data addresses like
+    /// Ibex and the Spike commit log. Repeat it for more data.
+    #[arg(long, value_name = "LOCATION", group = "stops", conflicts_with = "function", value_parser = Watchpoint::from_str)]
+    pub watch_data: Vec<Watchpoint>,
+
+    /// Show N instructions before and after each execution of the --break-at and each access of the --watch-data.
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "stops")]
+    pub break_context: u32,
+
+    /// Stop at the first execution of the --break-at or access of the --watch-data, once its context is shown.
+    #[arg(long, requires = "stops")]
+    pub first_hit: bool,
+
+    /// Show the callers of the functions, rebuilt from the calls and the returns of the trace.
+    #[arg(long)]
+    pub call_stack: bool,
+
+    /// Only show the entries and the exits of the functions, indented by their call depth, for an outline of the
+    /// execution.
+    #[arg(long, conflicts_with = "format")]
+    pub calls_only: bool,
+
+    /// Mark the entries and the exits of the trap handlers with banners and count the traps per cause, in the text
+    /// format.
+    #[arg(long)]
+    pub traps: bool,
+
+    /// Show the name and the description of the CSRs read and written by the instructions, i.e. mtvec for 0x305.
+    #[arg(long)]
+    pub csr_names: bool,
+
+    /// Mark the gaps of the trace with banners in the text format, where the cycle goes back or jumps by more than
+    /// --max-gap because the simulator dropped trace lines or the log was cut. They are warned about either way.
+    #[arg(long)]
+    pub gaps: bool,
+
+    /// The largest growth of the cycle between two instructions of a hart that isn't a gap, in the unit of the time
+    /// for the logs without cycles.
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    pub max_gap: u64,
+
+    /// Show the registers written by the instructions in a column before the log lines, for the logs with
+    /// write-backs like Ibex and the Spike commit log.
+    #[arg(long)]
+    pub show_writes: bool,
+
+    /// Only show the instructions that write this register, i.e. x10 or a0, repeat it for more registers.
+    #[arg(long, value_name = "REG", value_parser = parse_register)]
+    pub writes_to: Vec<String>,
+
+    /// Show the global variable or buffer each load and store accesses, from the data symbols of the elfs, for the
+    /// logs with data addresses like Ibex and the Spike commit log.
+    #[arg(long)]
+    pub data_symbols: bool,
+
+    /// Show the executed instructions in a column before the log lines, like objdump -S but only for the executed
+    /// code. They are decoded from the instruction words of the log by the built-in RV32/RV64 decoder, or
+    /// disassembled from the elfs by --objdump for the logs without instruction words.
+    #[arg(long)]
+    pub disassemble: bool,
+
+    /// Run the callbacks of this rhai script along the annotation: on_record(record) returns false to hide an
+    /// instruction or a string to add it as a note, on_end(summary) prints what the script counted and init()
+    /// returns the state they get as this.
+    #[arg(long, value_name = "PATH")]
+    pub script: Option<String>,
+
+    /// Keep annotating the lines appended to the log, like tail -f, until Ctrl-C is pressed.
+    #[arg(long)]
+    pub follow: bool,
+
+    /// Wait for a testbench to connect on this address, i.e. 0.0.0.0:9999, and annotate the trace lines it streams
+    /// over TCP, i.e. from a DPI function, as they arrive instead of reading a log file. The annotation ends when the
+    /// testbench closes the connection [default output: stdout].
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["log", "log_path", "follow", "tui", "pc_signal"])]
+    pub listen: Option<String>,
+
+    /// Browse the annotated log in the terminal instead of writing an output, with panes of the trace, of the source
+    /// and of the call stack. The log is read as it's browsed, so the large logs open at once. /regex searches the
+    /// instructions, g CYCLE goes to a cycle and :addr 0x1234 to the first execution of an address.
+    #[arg(long, conflicts_with_all = ["output", "output_path", "compress", "split_by", "format", "calls_only", "follow"])]
+    pub tui: bool,
+
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    #[command(flatten)]
+    pub toolchain: ToolchainArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ElfArgs{
+    /// Path to the elf that was simulated, repeat it for the images loaded at other addresses, i.e. a boot rom and
+    /// an application.
+    #[arg(long = "elf", value_name = "PATH", required = true)]
+    pub files: Vec<String>,
+
+    /// Runtime address minus link address of the images copied to or run from other addresses, i.e. 0x80000000 or
+    /// -0x1000. Repeat it in the order of the --elf, the images without one run at their link addresses.
+    #[arg(long, value_name = "OFFSET", allow_hyphen_values = true, value_parser = parse_offset)]
+    pub load_offset: Vec<i64>,
+
+    /// Number of threads symbolizing the addresses [default: one per core].
+    #[arg(long, short, env = "VERILATOR_DEBUGGER_JOBS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub jobs: Option<u64>,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct SourceArgs{
+    /// Read the sources of the debug info paths under FROM from TO instead, i.e. /builder/src=/home/me/src for the
+    /// elfs built on another machine. Repeat it for more directories, the first matching one is used.
+    #[arg(long, value_name = "FROM=TO", value_parser = PathMap::from_str)]
+    pub path_map: Vec<PathMap>,
+
+    /// Search this directory for the sources that aren't found at their path, by the end of the path or else by the
+    /// file name, like the dir command of GDB. Repeat it for more directories, they are searched in order.
+    #[arg(long, value_name = "DIR")]
+    pub source_dir: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct TraceArgs{
+    /// Format of the log [default: detected from its first lines].
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(TraceFormat::names()).map(|name| name.parse::<TraceFormat>().unwrap()))]
+    pub trace_format: Option<TraceFormat>,
+
+    /// Comma separated fields of the log columns, from hart, time, cycle, pc, opcode, instruction and skip
+    /// [default: time,cycle,pc,opcode,instruction].
+    #[arg(long, value_name = "LIST", conflicts_with = "trace_format", value_parser = TraceField::parse_layout)]
+    pub fields: Option<TraceLayout>,
+
+    /// Only read the address, from the Nth column of the log.
+    #[arg(long, value_name = "N", conflicts_with_all = ["trace_format", "fields"], value_parser = clap::value_parser!(u64).range(1..))]
+    pub pc_column: Option<u64>,
+
+    /// Regex extracting the address in hex from the log lines, i.e. 'pc=0x([0-9a-f]+)'. The address is the group
+    /// named pc or the first group, the groups named hart, time, cycle, opcode and instruction are also used.
+    #[arg(long, value_name = "REGEX", conflicts_with_all = ["trace_format", "fields", "pc_column"], value_parser = parse_pc_regex)]
+    pub pc_regex: Option<String>,
+
+    /// Read the log as a VCD or FST waveform, i.e. of Verilator --trace or --trace-fst, and synthesize the
+    /// instructions from the changes of this signal, i.e. top.core.pc_id, for the testbenches without an instruction
+    /// tracer. The scopes above the path can be left out.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["trace_format", "fields", "pc_column", "pc_regex"])]
+    pub pc_signal: Option<String>,
+}
+
+/**
+ * Check that the pc regex is valid.
+ */
+fn parse_pc_regex(pattern: &str) -> Result<String, String>{
+    RegexParser::new(pattern).map(|_| pattern.to_string()).map_err(|err| err.to_string())
+}
+
+/**
+ * Parse an address in hex, with or without 0x.
+ */
+pub fn parse_addr(value: &str) -> Result<u64, String>{
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    u64::from_str_radix(digits, 16).map_err(|err| format!("Invalid hex address {}: {}", value, err))
+}
+
+/**
+ * Parse a load offset in hex, with an optional minus sign.
+ */
+fn parse_offset(value: &str) -> Result<i64, String>{
+    match value.strip_prefix('-') {
+        Some(magnitude) => parse_addr(magnitude).map(|offset| (offset as i64).wrapping_neg()),
+        None => parse_addr(value).map(|offset| offset as i64),
+    }
+}
+
+/**
+ * Parse a register name to its architectural name.
+ */
+fn parse_register(value: &str) -> Result<String, String>{
+    isa::register_name(value).ok_or_else(|| format!("Unknown register {}, expected i.e. x10, a0 or f1", value))
+}
+
+/**
+ * Parse a CSR by name or by number, in hex or decimal.
+ */
+fn parse_csr(value: &str) -> Result<u16, String>{
+    let number = match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+    number.filter(|number| *number < 0x1000).or_else(|| Csr::by_name(value).map(|csr| csr.number))
+        .ok_or_else(|| format!("Unknown CSR {}, expected i.e. mcycle or 0xb00", value))
+}
+
+#[derive(Args, Debug)]
+pub struct ToolchainArgs{
+    /// Path and triple prepended to the binutils names, i.e. /opt/riscv/bin/riscv32-unknown-elf-, or the directory
+    /// of the binutils of the --triple, i.e. /opt/riscv/bin. By default the toolchain is searched in the PATH,
+    /// $RISCV/bin and the common install locations.
+    #[arg(long, env = "VERILATOR_DEBUGGER_TOOLCHAIN_PREFIX")]
+    pub toolchain_prefix: Option<String>,
+
+    /// Triple of the toolchain to search for [default: riscv32-unknown-elf, riscv64-unknown-elf, or arm-none-eabi
+    /// for ARM].
+    #[arg(long, env = "VERILATOR_DEBUGGER_TRIPLE")]
+    pub triple: Option<String>,
+
+    /// Architecture of the elfs, it picks the toolchain searched for and how the addresses and the instruction words
+    /// are read. The traps, the CSR names and the stack format are only known for RISC-V [default: detected from the
+    /// first elf].
+    #[arg(long, value_name = "NAME", env = "VERILATOR_DEBUGGER_ARCH", value_parser = PossibleValuesParser::new(Arch::names()).map(|name| name.parse::<Arch>().unwrap()))]
+    pub arch: Option<Arch>,
+
+    /// Use this addr2line instead of the built-in DWARF reader, the one of the toolchain when no path is given.
+    #[arg(long, value_name = "PATH", env = "VERILATOR_DEBUGGER_ADDR2LINE", num_args = 0..=1, default_missing_value = "")]
+    pub addr2line: Option<String>,
+
+    /// Disassemble the elfs with this objdump for --disassemble, instead of decoding the instruction words of the log,
+    /// the one of the toolchain when no path is given.
+    #[arg(long, value_name = "PATH", env = "VERILATOR_DEBUGGER_OBJDUMP", num_args = 0..=1, default_missing_value = "")]
+    pub objdump: Option<String>,
+}
+
+impl Cli {
+    /**
+     * Parse the command line arguments, inserting the annotate command when none is given.
+     */
+    pub fn parse_args() -> Cli{
+        let args = Cli::with_command(std::env::args_os().collect());
+        match Cli::try_parse_from(&args) {
+            Ok(cli) => cli,
+            // The help and the version aren't failures, they are printed as usual.
+            Err(err) if err.use_stderr() && args.iter().any(|arg| arg == "--errors-json") => {
+                Reporter { json: true }.fail(Failure::Usage, err.render());
+            },
+            Err(err) => err.exit(),
+        }
+    }
+
+    /**
+     * Insert the annotate command after the global options given before the command, when there is no command.
+     *
+     * @param args: The arguments, from the name of the program.
+     * @return The arguments with a command, or with only global options like --help.
+     */
+    fn with_command(mut args: Vec<OsString>) -> Vec<OsString>{
+        let mut command = Cli::command();
+        command.build();
+        let takes_value = |arg: &clap::Arg| arg.get_action().takes_values();
+        let mut index = 1;
+        while let Some(arg) = args.get(index).and_then(|arg| arg.to_str()) {
+            let skipped = if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = long.split_once('=').map_or((long, None), |(name, value)| (name, Some(value)));
+                command.get_arguments().find(|global| global.get_long() == Some(name))
+                    .map(|global| if takes_value(global) && value.is_none() { 2 } else { 1 })
+            } else if let Some(shorts) = arg.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+                // The flags can be grouped, i.e. -vv, the last one can take the next argument as its value.
+                let globals: Option<Vec<&clap::Arg>> = shorts.chars()
+                    .map(|short| command.get_arguments().find(|global| global.get_short() == Some(short))).collect();
+                globals.filter(|globals| globals.iter().rev().skip(1).all(|global| !takes_value(global)))
+                    .map(|globals| if globals.last().is_some_and(|global| takes_value(global)) { 2 } else { 1 })
+            } else {
+                None
+            };
+            match skipped {
+                Some(count) => index += count,
+                None => break,
+            }
+        }
+        let Some(first) = args.get(index).map(|arg| arg.to_string_lossy().into_owned()) else {
+            return args;
+        };
+        let is_command = first == "help" || command.get_subcommands()
+            .any(|cmd| cmd.get_name() == first || cmd.get_all_aliases().any(|alias| alias == first));
+        if !is_command {
+            args.insert(index, OsString::from("annotate"));
+        }
+        args
+    }
+}
+
+impl ToolchainArgs {
+    /**
+     * Get the toolchain, either the given prefix or the first one found.
+     *
+     * @return The toolchain, string error if none was found.
+     */
+    pub fn toolchain(&self) -> Result<Toolchain, String>{
+        let arch = self.arch.unwrap_or_default();
+        let triples = match &self.triple {
+            Some(triple) => vec![triple.as_str()],
+            None => toolchain::default_triples(arch).to_vec(),
+        };
+        match &self.toolchain_prefix {
+            Some(dir) if Path::new(dir).is_dir() => return Toolchain::find_in(Path::new(dir), &triples).ok_or_else(|| {
+                format!("Couldn't find the {} binutils in {}", triples.join(" or "), dir)
+            }),
+            Some(prefix) => return Ok(Toolchain::new(prefix)),
+            None => (),
+        }
+        Toolchain::discover(self.triple.as_deref(), arch).ok_or_else(|| {
+            let triples = triples.join(" or ");
+            let dirs = toolchain::install_dirs(arch).join(", ");
+            let searched = match arch {
+                Arch::Riscv => format!("the PATH, $RISCV/bin or {}", dirs),
+                Arch::Arm => format!("the PATH or {}", dirs),
+            };
+            format!("Couldn't find the {} binutils in {}, use --toolchain-prefix to point at them", triples, searched)
+        })
+    }
+
+    /**
+     * Detect the architecture from the machine of the first elf when it isn't given, the elfs that can't be read are
+     * reported when they are loaded.
+     *
+     * @param elf_files: The elfs.
+     */
+    pub fn detect_arch(&mut self, elf_files: &[String]){
+        if self.arch.is_none() {
+            self.arch = elf_files.first().and_then(|elf_file| elf::read_arch(elf_file).ok().flatten());
+        }
+    }
+
+    /**
+     * Fill the options that weren't given on the command line with the ones of the configuration file.
+     *
+     * @param section: The [toolchain] table of the configuration file.
+     */
+    pub fn apply_config(&mut self, section: &ToolchainSection){
+        self.toolchain_prefix = self.toolchain_prefix.take().or(section.prefix.clone());
+        self.triple = self.triple.take().or(section.triple.clone());
+        self.arch = self.arch.or(section.arch);
+        self.addr2line = self.addr2line.take().or(section.addr2line.clone());
+        self.objdump = self.objdump.take().or(section.objdump.clone());
+    }
+
+    /**
+     * Get the path of the addr2line to be used.
+     *
+     * @return The path, None to use the built-in DWARF reader, string error if it's the one of the toolchain and no
+     * toolchain was found.
+     */
+    pub fn addr2line_path(&self) -> Result<Option<String>, String>{
+        self.tool_path(self.addr2line.as_deref(), "addr2line")
+    }
+
+    /**
+     * Get the path of the objdump to be used.
+     *
+     * @return The path, None to decode the instruction words of the log, string error if it's the one of the
+     * toolchain and no toolchain was found.
+     */
+    pub fn objdump_path(&self) -> Result<Option<String>, String>{
+        self.tool_path(self.objdump.as_deref(), "objdump")
+    }
+
+    /**
+     * Get the path of an optional tool, an empty path is the tool of the toolchain.
+     */
+    fn tool_path(&self, path: Option<&str>, name: &str) -> Result<Option<String>, String>{
+        match path {
+            Some("") => Ok(Some(self.toolchain()?.tool(name))),
+            path => Ok(path.map(str::to_string)),
+        }
+    }
+}
+
+impl TraceArgs {
+    /**
+     * Fill the options that weren't given on the command line with the ones of the configuration file.
+     *
+     * @param section: The [trace] table of the configuration file.
+     */
+    pub fn apply_config(&mut self, section: &TraceSection){
+        if self.trace_format.is_none() && self.fields.is_none() && self.pc_column.is_none() && self.pc_regex.is_none()
+            && self.pc_signal.is_none() {
+            self.trace_format = section.format;
+            self.fields = section.fields.clone();
+            self.pc_column = section.pc_column;
+            self.pc_regex = section.pc_regex.clone();
+            self.pc_signal = section.pc_signal.clone();
+        }
+    }
+
+    /**
+     * Get the columns of the log lines, None for the default format.
+     */
+    pub fn layout(&self) -> Option<TraceLayout>{
+        self.fields.clone().or(self.pc_column.map(|column| TraceField::pc_column(column as usize)))
+    }
+
+    /**
+     * Get the parser of the log lines, None to detect the format from the first lines.
+     */
+    pub fn parser(&self) -> Option<Box<dyn TraceParser>>{
+        match (&self.pc_regex, self.layout()) {
+            (Some(pattern), _) => RegexParser::new(pattern).ok().map(|parser| Box::new(parser) as Box<dyn TraceParser>),
+            (None, Some(layout)) => Some(Box::new(LayoutParser::new(layout))),
+            (None, None) => self.trace_format.map(|format| format.parser()),
+        }
+    }
+}
+
+impl AnnotateArgs {
+    /**
+     * Fill the options that weren't given on the command line with the ones of the configuration file.
+     *
+     * @param section: The [output] table of the configuration file.
+     */
+    pub fn apply_config(&mut self, section: &OutputSection){
+        self.format = self.format.or(section.format);
+        self.compress = self.compress.or(section.compress);
+        self.color = self.color.or(section.color);
+        self.context = self.context.or(section.context);
+    }
+
+    /**
+     * Get the log path, given either with --log or as the first positional argument, or the address of --listen.
+     */
+    pub fn log_file(&self) -> String{
+        self.log.clone().or(self.log_path.clone()).or(self.listen.clone()).unwrap_or_default()
+    }
+
+    /**
+     * Get the output path, by default the log file name prefixed with parsed_ next to the log, without the extension of
+     * a compressed log, or stdout for the stdin log and the streamed log.
+     */
+    pub fn output_file(&self) -> String{
+        if let Some(output) = self.output.as_ref().or(self.output_path.as_ref()) {
+            return output.clone();
+        }
+        let log_file = self.log_file();
+        if log_file == STDIO_PATH || self.listen.is_some() {
+            return String::from(STDIO_PATH);
+        }
+        let log = std::path::Path::new(&log_file);
+        let name = log.file_name().map_or(log_file.clone(), |name| name.to_string_lossy().into_owned());
+        // The output of a compressed log isn't compressed.
+        let name = name.strip_suffix(".gz").or(name.strip_suffix(".zst")).unwrap_or(&name);
+        let name = match self.compress.filter(|_| self.split_by.is_none()) {
+            Some(compression) => format!("parsed_{}.{}", name, compression.name()),
+            None => format!("parsed_{}", name),
+        };
+        log.with_file_name(name).to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_command(args: &[&str]) -> Vec<String>{
+        let args = std::iter::once("verilator_debugger").chain(args.iter().copied()).map(OsString::from).collect();
+        Cli::with_command(args).into_iter().map(|arg| arg.into_string().unwrap()).collect()
+    }
+
+    #[test]
+    fn annotate_is_inserted_without_command(){
+        assert_eq!(with_command(&["--elf", "fw.elf", "trace.log"]), ["verilator_debugger", "annotate", "--elf", "fw.elf", "trace.log"]);
+        assert_eq!(with_command(&["trace.log"]), ["verilator_debugger", "annotate", "trace.log"]);
+    }
+
+    #[test]
+    fn commands_are_kept(){
+        assert_eq!(with_command(&["stats", "--elf", "fw.elf"]), ["verilator_debugger", "stats", "--elf", "fw.elf"]);
+        assert_eq!(with_command(&["help", "diff"]), ["verilator_debugger", "help", "diff"]);
+    }
+
+    #[test]
+    fn global_options_before_the_command_are_skipped(){
+        assert_eq!(with_command(&["-q", "stats", "--log", "trace.log"]), ["verilator_debugger", "-q", "stats", "--log", "trace.log"]);
+        assert_eq!(with_command(&["--errors-json", "-vv", "diff", "a.log", "b.log"]),
+            ["verilator_debugger", "--errors-json", "-vv", "diff", "a.log", "b.log"]);
+        assert_eq!(with_command(&["--config", "diff", "repl"]), ["verilator_debugger", "--config", "diff", "repl"]);
+        assert_eq!(with_command(&["--config=ci.toml", "-q", "--elf", "fw.elf"]),
+            ["verilator_debugger", "--config=ci.toml", "-q", "annotate", "--elf", "fw.elf"]);
+    }
+
+    #[test]
+    fn only_global_options_get_no_command(){
+        assert_eq!(with_command(&[]), ["verilator_debugger"]);
+        assert_eq!(with_command(&["-q", "--help"]), ["verilator_debugger", "-q", "--help"]);
+        assert_eq!(with_command(&["-V"]), ["verilator_debugger", "-V"]);
+    }
+
+    #[test]
+    fn subcommands_parse_after_global_options(){
+        let cli = Cli::try_parse_from(with_command(&["-q", "annotate", "--elf", "fw.elf", "trace.log", "out.txt"])).unwrap();
+        assert!(cli.quiet);
+        let Command::Annotate(args) = cli.command else { panic!("expected annotate") };
+        assert_eq!((args.log_path.as_deref(), args.output_path.as_deref()), (Some("trace.log"), Some("out.txt")));
+        let cli = Cli::try_parse_from(with_command(&["-q", "stats", "--elf", "fw.elf", "--log", "trace.log"])).unwrap();
+        assert!(matches!(cli.command, Command::Stats(_)));
+    }
+
+    #[test]
+    fn the_toolchain_is_only_needed_by_its_tools(){
+        let dir = std::env::temp_dir().join(format!("verilator_debugger_no_toolchain_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().into_owned();
+        let toolchain = |args: &[&str]| {
+            let args = [&["annotate", "--toolchain-prefix", &dir, "--elf", "fw.elf", "trace.log"], args].concat();
+            let Command::Annotate(args) = Cli::try_parse_from(with_command(&args)).unwrap().command else { panic!("expected annotate") };
+            args.toolchain
+        };
+        let none = toolchain(&[]);
+        assert_eq!((none.addr2line_path(), none.objdump_path()), (Ok(None), Ok(None)));
+        let paths = toolchain(&["--addr2line", "/bin/addr2line", "--objdump=/bin/objdump"]);
+        assert_eq!(paths.addr2line_path(), Ok(Some(String::from("/bin/addr2line"))));
+        assert_eq!(paths.objdump_path(), Ok(Some(String::from("/bin/objdump"))));
+        let missing = toolchain(&["--addr2line", "--objdump"]);
+        assert!(missing.addr2line_path().unwrap_err().starts_with("Couldn't find the riscv32-unknown-elf"));
+        assert!(missing.objdump_path().is_err());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use ruzstd::decoding::{FrameDecoder, StreamingDecoder};
+use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+use crate::debugger::STDIO_PATH;
+use crate::error::{Error, Result};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// The size of the output compressed in each zstd frame.
+const ZSTD_FRAME_SIZE: usize = 4 << 20;
+
+/**
+ * The compression of a log or of an output, i.e. of the logs kept by the regressions.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression{
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 2] = [Compression::Gzip, Compression::Zstd];
+
+    /**
+     * Get the name of the compression, as accepted by from_str, it's also the extension of the files.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /**
+     * Get the names of all the compressions.
+     */
+    pub fn names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|compression| compression.name()).collect()
+    }
+
+    /**
+     * Detect the compression of a file from its first bytes, or its extension when they are too few to tell.
+     *
+     * @param path: The path of the file, - for stdin.
+     * @param start: The first bytes of the file.
+     * @return The compression, None if the file isn't compressed.
+     */
+    pub fn detect(path: &str, start: &[u8]) -> Option<Compression>{
+        if start.starts_with(&GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if start.starts_with(&ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else if start.len() < ZSTD_MAGIC.len() {
+            Self::ALL.into_iter().find(|compression| path.ends_with(&format!(".{}", compression.name())))
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|compression| compression.name() == name)
+            .ok_or_else(|| format!("Unknown compression {}, expected one of {}", name, Self::names().join(", ")))
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/**
+ * Decompress the zstd frames of a stream one after the other, the zstd files can have many.
+ */
+struct ZstdReader<R: BufRead>{
+    /// The decoder of the current frame, None at the end of the stream.
+    decoder: Option<StreamingDecoder<R, FrameDecoder>>,
+}
+
+impl<R: BufRead> ZstdReader<R> {
+    fn new(input: R) -> io::Result<ZstdReader<R>>{
+        Ok(ZstdReader { decoder: Some(Self::frame(input)?) })
+    }
+
+    fn frame(input: R) -> io::Result<StreamingDecoder<R, FrameDecoder>>{
+        StreamingDecoder::new(input).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+}
+
+impl<R: BufRead> Read for ZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+        loop {
+            let Some(decoder) = &mut self.decoder else {
+                return Ok(0);
+            };
+            let len = decoder.read(buf)?;
+            if len > 0 || buf.is_empty() {
+                return Ok(len);
+            }
+            // The frame is over, the next one starts right after it.
+            let mut input = self.decoder.take().map(StreamingDecoder::into_inner).unwrap();
+            if input.fill_buf()?.is_empty() {
+                return Ok(0);
+            }
+            self.decoder = Some(Self::frame(input)?);
+        }
+    }
+}
+
+/**
+ * Decompress a stream if it's compressed.
+ *
+ * @param input: The stream.
+ * @param path: The path it's read from, - for stdin, used when the stream is too short to tell.
+ * @return The decompressed stream and its compression, the stream itself when it isn't compressed.
+ */
+pub fn decompress(mut input: impl BufRead + 'static, path: &str) -> io::Result<(Box<dyn BufRead>, Option<Compression>)>{
+    let compression = Compression::detect(path, input.fill_buf()?);
+    let reader: Box<dyn BufRead> = match compression {
+        Some(Compression::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(input))),
+        Some(Compression::Zstd) => Box::new(BufReader::new(ZstdReader::new(input)?)),
+        None => Box::new(input),
+    };
+    Ok((reader, compression))
+}
+
+/**
+ * Open a log to read its lines, decompressed if it's compressed with gzip or zstd.
+ *
+ * @param path: The path of the log, - for stdin.
+ * @return The lines of the log and its compression, error if it can't be opened.
+ */
+pub fn open_log(path: &str) -> Result<(Box<dyn BufRead>, Option<Compression>)>{
+    let opened = if path == STDIO_PATH {
+        decompress(io::stdin().lock(), path)
+    } else {
+        File::open(path).and_then(|log| decompress(BufReader::new(log), path))
+    };
+    opened.map_err(|err| Error::io(path, err))
+}
+
+/**
+ * Check if a log is read as it's written, from stdin, a fifo or another file that isn't regular. It has no size, can't
+ * be mapped and can only be opened once, its lines are lost when it's closed.
+ *
+ * @param path: The path of the log, - for stdin.
+ */
+pub fn is_stream(path: &str) -> bool{
+    path == STDIO_PATH || fs::metadata(path).is_ok_and(|metadata| !metadata.is_file())
+}
+
+/**
+ * Compress the zstd frames of a stream, a frame is written each time enough output is buffered or it's flushed.
+ */
+struct ZstdWriter<W: Write>{
+    out: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> ZstdWriter<W> {
+    fn write_frame(&mut self) -> io::Result<()>{
+        if !self.buffer.is_empty() {
+            self.out.write_all(&compress_to_vec(self.buffer.as_slice(), CompressionLevel::Fastest))?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZstdWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= ZSTD_FRAME_SIZE {
+            self.write_frame()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        self.write_frame()?;
+        self.out.flush()
+    }
+}
+
+/**
+ * Write an output, compressed or not.
+ *
+ * The compressed output is only complete once finish is called.
+ */
+pub struct Encoder<W: Write>(EncoderKind<W>);
+
+enum EncoderKind<W: Write>{
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(ZstdWriter<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /**
+     * Constructor.
+     *
+     * @param compression: The compression of the output, None to write it as is.
+     * @param out: The stream that receives the output.
+     */
+    pub fn new(compression: Option<Compression>, out: W) -> Encoder<W>{
+        Encoder(match compression {
+            Some(Compression::Gzip) => EncoderKind::Gzip(GzEncoder::new(out, flate2::Compression::default())),
+            Some(Compression::Zstd) => EncoderKind::Zstd(ZstdWriter { out, buffer: Vec::with_capacity(ZSTD_FRAME_SIZE) }),
+            None => EncoderKind::Plain(out),
+        })
+    }
+
+    /**
+     * Write the end of the compressed output.
+     *
+     * @return The stream, error if it can't be written.
+     */
+    pub fn finish(self) -> io::Result<W>{
+        let mut out = match self.0 {
+            EncoderKind::Plain(out) => out,
+            EncoderKind::Gzip(encoder) => encoder.finish()?,
+            EncoderKind::Zstd(mut writer) => {
+                writer.write_frame()?;
+                writer.out
+            },
+        };
+        out.flush()?;
+        Ok(out)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        match &mut self.0 {
+            EncoderKind::Plain(out) => out.write(buf),
+            EncoderKind::Gzip(encoder) => encoder.write(buf),
+            EncoderKind::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        match &mut self.0 {
+            EncoderKind::Plain(out) => out.flush(),
+            EncoderKind::Gzip(encoder) => encoder.flush(),
+            EncoderKind::Zstd(writer) => writer.flush(),
+        }
+    }
+}
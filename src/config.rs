@@ -0,0 +1,205 @@
+use crate::breakpoint::{Breakpoint, Watchpoint};
+use crate::compress::Compression;
+use crate::debugger::STDIO_PATH;
+use crate::elf;
+use crate::error::{Error, Result};
+use crate::isa::Arch;
+use crate::output::{ColorChoice, OutputFormat};
+use crate::output::split::SplitBy;
+use crate::source::PathMap;
+use crate::toolchain::Toolchain;
+use crate::trace::{TraceFormat, TraceLayout};
+
+/**
+ * The paths and the settings used by the debugger.
+ */
+#[derive(Debug)]
+pub struct Config{
+    /// Optional addr2line to use instead of the built-in DWARF reader.
+    pub addr2line_path: Option<String>,
+    /// The architecture of the elfs, it decides how their addresses and the instruction words of the log are read.
+    pub arch: Arch,
+    /// The elfs of the simulation, the addresses are symbolized with the first elf whose range contains them.
+    pub elf_files : Vec<String>,
+    /// The difference between the runtime and the link addresses of each elf, in the order of the elfs. The elfs
+    /// without one are loaded at their link addresses.
+    pub load_offsets: Vec<i64>,
+    /// The first runtime address of each elf, in the order of the elfs, instead of the one read from the elf.
+    pub start_addrs: Vec<u64>,
+    /// The runtime address after the end of each elf, in the order of the elfs, instead of the one read from the elf.
+    pub end_addrs: Vec<u64>,
+    /// Keep the instructions out of the ranges of the elfs, marked as external, instead of dropping them.
+    pub keep_external: bool,
+    pub log_file : String,
+    pub output_file : String,
+    /// The compression of the output, None to write it as is.
+    pub compress: Option<Compression>,
+    /// Split the output into files of the output directory, with an index, None to write a single output.
+    pub split_by: Option<SplitBy>,
+    /// The format of the log, used when neither a layout nor a pc regex is given. None to detect it from the first lines.
+    pub trace_format: Option<TraceFormat>,
+    /// The columns of the log lines, it takes precedence over the trace format.
+    pub layout: Option<TraceLayout>,
+    /// The regex extracting the address of the log lines, it takes precedence over the layout.
+    pub pc_regex: Option<String>,
+    /// Read the log as a VCD or FST waveform and synthesize the instructions from the changes of this signal, its path
+    /// in the hierarchy of the dump, i.e. top.core.pc_id. It takes precedence over the other formats.
+    pub pc_signal: Option<String>,
+    pub format: OutputFormat,
+    pub color: ColorChoice,
+    /// The number of source lines shown before and after the executed one.
+    pub context: u32,
+    /// The first and the last cycle of the instructions shown.
+    pub from_cycle: Option<u64>,
+    pub to_cycle: Option<u64>,
+    /// The first and the last time of the instructions shown.
+    pub from_time: Option<u64>,
+    pub to_time: Option<u64>,
+    /// Only show the instructions of these harts, all of them when empty. The records without a hart are of hart 0.
+    pub harts: Vec<u32>,
+    /// Only show the instructions whose source file is in one of these paths, all of them when empty.
+    pub only_paths: Vec<String>,
+    /// Hide the instructions whose source file is in one of these paths.
+    pub exclude_paths: Vec<String>,
+    /// The rules mapping the source paths of the debug info to the local ones before the sources are read. The paths
+    /// shown and filtered are those of the debug info.
+    pub path_maps: Vec<PathMap>,
+    /// The directories searched for the sources that aren't found at their path, by the suffixes of the path.
+    pub source_dirs: Vec<String>,
+    /// Only show the instructions of these functions, all of them when empty.
+    pub functions: Vec<String>,
+    /// The number of instructions shown before and after the instructions of the functions.
+    pub function_context: u32,
+    /// Only show the executions of these breakpoints, each after a banner with its call stack, all the instructions
+    /// when empty.
+    pub break_at: Vec<Breakpoint>,
+    /// Only show the loads and the stores of these watchpoints, each after a banner with the value, along with the
+    /// executions of the breakpoints.
+    pub watch_data: Vec<Watchpoint>,
+    /// The number of instructions shown before and after each execution of the breakpoints and each access of the
+    /// watchpoints.
+    pub break_context: u32,
+    /// Stop at the first execution of the breakpoints or access of the watchpoints, once its context is shown.
+    pub first_hit: bool,
+    /// Show the depth and the callers of the instructions, rebuilt from the calls and the returns of the trace.
+    pub call_stack: bool,
+    /// Only write the entries and the exits of the functions, indented by their call depth, instead of the format.
+    pub calls_only: bool,
+    /// Mark the trap entries and exits and count the traps per cause.
+    pub traps: bool,
+    /// Show the name and the description of the CSRs the instructions access.
+    pub csr_names: bool,
+    /// Mark the gaps of the trace in the output, they are warned about either way.
+    pub gaps: bool,
+    /// The largest growth of the cycle, or of the time, between two instructions of a hart that isn't a gap.
+    pub max_gap: u64,
+    /// Show the registers written by the instructions.
+    pub show_writes: bool,
+    /// Only keep the instructions that write one of these registers, by architectural name, i.e. x10.
+    pub writes_to: Vec<String>,
+    /// Show the variables the loads and the stores access, from the data symbols of the elfs.
+    pub data_symbols: bool,
+    /// Show the disassembly of the executed instructions.
+    pub disassemble: bool,
+    /// The objdump disassembling the elfs for the disassembly, None to show the instructions of the log, decoded from
+    /// the instruction words when the log only has them.
+    pub objdump_path: Option<String>,
+    /// Keep reading the log as the simulation writes it.
+    pub follow: bool,
+    /// Listen on this address, i.e. 0.0.0.0:9999, and annotate the log a testbench streams over TCP instead of reading
+    /// the log file.
+    pub listen: Option<String>,
+    /// The number of worker threads, None to use one per core.
+    pub jobs: Option<usize>,
+}
+
+impl Config {
+    /**
+     * Constructor for the commands that only symbolize the addresses of elfs, the log and the output are stdin and
+     * stdout and the other settings are those of the command line by default.
+     *
+     * @param addr2line_path: Optional addr2line to use instead of the built-in DWARF reader.
+     * @param elf_files: The elfs.
+     * @param load_offsets: The runtime address minus the link address of the elfs, in order.
+     */
+    pub fn new(addr2line_path: Option<String>, elf_files: Vec<String>, load_offsets: Vec<i64>) -> Config{
+        Config {
+            addr2line_path,
+            arch: Arch::default(),
+            elf_files,
+            load_offsets,
+            start_addrs: Vec::new(),
+            end_addrs: Vec::new(),
+            keep_external: false,
+            log_file: STDIO_PATH.to_string(),
+            output_file: STDIO_PATH.to_string(),
+            compress: None,
+            split_by: None,
+            trace_format: None,
+            layout: None,
+            pc_regex: None,
+            pc_signal: None,
+            format: OutputFormat::default(),
+            color: ColorChoice::default(),
+            context: 0,
+            from_cycle: None,
+            to_cycle: None,
+            from_time: None,
+            to_time: None,
+            harts: Vec::new(),
+            only_paths: Vec::new(),
+            exclude_paths: Vec::new(),
+            path_maps: Vec::new(),
+            source_dirs: Vec::new(),
+            functions: Vec::new(),
+            function_context: 0,
+            break_at: Vec::new(),
+            watch_data: Vec::new(),
+            break_context: 0,
+            first_hit: false,
+            call_stack: false,
+            calls_only: false,
+            traps: false,
+            csr_names: false,
+            gaps: false,
+            max_gap: 1000,
+            show_writes: false,
+            writes_to: Vec::new(),
+            data_symbols: false,
+            disassemble: false,
+            objdump_path: None,
+            follow: false,
+            listen: None,
+            jobs: None,
+        }
+    }
+
+    /**
+     * Constructor for the programs embedding the debugger, the architecture is read from the first elf.
+     *
+     * @param toolchain_prefix: The path and triple of the tools, i.e. /opt/riscv/bin/riscv32-unknown-elf-, the
+     * toolchain found for the architecture when None.
+     * @param addr2line_path: Optional addr2line to use instead of the built-in DWARF reader, an empty path is the one
+     * of the toolchain.
+     * @param elf_files: The elfs.
+     * @param load_offsets: The runtime address minus the link address of the elfs, in order.
+     * @return The config, error if the first elf can't be read or if the addr2line of the toolchain is asked for and
+     * no toolchain is found.
+     */
+    pub fn for_elfs(toolchain_prefix: Option<&str>, addr2line_path: Option<String>, elf_files: Vec<String>, load_offsets: Vec<i64>) -> Result<Config>{
+        let arch = match elf_files.first() {
+            Some(elf_file) => elf::read_arch(elf_file)?.unwrap_or_default(),
+            None => Arch::default(),
+        };
+        // The toolchain is only searched for when its addr2line is used.
+        let addr2line_path = match addr2line_path.as_deref() {
+            Some("") => Some(toolchain_prefix.map(Toolchain::new).or_else(|| Toolchain::discover(None, arch))
+                .ok_or_else(|| Error::tool("addr2line", "no toolchain was found for the elfs, give the toolchain prefix"))?
+                .tool("addr2line")),
+            _ => addr2line_path,
+        };
+        let mut config = Config::new(addr2line_path, elf_files, load_offsets);
+        config.arch = arch;
+        Ok(config)
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Deserializer};
+use verilator_debugger::{Arch, ColorChoice, Compression, OutputFormat, TraceField, TraceFormat, TraceLayout, RegexParser};
+
+/// The configuration file looked for in the current directory when --config isn't given.
+pub const DEFAULT_CONFIG_FILE: &str = "varilator_debugger.toml";
+
+/**
+ * The settings that can be checked in next to a regression instead of being passed on the command line.
+ *
+ * The command line options and environment variables take precedence over the file.
+ */
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile{
+    pub toolchain: ToolchainSection,
+    pub trace: TraceSection,
+    pub output: OutputSection,
+}
+
+/**
+ * The [toolchain] table, with the same meaning as the toolchain command line options.
+ */
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolchainSection{
+    pub prefix: Option<String>,
+    pub triple: Option<String>,
+    #[serde(deserialize_with = "parse_option")]
+    pub arch: Option<Arch>,
+    pub addr2line: Option<String>,
+    pub objdump: Option<String>,
+}
+
+/**
+ * The [trace] table, with the same meaning as the trace command line options.
+ */
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceSection{
+    #[serde(deserialize_with = "parse_option")]
+    pub format: Option<TraceFormat>,
+    #[serde(deserialize_with = "parse_layout")]
+    pub fields: Option<TraceLayout>,
+    pub pc_column: Option<u64>,
+    #[serde(deserialize_with = "parse_pc_regex")]
+    pub pc_regex: Option<String>,
+    pub pc_signal: Option<String>,
+}
+
+/**
+ * The [output] table, with the same meaning as the output command line options.
+ */
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSection{
+    #[serde(deserialize_with = "parse_option")]
+    pub format: Option<OutputFormat>,
+    #[serde(deserialize_with = "parse_option")]
+    pub compress: Option<Compression>,
+    #[serde(deserialize_with = "parse_option")]
+    pub color: Option<ColorChoice>,
+    pub context: Option<u32>,
+}
+
+/**
+ * Deserialize an optional value from its name.
+ */
+fn parse_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: std::str::FromStr<Err = String>,
+{
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/**
+ * Deserialize an optional field layout from its comma separated names.
+ */
+fn parse_layout<'de, D>(deserializer: D) -> Result<Option<TraceLayout>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let layout = String::deserialize(deserializer)?;
+    TraceField::parse_layout(&layout).map(Some).map_err(serde::de::Error::custom)
+}
+
+/**
+ * Deserialize an optional pc regex, checking that it's valid.
+ */
+fn parse_pc_regex<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    RegexParser::new(&pattern).map(|_| Some(pattern)).map_err(serde::de::Error::custom)
+}
+
+impl ConfigFile {
+    /**
+     * Load the configuration file.
+     *
+     * @param path: The file given with --config. When None, varilator_debugger.toml is used if it exists in the current directory.
+     * @return The settings, empty if there is no file, string error if the file can't be read or parsed.
+     */
+    pub fn load(path: Option<&str>) -> Result<ConfigFile, String>{
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => DEFAULT_CONFIG_FILE,
+            None => return Ok(ConfigFile::default()),
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(format!("Failed to read {}: {}", path, err)),
+        };
+        toml::from_str(&content).map_err(|err| format!("Failed to parse {}: {}", path, err))
+    }
+}
@@ -0,0 +1,157 @@
+use crate::trace::TraceRecord;
+
+/// The standard CSRs with a single number, their name and what they hold.
+const CSRS: [(u16, &str, &str); 65] = [
+    (0x001, "fflags", "Floating-point accrued exceptions"),
+    (0x002, "frm", "Floating-point dynamic rounding mode"),
+    (0x003, "fcsr", "Floating-point control and status register"),
+    (0xc00, "cycle", "Cycle counter for the rdcycle instruction"),
+    (0xc01, "time", "Timer for the rdtime instruction"),
+    (0xc02, "instret", "Instructions retired counter for the rdinstret instruction"),
+    (0xc80, "cycleh", "Upper 32 bits of cycle, RV32 only"),
+    (0xc81, "timeh", "Upper 32 bits of time, RV32 only"),
+    (0xc82, "instreth", "Upper 32 bits of instret, RV32 only"),
+    (0x100, "sstatus", "Supervisor status register"),
+    (0x104, "sie", "Supervisor interrupt enable register"),
+    (0x105, "stvec", "Supervisor trap handler base address"),
+    (0x106, "scounteren", "Supervisor counter enable"),
+    (0x10a, "senvcfg", "Supervisor environment configuration register"),
+    (0x140, "sscratch", "Scratch register for supervisor trap handlers"),
+    (0x141, "sepc", "Supervisor exception program counter"),
+    (0x142, "scause", "Supervisor trap cause"),
+    (0x143, "stval", "Supervisor bad address or instruction"),
+    (0x144, "sip", "Supervisor interrupt pending"),
+    (0x180, "satp", "Supervisor address translation and protection"),
+    (0xf11, "mvendorid", "Vendor ID"),
+    (0xf12, "marchid", "Architecture ID"),
+    (0xf13, "mimpid", "Implementation ID"),
+    (0xf14, "mhartid", "Hardware thread ID"),
+    (0xf15, "mconfigptr", "Pointer to the configuration data structure"),
+    (0x300, "mstatus", "Machine status register"),
+    (0x301, "misa", "ISA and extensions"),
+    (0x302, "medeleg", "Machine exception delegation register"),
+    (0x303, "mideleg", "Machine interrupt delegation register"),
+    (0x304, "mie", "Machine interrupt enable register"),
+    (0x305, "mtvec", "Machine trap handler base address"),
+    (0x306, "mcounteren", "Machine counter enable"),
+    (0x30a, "menvcfg", "Machine environment configuration register"),
+    (0x310, "mstatush", "Additional machine status register, RV32 only"),
+    (0x31a, "menvcfgh", "Upper 32 bits of menvcfg, RV32 only"),
+    (0x320, "mcountinhibit", "Machine counter inhibit register"),
+    (0x340, "mscratch", "Scratch register for machine trap handlers"),
+    (0x341, "mepc", "Machine exception program counter"),
+    (0x342, "mcause", "Machine trap cause"),
+    (0x343, "mtval", "Machine bad address or instruction"),
+    (0x344, "mip", "Machine interrupt pending"),
+    (0x34a, "mtinst", "Machine trap instruction (transformed)"),
+    (0x34b, "mtval2", "Machine bad guest physical address"),
+    (0x747, "mseccfg", "Machine security configuration register"),
+    (0x757, "mseccfgh", "Upper 32 bits of mseccfg, RV32 only"),
+    (0xb00, "mcycle", "Machine cycle counter"),
+    (0xb02, "minstret", "Machine instructions retired counter"),
+    (0xb80, "mcycleh", "Upper 32 bits of mcycle, RV32 only"),
+    (0xb82, "minstreth", "Upper 32 bits of minstret, RV32 only"),
+    (0x7a0, "tselect", "Debug/trace trigger register select"),
+    (0x7a1, "tdata1", "First debug/trace trigger data register"),
+    (0x7a2, "tdata2", "Second debug/trace trigger data register"),
+    (0x7a3, "tdata3", "Third debug/trace trigger data register"),
+    (0x7a8, "mcontext", "Machine-mode context register"),
+    (0x7b0, "dcsr", "Debug control and status register"),
+    (0x7b1, "dpc", "Debug program counter"),
+    (0x7b2, "dscratch0", "Debug scratch register 0"),
+    (0x7b3, "dscratch1", "Debug scratch register 1"),
+    (0x200, "vsstatus", "Virtual supervisor status register"),
+    (0x600, "hstatus", "Hypervisor status register"),
+    (0x602, "hedeleg", "Hypervisor exception delegation register"),
+    (0x603, "hideleg", "Hypervisor interrupt delegation register"),
+    (0x008, "vstart", "Vector start position"),
+    (0xc20, "vl", "Vector length"),
+    (0xc21, "vtype", "Vector data type register"),
+];
+
+/**
+ * A control and status register.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Csr{
+    pub number: u16,
+    /// The architectural name, i.e. mstatus.
+    pub name: String,
+    pub description: String,
+}
+
+impl Csr {
+    /**
+     * Look up a CSR by number.
+     *
+     * @param number: The 12 bit number of the CSR.
+     * @return The CSR, None if it isn't a standard one.
+     */
+    pub fn lookup(number: u16) -> Option<Csr>{
+        if let Some((number, name, description)) = CSRS.iter().find(|(csr, _, _)| *csr == number) {
+            return Some(Csr { number: *number, name: name.to_string(), description: description.to_string() });
+        }
+        // The numbered registers of the PMP and the hardware performance monitor, the high halves end with h.
+        let (name, index, suffix, description) = match number {
+            0x3a0..=0x3af => ("pmpcfg", number - 0x3a0, "", "Physical memory protection configuration"),
+            0x3b0..=0x3ef => ("pmpaddr", number - 0x3b0, "", "Physical memory protection address register"),
+            0x323..=0x33f => ("mhpmevent", number - 0x320, "", "Machine performance monitoring event selector"),
+            0xb03..=0xb1f => ("mhpmcounter", number - 0xb00, "", "Machine performance monitoring counter"),
+            0xb83..=0xb9f => ("mhpmcounter", number - 0xb80, "h", "Upper 32 bits of a machine performance monitoring counter, RV32 only"),
+            0xc03..=0xc1f => ("hpmcounter", number - 0xc00, "", "Performance monitoring counter"),
+            0xc83..=0xc9f => ("hpmcounter", number - 0xc80, "h", "Upper 32 bits of a performance monitoring counter, RV32 only"),
+            _ => return None,
+        };
+        Some(Csr { number, name: format!("{}{}{}", name, index, suffix), description: description.to_string() })
+    }
+
+    /**
+     * Look up a CSR by name.
+     *
+     * @param name: The architectural name, i.e. mtvec.
+     * @return The CSR, None if it isn't a standard one.
+     */
+    pub fn by_name(name: &str) -> Option<Csr>{
+        let numbered = name.trim_end_matches('h').ends_with(|c: char| c.is_ascii_digit());
+        let mut numbers = CSRS.iter().map(|(number, _, _)| *number)
+            .chain((0x320..0x3f0).chain(0xb00..0xca0).filter(|_| numbered));
+        numbers.find_map(|number| Csr::lookup(number).filter(|csr| csr.name == name))
+    }
+
+    /**
+     * Find the CSR a traced instruction reads or writes.
+     *
+     * The instruction word is decoded when the log has it, the operands of the mnemonic are used otherwise in both
+     * forms, i.e. csrrw x0,0x305,x5 and csrw mtvec,t0.
+     *
+     * @param record: The traced instruction.
+     * @return The CSR, None if the instruction doesn't access a standard CSR.
+     */
+    pub fn of(record: &TraceRecord) -> Option<Csr>{
+        let word = record.opcode.as_deref().and_then(|opcode| u32::from_str_radix(opcode.trim().trim_start_matches("0x"), 16).ok());
+        if let Some(word) = word {
+            return match (word & 0x7f, (word >> 12) & 0b111) {
+                (0x73, 1..=3) | (0x73, 5..=7) => Csr::lookup((word >> 20) as u16),
+                _ => None,
+            };
+        }
+        let instruction = record.instruction.as_deref()?.trim();
+        let (mnemonic, operands) = instruction.split_once(char::is_whitespace).unwrap_or((instruction, ""));
+        let number = match mnemonic {
+            "rdcycle" => return Csr::lookup(0xc00),
+            "rdtime" => return Csr::lookup(0xc01),
+            "rdinstret" => return Csr::lookup(0xc02),
+            "rdcycleh" => return Csr::lookup(0xc80),
+            "rdtimeh" => return Csr::lookup(0xc81),
+            "rdinstreth" => return Csr::lookup(0xc82),
+            // The csr is after the destination register in csrr and csrrw, first in csrw, csrs and csrc.
+            _ if mnemonic.starts_with("csrr") => operands.split(',').nth(1)?,
+            _ if mnemonic.starts_with("csr") => operands.split(',').next()?,
+            _ => return None,
+        }.trim();
+        match number.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).ok().and_then(Csr::lookup),
+            None => number.parse::<u16>().ok().and_then(Csr::lookup).or_else(|| Csr::by_name(number)),
+        }
+    }
+}
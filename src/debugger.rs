@@ -0,0 +1,1543 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use indicatif::{ProgressBar, ProgressStyle};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use regex::Regex;
+use crate::breakpoint::{BreakpointSet, WatchSet, Watchpoint};
+use crate::callstack::{CallStack, Frame};
+use crate::compress::{self, Compression, Encoder};
+use crate::config::Config;
+use crate::disasm::{self, Xlen};
+use crate::elf::{self, Disassembly, ElfFunction, LoadSegment, SymbolMap};
+use crate::error::{Error, Result};
+use crate::gap::{GapDetector, TraceGap};
+use crate::isa::Arch;
+use crate::output::{ColorChoice, OutputFormat, RenderOptions, Renderer};
+use crate::output::calls::CallsRenderer;
+use crate::output::coverage::CoverageRenderer;
+use crate::output::lcov::LcovRenderer;
+use crate::output::split::{SplitBy, SplitRenderer, INDEX_FILE};
+use crate::script::{Script, Verdict};
+use crate::source::{self, PathMap, SourceDirs};
+use crate::symbolizer::{SrcLocation, Symbolizer, SymbolizerPool};
+use crate::trace::{LayoutParser, RegexParser, TraceFormat, TraceParser, TraceReader, TraceRecord, DETECT_LINES};
+use crate::trap::{TrapDetector, TrapEvent};
+use crate::vfs;
+use crate::waveform;
+
+/// The log or output path that stands for stdin or stdout.
+pub const STDIO_PATH: &str = "-";
+
+/// The function of the instructions out of the ranges of the elfs, kept with --keep-external.
+pub const EXTERNAL_FUNCTION: &str = "[external]";
+
+/// How long to wait for the simulation to write more of the log when following it.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of log lines parsed and symbolized together by the worker threads.
+const CHUNK_LINES: usize = 16384;
+
+/// The most bytes of log lines in a chunk, a chunk of long lines is annotated before it has all its lines so the
+/// memory used stays bounded.
+const CHUNK_BYTES: usize = 8 << 20;
+
+/// The number of gaps of the trace warned about one by one.
+const MAX_GAP_WARNINGS: usize = 10;
+
+/// Below this number of new addresses in a chunk they are symbolized in the current thread.
+const PARALLEL_THRESHOLD: usize = 64;
+
+/// The number of instructions traced out of the ranges of the elfs before the first one in them that tells the elfs
+/// probably aren't the ones simulated.
+const MISMATCH_RECORDS: u64 = 256;
+
+/// The build-id a simulation prints in the log, i.e. "Build-ID: 3f2c9a1e" from the banner of the firmware.
+const BUILD_ID_PATTERN: &str = r"(?i)build[-_ ]?id\s*[:=]?\s*(?:0x)?([0-9a-f]{8,})";
+
+/**
+ * A log read as a stream of lines, with its size when it's known, or mapped in memory.
+ */
+enum LogInput{
+    Stream(Box<dyn BufRead>, Option<u64>),
+    Mapped(Mmap),
+}
+
+/**
+ * What an annotation went through, printed at its end.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnnotationSummary{
+    /// The lines of the log read.
+    pub lines: u64,
+    /// The instructions traced by the lines.
+    pub traced: u64,
+    /// The instructions in the ranges of the elfs.
+    pub kept: u64,
+}
+
+/**
+ * The source information of an address.
+ */
+#[derive(Debug, Clone)]
+pub struct SrcInfo{
+    pub location: SrcLocation,
+    /// The line of code pointed at by the location, None if the source isn't available.
+    pub code: Option<String>,
+    /// The numbered lines around the line of code when context lines are requested, including the line itself.
+    pub context: Vec<(u32, String)>,
+    /// The function symbol the address is in and the offset of the address in it when the code isn't available,
+    /// i.e. uart_init+0x24.
+    pub symbol: Option<String>,
+    /// The address is out of the ranges of the elfs, its symbol is [external: 0x...].
+    pub external: bool,
+}
+
+impl SrcInfo {
+    /**
+     * Get the source information of an address out of the ranges of the elfs, in the [external] function.
+     */
+    fn external(addr: u64) -> SrcInfo{
+        SrcInfo {
+            location: SrcLocation { function: Some(EXTERNAL_FUNCTION.to_string()), ..SrcLocation::default() },
+            code: None,
+            context: Vec::new(),
+            symbol: Some(format!("[external: 0x{:x}]", addr)),
+            external: true,
+        }
+    }
+
+    /**
+     * Describe an address whose code isn't available by its function symbol, i.e.
+     * uart_init+0x24 (uart.c:57, source unavailable), or as [external: 0x...] when it's out of the elfs.
+     *
+     * @return The description, None if the code is available or the address isn't in a function symbol.
+     */
+    pub fn unavailable(&self) -> Option<String>{
+        let symbol = self.symbol.as_ref()?;
+        if self.external {
+            return Some(symbol.clone());
+        }
+        let file = self.location.file.as_deref().map(|file| Path::new(file).file_name().map_or(file.into(), |name| name.to_string_lossy()));
+        Some(match (file, self.location.line) {
+            (Some(file), Some(line)) => format!("{} ({}:{}, source unavailable)", symbol, file, line),
+            (Some(file), None) => format!("{} ({}, source unavailable)", symbol, file),
+            (None, _) => format!("{} (source unavailable)", symbol),
+        })
+    }
+}
+
+/**
+ * The lines of the source files read so far, so each file is read once however many addresses point into it.
+ */
+#[derive(Debug, Default)]
+struct SourceCache{
+    /// The lines of each file, None for the files that can't be read.
+    files: Mutex<HashMap<PathBuf, Option<Arc<Vec<String>>>>>,
+    /// The rules mapping the paths of the debug info to the local ones.
+    path_maps: Vec<PathMap>,
+    /// The directories searched for the files that aren't at their local path.
+    source_dirs: SourceDirs,
+}
+
+impl SourceCache {
+    /**
+     * Get the lines of a source file, it's read the first time.
+     *
+     * @param path: The path of the file in the debug info, it's read from its local path.
+     * @return The lines, None if the file can't be read.
+     */
+    fn lines(&self, path: &str) -> Option<Arc<Vec<String>>>{
+        if let Some(lines) = self.files.lock().unwrap().get(Path::new(path)) {
+            return lines.clone();
+        }
+        // The file is read without holding the lock, the other threads may look up the files already read meanwhile.
+        let local_path = source::remap(&self.path_maps, path);
+        let opened = vfs::read(local_path.as_ref()).or_else(|err| match self.source_dirs.find(&local_path) {
+            Some(found) => {
+                log::debug!("Reading the source {} from {}", path, found.display());
+                vfs::read(found)
+            },
+            None => Err(err),
+        });
+        let lines = match opened {
+            Ok(data) => Some(Arc::new(data.lines().map_while(io::Result::ok).collect::<Vec<String>>())),
+            Err(err) => {
+                log::debug!("Can't read the source {}: {}", local_path, err);
+                None
+            },
+        };
+        self.files.lock().unwrap().insert(PathBuf::from(path), lines.clone());
+        lines
+    }
+}
+
+/**
+ * Annotate a Verilator trace log with the source code of the elf that produced it.
+ */
+pub struct DebuggerVarilator{
+    pub config :Config,
+    /// One symbolizer per elf, in the order of the elfs of the config.
+    symbolizers: Vec<Symbolizer>,
+    parser: Box<dyn TraceParser>,
+    /// The trace format will be detected from the first chunk.
+    detect_format: bool,
+    src_cache: HashMap<u64, SrcInfo>,
+    sources: SourceCache,
+    renderer: Box<dyn Renderer>,
+    stop: Arc<AtomicBool>,
+    pool: Arc<rayon::ThreadPool>,
+    workers: Vec<SymbolizerPool>,
+    /// The first address and the end address, excluded, of each elf, read when the annotation starts.
+    ranges: Vec<(u64, u64)>,
+    /// The last records out of the functions of the filter, or before the executions of the breakpoints, with their
+    /// call stacks and trap boundaries, shown if one of the functions is entered or a breakpoint is executed next.
+    before: VecDeque<(TraceRecord, Vec<Frame>, Option<TrapEvent>)>,
+    /// The number of records still shown after one of the functions of the filter was left, or after an execution
+    /// of a breakpoint.
+    after: u32,
+    /// A record after the end of the cycle or time window was read, the rest of the log is skipped.
+    past_window: bool,
+    breakpoints: BreakpointSet,
+    watchpoints: WatchSet,
+    /// The number of executions of the breakpoints and accesses of the watchpoints shown.
+    hits: u64,
+    /// The first execution of the breakpoints or access of the watchpoints was shown with its context, the rest of
+    /// the log is skipped.
+    stopped: bool,
+    call_stack: CallStack,
+    traps: TrapDetector,
+    gaps: GapDetector,
+    /// The last gap before records out of the ranges of the elfs, waiting for the next record in them.
+    dropped_gap: Option<TraceGap>,
+    /// The last gap before records hidden by the filters, waiting for the next record shown.
+    hidden_gap: Option<TraceGap>,
+    /// The first gaps found, warned about at the end, and the number of gaps.
+    gap_warnings: Vec<TraceGap>,
+    gap_count: u64,
+    /// The number of lines of the log that trace no instruction, of the stateless formats.
+    skipped_lines: u64,
+    /// The data symbols of the elfs, read when the variables accessed are shown.
+    data_symbols: Option<Arc<SymbolMap>>,
+    /// The disassembly shown, of objdump when it's given, empty to show the instructions of the log.
+    disassembly: Option<Arc<Disassembly>>,
+    /// The width of the registers of each elf, to decode the instruction words of the logs without instructions.
+    xlens: Vec<Xlen>,
+    /// The function symbols of the elfs, to show the addresses whose source isn't available by their offset.
+    functions: SymbolMap,
+    /// The build-id of each elf, compared with the one the log prints.
+    build_ids: Vec<Option<String>>,
+    /// Whether the first lines of the log were searched for the build-id of the simulation.
+    build_id_checked: bool,
+    /// The number of instructions traced out of the ranges of the elfs before the first one in them, None once one
+    /// is in them.
+    leading_misses: Option<u64>,
+    /// The script run on the instructions shown and at the end, None once it failed.
+    script: Option<Script>,
+}
+
+impl DebuggerVarilator {
+    /**
+     * Constructor.
+     *
+     * @param config: The paths of the elf, the log, the output and the tools and the output format.
+     * @return The debugger, or an error if there is no elf or an elf debug info can't be loaded.
+     */
+    pub fn new(config: Config) -> Result<DebuggerVarilator>{
+        if config.elf_files.is_empty() {
+            return Err(Error::Config(String::from("No elf given")));
+        }
+        if config.arch == Arch::Arm {
+            if config.traps || config.csr_names {
+                return Err(Error::Config(String::from("The traps and the CSR names are only known for RISC-V, not for ARM")));
+            }
+            if config.format == OutputFormat::Stack && !config.calls_only {
+                return Err(Error::Config(format!("The {} format follows the RISC-V stack pointer, it isn't available for ARM", config.format)));
+            }
+        }
+        if config.split_by.is_some() {
+            if config.output_file == STDIO_PATH {
+                return Err(Error::Config(String::from("The split output needs an output directory, not stdout")));
+            }
+            if matches!(config.format, OutputFormat::Coverage | OutputFormat::Lcov) && !config.calls_only {
+                return Err(Error::Config(format!("The {} format covers all the functions of the elfs, it can't be split", config.format)));
+            }
+        }
+        let symbolizers = config.elf_files.iter().map(|elf| Symbolizer::new(config.addr2line_path.as_deref(), elf))
+            .collect::<Result<Vec<Symbolizer>>>()?;
+        let pool = Self::thread_pool(config.jobs)?;
+        let workers = config.elf_files.iter()
+            .map(|elf| SymbolizerPool::new(config.addr2line_path.as_deref(), elf, pool.current_num_threads())).collect();
+        let data_symbols = match config.data_symbols {
+            true => Some(Arc::new(SymbolMap::read_data(&config.elf_files, &config.load_offsets)?)),
+            false => None,
+        };
+        let disassembly = match (config.disassemble, &config.objdump_path) {
+            (true, Some(objdump_path)) => Some(Arc::new(Disassembly::read(objdump_path, &config.elf_files, &config.load_offsets)?)),
+            (true, None) => Some(Arc::new(Disassembly::default())),
+            (false, _) => None,
+        };
+        // The symbols watched are found in the data symbols, read for them when they aren't shown.
+        let watched_symbols = config.watch_data.iter().any(|watchpoint| matches!(watchpoint, Watchpoint::Symbol(_)));
+        let watchpoints = match &data_symbols {
+            _ if config.watch_data.is_empty() => WatchSet::default(),
+            Some(symbols) => WatchSet::new(config.watch_data.clone(), symbols)?,
+            None if watched_symbols => WatchSet::new(config.watch_data.clone(),
+                &SymbolMap::read_data(&config.elf_files, &config.load_offsets)?)?,
+            None => WatchSet::new(config.watch_data.clone(), &SymbolMap::default())?,
+        };
+        let xlens = config.elf_files.iter().map(|elf| elf::read_xlen(elf)).collect::<Result<Vec<Xlen>>>()?;
+        let functions = SymbolMap::read_functions(&config.elf_files, &config.load_offsets, config.arch)?;
+        let build_ids = config.elf_files.iter().map(|elf| elf::read_build_id(elf)).collect::<Result<Vec<Option<String>>>>()?;
+        Ok(DebuggerVarilator {
+            pool,
+            workers,
+            symbolizers,
+            ranges: Vec::new(),
+            before: VecDeque::new(),
+            after: 0,
+            past_window: false,
+            breakpoints: BreakpointSet::new(config.break_at.clone()),
+            watchpoints,
+            hits: 0,
+            stopped: false,
+            call_stack: CallStack::new(config.arch),
+            traps: TrapDetector::new(),
+            gaps: GapDetector::new(config.max_gap),
+            dropped_gap: None,
+            hidden_gap: None,
+            gap_warnings: Vec::new(),
+            gap_count: 0,
+            skipped_lines: 0,
+            parser: match (&config.pc_regex, &config.layout) {
+                _ if config.pc_signal.is_some() => Box::new(LayoutParser::new(waveform::LAYOUT.to_vec())),
+                (Some(pattern), _) => Box::new(RegexParser::new(pattern)?),
+                (None, Some(layout)) => Box::new(LayoutParser::new(layout.clone())),
+                (None, None) => config.trace_format.unwrap_or_default().parser(),
+            },
+            detect_format: config.pc_regex.is_none() && config.layout.is_none() && config.trace_format.is_none()
+                && config.pc_signal.is_none(),
+            src_cache: HashMap::new(),
+            sources: SourceCache {
+                path_maps: config.path_maps.clone(),
+                source_dirs: SourceDirs::new(&config.source_dirs),
+                ..SourceCache::default()
+            },
+            renderer: Self::renderer(&config, config.color == ColorChoice::Always, data_symbols.clone(), disassembly.clone()),
+            data_symbols,
+            disassembly,
+            xlens,
+            functions,
+            build_ids,
+            build_id_checked: false,
+            leading_misses: Some(0),
+            script: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            config,
+        })
+    }
+
+    /**
+     * Start the worker threads that symbolize the addresses.
+     *
+     * @param jobs: The number of threads, None for one per core.
+     * @return The pool, error if the threads can't be started.
+     */
+    #[cfg(not(target_arch = "wasm32"))]
+    fn thread_pool(jobs: Option<usize>) -> Result<Arc<rayon::ThreadPool>> {
+        match rayon::ThreadPoolBuilder::new().num_threads(jobs.unwrap_or(0)).build() {
+            Ok(pool) => Ok(Arc::new(pool)),
+            Err(err) => Err(Error::Config(format!("Failed to start the worker threads: {}", err))),
+        }
+    }
+
+    /**
+     * Get the pool of the thread of the page, there are no threads in the browser. The thread can only be the worker
+     * of one pool, so the pool is shared by the debuggers.
+     */
+    #[cfg(target_arch = "wasm32")]
+    fn thread_pool(_jobs: Option<usize>) -> Result<Arc<rayon::ThreadPool>> {
+        thread_local! {
+            static POOL: std::cell::OnceCell<Arc<rayon::ThreadPool>> = const { std::cell::OnceCell::new() };
+        }
+        POOL.with(|pool| {
+            if let Some(pool) = pool.get() {
+                return Ok(pool.clone());
+            }
+            let built = match rayon::ThreadPoolBuilder::new().num_threads(1).use_current_thread().build() {
+                Ok(built) => Arc::new(built),
+                Err(err) => return Err(Error::Config(format!("Failed to start the worker threads: {}", err))),
+            };
+            Ok(pool.get_or_init(|| built).clone())
+        })
+    }
+
+    /**
+     * Get the flag that ends the annotation of a followed log once it's set, i.e. from a Ctrl-C handler.
+     */
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /**
+     * Parse the log with another parser than the configured one, i.e. the parser of a tracer that isn't built-in.
+     *
+     * @param parser: The parser, the trace format is no longer detected.
+     */
+    pub fn set_parser(&mut self, parser: Box<dyn TraceParser>) {
+        self.parser = parser;
+        self.detect_format = false;
+    }
+
+    /**
+     * Run the callbacks of a script along the annotation, to add notes to the instructions or hide them.
+     *
+     * @param script: The script, its on_record is called with the instructions that pass the filters.
+     */
+    pub fn set_script(&mut self, script: Script) {
+        self.script = Some(script);
+    }
+
+    /**
+     * Get the lines of a source file of the debug info, read from its local path like the code of the locations.
+     *
+     * @param path: The path of the file in the debug info.
+     * @return The lines, None if the file can't be read.
+     */
+    pub fn source_lines(&self, path: &str) -> Option<Arc<Vec<String>>> {
+        self.sources.lines(path)
+    }
+
+    /**
+     * Look up the source information of an address, the address is only symbolized the first time it's seen.
+     *
+     * @param addr: The address to be looked up.
+     * @return The source location and code of the address.
+     */
+    pub fn get_src_info(&mut self, addr: u64) -> &SrcInfo {
+        self.cache_src_info(addr);
+        &self.src_cache[&addr]
+    }
+
+    fn cache_src_info(&mut self, addr: u64) {
+        if self.is_external(addr) {
+            self.src_cache.entry(addr).or_insert_with(|| SrcInfo::external(addr));
+        } else if !self.src_cache.contains_key(&addr) {
+            let image = self.image_of(addr).unwrap_or(0);
+            let link_addr = self.link_addr(image, addr);
+            let location = self.symbolizers[image].locate(link_addr);
+            Self::log_unknown(addr, &location);
+            let info = Self::read_src_info(&self.sources, &self.functions, addr, location, self.config.context);
+            self.src_cache.insert(addr, info);
+        }
+    }
+
+    /**
+     * Symbolize the addresses that aren't cached yet, fanned out to the worker threads.
+     */
+    fn cache_addrs(&mut self, addrs: impl Iterator<Item = u64>) {
+        let mut addrs: Vec<u64> = addrs.filter(|addr| !self.src_cache.contains_key(addr)).collect();
+        addrs.sort_unstable();
+        addrs.dedup();
+        // The external addresses aren't symbolized with the first elf.
+        for addr in addrs.extract_if(.., |addr| self.is_external(*addr)).collect::<Vec<u64>>() {
+            self.src_cache.insert(addr, SrcInfo::external(addr));
+        }
+        // The addresses are symbolized in batches, one per elf and worker thread, so an addr2line process receives
+        // them all at once instead of waiting for the answer of each one.
+        let mut batches: Vec<Vec<(u64, u64)>> = vec![Vec::new(); self.symbolizers.len()];
+        for &addr in &addrs {
+            let image = self.image_of(addr).unwrap_or(0);
+            batches[image].push((addr, self.link_addr(image, addr)));
+        }
+        let (sources, functions, context) = (&self.sources, &self.functions, self.config.context);
+        let locate = |batch: &[(u64, u64)], locations: Vec<SrcLocation>| -> Vec<(u64, SrcInfo)> {
+            batch.iter().zip(locations).map(|(&(addr, _), location)| {
+                Self::log_unknown(addr, &location);
+                (addr, Self::read_src_info(sources, functions, addr, location, context))
+            }).collect()
+        };
+        if addrs.len() < PARALLEL_THRESHOLD {
+            for (symbolizer, batch) in self.symbolizers.iter_mut().zip(&batches) {
+                let link_addrs: Vec<u64> = batch.iter().map(|&(_, link_addr)| link_addr).collect();
+                self.src_cache.extend(locate(batch, symbolizer.locate_all(&link_addrs)));
+            }
+            return;
+        }
+        let workers = &self.workers;
+        let threads = self.pool.current_num_threads();
+        let infos: Vec<(u64, SrcInfo)> = self.pool.install(|| batches.par_iter().enumerate().flat_map(|(image, batch)| {
+            batch.par_chunks(batch.len().div_ceil(threads).max(1)).flat_map_iter(move |chunk| {
+                let link_addrs: Vec<u64> = chunk.iter().map(|&(_, link_addr)| link_addr).collect();
+                locate(chunk, workers[image].locate_all(&link_addrs))
+            })
+        }).collect());
+        self.src_cache.extend(infos);
+    }
+
+    /**
+     * Tell that the source of an address isn't known, the debug info may not cover it.
+     */
+    fn log_unknown(addr: u64, location: &SrcLocation) {
+        if location.file.is_none() {
+            match &location.function {
+                Some(function) => log::debug!("No source for the address 0x{:x} in {}", addr, function),
+                None => log::debug!("No source for the address 0x{:x}, it's in no function", addr),
+            }
+        }
+    }
+
+    /**
+     * Check if an address is kept though it's out of the ranges of the elfs, once they are read.
+     */
+    fn is_external(&self, addr: u64) -> bool {
+        self.config.keep_external && !self.ranges.is_empty() && self.image_of(addr).is_none()
+    }
+
+    /**
+     * Find the elf an address belongs to.
+     *
+     * @param addr: The address of an instruction.
+     * @return The index of the first elf whose range contains the address, None if there is none or the ranges
+     * aren't read yet.
+     */
+    fn image_of(&self, addr: u64) -> Option<usize> {
+        self.ranges.iter().position(|&(start_addr, end_addr)| start_addr <= addr && end_addr > addr)
+    }
+
+    /**
+     * Read the address ranges of the elfs, done when the annotation starts.
+     *
+     * @return Error if the range of an elf can't be read.
+     */
+    pub fn load_ranges(&mut self) -> Result<()> {
+        self.ranges = (0..self.config.elf_files.len()).map(|image| self.elf_range(image)).collect::<Result<Vec<(u64, u64)>>>()?;
+        Ok(())
+    }
+
+    /**
+     * Check if an address is in the range of one of the elfs, once they are loaded by load_ranges.
+     */
+    pub fn in_elfs(&self, addr: u64) -> bool {
+        self.image_of(addr).is_some()
+    }
+
+    /**
+     * Read the next record of a log in the ranges of the elfs, or out of them with keep_external, once the ranges are
+     * loaded by load_ranges. Its source information is then given by get_src_info.
+     *
+     * @param reader: The records of the log.
+     * @return The record with the address of its instruction, None at the end of the log, error if the log can't be
+     * read.
+     */
+    pub fn next_record(&mut self, reader: &mut TraceReader<impl BufRead>) -> Result<Option<TraceRecord>> {
+        for record in reader {
+            let mut record = record.map_err(|err| Error::io(&self.config.log_file, err))?;
+            record.pc = self.config.arch.code_addr(record.pc);
+            if self.in_elfs(record.pc) || self.config.keep_external {
+                return Ok(Some(record));
+            }
+        }
+        Ok(None)
+    }
+
+    /**
+     * Translate a runtime address to the address it has in its elf.
+     *
+     * @param image: The index of the elf.
+     * @param addr: The address of an instruction in the log.
+     * @return The address minus the load offset of the elf.
+     */
+    fn link_addr(&self, image: usize, addr: u64) -> u64 {
+        addr.wrapping_sub(self.load_offset(image) as u64)
+    }
+
+    fn load_offset(&self, image: usize) -> i64 {
+        self.config.load_offsets.get(image).copied().unwrap_or(0)
+    }
+
+    /**
+     * Read the code pointed at by a source location.
+     *
+     * @param sources: The source files read so far.
+     * @param functions: The function symbols, for the addresses whose code can't be read.
+     * @param addr: The address of the location.
+     * @param location: The source file and line.
+     * @param context: The number of lines to read before and after the line.
+     * @return The source information, with no code but the function symbol if it can't be read.
+     */
+    fn read_src_info(sources: &SourceCache, functions: &SymbolMap, addr: u64, location: SrcLocation, context: u32) -> SrcInfo {
+        let lines = Self::get_src_location(sources, &location, context);
+        let code = lines.iter().find(|(number, _)| Some(*number) == location.line).map(|(_, code)| code.clone());
+        let context = if context > 0 { lines } else { Vec::new() };
+        let symbol = if code.is_none() { functions.symbolize(addr) } else { None };
+        SrcInfo { location, code, context, symbol, external: false }
+    }
+
+    /**
+     * Get the lines of code around a source location.
+     *
+     * @param sources: The source files read so far.
+     * @param location: The source file and line.
+     * @param context: The number of lines to get before and after the line.
+     * @return The numbered lines, empty if they can't be read.
+     */
+    fn get_src_location(sources: &SourceCache, location: &SrcLocation, context: u32) -> Vec<(u32, String)> {
+        let (Some(number), Some(lines)) = (location.line, location.file.as_deref().and_then(|file| sources.lines(file))) else {
+            return Vec::new();
+        };
+        let first = number.saturating_sub(context).max(1);
+        lines.iter()
+            .zip(1..)
+            .skip(first as usize - 1)
+            .take_while(|(_, line)| *line <= number.saturating_add(context))
+            .map(|(code, line)| (line, code.clone()))
+            .collect()
+    }
+
+    /**
+     * Copy the lines of the log with addresses in the specified range, one by one so the memory used doesn't depend
+     * on the size of the log.
+     *
+     * @param input: The log, either a file or stdin.
+     * @param output: The output that receives the lines kept.
+     * @param start_addr: Range start address.
+     * @param end_addr: Range end address, the first address after the range.
+     * @return The number of lines kept, error if the log can't be read or the output can't be written.
+     */
+    pub fn filter_log(&mut self, mut input: impl BufRead, mut output: impl Write, start_addr:u64, end_addr:u64) -> Result<u64>{
+        let mut line = String::new();
+        let mut kept = 0;
+        while input.read_line(&mut line).map_err(|err| Error::io(&self.config.log_file, err))? > 0 {
+            let l = line.trim_end_matches(['\n', '\r']);
+            if self.parser.parse_pc(l).is_some_and(|addr| start_addr <= addr && end_addr > addr) {
+                writeln!(output, "{}", l).map_err(Error::Output)?;
+                kept += 1;
+            }
+            line.clear();
+        }
+        output.flush().map_err(Error::Output)?;
+        Ok(kept)
+    }
+
+
+    /**
+     * Read the elf and return the start address and the size of its code, from the entry point, or from the first
+     * executable LOAD segment when one is below it, to the end of the last executable LOAD segment.
+     *
+     * @param elf_file: Path to one of the elfs.
+     * @return a tuple with the address and size and error otherwise.
+     */
+    pub fn get_elf_addr_and_size(&self, elf_file: &str) -> Result<(u64,u64)>{
+        let (entry, segments) = elf::read_load_segments(elf_file)?;
+        let entry = self.config.arch.code_addr(entry);
+        let code: Vec<LoadSegment> = segments.into_iter().filter(|segment| segment.executable && segment.size > 0).collect();
+        let start_addr = code.iter().map(|segment| segment.addr).min().ok_or_else(|| Error::elf(elf_file, "no executable segment"))?;
+        let start_addr = start_addr.min(entry);
+        let end_addr = code.iter().map(|segment| segment.addr.saturating_add(segment.size)).max().unwrap_or(start_addr);
+        Ok((start_addr, end_addr - start_addr))
+    }
+
+    /**
+     * Read the functions of all the elfs, at the addresses they run from.
+     *
+     * @return The functions sorted by address, error if an elf can't be read.
+     */
+    pub fn elf_functions(&self) -> Result<Vec<ElfFunction>>{
+        let mut functions = Vec::new();
+        for (image, elf_file) in self.config.elf_files.iter().enumerate() {
+            let offset = self.load_offset(image) as u64;
+            functions.extend(elf::read_functions(elf_file, self.config.arch)?.into_iter().map(|function| ElfFunction {
+                addr: function.addr.wrapping_add(offset),
+                instructions: function.instructions.iter().map(|pc| pc.wrapping_add(offset)).collect(),
+                ..function
+            }));
+        }
+        functions.sort_by_key(|function| function.addr);
+        Ok(functions)
+    }
+
+    /**
+     * Symbolize the instructions of functions.
+     *
+     * @param functions: The functions at the addresses they run from.
+     * @return The source location of each instruction.
+     */
+    fn locate_functions(&mut self, functions: &[ElfFunction]) -> HashMap<u64, SrcLocation>{
+        self.cache_addrs(functions.iter().flat_map(|function| function.instructions.iter().copied()));
+        functions.iter().flat_map(|function| &function.instructions)
+            .map(|pc| (*pc, self.src_cache[pc].location.clone())).collect()
+    }
+
+    /**
+     * Get the addresses an elf is run from, the configured range or the one read from the elf.
+     *
+     * @param image: The index of the elf.
+     * @return The first address and the end address, excluded, error if the elf can't be read.
+     */
+    fn elf_range(&self, image: usize) -> Result<(u64, u64)>{
+        let start = self.config.start_addrs.get(image).copied();
+        let end = self.config.end_addrs.get(image).copied();
+        if let (Some(start_addr), Some(end_addr)) = (start, end) {
+            return Ok((start_addr, end_addr));
+        }
+        let (start_addr, size) = self.get_elf_addr_and_size(&self.config.elf_files[image])?;
+        let start_addr = start_addr.wrapping_add_signed(self.load_offset(image));
+        let range = (start.unwrap_or(start_addr), end.unwrap_or(start_addr.saturating_add(size)));
+        log::debug!("The code of {} is at 0x{:x}-0x{:x}", self.config.elf_files[image], range.0, range.1);
+        Ok(range)
+    }
+
+    /**
+     * Annotate a log line with the source code that produced it.
+     *
+     * @param line: A line of the log, lines that don't trace an instruction are ignored.
+     * @param output: The output that receives the annotated line in the configured format.
+     */
+    pub fn annotate_line(&mut self, line: &str, output: &mut dyn Write) -> io::Result<()> {
+        self.detect_trace_format(&[line.to_string()]);
+        let mut records = Vec::new();
+        self.parser.parse_into(line, &mut records);
+        for mut record in records {
+            record.pc = self.config.arch.code_addr(record.pc);
+            let gap = self.find_gap(&record);
+            if !self.in_harts(&record) {
+                continue;
+            }
+            self.cache_src_info(record.pc);
+            self.emit(record, gap, output)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Move the call stack to a symbolized record and write it unless it's filtered out by the path or the function
+     * filters.
+     *
+     * @param record: The record, its address must be cached.
+     * @param gap: The gap of the trace right before the record, if any.
+     * @param output: The output that receives the annotated record in the configured format.
+     */
+    fn emit(&mut self, mut record: TraceRecord, gap: Option<TraceGap>, output: &mut dyn Write) -> io::Result<()> {
+        if record.instruction.is_none() && self.config.arch == Arch::Riscv {
+            let xlen = self.image_of(record.pc).map_or(Xlen::default(), |image| self.xlens[image]);
+            record.instruction = disasm::decode_record(&record, xlen);
+        }
+        let trap = if self.config.traps { self.traps.update(&record) } else { None };
+        let gap = gap.or_else(|| self.hidden_gap.take()).filter(|_| self.config.gaps);
+        let location = &self.src_cache[&record.pc].location;
+        let shown = self.in_paths(location) && self.in_writes(&record);
+        let selected = Self::in_functions(&self.config.functions, location);
+        let stack = self.call_stack.update(&record, location.outer_function().unwrap_or("??"));
+        let hit = self.breakpoints.update(&record, location, &self.functions);
+        let watch = self.watchpoints.update(&record);
+        if !shown {
+            self.hidden_gap = gap;
+            return Ok(());
+        }
+        if let Some(script) = &mut self.script {
+            match script.on_record(&record, &self.src_cache[&record.pc]) {
+                Ok(Verdict::Show(notes)) => record.notes.extend(notes),
+                Ok(Verdict::Hide) => {
+                    self.hidden_gap = gap;
+                    return Ok(());
+                },
+                Err(err) => {
+                    log::warn!("{}, the script is disabled", err);
+                    self.script = None;
+                },
+            }
+        }
+        // The breakpoints and the watchpoints filter the instructions like the functions, around their hits.
+        let (filtered, selected, context) = match self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            true => (!self.config.functions.is_empty(), selected, self.config.function_context),
+            false => (true, (hit.is_some() || watch.is_some()) && !(self.config.first_hit && self.hits > 0), self.config.break_context),
+        };
+        if filtered {
+            if selected {
+                while let Some((before, before_stack, before_trap)) = self.before.pop_front() {
+                    if let Some(event) = before_trap {
+                        self.renderer.trap(&event, &before, output)?;
+                    }
+                    self.renderer.record(&before, &self.src_cache[&before.pc], &before_stack, output)?;
+                }
+                self.after = context;
+            } else if self.after > 0 {
+                self.after -= 1;
+            } else if self.config.first_hit && self.hits > 0 {
+                self.stopped = true;
+                return Ok(());
+            } else {
+                if context > 0 {
+                    if self.before.len() == context as usize {
+                        self.before.pop_front();
+                    }
+                    self.before.push_back((record, stack.to_vec(), trap));
+                }
+                self.hidden_gap = gap;
+                return Ok(());
+            }
+        }
+        if let Some(gap) = gap {
+            self.renderer.gap(&gap, &record, output)?;
+        }
+        if let Some(event) = trap {
+            self.renderer.trap(&event, &record, output)?;
+        }
+        if selected && (hit.is_some() || watch.is_some()) {
+            self.hits += 1;
+        }
+        if let Some(hit) = hit.filter(|_| selected) {
+            self.renderer.hit(&hit, &record, stack, output)?;
+        }
+        if let Some(watch) = watch.filter(|_| selected) {
+            self.renderer.watch(&watch, &record, output)?;
+        }
+        self.renderer.record(&record, &self.src_cache[&record.pc], stack, output)
+    }
+
+    /**
+     * Check if a record passes the register filter, by writing one of its registers.
+     */
+    fn in_writes(&self, record: &TraceRecord) -> bool {
+        self.config.writes_to.is_empty()
+            || record.register_writes().iter().any(|write| self.config.writes_to.contains(&write.register))
+    }
+
+    /**
+     * Check if the source file of a location passes the path filters.
+     *
+     * @param location: The location of an address, the unknown files are only kept when there is no --only-path.
+     */
+    fn in_paths(&self, location: &SrcLocation) -> bool {
+        let matches = |file: &str, path: &String| file.starts_with(path.as_str()) || file.contains(&format!("/{}", path.trim_start_matches("./")));
+        match location.file.as_deref() {
+            Some(file) => (self.config.only_paths.is_empty() || self.config.only_paths.iter().any(|path| matches(file, path)))
+                && !self.config.exclude_paths.iter().any(|path| matches(file, path)),
+            None => self.config.only_paths.is_empty(),
+        }
+    }
+
+    /**
+     * Check if a location is in one of the functions, either directly or inlined into it.
+     *
+     * @param functions: The names, that match the full function names or the names without their namespaces.
+     * @param location: The location of an address.
+     */
+    fn in_functions(functions: &[String], location: &SrcLocation) -> bool {
+        std::iter::once(location).chain(&location.inlined_at).filter_map(|frame| frame.function.as_deref())
+            .any(|function| {
+                let short = function.rsplit("::").next().unwrap_or(function);
+                functions.iter().any(|name| name == function || name == short)
+            })
+    }
+
+    /**
+     * Select the parser of the format detected from the first lines of the log, when no format was configured.
+     *
+     * @param lines: The first lines, nothing is done if they are empty.
+     */
+    fn detect_trace_format(&mut self, lines: &[impl AsRef<str>]) {
+        if !self.detect_format || lines.is_empty() {
+            return;
+        }
+        self.detect_format = false;
+        match TraceFormat::detect(lines) {
+            Some(format) => {
+                log::info!("Detected the {} trace format", format);
+                self.parser = format.parser();
+            },
+            None => log::warn!("no line of the log traces an instruction in a known format, use --trace-format, --fields or --pc-regex"),
+        }
+    }
+
+    /**
+     * Warn when the log prints a build-id that none of the elfs has, the elfs probably aren't the ones simulated.
+     *
+     * @param lines: The first lines of the log, nothing is done if they are empty or were already searched.
+     */
+    fn check_build_id(&mut self, lines: &[impl AsRef<str>]) {
+        if self.build_id_checked || lines.is_empty() {
+            return;
+        }
+        self.build_id_checked = true;
+        let build_id_re = Regex::new(BUILD_ID_PATTERN).unwrap();
+        let Some(logged) = lines.iter().find_map(|line| build_id_re.captures(line.as_ref()).map(|cap| cap[1].to_lowercase())) else {
+            return;
+        };
+        // The simulations may print a short build-id, like the short hashes of git.
+        if self.build_ids.iter().flatten().any(|build_id| build_id.starts_with(&logged) || logged.starts_with(build_id.as_str())) {
+            log::debug!("The log has the build-id {} of the elfs", logged);
+            return;
+        }
+        let elfs: Vec<String> = self.config.elf_files.iter().zip(&self.build_ids).map(|(elf, build_id)| match build_id {
+            Some(build_id) => format!("{} has {}", elf, build_id),
+            None => format!("{} has none", elf),
+        }).collect();
+        log::warn!("the elfs probably don't match the simulation, the log has the build-id {} but {}", logged, elfs.join(", "));
+    }
+
+    /**
+     * Count the instructions traced out of the elfs before the first one in them, and warn when there are so many
+     * the elfs probably aren't the ones simulated.
+     *
+     * @param in_elfs: Whether the next instruction traced is in the ranges of the elfs.
+     */
+    fn check_leading_miss(&mut self, in_elfs: bool) {
+        let Some(misses) = &mut self.leading_misses else {
+            return;
+        };
+        if in_elfs {
+            self.leading_misses = None;
+            return;
+        }
+        *misses += 1;
+        if *misses == MISMATCH_RECORDS {
+            let ranges: Vec<String> = self.ranges.iter().map(|(start_addr, end_addr)| format!("{:#x}-{:#x}", start_addr, end_addr)).collect();
+            log::warn!("the elfs probably don't match the simulation, none of the first {} traced instructions is in their address range ({}), check the elfs or use --start-addr and --end-addr",
+                MISMATCH_RECORDS, ranges.join(", "));
+        }
+    }
+
+    /**
+     * Annotate a chunk of log lines, the lines are parsed and their addresses symbolized by the worker threads.
+     *
+     * The lines of stateful formats are parsed in order in the current thread.
+     *
+     * @param lines: The lines of the chunk, in the order of the log. The instructions out of the ranges of the elfs
+     * are dropped.
+     * @param output: The output that receives the annotated lines in the configured format.
+     * @return The number of instructions traced by the lines and the number of them in the ranges.
+     */
+    fn annotate_chunk<S: AsRef<str> + Sync>(&mut self, lines: &[S], output: &mut dyn Write) -> io::Result<(u64, u64)> {
+        self.detect_trace_format(&lines[..lines.len().min(DETECT_LINES)]);
+        self.check_build_id(lines);
+        let records: Vec<TraceRecord> = if self.parser.is_stateful() {
+            let mut records = Vec::new();
+            for line in lines {
+                self.parser.parse_into(line.as_ref(), &mut records);
+            }
+            records
+        } else {
+            let parser = &self.parser;
+            let parsed: Vec<Option<TraceRecord>> = self.pool.install(|| lines.par_iter().map(|line| parser.parse(line.as_ref())).collect());
+            for (line, _) in lines.iter().zip(&parsed).filter(|(_, record)| record.is_none()) {
+                self.skipped_lines += 1;
+                log::trace!("Skipped the line {:?}, it traces no instruction", line.as_ref());
+            }
+            parsed.into_iter().flatten().collect()
+        };
+        let traced = records.len() as u64;
+        let mut kept_records = Vec::with_capacity(records.len());
+        let mut kept = 0;
+        for mut record in records {
+            record.pc = self.config.arch.code_addr(record.pc);
+            if let Some(gap) = self.find_gap(&record) {
+                self.dropped_gap = Some(gap);
+            }
+            let in_elfs = self.image_of(record.pc).is_some();
+            self.check_leading_miss(in_elfs);
+            if in_elfs {
+                kept += 1;
+            }
+            if in_elfs || self.config.keep_external {
+                let gap = self.dropped_gap.take();
+                kept_records.push((record, gap));
+            }
+        }
+        self.past_window |= kept_records.iter().any(|(record, _)| self.is_past_window(record));
+        kept_records.retain(|(record, _)| self.in_window(record) && self.in_harts(record));
+        self.cache_addrs(kept_records.iter().map(|(record, _)| record.pc));
+        for (record, gap) in kept_records {
+            if self.stopped {
+                break;
+            }
+            self.emit(record, gap, output)?;
+        }
+        Ok((traced, kept))
+    }
+
+    /**
+     * Process the log file by iterating through all lines.
+     *
+     * The log and the output are read from stdin and written to stdout when their path is -. The status messages
+     * are logged with the log crate so they don't mix with the output. When following, the log is read until the stop
+     * flag is set instead of until its end.
+     *
+     * @return The lines read and the instructions annotated, error if the log can't be read, the output can't be
+     * written or the elfs can't be used.
+     */
+    pub fn run (&mut self) -> Result<AnnotationSummary> {
+        log::info!("Starting ...");
+
+        // The log is mapped unless it's compressed, written while it's read, streamed or synthesized from a waveform.
+        let stream = compress::is_stream(&self.config.log_file);
+        let mapped = match stream || self.config.follow || self.config.pc_signal.is_some()
+            || self.config.listen.is_some() {
+            true => None,
+            false => self.map_log()?,
+        };
+        let (input, compression) = match (mapped, &self.config.pc_signal, &self.config.listen) {
+            (Some(log), _, _) => (LogInput::Mapped(log), None),
+            (None, _, Some(address)) => (LogInput::Stream(self.accept_log(address)?, None), None),
+            (None, Some(pc_signal), None) => {
+                log::info!("Reading the changes of {} from the waveform", pc_signal);
+                if self.config.follow {
+                    log::warn!("a waveform is read to its end, it isn't followed");
+                }
+                (LogInput::Stream(waveform::open(&self.config.log_file, pc_signal)?, None), None)
+            },
+            (None, None, None) => {
+                let (input, compression) = compress::open_log(&self.config.log_file)?;
+                // The progress of a compressed log is unknown, its size isn't the size of its lines.
+                let total = if stream || compression.is_some() {
+                    None
+                } else {
+                    Some(fs::metadata(&self.config.log_file).map_err(|err| Error::io(&self.config.log_file, err))?.len())
+                };
+                (LogInput::Stream(input, total), compression)
+            },
+        };
+        if self.config.listen.is_none() {
+            log::info!("File {} opened successfully", self.config.log_file);
+        }
+        if let Some(compression) = compression {
+            log::info!("Decompressing the {} log", compression);
+            if self.config.follow {
+                log::warn!("a compressed log is complete, it isn't followed");
+            }
+        }
+        let follow = self.config.follow && self.config.log_file != STDIO_PATH && compression.is_none() && self.config.pc_signal.is_none();
+        if follow {
+            log::info!("Following it, press Ctrl-C to stop...");
+        } else {
+            log::info!("Parsing it...");
+        }
+
+        let (output, color): (Box<dyn Write>, bool) = if self.config.output_file == STDIO_PATH {
+            (Box::new(io::stdout().lock()), self.config.color.enabled(&io::stdout()))
+        } else if self.config.split_by.is_some() {
+            // The output is the index of the files written into the directory, the files are never terminals.
+            let index = Path::new(&self.config.output_file).join(INDEX_FILE);
+            let file = fs::create_dir_all(&self.config.output_file).and_then(|_| File::create(&index))
+                .map_err(|err| Error::Output(io::Error::new(err.kind(), format!("{}, {}", index.display(), err))))?;
+            (Box::new(file), self.config.color == ColorChoice::Always)
+        } else {
+            let file = File::create(&self.config.output_file)
+                .map_err(|err| Error::Output(io::Error::new(err.kind(), format!("{}, {}", self.config.output_file, err))))?;
+            let color = self.config.color.enabled(&file);
+            (Box::new(file), color)
+        };
+        // The colors of a compressed output are only written when asked for, even to a terminal.
+        let color = color && (self.config.compress.is_none() || self.config.color == ColorChoice::Always);
+        self.renderer = Self::renderer(&self.config, color, self.data_symbols.clone(), self.disassembly.clone());
+        let mut output = Encoder::new(self.config.compress.filter(|_| self.config.split_by.is_none()), output);
+        let summary = match input {
+            LogInput::Mapped(log) => self.annotate_bytes(&log, io::BufWriter::new(&mut output))?,
+            LogInput::Stream(input, total) => self.annotate(input, io::BufWriter::new(&mut output), total.filter(|_| !follow), follow)?,
+        };
+        output.finish().map_err(Error::Output)?;
+
+        if self.config.output_file == STDIO_PATH {
+            log::info!("Finished");
+        } else {
+            log::info!("Finished\nOutput {} generated successfully", self.config.output_file);
+        }
+        Ok(summary)
+    }
+
+    /**
+     * Wait for a testbench to connect and stream its log over TCP, until the stop flag is set.
+     *
+     * @param address: The address to listen on, i.e. 0.0.0.0:9999.
+     * @return The lines of the connection, read until the testbench closes it, no lines when stopped before a
+     * connection, error if the address can't be listened on.
+     */
+    fn accept_log(&self, address: &str) -> Result<Box<dyn BufRead>> {
+        let listener = TcpListener::bind(address).and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|err| Error::io(address, err))?;
+        log::info!("Listening on {}, waiting for the testbench to connect...", address);
+        // The accept is polled so Ctrl-C still stops the wait.
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    log::info!("Annotating the log streamed by {}, press Ctrl-C to stop...", peer);
+                    break stream;
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock && !self.stop.load(Ordering::Relaxed) => thread::sleep(FOLLOW_POLL_INTERVAL),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(Box::new(io::empty())),
+                Err(err) => return Err(Error::io(address, err)),
+            }
+        };
+        // The reads time out so the lines already received are written while the testbench runs.
+        stream.set_nonblocking(false).and_then(|_| stream.set_read_timeout(Some(FOLLOW_POLL_INTERVAL)))
+            .map_err(|err| Error::io(address, err))?;
+        Ok(Box::new(io::BufReader::new(stream)))
+    }
+
+    /**
+     * Map the log file in memory so its lines aren't copied.
+     *
+     * @return The mapped log, None if it's compressed or it can't be mapped, i.e. it's a pipe, error if it can't be
+     * opened.
+     */
+    fn map_log(&self) -> Result<Option<Mmap>> {
+        let file = File::open(&self.config.log_file).map_err(|err| Error::io(&self.config.log_file, err))?;
+        // SAFETY: the log isn't followed, it's complete and isn't expected to change while it's mapped.
+        let Ok(log) = (unsafe { Mmap::map(&file) }) else {
+            return Ok(None);
+        };
+        let compressed = Compression::detect(&self.config.log_file, &log[..log.len().min(4)]).is_some();
+        Ok(Some(log).filter(|_| !compressed))
+    }
+
+    /**
+     * Move the gap detector to a record and keep the gap the record is after for the warnings.
+     *
+     * @param record: The next record of the log, out of the ranges of the elfs or not.
+     * @return The gap right before the record, None if there is none.
+     */
+    fn find_gap(&mut self, record: &TraceRecord) -> Option<TraceGap> {
+        let gap = self.gaps.update(record)?;
+        self.gap_count += 1;
+        if self.gap_warnings.len() < MAX_GAP_WARNINGS {
+            self.gap_warnings.push(gap.clone());
+        }
+        Some(gap)
+    }
+
+    /**
+     * Check if a record is in the cycle and time windows.
+     *
+     * @param record: A record, those without a cycle or a time are out of the windows on them.
+     */
+    fn in_window(&self, record: &TraceRecord) -> bool {
+        let inside = |value: Option<u64>, from: Option<u64>, to: Option<u64>| (from.is_none() && to.is_none())
+            || value.is_some_and(|value| from.is_none_or(|from| value >= from) && to.is_none_or(|to| value <= to));
+        inside(record.cycle, self.config.from_cycle, self.config.to_cycle)
+            && inside(record.time, self.config.from_time, self.config.to_time)
+    }
+
+    /**
+     * Check if a record is of one of the harts of the filter.
+     */
+    fn in_harts(&self, record: &TraceRecord) -> bool {
+        self.config.harts.is_empty() || self.config.harts.contains(&record.hart.unwrap_or(0))
+    }
+
+    /**
+     * Check if a record is after the end of the windows, the cycles and the times only grow along the log.
+     */
+    fn is_past_window(&self, record: &TraceRecord) -> bool {
+        let past = |value: Option<u64>, to: Option<u64>| value.zip(to).is_some_and(|(value, to)| value > to);
+        past(record.cycle, self.config.to_cycle) || past(record.time, self.config.to_time)
+    }
+
+    /**
+     * Annotate a whole log, the lines are read and written one by one so the memory used doesn't depend on the size of the log.
+     *
+     * @param input: The log.
+     * @param output: The output that receives the annotated log in the configured format.
+     * @param total: The size of the log in bytes used to report the progress, None when it's unknown.
+     * @param follow: Wait for more lines at the end of the input, like tail -f, until the stop flag is set.
+     * @return The number of lines and instructions annotated, error if the elfs can't be used, the input can't be read
+     * or the output can't be written.
+     *
+     * When a read of the input times out, i.e. a socket with no line yet, the lines read so far are written and it's
+     * read again until the stop flag is set.
+     */
+    pub fn annotate(&mut self, mut input: impl BufRead, mut output: impl Write, total: Option<u64>, follow: bool) -> Result<AnnotationSummary> {
+        self.begin_annotation(&mut output)?;
+        let mut line = String::new();
+        let mut chunk = Vec::with_capacity(CHUNK_LINES);
+        let mut chunk_bytes = 0;
+        let mut read: u64 = 0;
+        let mut summary = AnnotationSummary::default();
+        let progress = Self::progress_bar(total);
+        loop {
+            let (len, idle) = match input.read_line(&mut line) {
+                Ok(len) => (len, false),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (0, true),
+                Err(err) => return Err(Error::io(&self.config.log_file, err)),
+            };
+            read += len as u64;
+            // When following, a line without its newline is still being written by the simulation.
+            let waiting = (idle || follow && !line.ends_with('\n')) && !self.stop.load(Ordering::Relaxed);
+            if !waiting && !line.is_empty() {
+                chunk_bytes += line.len();
+                chunk.push(line.trim_end_matches(['\n', '\r']).to_string());
+                line.clear();
+            }
+            if waiting || len == 0 || chunk.len() >= CHUNK_LINES || chunk_bytes >= CHUNK_BYTES {
+                self.annotate_counted(&chunk, &mut output, &mut summary)?;
+                chunk.clear();
+                chunk_bytes = 0;
+                Self::show_progress(&progress, read, &summary);
+            }
+            if self.past_window || self.stopped {
+                break;
+            }
+            if waiting {
+                // Show what was annotated so far and wait for the simulation to write more.
+                output.flush().map_err(Error::Output)?;
+                if !idle {
+                    thread::sleep(FOLLOW_POLL_INTERVAL);
+                }
+            } else if len == 0 {
+                break;
+            }
+        }
+        self.end_annotation(progress, summary, &mut output)
+    }
+
+    /**
+     * Annotate a whole log held in memory, i.e. a mapped file, the lines are borrowed from it instead of copied.
+     *
+     * @param log: The bytes of the log.
+     * @param output: The output that receives the annotated log in the configured format.
+     * @return The number of lines and instructions annotated, error if the elfs can't be used, a line isn't UTF-8 or
+     * the output can't be written.
+     */
+    pub fn annotate_bytes(&mut self, log: &[u8], mut output: impl Write) -> Result<AnnotationSummary> {
+        self.begin_annotation(&mut output)?;
+        let mut lines = log.split_inclusive(|byte| *byte == b'\n');
+        let mut chunk = Vec::with_capacity(CHUNK_LINES);
+        let mut chunk_bytes = 0;
+        let mut read: u64 = 0;
+        let mut summary = AnnotationSummary::default();
+        let progress = Self::progress_bar(Some(log.len() as u64));
+        loop {
+            let line = lines.next();
+            if let Some(line) = line {
+                read += line.len() as u64;
+                let line = std::str::from_utf8(line).map_err(|err| {
+                    Error::io(&self.config.log_file, io::Error::new(io::ErrorKind::InvalidData, err))
+                })?;
+                chunk_bytes += line.len();
+                chunk.push(line.trim_end_matches(['\n', '\r']));
+            }
+            if line.is_none() || chunk.len() >= CHUNK_LINES || chunk_bytes >= CHUNK_BYTES {
+                self.annotate_counted(&chunk, &mut output, &mut summary)?;
+                chunk.clear();
+                chunk_bytes = 0;
+                Self::show_progress(&progress, read, &summary);
+            }
+            if self.past_window || self.stopped || line.is_none() {
+                break;
+            }
+        }
+        self.end_annotation(progress, summary, &mut output)
+    }
+
+    /**
+     * Read the ranges of the elfs, select the renderer of the coverage and write the start of the output.
+     */
+    fn begin_annotation(&mut self, output: &mut impl Write) -> Result<()> {
+        self.load_ranges()?;
+        if matches!(self.config.format, OutputFormat::Coverage | OutputFormat::Lcov) && !self.config.calls_only {
+            let functions = self.elf_functions()?;
+            self.renderer = match self.config.format {
+                OutputFormat::Lcov => {
+                    let locations = self.locate_functions(&functions);
+                    Box::new(LcovRenderer::new(functions, locations))
+                },
+                _ => Box::new(CoverageRenderer::new(functions)),
+            };
+        }
+        self.renderer.begin(output).map_err(Error::Output)
+    }
+
+    /**
+     * Annotate a chunk of lines and add them to the summary.
+     */
+    fn annotate_counted<S: AsRef<str> + Sync>(&mut self, chunk: &[S], output: &mut impl Write, summary: &mut AnnotationSummary) -> Result<()> {
+        let (traced, kept) = self.annotate_chunk(chunk, output).map_err(Error::Output)?;
+        summary.traced += traced;
+        summary.kept += kept;
+        summary.lines += chunk.len() as u64;
+        Ok(())
+    }
+
+    /**
+     * Move the progress bar to the bytes of the log read so far.
+     */
+    fn show_progress(progress: &ProgressBar, read: u64, summary: &AnnotationSummary) {
+        let rate = summary.lines as f64 / progress.elapsed().as_secs_f64().max(1e-3);
+        progress.set_position(read);
+        progress.set_message(format!("{} lines, {:.0} lines/s", summary.lines, rate));
+    }
+
+    /**
+     * Warn about what the annotation found wrong in the log and write the end of the output.
+     */
+    fn end_annotation(&mut self, progress: ProgressBar, summary: AnnotationSummary, output: &mut impl Write) -> Result<AnnotationSummary> {
+        progress.finish();
+        if !progress.is_hidden() {
+            eprintln!();
+        }
+        // The instructions out of the elfs are already warned about when there are enough of them.
+        if summary.traced > 0 && summary.kept == 0 && summary.traced < MISMATCH_RECORDS {
+            let ranges: Vec<String> = self.ranges.iter().map(|(start_addr, end_addr)| format!("{:#x}-{:#x}", start_addr, end_addr)).collect();
+            log::warn!("none of the {} traced instructions is in the address range of the elfs ({}), use --start-addr and --end-addr",
+                summary.traced, ranges.join(", "));
+        }
+        for gap in &self.gap_warnings {
+            log::warn!("gap in the trace, the {}, the simulator may have dropped lines or the log was cut", gap);
+        }
+        if self.gap_count > self.gap_warnings.len() as u64 {
+            log::warn!("{} more gaps in the trace", self.gap_count - self.gap_warnings.len() as u64);
+        }
+        if self.skipped_lines > 0 {
+            log::debug!("{} lines of the log trace no instruction and were skipped", self.skipped_lines);
+        }
+        if self.stopped {
+            log::info!("Stopped at the first hit, the rest of the log isn't read");
+        } else {
+            for (number, (breakpoint, count)) in self.breakpoints.counts().enumerate() {
+                match count {
+                    0 => log::warn!("breakpoint {} ({}) wasn't hit, check the address, the function or the line", number + 1, breakpoint),
+                    1 => log::info!("Breakpoint {} ({}) hit 1 time", number + 1, breakpoint),
+                    _ => log::info!("Breakpoint {} ({}) hit {} times", number + 1, breakpoint, count),
+                }
+            }
+            for (number, (watchpoint, count)) in self.watchpoints.counts().enumerate() {
+                match count {
+                    0 => log::warn!("watchpoint {} ({}) wasn't accessed, the log may not have the data addresses", number + 1, watchpoint),
+                    1 => log::info!("Watchpoint {} ({}) hit 1 time", number + 1, watchpoint),
+                    _ => log::info!("Watchpoint {} ({}) hit {} times", number + 1, watchpoint, count),
+                }
+            }
+        }
+        match self.script.as_mut().map(|script| script.on_end(&summary)) {
+            Some(Ok(Some(text))) => eprintln!("{}", text),
+            Some(Err(err)) => log::warn!("{}", err),
+            _ => {},
+        }
+        self.renderer.end(output).map_err(Error::Output)?;
+        output.flush().map_err(Error::Output)?;
+        Ok(summary)
+    }
+
+    /**
+     * Create the renderer of the configured output, the outline of the calls with --calls-only, writing the files of
+     * the functions when the output is split.
+     *
+     * @param config: The configuration of the run.
+     * @param color: Whether to highlight the annotations with ANSI colors.
+     * @param data_symbols: The data symbols of the elfs, when the variables accessed are shown.
+     * @param disassembly: The disassembly of objdump, or an empty one for the instructions of the log, when it's shown.
+     */
+    fn renderer(config: &Config, color: bool, data_symbols: Option<Arc<SymbolMap>>, disassembly: Option<Arc<Disassembly>>) -> Box<dyn Renderer> {
+        let (calls_only, format) = (config.calls_only, config.format);
+        let options = RenderOptions {
+            color,
+            call_stack: config.call_stack,
+            csr_names: config.csr_names,
+            writes: config.show_writes,
+            data_symbols,
+            disassembly,
+            arch: config.arch,
+        };
+        let new_renderer = move || -> Box<dyn Renderer> {
+            if calls_only {
+                Box::new(CallsRenderer::new())
+            } else {
+                format.renderer(options.clone())
+            }
+        };
+        match config.split_by {
+            Some(SplitBy::Function) => {
+                let extension = if calls_only { "txt" } else { format.extension() };
+                Box::new(SplitRenderer::new(&config.output_file, config.compress, extension, Box::new(new_renderer)))
+            },
+            None => new_renderer(),
+        }
+    }
+
+    /**
+     * Create the progress bar drawn on stderr, hidden when stderr isn't a terminal, the output is quiet or the
+     * diagnostics of the lines and the addresses would be drawn over it.
+     *
+     * @param total: The size of the log in bytes, None for a spinner when it's unknown.
+     */
+    fn progress_bar(total: Option<u64>) -> ProgressBar {
+        if !log::log_enabled!(log::Level::Info) || log::log_enabled!(log::Level::Debug) {
+            return ProgressBar::hidden();
+        }
+        match total {
+            Some(total) => ProgressBar::new(total).with_style(
+                ProgressStyle::with_template("{bar:40} {percent:>3}% {msg} [{elapsed_precise}, ETA {eta_precise}]").unwrap()),
+            None => {
+                let spinner = ProgressBar::new_spinner().with_style(
+                    ProgressStyle::with_template("{spinner} {msg} [{elapsed_precise}]").unwrap());
+                spinner.enable_steady_tick(Duration::from_millis(100));
+                spinner
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * Build a RISC-V elf32 with one executable segment and no symbols nor debug info.
+     */
+    fn code_elf(addr: u32, size: u32) -> Vec<u8>{
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        // The header: executable, RISC-V, version, entry, program headers after it, no section.
+        for (value, width) in [(2, 2), (0xf3, 2), (1, 4), (addr, 4), (52, 4), (0, 4), (0, 4), (52, 2), (32, 2), (1, 2),
+            (40, 2), (0, 2), (0, 2)] {
+            elf.extend_from_slice(&u32::to_le_bytes(value)[..width]);
+        }
+        // The loaded, readable and executable segment, its bytes follow.
+        for value in [1, 84, addr, addr, size, size, 5, 4] {
+            elf.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        elf.resize(84 + size as usize, 0);
+        elf
+    }
+
+    /**
+     * Build a RISC-V elf32 with its code in a .text section and the functions in its .symtab.
+     *
+     * @param functions: The name, address and size of the functions.
+     */
+    fn function_elf(addr: u32, code: &[u8], functions: &[(&str, u32, u32)]) -> Vec<u8>{
+        let mut strtab = vec![0];
+        let mut symtab = vec![0; 16];
+        for (name, addr, size) in functions {
+            // A global function of the section 1.
+            for (value, width) in [(strtab.len() as u32, 4), (*addr, 4), (*size, 4), (0x12, 1), (0, 1), (1, 2)] {
+                symtab.extend_from_slice(&u32::to_le_bytes(value)[..width]);
+            }
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+        }
+        let shstrtab = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+        let symtab_offset = 84 + code.len() as u32;
+        let strtab_offset = symtab_offset + symtab.len() as u32;
+        let shstrtab_offset = strtab_offset + strtab.len() as u32;
+        let shoff = shstrtab_offset + shstrtab.len() as u32;
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        // The header: executable, RISC-V, version, entry, program headers after it, 5 sections at the end.
+        for (value, width) in [(2, 2), (0xf3, 2), (1, 4), (addr, 4), (52, 4), (shoff, 4), (0, 4), (52, 2), (32, 2), (1, 2),
+            (40, 2), (5, 2), (4, 2)] {
+            elf.extend_from_slice(&u32::to_le_bytes(value)[..width]);
+        }
+        let size = code.len() as u32;
+        for value in [1, 84, addr, addr, size, size, 5, 4] {
+            elf.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        elf.extend_from_slice(code);
+        elf.extend_from_slice(&symtab);
+        elf.extend_from_slice(&strtab);
+        elf.extend_from_slice(shstrtab);
+        // The null section, .text, .symtab linked to .strtab, .strtab and .shstrtab.
+        for header in [[0; 10], [1, 1, 6, addr, 84, size, 0, 0, 4, 0],
+            [7, 2, 0, 0, symtab_offset, symtab.len() as u32, 3, 1, 4, 16],
+            [15, 3, 0, 0, strtab_offset, strtab.len() as u32, 0, 0, 1, 0],
+            [23, 3, 0, 0, shstrtab_offset, shstrtab.len() as u32, 0, 0, 1, 0]] {
+            for value in header {
+                elf.extend_from_slice(&u32::to_le_bytes(value));
+            }
+        }
+        elf
+    }
+
+    fn debugger(path: &str, start_addrs: Vec<u64>, end_addrs: Vec<u64>) -> DebuggerVarilator{
+        vfs::insert(path, code_elf(0x80000090, 0x9c));
+        let mut config = Config::new(None, vec![path.to_string()], Vec::new());
+        config.start_addrs = start_addrs;
+        config.end_addrs = end_addrs;
+        config.jobs = Some(1);
+        DebuggerVarilator::new(config).unwrap()
+    }
+
+    #[test]
+    fn elf_range_starts_at_the_first_instruction(){
+        let mut dv = debugger("/elf_range/first.elf", Vec::new(), Vec::new());
+        assert_eq!(dv.elf_range(0).unwrap(), (0x80000090, 0x8000012c));
+        dv.load_ranges().unwrap();
+        assert!(dv.in_elfs(0x80000090));
+        assert!(dv.in_elfs(0x8000012a));
+        assert!(!dv.in_elfs(0x8000008e));
+        assert!(!dv.in_elfs(0x8000012c));
+    }
+
+    #[test]
+    fn elf_range_is_overridden_by_the_config(){
+        let dv = debugger("/elf_range/both.elf", vec![0x1000], vec![0x2000]);
+        assert_eq!(dv.elf_range(0).unwrap(), (0x1000, 0x2000));
+        let dv = debugger("/elf_range/start.elf", vec![0x800000a0], Vec::new());
+        assert_eq!(dv.elf_range(0).unwrap(), (0x800000a0, 0x8000012c));
+        let dv = debugger("/elf_range/end.elf", Vec::new(), vec![0x80000100]);
+        assert_eq!(dv.elf_range(0).unwrap(), (0x80000090, 0x80000100));
+    }
+
+    #[test]
+    fn coverage_counts_the_instructions_of_each_function(){
+        // first: nop, c.nop, c.nop, nop; second: nop, ret.
+        let code = [0x13, 0, 0, 0, 1, 0, 1, 0, 0x13, 0, 0, 0, 0x13, 0, 0, 0, 0x67, 0x80, 0, 0];
+        let path = "/coverage/functions.elf";
+        vfs::insert(path, function_elf(0x80000000, &code, &[("first", 0x80000000, 12), ("second", 0x8000000c, 8)]));
+        let functions = elf::read_functions(path, Arch::Riscv).unwrap();
+        let instructions: Vec<(&str, &[u64])> = functions.iter().map(|f| (f.name.as_str(), f.instructions.as_slice())).collect();
+        assert_eq!(instructions, [("first", &[0x80000000, 0x80000004, 0x80000006, 0x80000008][..]),
+            ("second", &[0x8000000c, 0x80000010][..])]);
+        let mut config = Config::new(None, vec![path.to_string()], Vec::new());
+        config.format = OutputFormat::Coverage;
+        config.jobs = Some(1);
+        let mut dv = DebuggerVarilator::new(config).unwrap();
+        let log = "Time\tCycle\tPC\tInsn\tDecoded instruction\tRegister and memory contents\n\
+            10\t1\t80000000\t00000013\tnop\t\n\
+            20\t2\t80000004\t0001\tc.nop\t\n\
+            30\t3\t8000000c\t00000013\tnop\t\n";
+        let mut output = Vec::new();
+        dv.annotate_bytes(log.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Coverage of 3 of 6 instructions (50.00%), 2 of 2 functions executed\n"), "{}", output);
+        assert!(output.contains("  80000000       12          2/4  50.00%  first\n"), "{}", output);
+        assert!(output.contains("  8000000c        8          1/2  50.00%  second\n"), "{}", output);
+    }
+
+    #[test]
+    fn annotates_without_a_toolchain(){
+        let path = "/no_toolchain/fw.elf";
+        vfs::insert(path, code_elf(0x80000000, 8));
+        let mut config = Config::for_elfs(Some("/no/toolchain/riscv32-unknown-elf-"), None, vec![path.to_string()], Vec::new()).unwrap();
+        config.data_symbols = true;
+        config.jobs = Some(1);
+        let mut dv = DebuggerVarilator::new(config).unwrap();
+        let log = "Time\tCycle\tPC\tInsn\tDecoded instruction\tRegister and memory contents\n\
+            10\t1\t80000000\t00000013\tnop\t\n\
+            20\t2\t80000004\t00000013\tnop\t\n";
+        let mut output = Vec::new();
+        let summary = dv.annotate_bytes(log.as_bytes(), &mut output).unwrap();
+        assert_eq!((summary.traced, summary.kept), (2, 2));
+        assert_eq!(dv.config.addr2line_path, None);
+        assert!(Config::for_elfs(Some("/no/toolchain/riscv32-unknown-elf-"), Some(String::new()), vec![path.to_string()], Vec::new())
+            .unwrap().addr2line_path.is_some_and(|path| path == "/no/toolchain/riscv32-unknown-elf-addr2line"));
+    }
+
+    #[test]
+    fn filter_log_keeps_the_start_of_the_range_but_not_its_end(){
+        let mut dv = debugger("/filter_log/fw.elf", Vec::new(), Vec::new());
+        dv.set_parser(TraceFormat::Default.parser());
+        let log = "10\t1\t8000008e\t00000013\tnop\n\
+            20\t2\t80000090\t00000013\tnop\n\
+            30\t3\t80000094\t00000013\tnop\n\
+            40\t4\t80000098\t00000013\tnop\n";
+        let mut output = Vec::new();
+        assert_eq!(dv.filter_log(log.as_bytes(), &mut output, 0x80000090, 0x80000098).unwrap(), 2);
+        assert_eq!(String::from_utf8(output).unwrap(), "20\t2\t80000090\t00000013\tnop\n30\t3\t80000094\t00000013\tnop\n");
+    }
+}
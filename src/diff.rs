@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use crate::csr::Csr;
+use crate::trace::TraceRecord;
+
+/**
+ * What differs between the traces at a divergence.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch{
+    /// The instructions are at different addresses.
+    Pc,
+    /// One of the traces ended before the other.
+    Ended,
+    /// The instructions write different values to a register, None for the trace that doesn't write it.
+    Write{
+        register: String,
+        left: Option<u64>,
+        right: Option<u64>,
+    },
+}
+
+/**
+ * The first point where two traces stop executing the same instructions.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence{
+    /// The number of instructions the traces have in common before the divergence.
+    pub matching: u64,
+    /// The last common instructions, from the first trace.
+    pub before: Vec<TraceRecord>,
+    /// The first instruction that differs in each trace, None for the trace that ended first.
+    pub left: Option<TraceRecord>,
+    pub right: Option<TraceRecord>,
+    pub mismatch: Mismatch,
+}
+
+/**
+ * The result of the comparison of two traces.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffOutcome{
+    /// The traces execute the same instructions, this many.
+    Identical(u64),
+    Diverged(Box<Divergence>),
+}
+
+/**
+ * What is compared besides the addresses of the instructions.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffRules{
+    /// Compare the values written to the registers, for the instructions whose write-backs both traces show.
+    pub writes: bool,
+    /// The registers whose writes aren't compared, by architectural name, i.e. x10.
+    pub ignore_registers: Vec<String>,
+    /// The CSRs whose accesses aren't compared, the values read from the timers and the counters differ between an
+    /// RTL simulation and an ISS.
+    pub ignore_csrs: Vec<u16>,
+}
+
+impl DiffRules {
+    /**
+     * Compare the register writes of two instructions at the same address.
+     *
+     * @return The first register written differently, None if the writes match or aren't compared.
+     */
+    fn compare_writes(&self, left: &TraceRecord, right: &TraceRecord) -> Option<Mismatch>{
+        if !self.writes || Csr::of(left).is_some_and(|csr| self.ignore_csrs.contains(&csr.number)) {
+            return None;
+        }
+        let writes = |record: &TraceRecord| -> BTreeMap<String, u64> {
+            record.register_writes().into_iter()
+                .filter(|write| write.register != "x0" && !self.ignore_registers.contains(&write.register))
+                .map(|write| (write.register, write.value)).collect()
+        };
+        let (left, right) = (writes(left), writes(right));
+        // A log without the write-backs of an instruction doesn't tell what it wrote.
+        if left.is_empty() || right.is_empty() {
+            return None;
+        }
+        left.keys().chain(right.keys()).find(|register| left.get(*register) != right.get(*register))
+            .map(|register| Mismatch::Write {
+                register: register.clone(),
+                left: left.get(register).copied(),
+                right: right.get(register).copied(),
+            })
+    }
+}
+
+/**
+ * Compare two traces in lockstep and find where they diverge, by the addresses of the instructions and what the rules
+ * add.
+ *
+ * @param left: The records of the first trace, i.e. the RTL simulation.
+ * @param right: The records of the second trace, i.e. the golden trace of the ISS.
+ * @param rules: What is compared besides the addresses.
+ * @param context: The number of common instructions kept before the divergence.
+ * @return The outcome, the first read error of either trace.
+ */
+pub fn diff_traces(left: impl Iterator<Item = io::Result<TraceRecord>>, right: impl Iterator<Item = io::Result<TraceRecord>>,
+    rules: &DiffRules, context: usize) -> io::Result<DiffOutcome>{
+    let (mut left, mut right) = (left.fuse(), right.fuse());
+    let mut before = VecDeque::with_capacity(context + 1);
+    let mut matching = 0;
+    loop {
+        let (l, r) = (left.next().transpose()?, right.next().transpose()?);
+        let mismatch = match (&l, &r) {
+            (None, None) => return Ok(DiffOutcome::Identical(matching)),
+            (Some(l), Some(r)) if l.pc == r.pc => rules.compare_writes(l, r),
+            (Some(_), Some(_)) => Some(Mismatch::Pc),
+            _ => Some(Mismatch::Ended),
+        };
+        if let Some(mismatch) = mismatch {
+            return Ok(DiffOutcome::Diverged(Box::new(Divergence { matching, before: before.into(), left: l, right: r, mismatch })));
+        }
+        matching += 1;
+        if context > 0 {
+            if before.len() == context {
+                before.pop_front();
+            }
+            before.extend(l);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::TraceFormat;
+
+    const LEFT: [&str; 4] = [
+        "    10\t     1\t20000000\t10001137\tlui x2,0x10001                \tx2=0x10001000",
+        "    20\t     2\t20000004\t00000297\tauipc x5,0x0                  \tx5=0x20000004",
+        "    30\t     3\t20000008\t01028293\taddi x5,x5,16                 \tx5=0x20000014",
+        "    40\t     4\t2000000c\t30529073\tcsrw mtvec,x5                 \tx0=0x00000000",
+    ];
+
+    fn records<'a>(lines: &'a [&str]) -> impl Iterator<Item = io::Result<TraceRecord>> + 'a{
+        let parser = TraceFormat::Ibex.parser();
+        lines.iter().map(move |line| Ok(parser.parse(line).unwrap()))
+    }
+
+    #[test]
+    fn identical_traces(){
+        let outcome = diff_traces(records(&LEFT), records(&LEFT), &DiffRules { writes: true, ..DiffRules::default() }, 2).unwrap();
+        assert_eq!(outcome, DiffOutcome::Identical(4));
+    }
+
+    #[test]
+    fn diverges_at_the_first_other_address(){
+        let right = [LEFT[0], LEFT[1], LEFT[2], "    40\t     4\t20000010\t00000013\tnop                           \t"];
+        let DiffOutcome::Diverged(divergence) = diff_traces(records(&LEFT), records(&right), &DiffRules::default(), 2).unwrap() else {
+            panic!("expected a divergence");
+        };
+        assert_eq!((divergence.matching, &divergence.mismatch), (3, &Mismatch::Pc));
+        let before: Vec<u64> = divergence.before.iter().map(|record| record.pc).collect();
+        assert_eq!(before, [0x20000004, 0x20000008]);
+        assert_eq!((divergence.left.map(|record| record.pc), divergence.right.map(|record| record.pc)),
+            (Some(0x2000000c), Some(0x20000010)));
+    }
+
+    #[test]
+    fn diverges_where_a_trace_ends(){
+        let DiffOutcome::Diverged(divergence) = diff_traces(records(&LEFT[..2]), records(&LEFT), &DiffRules::default(), 0).unwrap() else {
+            panic!("expected a divergence");
+        };
+        assert_eq!((divergence.matching, &divergence.mismatch), (2, &Mismatch::Ended));
+        assert!(divergence.before.is_empty());
+        assert_eq!((divergence.left, divergence.right.map(|record| record.pc)), (None, Some(0x20000008)));
+    }
+
+    #[test]
+    fn diverges_at_the_first_other_write(){
+        let right = [LEFT[0], "    20\t     2\t20000004\t00000297\tauipc x5,0x0                  \tx5=0x20000008", LEFT[2], LEFT[3]];
+        let writes = DiffRules { writes: true, ..DiffRules::default() };
+        let DiffOutcome::Diverged(divergence) = diff_traces(records(&LEFT), records(&right), &writes, 1).unwrap() else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(divergence.matching, 1);
+        assert_eq!(divergence.mismatch, Mismatch::Write { register: String::from("x5"), left: Some(0x20000004), right: Some(0x20000008) });
+        // The addresses match, the writes are only compared when asked for or to the registers that aren't ignored.
+        assert_eq!(diff_traces(records(&LEFT), records(&right), &DiffRules::default(), 1).unwrap(), DiffOutcome::Identical(4));
+        let ignored = DiffRules { ignore_registers: vec![String::from("x5")], ..writes };
+        assert_eq!(diff_traces(records(&LEFT), records(&right), &ignored, 1).unwrap(), DiffOutcome::Identical(4));
+    }
+}
@@ -0,0 +1,666 @@
+use std::fmt;
+use crate::csr::Csr;
+use crate::isa::{FLOAT_ABI_NAMES, INT_ABI_NAMES};
+use crate::trace::TraceRecord;
+
+/**
+ * The width of the integer registers, the compressed instructions and some of the others decode differently.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Xlen{
+    #[default]
+    Rv32,
+    Rv64,
+}
+
+impl fmt::Display for Xlen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Xlen::Rv32 => write!(f, "RV32"),
+            Xlen::Rv64 => write!(f, "RV64"),
+        }
+    }
+}
+
+/**
+ * Decode the instruction word of a traced instruction, for the logs that only have the word.
+ *
+ * @param record: The traced instruction.
+ * @param xlen: The width of the registers of the hart.
+ * @return The mnemonic and the operands, i.e. addi sp, sp, -16, None if the log has no word or it isn't a RV32IMAFDC
+ * or RV64IMAFDC instruction.
+ */
+pub fn decode_record(record: &TraceRecord, xlen: Xlen) -> Option<String>{
+    let word = u32::from_str_radix(record.opcode.as_deref()?.trim().trim_start_matches("0x"), 16).ok()?;
+    decode(word, record.pc, xlen)
+}
+
+/**
+ * Decode a RISC-V instruction of the I, M, A, F, D, C, Zicsr and Zifencei extensions.
+ *
+ * The registers have their ABI names, the CSRs their names, and the targets of the branches and the jumps are
+ * absolute, as objdump shows them. The instructions that have an alias show as the alias, i.e. beqz or csrr, and the
+ * compressed instructions as the instruction they expand to, so c.jr ra is ret.
+ *
+ * @param word: The instruction, the 16 bit compressed instructions in the low half.
+ * @param pc: The address of the instruction, for the targets of the branches and the jumps.
+ * @param xlen: The width of the registers of the hart.
+ * @return The mnemonic and the operands, None if the word isn't a known instruction.
+ */
+pub fn decode(word: u32, pc: u64, xlen: Xlen) -> Option<String>{
+    if word & 0b11 != 0b11 {
+        return decode(expand(word as u16, xlen)?, pc, xlen);
+    }
+    let rd = ((word >> 7) & 0x1f) as usize;
+    let funct3 = (word >> 12) & 0b111;
+    let rs1 = ((word >> 15) & 0x1f) as usize;
+    let rs2 = ((word >> 20) & 0x1f) as usize;
+    let funct7 = word >> 25;
+    let imm_i = (word as i32 >> 20) as i64;
+    let imm_s = (((word as i32 >> 25) << 5) as i64) | ((word >> 7) & 0x1f) as i64;
+    let rv64 = xlen == Xlen::Rv64;
+    let (x, f) = (|reg: usize| INT_ABI_NAMES[reg], |reg: usize| FLOAT_ABI_NAMES[reg]);
+    let text = match word & 0x7f {
+        0x37 => format!("lui {}, 0x{:x}", x(rd), word >> 12),
+        0x17 => format!("auipc {}, 0x{:x}", x(rd), word >> 12),
+        0x6f => {
+            let imm = sign_extend(((word >> 11) & 0x10_0000) | (word & 0xf_f000) | ((word >> 9) & 0x800)
+                | ((word >> 20) & 0x7fe), 21);
+            let target = target(pc, imm, xlen);
+            match rd {
+                0 => format!("j {}", target),
+                1 => format!("jal {}", target),
+                _ => format!("jal {}, {}", x(rd), target),
+            }
+        },
+        0x67 if funct3 == 0 => match (rd, rs1, imm_i) {
+            (0, 1, 0) => String::from("ret"),
+            (0, _, 0) => format!("jr {}", x(rs1)),
+            (1, _, 0) => format!("jalr {}", x(rs1)),
+            (_, _, 0) => format!("jalr {}, {}", x(rd), x(rs1)),
+            (0, _, _) => format!("jr {}({})", imm_i, x(rs1)),
+            (1, _, _) => format!("jalr {}({})", imm_i, x(rs1)),
+            _ => format!("jalr {}, {}({})", x(rd), imm_i, x(rs1)),
+        },
+        0x63 => {
+            let mnemonic = ["beq", "bne", "", "", "blt", "bge", "bltu", "bgeu"][funct3 as usize];
+            let imm = sign_extend(((word >> 19) & 0x1000) | ((word << 4) & 0x800) | ((word >> 20) & 0x7e0)
+                | ((word >> 7) & 0x1e), 13);
+            if mnemonic.is_empty() {
+                return None;
+            }
+            let target = target(pc, imm, xlen);
+            // The comparisons with zero, blt zero, a0 is bgtz a0, objdump's order decides when both are zero.
+            match (mnemonic, rs1, rs2) {
+                ("bge", 0, _) => format!("blez {}, {}", x(rs2), target),
+                ("beq" | "bne" | "blt" | "bge", _, 0) => format!("{}z {}, {}", mnemonic, x(rs1), target),
+                ("blt", 0, _) => format!("bgtz {}, {}", x(rs2), target),
+                _ => format!("{} {}, {}, {}", mnemonic, x(rs1), x(rs2), target),
+            }
+        },
+        0x03 => {
+            let mnemonic = match (funct3, rv64) {
+                (0, _) => "lb",
+                (1, _) => "lh",
+                (2, _) => "lw",
+                (3, true) => "ld",
+                (4, _) => "lbu",
+                (5, _) => "lhu",
+                (6, true) => "lwu",
+                _ => return None,
+            };
+            format!("{} {}, {}({})", mnemonic, x(rd), imm_i, x(rs1))
+        },
+        0x23 => {
+            let mnemonic = match (funct3, rv64) {
+                (0, _) => "sb",
+                (1, _) => "sh",
+                (2, _) => "sw",
+                (3, true) => "sd",
+                _ => return None,
+            };
+            format!("{} {}, {}({})", mnemonic, x(rs2), imm_s, x(rs1))
+        },
+        0x13 => {
+            // The shift amounts have 6 bits on RV64 and 5 on RV32.
+            let (shamt, shift_funct) = match rv64 {
+                true => ((word >> 20) & 0x3f, word >> 26),
+                false => ((word >> 20) & 0x1f, word >> 25),
+            };
+            match funct3 {
+                0 if rd == 0 && rs1 == 0 && imm_i == 0 => String::from("nop"),
+                0 if rs1 == 0 => format!("li {}, {}", x(rd), imm_i),
+                0 if imm_i == 0 => format!("mv {}, {}", x(rd), x(rs1)),
+                0 => format!("addi {}, {}, {}", x(rd), x(rs1), imm_i),
+                2 => format!("slti {}, {}, {}", x(rd), x(rs1), imm_i),
+                3 if imm_i == 1 => format!("seqz {}, {}", x(rd), x(rs1)),
+                3 => format!("sltiu {}, {}, {}", x(rd), x(rs1), imm_i),
+                4 if imm_i == -1 => format!("not {}, {}", x(rd), x(rs1)),
+                4 => format!("xori {}, {}, {}", x(rd), x(rs1), imm_i),
+                6 => format!("ori {}, {}, {}", x(rd), x(rs1), imm_i),
+                7 => format!("andi {}, {}, {}", x(rd), x(rs1), imm_i),
+                1 if shift_funct == 0 => format!("slli {}, {}, {}", x(rd), x(rs1), shamt),
+                5 if shift_funct == 0 => format!("srli {}, {}, {}", x(rd), x(rs1), shamt),
+                5 if shift_funct == 0x20 >> (rv64 as u32) => format!("srai {}, {}, {}", x(rd), x(rs1), shamt),
+                _ => return None,
+            }
+        },
+        0x1b if rv64 => match (funct3, funct7) {
+            (0, _) if imm_i == 0 => format!("sext.w {}, {}", x(rd), x(rs1)),
+            (0, _) => format!("addiw {}, {}, {}", x(rd), x(rs1), imm_i),
+            (1, 0) => format!("slliw {}, {}, {}", x(rd), x(rs1), rs2),
+            (5, 0) => format!("srliw {}, {}, {}", x(rd), x(rs1), rs2),
+            (5, 0x20) => format!("sraiw {}, {}, {}", x(rd), x(rs1), rs2),
+            _ => return None,
+        },
+        0x33 => {
+            let mnemonic = match (funct7, funct3) {
+                (0, 0) => "add",
+                (0x20, 0) => "sub",
+                (0, 1) => "sll",
+                (0, 2) => "slt",
+                (0, 3) => "sltu",
+                (0, 4) => "xor",
+                (0, 5) => "srl",
+                (0x20, 5) => "sra",
+                (0, 6) => "or",
+                (0, 7) => "and",
+                (1, _) => ["mul", "mulh", "mulhsu", "mulhu", "div", "divu", "rem", "remu"][funct3 as usize],
+                _ => return None,
+            };
+            match (mnemonic, rs1, rs2) {
+                ("sub", 0, _) => format!("neg {}, {}", x(rd), x(rs2)),
+                ("sltu", 0, _) => format!("snez {}, {}", x(rd), x(rs2)),
+                ("slt", _, 0) => format!("sltz {}, {}", x(rd), x(rs1)),
+                ("slt", 0, _) => format!("sgtz {}, {}", x(rd), x(rs2)),
+                _ => format!("{} {}, {}, {}", mnemonic, x(rd), x(rs1), x(rs2)),
+            }
+        },
+        0x3b if rv64 => {
+            let mnemonic = match (funct7, funct3) {
+                (0, 0) => "addw",
+                (0x20, 0) => "subw",
+                (0, 1) => "sllw",
+                (0, 5) => "srlw",
+                (0x20, 5) => "sraw",
+                (1, 0) => "mulw",
+                (1, 4) => "divw",
+                (1, 5) => "divuw",
+                (1, 6) => "remw",
+                (1, 7) => "remuw",
+                _ => return None,
+            };
+            match (mnemonic, rs1) {
+                ("subw", 0) => format!("negw {}, {}", x(rd), x(rs2)),
+                _ => format!("{} {}, {}, {}", mnemonic, x(rd), x(rs1), x(rs2)),
+            }
+        },
+        // The fields of the fences that aren't used must be 0, the fm of fence is 0 or 8 for fence.tso.
+        0x0f => match (funct3, word >> 28) {
+            (0, 8) if rd == 0 && rs1 == 0 && (word >> 20) & 0xff == 0x33 => String::from("fence.tso"),
+            (0, 0) if rd == 0 && rs1 == 0 && (word >> 20) & 0xff == 0xff => String::from("fence"),
+            (0, 0) if rd == 0 && rs1 == 0 => format!("fence {}, {}", fence_set(word >> 24), fence_set(word >> 20)),
+            (1, 0) if word >> 7 == 0x20 => String::from("fence.i"),
+            _ => return None,
+        },
+        0x73 => match (word, funct3) {
+            (0x0000_0073, _) => String::from("ecall"),
+            (0x0010_0073, _) => String::from("ebreak"),
+            (0x0020_0073, _) => String::from("uret"),
+            (0x1020_0073, _) => String::from("sret"),
+            (0x3020_0073, _) => String::from("mret"),
+            (0x7b20_0073, _) => String::from("dret"),
+            (0x1050_0073, _) => String::from("wfi"),
+            (_, 0) if funct7 == 0x09 && rd == 0 => format!("sfence.vma {}, {}", x(rs1), x(rs2)),
+            // unimp is a write of the read only cycle.
+            (0xc000_1073, _) => String::from("unimp"),
+            (_, 1..=3) | (_, 5..=7) => decode_csr(word, xlen),
+            _ => return None,
+        },
+        0x2f => {
+            let width = match (funct3, rv64) {
+                (2, _) => "w",
+                (3, true) => "d",
+                _ => return None,
+            };
+            let order = ["", ".rl", ".aq", ".aqrl"][((word >> 25) & 0b11) as usize];
+            let operation = match word >> 27 {
+                0x02 if rs2 == 0 => return Some(format!("lr.{}{} {}, ({})", width, order, x(rd), x(rs1))),
+                0x03 => "sc",
+                0x01 => "amoswap",
+                0x00 => "amoadd",
+                0x04 => "amoxor",
+                0x0c => "amoand",
+                0x08 => "amoor",
+                0x10 => "amomin",
+                0x14 => "amomax",
+                0x18 => "amominu",
+                0x1c => "amomaxu",
+                _ => return None,
+            };
+            format!("{}.{}{} {}, {}, ({})", operation, width, order, x(rd), x(rs2), x(rs1))
+        },
+        0x07 => match funct3 {
+            2 => format!("flw {}, {}({})", f(rd), imm_i, x(rs1)),
+            3 => format!("fld {}, {}({})", f(rd), imm_i, x(rs1)),
+            _ => return None,
+        },
+        0x27 => match funct3 {
+            2 => format!("fsw {}, {}({})", f(rs2), imm_s, x(rs1)),
+            3 => format!("fsd {}, {}({})", f(rs2), imm_s, x(rs1)),
+            _ => return None,
+        },
+        opcode @ (0x43 | 0x47 | 0x4b | 0x4f) => {
+            let precision = precision(funct7 & 0b11)?;
+            if !valid_rounding(funct3) {
+                return None;
+            }
+            let mnemonic = match opcode {
+                0x43 => "fmadd",
+                0x47 => "fmsub",
+                0x4b => "fnmsub",
+                _ => "fnmadd",
+            };
+            format!("{}.{} {}, {}, {}, {}{}", mnemonic, precision, f(rd), f(rs1), f(rs2), f((word >> 27) as usize),
+                rounding(funct3))
+        },
+        0x53 => decode_float(funct7, funct3, rd, rs1, rs2, rv64)?,
+        _ => return None,
+    };
+    Some(text)
+}
+
+/**
+ * Decode the CSR instructions, with the aliases of the reads and writes that ignore the other side, i.e. csrr, csrw,
+ * rdcycle or frflags.
+ */
+fn decode_csr(word: u32, xlen: Xlen) -> String{
+    let x = |reg: u32| INT_ABI_NAMES[reg as usize];
+    let (rd, funct3, rs1, number) = ((word >> 7) & 0x1f, (word >> 12) & 0b111, (word >> 15) & 0x1f, (word >> 20) as u16);
+    let csr = Csr::lookup(number).map_or_else(|| format!("0x{:03x}", number), |csr| csr.name);
+    // The counters and the float CSRs have their own aliases, the high halves of the counters only on RV32.
+    let counter = match number {
+        0xc00..=0xc02 => Some(["rdcycle", "rdtime", "rdinstret"][number as usize - 0xc00]),
+        0xc80..=0xc82 if xlen == Xlen::Rv32 => Some(["rdcycleh", "rdtimeh", "rdinstreth"][number as usize - 0xc80]),
+        _ => None,
+    };
+    let float = match number {
+        0x001 => Some(("frflags", "fsflags")),
+        0x002 => Some(("frrm", "fsrm")),
+        0x003 => Some(("frcsr", "fscsr")),
+        _ => None,
+    };
+    match (funct3, rd, rs1, counter, float) {
+        (2, _, 0, Some(counter), _) => format!("{} {}", counter, x(rd)),
+        (2, _, 0, _, Some((read, _))) => format!("{} {}", read, x(rd)),
+        (1, 0, _, _, Some((_, write))) => format!("{} {}", write, x(rs1)),
+        (1, _, _, _, Some((_, write))) => format!("{} {}, {}", write, x(rd), x(rs1)),
+        (5, 0, _, _, Some((_, write))) if number != 0x003 => format!("{}i {}", write, rs1),
+        (5, _, _, _, Some((_, write))) if number != 0x003 => format!("{}i {}, {}", write, x(rd), rs1),
+        (2, _, 0, _, _) => format!("csrr {}, {}", x(rd), csr),
+        (1..=3, 0, _, _, _) => format!("{} {}, {}", ["", "csrw", "csrs", "csrc"][funct3 as usize], csr, x(rs1)),
+        (5..=7, 0, _, _, _) => format!("{} {}, {}", ["csrwi", "csrsi", "csrci"][funct3 as usize - 5], csr, rs1),
+        (1..=3, _, _, _, _) => format!("{} {}, {}, {}", ["", "csrrw", "csrrs", "csrrc"][funct3 as usize], x(rd), csr, x(rs1)),
+        _ => format!("{} {}, {}, {}", ["csrrwi", "csrrsi", "csrrci"][funct3 as usize - 5], x(rd), csr, rs1),
+    }
+}
+
+/**
+ * Get the ordering of a fence, the accesses of its predecessor or successor set in the iorw order.
+ */
+fn fence_set(bits: u32) -> String{
+    let set: String = "iorw".chars().enumerate().filter(|(bit, _)| bits & (0b1000 >> bit) != 0).map(|(_, access)| access)
+        .collect();
+    if set.is_empty() { String::from("unknown") } else { set }
+}
+
+/**
+ * Decode the floating point instructions of the OP-FP opcode.
+ */
+fn decode_float(funct7: u32, funct3: u32, rd: usize, rs1: usize, rs2: usize, rv64: bool) -> Option<String>{
+    let (x, f) = (|reg: usize| INT_ABI_NAMES[reg], |reg: usize| FLOAT_ABI_NAMES[reg]);
+    let precision = precision(funct7 & 0b11)?;
+    let integer = |rs2: usize| match (rs2, rv64) {
+        (0, _) => Some("w"),
+        (1, _) => Some("wu"),
+        (2, true) => Some("l"),
+        (3, true) => Some("lu"),
+        _ => None,
+    };
+    // The operations that round take the rounding mode in funct3.
+    if matches!(funct7 >> 2, 0x00..=0x03 | 0x0b | 0x08 | 0x18 | 0x1a) && !valid_rounding(funct3) {
+        return None;
+    }
+    let text = match (funct7 >> 2, funct3) {
+        (0x00, rm) => format!("fadd.{} {}, {}, {}{}", precision, f(rd), f(rs1), f(rs2), rounding(rm)),
+        (0x01, rm) => format!("fsub.{} {}, {}, {}{}", precision, f(rd), f(rs1), f(rs2), rounding(rm)),
+        (0x02, rm) => format!("fmul.{} {}, {}, {}{}", precision, f(rd), f(rs1), f(rs2), rounding(rm)),
+        (0x03, rm) => format!("fdiv.{} {}, {}, {}{}", precision, f(rd), f(rs1), f(rs2), rounding(rm)),
+        (0x0b, rm) if rs2 == 0 => format!("fsqrt.{} {}, {}{}", precision, f(rd), f(rs1), rounding(rm)),
+        (0x04, 0) if rs1 == rs2 => format!("fmv.{} {}, {}", precision, f(rd), f(rs1)),
+        (0x04, 1) if rs1 == rs2 => format!("fneg.{} {}, {}", precision, f(rd), f(rs1)),
+        (0x04, 2) if rs1 == rs2 => format!("fabs.{} {}, {}", precision, f(rd), f(rs1)),
+        (0x04, 0..=2) => format!("{}.{} {}, {}, {}", ["fsgnj", "fsgnjn", "fsgnjx"][funct3 as usize], precision, f(rd), f(rs1), f(rs2)),
+        (0x05, 0..=1) => format!("{}.{} {}, {}, {}", ["fmin", "fmax"][funct3 as usize], precision, f(rd), f(rs1), f(rs2)),
+        (0x08, rm) if precision == "s" && rs2 == 1 => format!("fcvt.s.d {}, {}{}", f(rd), f(rs1), rounding(rm)),
+        // The conversions to double of a float or a word are exact, they don't show their rounding mode.
+        (0x08, _) if precision == "d" && rs2 == 0 => format!("fcvt.d.s {}, {}", f(rd), f(rs1)),
+        (0x14, 0..=2) => format!("{}.{} {}, {}, {}", ["fle", "flt", "feq"][funct3 as usize], precision, x(rd), f(rs1), f(rs2)),
+        (0x18, rm) => format!("fcvt.{}.{} {}, {}{}", integer(rs2)?, precision, x(rd), f(rs1), rounding(rm)),
+        (0x1a, _) if precision == "d" && rs2 < 2 => format!("fcvt.d.{} {}, {}", integer(rs2)?, f(rd), x(rs1)),
+        (0x1a, rm) => format!("fcvt.{}.{} {}, {}{}", precision, integer(rs2)?, f(rd), x(rs1), rounding(rm)),
+        (0x1c, 0) if rs2 == 0 && (precision == "s" || rv64) => {
+            format!("fmv.x.{} {}, {}", if precision == "s" { "w" } else { "d" }, x(rd), f(rs1))
+        },
+        (0x1c, 1) if rs2 == 0 => format!("fclass.{} {}, {}", precision, x(rd), f(rs1)),
+        (0x1e, 0) if rs2 == 0 && (precision == "s" || rv64) => {
+            format!("fmv.{}.x {}, {}", if precision == "s" { "w" } else { "d" }, f(rd), x(rs1))
+        },
+        _ => return None,
+    };
+    Some(text)
+}
+
+/**
+ * Expand a compressed instruction to the base instruction it is a short form of.
+ *
+ * @return The 32 bit instruction, None if the half word isn't a known compressed instruction or it's reserved.
+ */
+fn expand(word: u16, xlen: Xlen) -> Option<u32>{
+    let word = word as u32;
+    let rv64 = xlen == Xlen::Rv64;
+    // The 3 bit registers of the CIW, CL, CS, CA and CB formats are x8 to x15.
+    let (rd_short, rs2_short) = (((word >> 2) & 0b111) + 8, ((word >> 2) & 0b111) + 8);
+    let rs1_short = ((word >> 7) & 0b111) + 8;
+    let (rd, rs2) = ((word >> 7) & 0x1f, (word >> 2) & 0x1f);
+    let imm6 = sign_extend(((word >> 7) & 0x20) | ((word >> 2) & 0x1f), 6);
+    let shamt = (((word >> 7) & 0x20) | ((word >> 2) & 0x1f)) as i64;
+    // The offsets of the word and of the double word loads and stores.
+    let offset_w = (((word >> 7) & 0x38) | ((word >> 4) & 0x4) | ((word << 1) & 0x40)) as i64;
+    let offset_d = (((word >> 7) & 0x38) | ((word << 1) & 0xc0)) as i64;
+    let expanded = match (word & 0b11, word >> 13) {
+        // c.unimp is the unimp of the base ISA, csrrw zero, cycle, zero.
+        (0b00, 0b000) if word == 0 => 0xc000_1073,
+        (0b00, 0b000) => {
+            let imm = ((word >> 7) & 0x30) | ((word >> 1) & 0x3c0) | ((word >> 4) & 0x4) | ((word >> 2) & 0x8);
+            if imm == 0 {
+                return None;
+            }
+            i_type(0x13, rd_short, 0, 2, imm as i64)
+        },
+        (0b00, 0b001) => i_type(0x07, rd_short, 3, rs1_short, offset_d),
+        (0b00, 0b010) => i_type(0x03, rd_short, 2, rs1_short, offset_w),
+        (0b00, 0b011) if rv64 => i_type(0x03, rd_short, 3, rs1_short, offset_d),
+        (0b00, 0b011) => i_type(0x07, rd_short, 2, rs1_short, offset_w),
+        (0b00, 0b101) => s_type(0x27, 3, rs1_short, rs2_short, offset_d),
+        (0b00, 0b110) => s_type(0x23, 2, rs1_short, rs2_short, offset_w),
+        (0b00, 0b111) if rv64 => s_type(0x23, 3, rs1_short, rs2_short, offset_d),
+        (0b00, 0b111) => s_type(0x27, 2, rs1_short, rs2_short, offset_w),
+        (0b01, 0b000) => i_type(0x13, rd, 0, rd, imm6),
+        (0b01, 0b001) if rv64 && rd == 0 => return None,
+        (0b01, 0b001) if rv64 => i_type(0x1b, rd, 0, rd, imm6),
+        (0b01, 0b001) => j_type(1, jump_offset(word)),
+        (0b01, 0b010) => i_type(0x13, rd, 0, 0, imm6),
+        (0b01, 0b011) if rd == 2 => {
+            let imm = sign_extend(((word >> 3) & 0x200) | ((word >> 2) & 0x10) | ((word << 1) & 0x40)
+                | ((word << 4) & 0x180) | ((word << 3) & 0x20), 10);
+            if imm == 0 {
+                return None;
+            }
+            i_type(0x13, 2, 0, 2, imm)
+        },
+        (0b01, 0b011) if imm6 == 0 => return None,
+        (0b01, 0b011) => ((imm6 as u32 & 0xf_ffff) << 12) | (rd << 7) | 0x37,
+        (0b01, 0b100) => match ((word >> 10) & 0b11, (word >> 12) & 1, (word >> 5) & 0b11) {
+            (0b00, _, _) => i_type(0x13, rs1_short, 5, rs1_short, shamt),
+            (0b01, _, _) => i_type(0x13, rs1_short, 5, rs1_short, 0x400 | shamt),
+            (0b10, _, _) => i_type(0x13, rs1_short, 7, rs1_short, imm6),
+            (0b11, 0, operation) => {
+                let (funct7, funct3) = [(0x20, 0), (0, 4), (0, 6), (0, 7)][operation as usize];
+                r_type(0x33, funct7, rs1_short, funct3, rs1_short, rs2_short)
+            },
+            (0b11, 1, 0) if rv64 => r_type(0x3b, 0x20, rs1_short, 0, rs1_short, rs2_short),
+            (0b11, 1, 1) if rv64 => r_type(0x3b, 0, rs1_short, 0, rs1_short, rs2_short),
+            _ => return None,
+        },
+        (0b01, 0b101) => j_type(0, jump_offset(word)),
+        (0b01, funct3 @ (0b110 | 0b111)) => {
+            let imm = sign_extend(((word >> 4) & 0x100) | ((word >> 7) & 0x18) | ((word << 1) & 0xc0)
+                | ((word >> 2) & 0x6) | ((word << 3) & 0x20), 9);
+            b_type(funct3 & 1, rs1_short, 0, imm)
+        },
+        (0b10, 0b000) => i_type(0x13, rd, 1, rd, shamt),
+        (0b10, 0b001) => {
+            let offset = ((word >> 7) & 0x20) | ((word >> 2) & 0x18) | ((word << 4) & 0x1c0);
+            i_type(0x07, rd, 3, 2, offset as i64)
+        },
+        (0b10, 0b010) | (0b10, 0b011) => {
+            let offset_w = (((word >> 7) & 0x20) | ((word >> 2) & 0x1c) | ((word << 4) & 0xc0)) as i64;
+            let offset_d = (((word >> 7) & 0x20) | ((word >> 2) & 0x18) | ((word << 4) & 0x1c0)) as i64;
+            match (word >> 13, rv64) {
+                (0b010, _) if rd == 0 => return None,
+                (0b010, _) => i_type(0x03, rd, 2, 2, offset_w),
+                (_, true) if rd == 0 => return None,
+                (_, true) => i_type(0x03, rd, 3, 2, offset_d),
+                (_, false) => i_type(0x07, rd, 2, 2, offset_w),
+            }
+        },
+        (0b10, 0b100) => match ((word >> 12) & 1, rd, rs2) {
+            (0, 0, 0) => return None,
+            (0, _, 0) => i_type(0x67, 0, 0, rd, 0),
+            // c.mv is add rd, zero, rs2, but objdump shows it as the mv of addi.
+            (0, _, _) => i_type(0x13, rd, 0, rs2, 0),
+            (1, 0, 0) => 0x0010_0073,
+            (1, _, 0) => i_type(0x67, 1, 0, rd, 0),
+            (_, _, _) => r_type(0x33, 0, rd, 0, rd, rs2),
+        },
+        (0b10, 0b101) => s_type(0x27, 3, 2, rs2, (((word >> 7) & 0x38) | ((word >> 1) & 0x1c0)) as i64),
+        (0b10, 0b110) => s_type(0x23, 2, 2, rs2, (((word >> 7) & 0x3c) | ((word >> 1) & 0xc0)) as i64),
+        (0b10, 0b111) if rv64 => s_type(0x23, 3, 2, rs2, (((word >> 7) & 0x38) | ((word >> 1) & 0x1c0)) as i64),
+        (0b10, 0b111) => s_type(0x27, 2, 2, rs2, (((word >> 7) & 0x3c) | ((word >> 1) & 0xc0)) as i64),
+        _ => return None,
+    };
+    Some(expanded)
+}
+
+fn i_type(opcode: u32, rd: u32, funct3: u32, rs1: u32, imm: i64) -> u32{
+    ((imm as u32 & 0xfff) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn s_type(opcode: u32, funct3: u32, rs1: u32, rs2: u32, imm: i64) -> u32{
+    let imm = imm as u32;
+    (((imm >> 5) & 0x7f) << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | ((imm & 0x1f) << 7) | opcode
+}
+
+fn r_type(opcode: u32, funct7: u32, rd: u32, funct3: u32, rs1: u32, rs2: u32) -> u32{
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn b_type(funct3: u32, rs1: u32, rs2: u32, imm: i64) -> u32{
+    let imm = imm as u32;
+    (((imm >> 12) & 1) << 31) | (((imm >> 5) & 0x3f) << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12)
+        | (((imm >> 1) & 0xf) << 8) | (((imm >> 11) & 1) << 7) | 0x63
+}
+
+fn j_type(rd: u32, imm: i64) -> u32{
+    let imm = imm as u32;
+    (((imm >> 20) & 1) << 31) | (((imm >> 1) & 0x3ff) << 21) | (((imm >> 11) & 1) << 20) | (((imm >> 12) & 0xff) << 12)
+        | (rd << 7) | 0x6f
+}
+
+/**
+ * Get the offset of c.j and c.jal.
+ */
+fn jump_offset(word: u32) -> i64{
+    sign_extend(((word >> 1) & 0x800) | ((word >> 7) & 0x10) | ((word >> 1) & 0x300) | ((word << 2) & 0x400)
+        | ((word >> 1) & 0x40) | ((word << 1) & 0x80) | ((word >> 2) & 0xe) | ((word << 3) & 0x20), 12)
+}
+
+/**
+ * Check the rounding mode of a floating point instruction, 5 and 6 are reserved.
+ */
+fn valid_rounding(rm: u32) -> bool{
+    !matches!(rm, 5 | 6)
+}
+
+/**
+ * Get the rounding mode operand of a floating point instruction, the dynamic mode of the frm CSR isn't shown.
+ */
+fn rounding(rm: u32) -> &'static str{
+    [", rne", ", rtz", ", rdn", ", rup", ", rmm", "", "", ""][rm as usize & 0b111]
+}
+
+/**
+ * Get the suffix of the precision of a floating point instruction from its fmt field.
+ */
+fn precision(fmt: u32) -> Option<&'static str>{
+    match fmt {
+        0 => Some("s"),
+        1 => Some("d"),
+        _ => None,
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> i64{
+    ((value << (32 - bits)) as i32 >> (32 - bits)) as i64
+}
+
+/**
+ * Get the target of a branch or a jump, as objdump shows it, it wraps around the 32 bit address space on RV32.
+ */
+fn target(pc: u64, offset: i64, xlen: Xlen) -> String{
+    let target = pc.wrapping_add(offset as u64);
+    match xlen {
+        Xlen::Rv32 => format!("0x{:x}", target as u32),
+        Xlen::Rv64 => format!("0x{:x}", target),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * Check the decoded instructions against llvm-objdump --no-print-imm-hex, at 0x80000000.
+     */
+    fn assert_decodes(xlen: Xlen, table: &[(u32, &str)]){
+        for (word, expected) in table {
+            assert_eq!(decode(*word, 0x8000_0000, xlen).as_deref(), Some(*expected), "0x{:08x}", word);
+        }
+    }
+
+    #[test]
+    fn compressed_instructions_are_expanded(){
+        assert_decodes(Xlen::Rv32, &[
+            (0x0085, "addi ra, ra, 1"),
+            (0x8d6d, "and a0, a0, a1"),
+            (0x6105, "addi sp, sp, 32"),
+            (0x0808, "addi a0, sp, 16"),
+            (0x8082, "ret"),
+            (0x8502, "jr a0"),
+            (0x9502, "jalr a0"),
+            (0x852e, "mv a0, a1"),
+            (0x952e, "add a0, a0, a1"),
+            (0x557d, "li a0, -1"),
+            (0x6541, "lui a0, 0x10"),
+            (0x0001, "nop"),
+            (0x41c8, "lw a0, 4(a1)"),
+            (0xc590, "sw a2, 8(a1)"),
+            (0x40b2, "lw ra, 12(sp)"),
+            (0xc606, "sw ra, 12(sp)"),
+            (0x850d, "srai a0, a0, 3"),
+            (0x9961, "andi a0, a0, -8"),
+            (0x8d0d, "sub a0, a0, a1"),
+            (0x050a, "slli a0, a0, 2"),
+            (0xc501, "beqz a0, 0x80000008"),
+            (0xfded, "bnez a1, 0x7ffffffa"),
+            (0xbff5, "j 0x7ffffffc"),
+            (0x2201, "jal 0x80000100"),
+            (0x9002, "ebreak"),
+            (0x0000, "unimp"),
+            (0x61c8, "flw fa0, 4(a1)"),
+            (0xa422, "fsd fs0, 8(sp)"),
+        ]);
+    }
+
+    #[test]
+    fn aliases_are_used(){
+        assert_decodes(Xlen::Rv32, &[
+            (0x0002_8863, "beqz t0, 0x80000010"),
+            (0x00a0_1863, "bne zero, a0, 0x80000010"),
+            (0x00a0_4863, "bgtz a0, 0x80000010"),
+            (0x0005_5863, "bgez a0, 0x80000010"),
+            (0x00a0_5863, "blez a0, 0x80000010"),
+            (0x0005_4863, "bltz a0, 0x80000010"),
+            (0xfeb5_68e3, "bltu a0, a1, 0x7ffffff0"),
+            (0x0050_0513, "li a0, 5"),
+            (0x0100_006f, "j 0x80000010"),
+            (0x0100_00ef, "jal 0x80000010"),
+            (0x0000_8067, "ret"),
+            (0x0014_b493, "seqz s1, s1"),
+            (0x4050_02b3, "neg t0, t0"),
+            (0x00b0_3533, "snez a0, a1"),
+            (0x0005_a533, "sltz a0, a1"),
+            (0x00b0_2533, "sgtz a0, a1"),
+            (0xfff5_c513, "not a0, a1"),
+            (0x0100_056f, "jal a0, 0x80000010"),
+            (0x0005_8567, "jalr a0, a1"),
+            (0x0040_8067, "jr 4(ra)"),
+            (0x0085_80e7, "jalr 8(a1)"),
+            (0x3000_2573, "csrr a0, mstatus"),
+            (0x3052_9073, "csrw mtvec, t0"),
+            (0x3045_2073, "csrs mie, a0"),
+            (0x3045_3073, "csrc mie, a0"),
+            (0x3050_d073, "csrwi mtvec, 1"),
+            (0x3004_7073, "csrci mstatus, 8"),
+            (0x3405_1573, "csrrw a0, mscratch, a0"),
+            (0xc000_2573, "rdcycle a0"),
+            (0xc800_2573, "rdcycleh a0"),
+            (0x0010_2573, "frflags a0"),
+            (0x0035_1073, "fscsr a0"),
+            (0xc830_2573, "csrr a0, hpmcounter3h"),
+            (0x0020_d073, "fsrmi 1"),
+            (0xc000_1073, "unimp"),
+            (0x0ff0_000f, "fence"),
+            (0x0330_000f, "fence rw, rw"),
+            (0x8330_000f, "fence.tso"),
+            (0x0000_100f, "fence.i"),
+            (0xc005_1553, "fcvt.w.s a0, fa0, rtz"),
+            (0xc005_7553, "fcvt.w.s a0, fa0"),
+            (0x00c5_8553, "fadd.s fa0, fa1, fa2, rne"),
+            (0x6ac5_b543, "fmadd.d fa0, fa1, fa2, fa3, rup"),
+            (0xd205_0553, "fcvt.d.w fa0, a0"),
+            (0x22b5_9553, "fneg.d fa0, fa1"),
+            (0x1605_a52f, "lr.w.aqrl a0, (a1)"),
+            (0x0cb6_252f, "amoswap.w.aq a0, a1, (a2)"),
+            (0x8000_0537, "lui a0, 0x80000"),
+        ]);
+    }
+
+    #[test]
+    fn rv64_instructions(){
+        assert_decodes(Xlen::Rv64, &[
+            (0x2501, "sext.w a0, a0"),
+            (0x2505, "addiw a0, a0, 1"),
+            (0x6588, "ld a0, 8(a1)"),
+            (0xe406, "sd ra, 8(sp)"),
+            (0x9d0d, "subw a0, a0, a1"),
+            (0x9d2d, "addw a0, a0, a1"),
+            (0x1502, "slli a0, a0, 32"),
+            (0x8082, "ret"),
+            (0x40b0_053b, "negw a0, a1"),
+            (0x0005_851b, "sext.w a0, a1"),
+            (0xc225_1553, "fcvt.l.d a0, fa0, rtz"),
+            (0x1ab6_352f, "sc.d.rl a0, a1, (a2)"),
+        ]);
+    }
+
+    #[test]
+    fn reserved_encodings_are_unknown(){
+        // c.addi4spn with a 0 immediate, c.lwsp and c.jr of zero, c.lui of 0, and a shift by 32 on RV32.
+        for word in [0x0004, 0x4002, 0x8002, 0x6501, 0x0205_1513] {
+            assert_eq!(decode(word, 0, Xlen::Rv32), None, "0x{:08x}", word);
+        }
+        assert_eq!(decode(0x0205_1513, 0, Xlen::Rv64).as_deref(), Some("slli a0, a0, 32"));
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::process;
+use object::{Architecture, BinaryFormat, Object, ObjectSection, ObjectSegment, ObjectSymbol, SectionKind, SymbolKind, SymbolSection};
+use regex::Regex;
+use crate::disasm::Xlen;
+use crate::error::{Error, Result};
+use crate::isa::Arch;
+use crate::symbolizer;
+use crate::vfs;
+
+/**
+ * A function of the symbol table of an elf with the addresses of its instructions.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElfFunction{
+    /// The demangled name.
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    /// The address of each instruction, decoded from the code of the function.
+    pub instructions: Vec<u64>,
+}
+
+/**
+ * A symbol of the symbol table of an elf.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfSymbol{
+    /// The demangled name.
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+}
+
+/**
+ * Remove the hash the legacy Rust mangling adds at the end of the names, i.e. ::h8cb9cbf66c959ebf.
+ */
+fn without_hash(name: &str) -> &str{
+    match name.rsplit_once("::") {
+        Some((path, hash)) if hash.len() == 17 && hash.starts_with('h') && hash[1..].bytes().all(|digit| digit.is_ascii_hexdigit()) => path,
+        _ => name,
+    }
+}
+
+/**
+ * A LOAD segment of the program headers of an elf.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadSegment{
+    /// The virtual address the segment is loaded at.
+    pub addr: u64,
+    /// The size of the segment in memory.
+    pub size: u64,
+    pub executable: bool,
+}
+
+/**
+ * The symbols of the elfs sorted by address, to find the symbol an address is in.
+ */
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SymbolMap{
+    symbols: Vec<ElfSymbol>,
+}
+
+impl SymbolMap {
+    /**
+     * Constructor.
+     *
+     * @param symbols: The symbols at the addresses they are used at, in any order.
+     */
+    pub fn new(mut symbols: Vec<ElfSymbol>) -> SymbolMap{
+        // The largest of the symbols at the same address is kept.
+        symbols.sort_by(|a, b| a.addr.cmp(&b.addr).then(b.size.cmp(&a.size)).then(a.name.cmp(&b.name)));
+        symbols.dedup_by(|a, b| a.addr == b.addr);
+        SymbolMap { symbols }
+    }
+
+    /**
+     * Read the data symbols of elfs, from their .symtab and .dynsym.
+     *
+     * @param elf_files: The elfs.
+     * @param load_offsets: The runtime address minus the link address of the elfs in order, the elfs without one
+     * are used at their link addresses.
+     * @return The map of the symbols at the addresses they are used at, error if an elf can't be read.
+     */
+    pub fn read_data(elf_files: &[String], load_offsets: &[i64]) -> Result<SymbolMap>{
+        let mut symbols = Vec::new();
+        for (image, elf_file) in elf_files.iter().enumerate() {
+            let offset = load_offsets.get(image).copied().unwrap_or(0) as u64;
+            symbols.extend(read_data_symbols(elf_file)?.into_iter()
+                .map(|symbol| ElfSymbol { addr: symbol.addr.wrapping_add(offset), ..symbol }));
+        }
+        Ok(SymbolMap::new(symbols))
+    }
+
+    /**
+     * Read the function symbols of elfs, from their .symtab and .dynsym.
+     *
+     * @param elf_files: The elfs.
+     * @param load_offsets: The runtime address minus the link address of the elfs in order, the elfs without one
+     * are used at their link addresses.
+     * @param arch: The architecture of the elfs.
+     * @return The map of the functions at the addresses they run from, error if an elf can't be read.
+     */
+    pub fn read_functions(elf_files: &[String], load_offsets: &[i64], arch: Arch) -> Result<SymbolMap>{
+        let mut symbols = Vec::new();
+        for (image, elf_file) in elf_files.iter().enumerate() {
+            let offset = load_offsets.get(image).copied().unwrap_or(0) as u64;
+            symbols.extend(read_function_symbols(elf_file, arch)?.into_iter()
+                .map(|symbol| ElfSymbol { addr: symbol.addr.wrapping_add(offset), ..symbol }));
+        }
+        Ok(SymbolMap::new(symbols))
+    }
+
+    /**
+     * Find the symbol an address is in.
+     *
+     * @param addr: The address.
+     * @return The symbol and the offset of the address in it, None if the address isn't in a symbol. The symbols
+     * without a size only hold their own address.
+     */
+    pub fn lookup(&self, addr: u64) -> Option<(&ElfSymbol, u64)>{
+        let index = self.symbols.partition_point(|symbol| symbol.addr <= addr);
+        let symbol = &self.symbols[index.checked_sub(1)?];
+        let offset = addr - symbol.addr;
+        Some((symbol, offset)).filter(|_| offset < symbol.size.max(1))
+    }
+
+    /**
+     * Find a symbol by its name.
+     *
+     * @param name: The demangled name, with or without its namespaces.
+     * @return The first symbol of the name, None if there is none.
+     */
+    pub fn find(&self, name: &str) -> Option<&ElfSymbol>{
+        self.symbols.iter().find(|symbol| {
+            let full = without_hash(&symbol.name);
+            full == name || full.rsplit("::").next() == Some(name)
+        })
+    }
+
+    /**
+     * Get the name of the symbol an address is in.
+     *
+     * @param addr: The address.
+     * @return The name, followed by the offset in hex when the address isn't the start of the symbol, i.e.
+     * BUFFER+0x10. None if the address isn't in a symbol.
+     */
+    pub fn symbolize(&self, addr: u64) -> Option<String>{
+        self.lookup(addr).map(|(symbol, offset)| match offset {
+            0 => symbol.name.clone(),
+            _ => format!("{}+0x{:x}", symbol.name, offset),
+        })
+    }
+}
+
+/**
+ * The instructions of the elfs disassembled by objdump, at the addresses they run from.
+ */
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Disassembly{
+    instructions: HashMap<u64, String>,
+}
+
+impl Disassembly {
+    /**
+     * Disassemble the code of elfs with objdump.
+     *
+     * @param objdump_path: The path of objdump.
+     * @param elf_files: The elfs.
+     * @param load_offsets: The runtime address minus the link address of the elfs in order, the elfs without one
+     * are used at their link addresses.
+     * @return The instructions of the executable sections, error if objdump fails.
+     */
+    pub fn read(objdump_path: &str, elf_files: &[String], load_offsets: &[i64]) -> Result<Disassembly>{
+        // i.e. "20000008:\taddi\tt0,t0,24" or "20000014:      \tjalr\t0xb6(ra) <main>" for llvm-objdump.
+        let instruction_re = Regex::new(r"^\s*([\da-fA-F]+):\s+(\S+)\s*(.*)$").unwrap();
+        let mut instructions = HashMap::new();
+        for (image, elf_file) in elf_files.iter().enumerate() {
+            let offset = load_offsets.get(image).copied().unwrap_or(0) as u64;
+            let output = run_tool(objdump_path, "objdump", &["-d", "--no-show-raw-insn"], elf_file)?;
+            for line in output.lines() {
+                let Some(cap) = instruction_re.captures(line) else {
+                    log::trace!("Skipped the line {:?} of the disassembly of {}", line, elf_file);
+                    continue;
+                };
+                let Ok(addr) = u64::from_str_radix(&cap[1], 16) else {
+                    continue;
+                };
+                // The comments of the ARM disassembly, i.e. "@ imm = #0x6" or "; (10028 <delay+0x8>)", are dropped.
+                let operands = cap[3].split(['@', ';']).next().unwrap_or("");
+                let operands = operands.split('\t').map(str::trim).filter(|part| !part.is_empty()).collect::<Vec<&str>>().join(" ");
+                let text = if operands.is_empty() { cap[2].to_string() } else { format!("{} {}", &cap[2], operands) };
+                instructions.insert(addr.wrapping_add(offset), text);
+            }
+        }
+        Ok(Disassembly { instructions })
+    }
+
+    /**
+     * Get the disassembly of the instruction at an address, i.e. addi t0, t0, 24.
+     *
+     * @param addr: The runtime address.
+     * @return The mnemonic and the operands, None if no instruction of the elfs is there.
+     */
+    pub fn get(&self, addr: u64) -> Option<&str>{
+        self.instructions.get(&addr).map(String::as_str)
+    }
+}
+
+/**
+ * Read the entry point and the LOAD segments of an elf from its headers.
+ *
+ * @param elf_file: The elf.
+ * @return The entry point and the segments in the order of the program headers, error if the file can't be read or
+ * isn't an elf.
+ */
+pub fn read_load_segments(elf_file: &str) -> Result<(u64, Vec<LoadSegment>)>{
+    let data = vfs::read(elf_file).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+    let file = object::File::parse(&*data).ok().filter(|file| file.format() == BinaryFormat::Elf)
+        .ok_or_else(|| Error::elf(elf_file, "not an elf"))?;
+    let segments = file.segments().map(|segment| LoadSegment {
+        addr: segment.address(),
+        size: segment.size(),
+        executable: segment.permissions().executable(),
+    }).collect();
+    Ok((file.entry(), segments))
+}
+
+/**
+ * Read the width of the registers an elf is built for, from its class.
+ *
+ * @param elf_file: The elf.
+ * @return RV64 for the 64 bit elfs and RV32 for the others, error if the file can't be read or isn't an elf.
+ */
+pub fn read_xlen(elf_file: &str) -> Result<Xlen>{
+    let data = vfs::read(elf_file).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+    let file = object::File::parse(&*data).ok().filter(|file| file.format() == BinaryFormat::Elf)
+        .ok_or_else(|| Error::elf(elf_file, "not an elf"))?;
+    Ok(if file.is_64() { Xlen::Rv64 } else { Xlen::Rv32 })
+}
+
+/**
+ * Read the architecture an elf is built for, from its machine.
+ *
+ * @param elf_file: The elf.
+ * @return The architecture, None for the machines that aren't RISC-V or ARM, error if the file can't be read or
+ * isn't an elf.
+ */
+pub fn read_arch(elf_file: &str) -> Result<Option<Arch>>{
+    let data = vfs::read(elf_file).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+    let file = object::File::parse(&*data).ok().filter(|file| file.format() == BinaryFormat::Elf)
+        .ok_or_else(|| Error::elf(elf_file, "not an elf"))?;
+    Ok(match file.architecture() {
+        Architecture::Riscv32 | Architecture::Riscv64 => Some(Arch::Riscv),
+        Architecture::Arm => Some(Arch::Arm),
+        _ => None,
+    })
+}
+
+/**
+ * Read the identity of the build of an elf, its GNU build-id or else a hash of its .text section, to tell if a log
+ * was simulated with it.
+ *
+ * @param elf_file: The elf.
+ * @return The build-id or the FNV-1a hash of the code in hex, None if the elf has neither, error if the file can't be
+ * read or isn't an elf.
+ */
+pub fn read_build_id(elf_file: &str) -> Result<Option<String>>{
+    let data = vfs::read(elf_file).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+    let file = object::File::parse(&*data).ok().filter(|file| file.format() == BinaryFormat::Elf)
+        .ok_or_else(|| Error::elf(elf_file, "not an elf"))?;
+    if let Ok(Some(build_id)) = file.build_id() {
+        return Ok(Some(build_id.iter().map(|byte| format!("{:02x}", byte)).collect()));
+    }
+    let Some(code) = file.section_by_name(".text").and_then(|section| section.data().ok()) else {
+        return Ok(None);
+    };
+    let hash = code.iter().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+    Ok(Some(format!("{:016x}", hash)))
+}
+
+/**
+ * Read the function symbols of an elf from its symbol table.
+ *
+ * @param elf_file: The elf.
+ * @param arch: The architecture of the elf, the ARM functions start at their symbol without its Thumb bit.
+ * @return The demangled symbols in the order of the symbol table, error if the file can't be read or isn't an elf.
+ */
+pub fn read_function_symbols(elf_file: &str, arch: Arch) -> Result<Vec<ElfSymbol>>{
+    let data = vfs::read(elf_file).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+    let file = object::File::parse(&*data).ok().filter(|file| file.format() == BinaryFormat::Elf)
+        .ok_or_else(|| Error::elf(elf_file, "not an elf"))?;
+    Ok(function_symbols(&file, arch))
+}
+
+/**
+ * Get the function symbols of a parsed elf, from its .symtab and .dynsym.
+ */
+fn function_symbols(file: &object::File, arch: Arch) -> Vec<ElfSymbol>{
+    file.symbols().chain(file.dynamic_symbols())
+        .filter(|symbol| symbol.kind() == SymbolKind::Text)
+        .filter_map(|symbol| Some(ElfSymbol {
+            name: symbolizer::demangle(symbol.name().ok().filter(|name| !name.is_empty())?),
+            addr: arch.code_addr(symbol.address()),
+            size: symbol.size(),
+        }))
+        .collect()
+}
+
+/**
+ * Run a tool of the toolchain on an elf and get its output.
+ *
+ * @param name: The name of the tool in the errors, i.e. objdump.
+ */
+fn run_tool(path: &str, name: &str, args: &[&str], elf_file: &str) -> Result<String>{
+    log::debug!("Running {} {} {}", path, args.join(" "), elf_file);
+    let res = process::Command::new(path).args(args).arg(elf_file).output().map_err(|err| Error::tool(path, err.to_string()))?;
+    if !res.status.success() {
+        let stderr = String::from_utf8_lossy(&res.stderr);
+        return Err(Error::tool(path, format!("{} {} {} failed, {}", name, args.join(" "), elf_file, stderr.trim())));
+    }
+    String::from_utf8(res.stdout).map_err(|_| Error::tool(path, "the output isn't UTF-8"))
+}
+
+/**
+ * Read the data symbols of an elf, its global, static and thread local variables.
+ *
+ * @param elf_file: The elf.
+ * @return The symbols defined in a section, in the order of the .symtab then the .dynsym, error if the file can't
+ * be read or isn't an elf.
+ */
+pub fn read_data_symbols(elf_file: &str) -> Result<Vec<ElfSymbol>>{
+    let data = vfs::read(elf_file).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+    let file = object::File::parse(&*data).ok().filter(|file| file.format() == BinaryFormat::Elf)
+        .ok_or_else(|| Error::elf(elf_file, "not an elf"))?;
+    Ok(file.symbols().chain(file.dynamic_symbols())
+        .filter(|symbol| matches!(symbol.kind(), SymbolKind::Data | SymbolKind::Tls))
+        // The undefined, absolute and common symbols have no address of their own.
+        .filter(|symbol| matches!(symbol.section(), SymbolSection::Section(_)))
+        .filter_map(|symbol| Some(ElfSymbol {
+            name: symbolizer::demangle(symbol.name().ok().filter(|name| !name.is_empty())?),
+            addr: symbol.address(),
+            size: symbol.size(),
+        }))
+        .collect())
+}
+
+/**
+ * Read the functions of an elf from its symbol table and decode the addresses of their instructions.
+ *
+ * @param elf_file: The elf.
+ * @param arch: The architecture of the elf, the ARM functions start at their symbol without its Thumb bit.
+ * @return The functions sorted by address, the same as read_function_symbols, error if the file can't be read or
+ * isn't an elf.
+ */
+pub fn read_functions(elf_file: &str, arch: Arch) -> Result<Vec<ElfFunction>>{
+    let data = vfs::read(elf_file).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+    let file = object::File::parse(&*data).ok().filter(|file| file.format() == BinaryFormat::Elf)
+        .ok_or_else(|| Error::elf(elf_file, "not an elf"))?;
+    // The code of the functions is in the sections that have their addresses and contents, .text and the others.
+    let sections: Vec<(u64, &[u8])> = file.sections().filter(|section| section.kind() == SectionKind::Text)
+        .filter_map(|section| Some((section.address(), section.data().ok()?))).collect();
+    let mut functions = Vec::new();
+    for ElfSymbol { name, addr, size } in function_symbols(&file, arch) {
+        if size == 0 {
+            continue;
+        }
+        let Some((start, bytes)) = sections.iter().find(|(start, bytes)| (*start..*start + bytes.len() as u64).contains(&addr)) else {
+            log::trace!("Skipped the function {} of {}, it isn't in a code section", name, elf_file);
+            continue;
+        };
+        let mut instructions = Vec::new();
+        let mut pc = addr;
+        while pc < addr + size {
+            let offset = (pc - start) as usize;
+            if offset + 1 >= bytes.len() {
+                break;
+            }
+            instructions.push(pc);
+            pc += arch.instruction_size(u16::from_le_bytes([bytes[offset], bytes[offset + 1]]));
+        }
+        functions.push(ElfFunction { name, addr, size, instructions });
+    }
+    functions.sort_by(|a, b| a.addr.cmp(&b.addr).then(a.name.cmp(&b.name)));
+    functions.dedup_by(|a, b| a.addr == b.addr);
+    Ok(functions)
+}
@@ -0,0 +1,87 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/**
+ * The errors of the debugger, from the files it reads to the tools it runs.
+ */
+#[derive(Debug)]
+pub enum Error{
+    /// A file can't be opened or read, the path is - for stdin.
+    Io{
+        path: String,
+        source: io::Error,
+    },
+    /// The output can't be written.
+    Output(io::Error),
+    /// A tool of the toolchain can't be run or its output can't be read, i.e. addr2line.
+    Tool{
+        tool: String,
+        message: String,
+    },
+    /// An elf can't be loaded or isn't usable, i.e. it has no code.
+    Elf{
+        path: String,
+        message: String,
+    },
+    /// The format of the log isn't known.
+    Log(String),
+    /// The options are invalid, i.e. a pc regex without a capture group.
+    Config(String),
+}
+
+/// The result of the functions of the debugger.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /**
+     * Create the error of a file that can't be opened or read.
+     *
+     * @param path: The path of the file.
+     * @param source: The error of the read.
+     */
+    pub fn io(path: &str, source: io::Error) -> Error{
+        Error::Io { path: path.to_string(), source }
+    }
+
+    /**
+     * Create the error of a tool that can't be run.
+     *
+     * @param tool: The name or the path of the tool.
+     * @param message: What went wrong.
+     */
+    pub fn tool(tool: &str, message: impl Into<String>) -> Error{
+        Error::Tool { tool: tool.to_string(), message: message.into() }
+    }
+
+    /**
+     * Create the error of an elf that can't be used.
+     *
+     * @param path: The path of the elf.
+     * @param message: What went wrong.
+     */
+    pub fn elf(path: &str, message: impl Into<String>) -> Error{
+        Error::Elf { path: path.to_string(), message: message.into() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "Failed to read {}: {}", path, source),
+            Error::Output(source) => write!(f, "Failed to write the output: {}", source),
+            Error::Tool { tool, message } => write!(f, "Failed to run {}: {}", tool, message),
+            Error::Elf { path, message } => write!(f, "{}: {}", path, message),
+            Error::Log(message) | Error::Config(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::Output(source) => Some(source),
+            _ => None,
+        }
+    }
+}
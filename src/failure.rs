@@ -0,0 +1,108 @@
+use std::fmt::Display;
+use std::process;
+use serde::Serialize;
+use verilator_debugger::Error;
+
+/**
+ * Why a command fails, each has its own exit code so a CI wrapper can tell a broken setup from a trace that shows a
+ * firmware bug:
+ *
+ * - 1, the logs diverge, the diff found a bug.
+ * - 2, the arguments, the options or the configuration file are invalid.
+ * - 3, an elf is missing or can't be used.
+ * - 4, no toolchain is found or one of its tools fails.
+ * - 5, a log is missing, can't be read or its format is unknown.
+ * - 6, the output can't be written.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure{
+    Divergence = 1,
+    Usage = 2,
+    Elf = 3,
+    Toolchain = 4,
+    Log = 5,
+    Output = 6,
+}
+
+impl Failure {
+    /**
+     * Get the failure of an error of the debugger.
+     */
+    pub fn of(err: &Error) -> Failure{
+        match err {
+            Error::Io { .. } | Error::Log(_) => Failure::Log,
+            Error::Output(_) => Failure::Output,
+            Error::Tool { .. } => Failure::Toolchain,
+            Error::Elf { .. } => Failure::Elf,
+            Error::Config(_) => Failure::Usage,
+        }
+    }
+
+    pub fn exit_code(self) -> i32{
+        self as i32
+    }
+
+    /**
+     * Get the name of the failure in the json reports.
+     */
+    pub fn name(self) -> &'static str{
+        match self {
+            Failure::Divergence => "divergence",
+            Failure::Usage => "usage",
+            Failure::Elf => "elf",
+            Failure::Toolchain => "toolchain",
+            Failure::Log => "log",
+            Failure::Output => "output",
+        }
+    }
+}
+
+/**
+ * The json object written to stderr for a failure with --errors-json, one per line.
+ */
+#[derive(Serialize)]
+struct JsonFailure<'a>{
+    kind: &'static str,
+    exit_code: i32,
+    message: &'a str,
+}
+
+/**
+ * Report the failures of the commands on stderr, as text or as json lines.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reporter{
+    pub json: bool,
+}
+
+impl Reporter {
+    /**
+     * Report a failure without exiting, i.e. the divergence of the logs after it is printed.
+     */
+    pub fn report(self, failure: Failure, message: impl Display){
+        let message = message.to_string();
+        if self.json {
+            let json = JsonFailure { kind: failure.name(), exit_code: failure.exit_code(), message: message.trim_end() };
+            eprintln!("{}", serde_json::to_string(&json).unwrap_or_default());
+        } else if failure != Failure::Divergence {
+            eprintln!("{}", message);
+        }
+    }
+
+    /**
+     * Report a failure and exit with its code.
+     */
+    pub fn fail(self, failure: Failure, message: impl Display) -> !{
+        self.report(failure, message);
+        process::exit(failure.exit_code())
+    }
+
+    /**
+     * Report an error of the debugger and exit with the code of its failure.
+     *
+     * @param context: What was done, i.e. Problem loading the elf.
+     */
+    pub fn fail_with(self, context: &str, err: &Error) -> !{
+        self.fail(Failure::of(err), format!("{}: {}", context, err))
+    }
+}
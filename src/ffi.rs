@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::io::BufRead;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use crate::compress::open_log;
+use crate::config::Config;
+use crate::debugger::{DebuggerVarilator, SrcInfo};
+use crate::error::{Error, Result};
+use crate::trace::{TraceReader, TraceRecord};
+
+/// The time and the cycle of the records when the log doesn't tell them.
+pub const VD_UNKNOWN: u64 = u64::MAX;
+
+/// The hart of the records when the log doesn't tell it.
+pub const VD_UNKNOWN_HART: u32 = u32::MAX;
+
+thread_local! {
+    /// The error of the last call that failed on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/**
+ * The debugger of vd_open, with the log it reads and the strings of the last location or record it gave.
+ */
+pub struct VdDebugger{
+    dv: DebuggerVarilator,
+    reader: Option<TraceReader<Box<dyn BufRead>>>,
+    /// The strings the pointers of the last location or record point to, freed by the next call.
+    strings: Vec<CString>,
+}
+
+/**
+ * The source location of an address, the strings are NULL when they aren't known.
+ */
+#[repr(C)]
+#[derive(Debug)]
+pub struct VdLocation{
+    pub file: *const c_char,
+    /// The line in the file, 0 when it isn't known.
+    pub line: u32,
+    /// The function that contains the address, the one the code was inlined into.
+    pub function: *const c_char,
+    /// The line of code, NULL if the source isn't available.
+    pub code: *const c_char,
+    /// The function symbol and the offset of the address when the code isn't available, i.e. uart_init+0x24.
+    pub symbol: *const c_char,
+    /// 1 if the address is out of the elfs.
+    pub external: c_int,
+}
+
+/**
+ * An instruction of the log with its source location.
+ */
+#[repr(C)]
+#[derive(Debug)]
+pub struct VdRecord{
+    pub pc: u64,
+    /// VD_UNKNOWN_HART when the log doesn't tell.
+    pub hart: u32,
+    /// VD_UNKNOWN when the log doesn't tell.
+    pub time: u64,
+    /// VD_UNKNOWN when the log doesn't tell.
+    pub cycle: u64,
+    /// The log line the record was parsed from.
+    pub line: *const c_char,
+    /// The decoded instruction, NULL if the log doesn't have it.
+    pub instruction: *const c_char,
+    pub location: VdLocation,
+}
+
+impl VdDebugger {
+    /**
+     * Keep a string until the next call, for the pointers given to C.
+     */
+    fn keep(&mut self, text: Option<&str>) -> *const c_char{
+        let Some(text) = text else {
+            return ptr::null();
+        };
+        // The strings with a NUL can't be given to C, they come from binary garbage in a log or a source.
+        self.strings.push(CString::new(text).unwrap_or_default());
+        self.strings.last().map_or(ptr::null(), |string| string.as_ptr())
+    }
+
+    fn location(&mut self, info: &SrcInfo) -> VdLocation{
+        VdLocation {
+            file: self.keep(info.location.file.as_deref()),
+            line: info.location.line.unwrap_or(0),
+            function: self.keep(info.location.outer_function()),
+            code: self.keep(info.code.as_deref()),
+            symbol: self.keep(info.symbol.as_deref()),
+            external: c_int::from(info.external),
+        }
+    }
+
+    fn record(&mut self, record: &TraceRecord) -> VdRecord{
+        let info = self.dv.get_src_info(record.pc).clone();
+        VdRecord {
+            pc: record.pc,
+            hart: record.hart.unwrap_or(VD_UNKNOWN_HART),
+            time: record.time.unwrap_or(VD_UNKNOWN),
+            cycle: record.cycle.unwrap_or(VD_UNKNOWN),
+            line: self.keep(Some(&record.line)),
+            instruction: self.keep(record.instruction.as_deref()),
+            location: self.location(&info),
+        }
+    }
+}
+
+/**
+ * Run a call of the API, its error or its panic is kept for vd_last_error.
+ *
+ * @param failed: The value returned when the call fails.
+ * @param call: The call.
+ * @return The value of the call, failed if it fails.
+ */
+fn guard<T>(failed: T, call: impl FnOnce() -> Result<T>) -> T{
+    let message = match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(err)) => err.to_string(),
+        Err(panic) => match panic.downcast_ref::<&str>() {
+            Some(message) => format!("Panic: {}", message),
+            None => panic.downcast_ref::<String>().map_or(String::from("Panic"), |message| format!("Panic: {}", message)),
+        },
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(CString::new(message).unwrap_or_default()));
+    failed
+}
+
+/**
+ * Read a string given by C.
+ */
+unsafe fn string(text: *const c_char, what: &str) -> Result<String>{
+    match text.is_null() {
+        true => Err(Error::Config(format!("No {} given", what))),
+        false => Ok(CStr::from_ptr(text).to_string_lossy().into_owned()),
+    }
+}
+
+/**
+ * Open elfs to annotate the addresses of their instructions and optionally a log to read its records.
+ *
+ * The architecture is read from the first elf and the source is read from the DWARF debug info.
+ *
+ * # Safety
+ *
+ * elf_files must point to elf_count strings and log_file must be a string or NULL.
+ *
+ * @param elf_files: The paths of the elfs, the addresses are symbolized with the first elf whose range has them.
+ * @param elf_count: The number of elfs.
+ * @param log_file: The path of the log read by vd_next_record, - for stdin, NULL to only annotate addresses.
+ * @return The debugger to give to the other calls and to close with vd_close, NULL on error.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn vd_open(elf_files: *const *const c_char, elf_count: usize, log_file: *const c_char) -> *mut VdDebugger{
+    guard(ptr::null_mut(), || {
+        if elf_files.is_null() || elf_count == 0 {
+            return Err(Error::Config(String::from("No elf given")));
+        }
+        let elf_files = std::slice::from_raw_parts(elf_files, elf_count).iter()
+            .map(|elf_file| string(*elf_file, "elf path")).collect::<Result<Vec<String>>>()?;
+        let mut config = Config::for_elfs(None, None, elf_files, Vec::new())?;
+        let log_file = (!log_file.is_null()).then(|| string(log_file, "log path")).transpose()?;
+        if let Some(log_file) = &log_file {
+            config.log_file = log_file.clone();
+        }
+        let mut dv = DebuggerVarilator::new(config)?;
+        dv.load_ranges()?;
+        let reader = match log_file {
+            Some(log_file) => Some(TraceReader::new(open_log(&log_file)?.0, None)?),
+            None => None,
+        };
+        Ok(Box::into_raw(Box::new(VdDebugger { dv, reader, strings: Vec::new() })))
+    })
+}
+
+/**
+ * Get the source location of an address, i.e. the pc of the core in a testbench.
+ *
+ * # Safety
+ *
+ * vd must come from vd_open and location must point to a VdLocation.
+ *
+ * @param vd: The debugger.
+ * @param addr: The runtime address of an instruction.
+ * @param location: Receives the location, its strings are valid until the next call with the debugger.
+ * @return 0, -1 on error.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn vd_annotate_address(vd: *mut VdDebugger, addr: u64, location: *mut VdLocation) -> c_int{
+    guard(-1, || {
+        let (Some(vd), false) = (vd.as_mut(), location.is_null()) else {
+            return Err(Error::Config(String::from("No debugger or location given")));
+        };
+        vd.strings.clear();
+        let addr = vd.dv.config.arch.code_addr(addr);
+        let info = vd.dv.get_src_info(addr).clone();
+        *location = vd.location(&info);
+        Ok(0)
+    })
+}
+
+/**
+ * Read the next record of the log in the elfs, with its source location.
+ *
+ * # Safety
+ *
+ * vd must come from vd_open and record must point to a VdRecord.
+ *
+ * @param vd: The debugger, opened with a log.
+ * @param record: Receives the record, its strings are valid until the next call with the debugger.
+ * @return 1 when a record is read, 0 at the end of the log, -1 on error.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn vd_next_record(vd: *mut VdDebugger, record: *mut VdRecord) -> c_int{
+    guard(-1, || {
+        let (Some(vd), false) = (vd.as_mut(), record.is_null()) else {
+            return Err(Error::Config(String::from("No debugger or record given")));
+        };
+        vd.strings.clear();
+        let Some(mut reader) = vd.reader.take() else {
+            return Err(Error::Config(String::from("The debugger was opened without a log")));
+        };
+        let next = vd.dv.next_record(&mut reader);
+        vd.reader = Some(reader);
+        match next? {
+            Some(next) => {
+                *record = vd.record(&next);
+                Ok(1)
+            },
+            None => Ok(0),
+        }
+    })
+}
+
+/**
+ * Get the error of the last call that failed on this thread.
+ *
+ * @return The message, valid until the next call that fails, NULL if no call failed.
+ */
+#[no_mangle]
+pub extern "C" fn vd_last_error() -> *const c_char{
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/**
+ * Close a debugger.
+ *
+ * # Safety
+ *
+ * vd must come from vd_open or be NULL, it can't be used after.
+ *
+ * @param vd: The debugger.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn vd_close(vd: *mut VdDebugger){
+    if !vd.is_null() {
+        drop(Box::from_raw(vd));
+    }
+}
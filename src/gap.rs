@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::trace::TraceRecord;
+
+/**
+ * A discontinuity of the counters of a hart, where the simulator may have dropped trace lines or the log was cut.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceGap{
+    pub hart: Option<u32>,
+    /// The counter that jumped, cycle or time.
+    pub counter: &'static str,
+    /// The value of the counter at the record before the gap.
+    pub from: u64,
+    /// The value of the counter at the record after the gap.
+    pub to: u64,
+}
+
+impl fmt::Display for TraceGap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(hart) = self.hart {
+            write!(f, "hart {} ", hart)?;
+        }
+        if self.to < self.from {
+            write!(f, "{} goes back from {} to {}", self.counter, self.from, self.to)
+        } else {
+            write!(f, "{} jumps from {} to {}", self.counter, self.from, self.to)
+        }
+    }
+}
+
+/**
+ * Find the gaps of the trace from the cycles of the harts, or from their times when the log has no cycles.
+ *
+ * A gap is a counter that goes back, or that grows by more than the maximum step between two instructions.
+ */
+#[derive(Debug, Default)]
+pub struct GapDetector{
+    max_step: u64,
+    /// The counter of the last record of each hart.
+    harts: HashMap<Option<u32>, u64>,
+}
+
+impl GapDetector {
+    /**
+     * Constructor.
+     *
+     * @param max_step: The largest growth of the counter between two instructions of a hart that isn't a gap.
+     */
+    pub fn new(max_step: u64) -> GapDetector{
+        GapDetector { max_step, harts: HashMap::new() }
+    }
+
+    /**
+     * Move the hart of a record to the record, the records must be given in the order of the trace.
+     *
+     * @param record: The traced instruction.
+     * @return The gap the record is right after, None if its counter follows the last one of its hart.
+     */
+    pub fn update(&mut self, record: &TraceRecord) -> Option<TraceGap>{
+        let (counter, to) = record.cycle.map(|cycle| ("cycle", cycle)).or(record.time.map(|time| ("time", time)))?;
+        let from = self.harts.insert(record.hart, to)?;
+        Some(TraceGap { hart: record.hart, counter, from, to })
+            .filter(|_| to < from || to - from > self.max_step)
+    }
+}
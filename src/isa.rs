@@ -0,0 +1,456 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::thumb;
+use crate::trace::TraceRecord;
+
+/**
+ * The architecture of the simulated cores, it decides how the instruction words decode and which toolchain built the
+ * elfs.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arch{
+    /// RISC-V, RV32 and RV64.
+    #[default]
+    Riscv,
+    /// ARM Cortex-M, the Thumb instructions of ARMv6-M, ARMv7-M and ARMv8-M.
+    Arm,
+}
+
+impl Arch {
+    /// All the architectures.
+    pub const ALL: [Arch; 2] = [Arch::Riscv, Arch::Arm];
+
+    /**
+     * Get the name of the architecture, as accepted by from_str.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            Arch::Riscv => "riscv",
+            Arch::Arm => "arm",
+        }
+    }
+
+    /**
+     * Get the names of all the architectures.
+     */
+    pub fn names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|arch| arch.name()).collect()
+    }
+
+    /**
+     * Get the address of the code an address points at, without the Thumb bit the ARM function symbols and entry
+     * points have set.
+     *
+     * @param addr: The address of a function, an entry point or an instruction.
+     * @return The address of the instruction.
+     */
+    pub fn code_addr(&self, addr: u64) -> u64{
+        match self {
+            Arch::Riscv => addr,
+            Arch::Arm => addr & !1,
+        }
+    }
+
+    /**
+     * Get the size of an instruction from its first 16 bits.
+     *
+     * @param first_half: The half word at the address of the instruction.
+     * @return 2 or 4, the size in bytes.
+     */
+    pub fn instruction_size(&self, first_half: u16) -> u64{
+        match self {
+            Arch::Riscv => instruction_size(first_half),
+            Arch::Arm => thumb::instruction_size(first_half),
+        }
+    }
+}
+
+impl FromStr for Arch {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|arch| arch.name() == name)
+            .ok_or_else(|| format!("Unknown architecture {}, expected one of {}", name, Self::names().join(", ")))
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * The kind of work an instruction does.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstrClass{
+    Load,
+    Store,
+    /// The conditional branches.
+    Branch,
+    /// The unconditional jumps, calls and returns.
+    Jump,
+    /// The integer, multiply and floating point arithmetic.
+    Alu,
+    Csr,
+    /// The environment calls, the returns from traps, the fences and wfi.
+    System,
+    /// The atomics and the instructions that couldn't be decoded.
+    Other,
+}
+
+impl InstrClass {
+    /// All the classes.
+    pub const ALL: [InstrClass; 8] = [InstrClass::Load, InstrClass::Store, InstrClass::Branch, InstrClass::Jump,
+        InstrClass::Alu, InstrClass::Csr, InstrClass::System, InstrClass::Other];
+
+    /**
+     * Get the name of the class.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            InstrClass::Load => "load",
+            InstrClass::Store => "store",
+            InstrClass::Branch => "branch",
+            InstrClass::Jump => "jump",
+            InstrClass::Alu => "alu",
+            InstrClass::Csr => "csr",
+            InstrClass::System => "system",
+            InstrClass::Other => "other",
+        }
+    }
+
+    /**
+     * Get the position of the class in ALL, to index the tables of counters.
+     */
+    pub fn index(&self) -> usize{
+        *self as usize
+    }
+
+    /**
+     * Classify a traced instruction.
+     *
+     * The instruction word is decoded when the log has it, the mnemonic of the decoded instruction is used otherwise
+     * and to tell c.jal from c.addiw as they share their encoding.
+     *
+     * @param record: The traced instruction.
+     * @param arch: The architecture of the hart.
+     * @return The class, Other if neither the opcode nor the mnemonic is known.
+     */
+    pub fn of(record: &TraceRecord, arch: Arch) -> InstrClass{
+        if arch == Arch::Arm {
+            return thumb::class(record);
+        }
+        let mnemonic = record.instruction.as_deref().and_then(|insn| insn.split_whitespace().next())
+            .map(|mnemonic| mnemonic.to_lowercase());
+        let word = record.opcode.as_deref().and_then(|opcode| {
+            let digits = opcode.trim().trim_start_matches("0x");
+            u32::from_str_radix(digits, 16).ok()
+        });
+        match word {
+            Some(word) => Self::decode(word, mnemonic.as_deref()),
+            None => mnemonic.as_deref().map_or(InstrClass::Other, Self::from_mnemonic),
+        }
+    }
+
+    /**
+     * Classify an instruction word.
+     *
+     * @param word: The instruction, the 16 bit compressed instructions in the low half.
+     * @param mnemonic: The mnemonic, if known, used for the encodings that depend on the XLEN.
+     */
+    fn decode(word: u32, mnemonic: Option<&str>) -> InstrClass{
+        if word & 0b11 != 0b11 {
+            return Self::decode_compressed(word as u16, mnemonic);
+        }
+        match word & 0x7f {
+            0x03 | 0x07 => InstrClass::Load,
+            0x23 | 0x27 => InstrClass::Store,
+            0x63 => InstrClass::Branch,
+            0x67 | 0x6f => InstrClass::Jump,
+            0x13 | 0x17 | 0x1b | 0x33 | 0x37 | 0x3b | 0x43 | 0x47 | 0x4b | 0x4f | 0x53 => InstrClass::Alu,
+            0x73 if (word >> 12) & 0b111 != 0 => InstrClass::Csr,
+            0x73 | 0x0f => InstrClass::System,
+            _ => InstrClass::Other,
+        }
+    }
+
+    fn decode_compressed(word: u16, mnemonic: Option<&str>) -> InstrClass{
+        let funct3 = word >> 13;
+        let rs1 = (word >> 7) & 0x1f;
+        let rs2 = (word >> 2) & 0x1f;
+        match (word & 0b11, funct3) {
+            (0b00, 0b000) => InstrClass::Alu,
+            (0b00, 0b001..=0b011) => InstrClass::Load,
+            (0b00, 0b101..=0b111) => InstrClass::Store,
+            // c.jal on RV32 and c.addiw on RV64, that shows as sext.w when its immediate is 0.
+            (0b01, 0b001) if mnemonic.is_some_and(|mnemonic| mnemonic.ends_with("addiw") || mnemonic == "sext.w") => InstrClass::Alu,
+            (0b01, 0b001) | (0b01, 0b101) => InstrClass::Jump,
+            (0b01, 0b110) | (0b01, 0b111) => InstrClass::Branch,
+            (0b01, _) => InstrClass::Alu,
+            (0b10, 0b000) => InstrClass::Alu,
+            (0b10, 0b001..=0b011) => InstrClass::Load,
+            (0b10, 0b100) => match ((word >> 12) & 1, rs1, rs2) {
+                (1, 0, 0) => InstrClass::System,
+                (_, _, 0) => InstrClass::Jump,
+                _ => InstrClass::Alu,
+            },
+            (0b10, 0b101..=0b111) => InstrClass::Store,
+            _ => InstrClass::Other,
+        }
+    }
+
+    /**
+     * Classify an instruction by its mnemonic, i.e. c.lw or csrrw.
+     */
+    fn from_mnemonic(mnemonic: &str) -> InstrClass{
+        let mnemonic = mnemonic.strip_prefix("c.").unwrap_or(mnemonic);
+        match mnemonic {
+            "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu" | "lwu" | "flh" | "flw" | "fld" | "flq" | "lwsp" | "ldsp"
+                | "flwsp" | "fldsp" | "lr.w" | "lr.d" => InstrClass::Load,
+            "sb" | "sh" | "sw" | "sd" | "fsh" | "fsw" | "fsd" | "fsq" | "swsp" | "sdsp" | "fswsp" | "fsdsp"
+                | "sc.w" | "sc.d" => InstrClass::Store,
+            "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" | "beqz" | "bnez" | "blez" | "bgez" | "bltz" | "bgtz"
+                | "bgt" | "ble" | "bgtu" | "bleu" => InstrClass::Branch,
+            "jal" | "jalr" | "j" | "jr" | "ret" | "call" | "tail" => InstrClass::Jump,
+            "ecall" | "ebreak" | "mret" | "sret" | "uret" | "dret" | "wfi" | "fence" | "fence.i" | "sfence.vma"
+                | "unimp" => InstrClass::System,
+            _ if mnemonic.starts_with("csr") || mnemonic.starts_with("rdcycle") || mnemonic.starts_with("rdtime")
+                || mnemonic.starts_with("rdinstret") => InstrClass::Csr,
+            _ if mnemonic.starts_with("amo") => InstrClass::Other,
+            _ if mnemonic.chars().all(|c| c.is_ascii_alphanumeric() || c == '.') && !mnemonic.is_empty() => InstrClass::Alu,
+            _ => InstrClass::Other,
+        }
+    }
+}
+
+impl fmt::Display for InstrClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * The control transfers that change the call stack.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer{
+    /// A jump that links to ra or t0, or to lr on ARM, the next instruction is in the callee.
+    Call,
+    /// A jump to ra or t0 that doesn't link, or to lr on ARM, the next instruction is back in the caller.
+    Return,
+    /// Any other jump, i.e. a loop or a tail call.
+    Jump,
+}
+
+impl Transfer {
+    /**
+     * Classify the unconditional jumps of a traced instruction.
+     *
+     * @param record: The traced instruction.
+     * @param arch: The architecture of the hart.
+     * @return The transfer, None if the instruction isn't a jump.
+     */
+    pub fn of(record: &TraceRecord, arch: Arch) -> Option<Transfer>{
+        if arch == Arch::Arm {
+            return thumb::transfer(record);
+        }
+        let word = record.opcode.as_deref().and_then(|opcode| u32::from_str_radix(opcode.trim().trim_start_matches("0x"), 16).ok());
+        match word {
+            Some(word) => Self::decode(word, record.instruction.as_deref()),
+            None => Self::from_instruction(record.instruction.as_deref()?),
+        }
+    }
+
+    fn decode(word: u32, instruction: Option<&str>) -> Option<Transfer>{
+        let link = |reg: u32| reg == 1 || reg == 5;
+        if word & 0b11 != 0b11 {
+            let rs1 = (word >> 7) & 0x1f;
+            return match (word & 0b11, (word >> 13) & 0b111, (word >> 12) & 1, (word >> 2) & 0x1f) {
+                // c.jal on RV32 and c.addiw on RV64, that shows as sext.w when its immediate is 0.
+                (0b01, 0b001, _, _) if instruction.is_some_and(|insn| insn.contains("addiw") || insn.contains("sext.w")) => None,
+                (0b01, 0b001, _, _) => Some(Transfer::Call),
+                (0b01, 0b101, _, _) => Some(Transfer::Jump),
+                (0b10, 0b100, 0, 0) if rs1 != 0 && link(rs1) => Some(Transfer::Return),
+                (0b10, 0b100, 0, 0) if rs1 != 0 => Some(Transfer::Jump),
+                (0b10, 0b100, 1, 0) if rs1 != 0 => Some(Transfer::Call),
+                _ => None,
+            };
+        }
+        let rd = (word >> 7) & 0x1f;
+        let rs1 = (word >> 15) & 0x1f;
+        match word & 0x7f {
+            0x6f if link(rd) => Some(Transfer::Call),
+            0x6f => Some(Transfer::Jump),
+            0x67 if link(rd) => Some(Transfer::Call),
+            0x67 if rd == 0 && link(rs1) => Some(Transfer::Return),
+            0x67 => Some(Transfer::Jump),
+            _ => None,
+        }
+    }
+
+    /**
+     * Classify a jump by its decoded instruction, i.e. jal ra,0x20000040 or jalr x0,0(x1).
+     */
+    fn from_instruction(instruction: &str) -> Option<Transfer>{
+        let mut parts = instruction.split_whitespace();
+        let mnemonic = parts.next()?.to_lowercase();
+        let operands: Vec<String> = parts.collect::<Vec<&str>>().join("").split([',', '(', ')'])
+            .filter(|operand| !operand.is_empty()).map(str::to_lowercase).collect();
+        let link = |reg: &str| matches!(reg, "ra" | "x1" | "t0" | "x5");
+        let zero = |reg: &str| matches!(reg, "zero" | "x0");
+        match mnemonic.strip_prefix("c.").unwrap_or(&mnemonic) {
+            "call" => Some(Transfer::Call),
+            "ret" => Some(Transfer::Return),
+            "j" | "tail" => Some(Transfer::Jump),
+            // jal <offset> links to ra.
+            "jal" if operands.len() < 2 || link(&operands[0]) => Some(Transfer::Call),
+            "jal" => Some(Transfer::Jump),
+            "jr" if operands.first().is_some_and(|reg| link(reg)) => Some(Transfer::Return),
+            "jr" => Some(Transfer::Jump),
+            "jalr" if operands.len() < 2 || link(&operands[0]) => Some(Transfer::Call),
+            "jalr" if zero(&operands[0]) && operands.iter().skip(1).any(|reg| link(reg)) => Some(Transfer::Return),
+            "jalr" => Some(Transfer::Jump),
+            _ => None,
+        }
+    }
+}
+
+/// The ABI names of the integer registers, by number.
+pub(crate) const INT_ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// The ABI names of the floating-point registers, by number.
+pub(crate) const FLOAT_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+/**
+ * Get the architectural name of a RISC-V register.
+ *
+ * @param name: The architectural name, i.e. x10, or the ABI name, i.e. a0.
+ * @return The architectural name, None if it isn't a register.
+ */
+pub fn register_name(name: &str) -> Option<String>{
+    let numbered = |prefix: &str| name.strip_prefix(prefix)
+        .filter(|number| !number.is_empty() && number.bytes().all(|digit| digit.is_ascii_digit()))
+        .and_then(|number| number.parse::<u32>().ok()).filter(|number| *number < 32);
+    if let Some(number) = numbered("x") {
+        return Some(format!("x{}", number));
+    }
+    if let Some(number) = numbered("f") {
+        return Some(format!("f{}", number));
+    }
+    if name == "fp" {
+        return Some(String::from("x8"));
+    }
+    INT_ABI_NAMES.iter().position(|abi| *abi == name).map(|number| format!("x{}", number))
+        .or_else(|| FLOAT_ABI_NAMES.iter().position(|abi| *abi == name).map(|number| format!("f{}", number)))
+}
+
+/**
+ * Get how much a traced RISC-V instruction moves the stack pointer, for the addi sp,sp,N of the prologues and the
+ * epilogues and their compressed forms.
+ *
+ * @param record: The traced instruction.
+ * @return The immediate added to sp, None if the instruction isn't one of those.
+ */
+pub fn stack_adjustment(record: &TraceRecord) -> Option<i64>{
+    let word = record.opcode.as_deref().and_then(|opcode| u32::from_str_radix(opcode.trim().trim_start_matches("0x"), 16).ok());
+    if let Some(word) = word {
+        let sign_extend = |value: u32, bits: u32| ((value << (32 - bits)) as i32 >> (32 - bits)) as i64;
+        let rd = (word >> 7) & 0x1f;
+        if word & 0b11 != 0b11 {
+            return match (word & 0b11, (word >> 13) & 0b111) {
+                // c.addi16sp
+                (0b01, 0b011) if rd == 2 => Some(sign_extend(((word >> 3) & 0x200) | ((word >> 2) & 0x10) | ((word << 1) & 0x40)
+                    | ((word << 4) & 0x180) | ((word << 3) & 0x20), 10)).filter(|imm| *imm != 0),
+                // c.addi
+                (0b01, 0b000) if rd == 2 => Some(sign_extend(((word >> 7) & 0x20) | ((word >> 2) & 0x1f), 6)),
+                _ => None,
+            };
+        }
+        let rs1 = (word >> 15) & 0x1f;
+        return match (word & 0x7f, (word >> 12) & 0b111) {
+            (0x13, 0) | (0x1b, 0) if rd == 2 && rs1 == 2 => Some(((word as i32) >> 20) as i64),
+            _ => None,
+        };
+    }
+    let instruction = record.instruction.as_deref()?.trim();
+    let (mnemonic, operands) = instruction.split_once(char::is_whitespace)?;
+    if !matches!(mnemonic, "addi" | "addiw" | "c.addi" | "c.addi16sp") {
+        return None;
+    }
+    let operands: Vec<&str> = operands.split(',').map(str::trim).collect();
+    let (first, last) = (*operands.first()?, *operands.last()?);
+    let sp = |operand: &str| register_name(operand).as_deref() == Some("x2");
+    if !sp(first) || (operands.len() == 3 && !sp(operands[1])) {
+        return None;
+    }
+    match last.strip_prefix('-') {
+        Some(magnitude) => magnitude.parse::<i64>().ok().map(|imm| -imm),
+        None => last.parse().ok(),
+    }
+}
+
+/**
+ * Get the number of bytes a RISC-V load or store accesses, from its mnemonic, i.e. 4 for lw, c.swsp and amoadd.w.
+ *
+ * @param record: The traced instruction.
+ * @return The size, None if the instruction isn't a known load or store.
+ */
+pub fn access_size(record: &TraceRecord) -> Option<u64>{
+    let mnemonic = record.instruction.as_deref()?.split_whitespace().next()?.to_lowercase();
+    let mnemonic = mnemonic.strip_prefix("c.").unwrap_or(&mnemonic);
+    // The atomics have the width after their operation, i.e. lr.w or amoswap.d.aq.
+    let width = match mnemonic.split_once('.') {
+        Some((_, width)) => width.split('.').next().unwrap_or(width),
+        None => mnemonic.strip_suffix("sp").unwrap_or(mnemonic).trim_start_matches('f').trim_end_matches('u'),
+    };
+    match width {
+        "b" | "lb" | "sb" => Some(1),
+        "h" | "lh" | "sh" => Some(2),
+        "w" | "lw" | "sw" => Some(4),
+        "d" | "ld" | "sd" => Some(8),
+        "q" | "lq" | "sq" => Some(16),
+        _ => None,
+    }
+}
+
+/**
+ * Get the size of a traced instruction, from its opcode or else from its mnemonic.
+ *
+ * @param record: The traced instruction.
+ * @param arch: The architecture of the hart.
+ * @return For RISC-V 2 for the compressed instructions, 4 for the others and when neither the opcode nor the mnemonic
+ * is known.
+ */
+pub fn record_size(record: &TraceRecord, arch: Arch) -> u64{
+    if arch == Arch::Arm {
+        return thumb::record_size(record);
+    }
+    let word = record.opcode.as_deref().and_then(|opcode| u32::from_str_radix(opcode.trim().trim_start_matches("0x"), 16).ok());
+    match (word, record.instruction.as_deref()) {
+        (Some(word), _) => instruction_size(word as u16),
+        (None, Some(insn)) if insn.trim_start().starts_with("c.") => 2,
+        _ => 4,
+    }
+}
+
+/**
+ * Get the size of a RISC-V instruction from its first 16 bits.
+ *
+ * @param low_half: The first half word of the instruction.
+ * @return 2 for the compressed instructions, 4 for the others.
+ */
+pub fn instruction_size(low_half: u16) -> u64{
+    if low_half & 0b11 != 0b11 {
+        2
+    } else {
+        4
+    }
+}
@@ -0,0 +1,51 @@
+//! Annotate Verilator instruction trace logs with the source code of the elf that executed.
+
+pub mod breakpoint;
+pub mod callstack;
+pub mod compress;
+pub mod config;
+pub mod csr;
+pub mod debugger;
+pub mod diff;
+pub mod disasm;
+pub mod elf;
+pub mod error;
+pub mod ffi;
+pub mod gap;
+pub mod isa;
+pub mod output;
+#[cfg(feature = "python")]
+mod python;
+pub mod script;
+pub mod source;
+pub mod symbolizer;
+pub mod thumb;
+pub mod toolchain;
+pub mod trace;
+pub mod trap;
+pub mod vfs;
+pub mod waveform;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+pub use breakpoint::{Breakpoint, BreakpointHit, BreakpointSet, WatchHit, WatchSet, Watchpoint};
+pub use callstack::{CallStack, Frame};
+pub use compress::{open_log, Compression};
+pub use config::Config;
+pub use csr::Csr;
+pub use debugger::{AnnotationSummary, DebuggerVarilator, SrcInfo};
+pub use disasm::Xlen;
+pub use diff::{diff_traces, DiffOutcome, DiffRules, Divergence, Mismatch};
+pub use elf::{Disassembly, ElfFunction, ElfSymbol, LoadSegment, SymbolMap};
+pub use error::Error;
+pub use gap::{GapDetector, TraceGap};
+pub use isa::{Arch, InstrClass, Transfer};
+pub use output::{ColorChoice, OutputFormat, RenderOptions};
+pub use output::split::SplitBy;
+pub use script::{Script, Verdict};
+pub use source::{PathMap, SourceDirs};
+pub use symbolizer::{SrcLocation, Symbolizer, SymbolizerPool};
+pub use toolchain::Toolchain;
+pub use trace::{CsvParser, DefaultParser, LayoutParser, QemuParser, RegexParser, SpikeParser, TraceField, TraceFormat, TraceLayout,
+    TraceParser, TraceReader, TraceRecord, DataAccess};
+pub use trap::{TrapCause, TrapDetector, TrapEvent};
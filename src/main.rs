@@ -1,264 +1,385 @@
-use std::env;
-use std::fs;
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::process;
-use regex::Regex;
+mod cli;
+mod config_file;
+mod failure;
+mod repl;
+mod serve;
+mod tui;
 
-const DEFAULT_ERROR: &str = "    Not found\n";
+use std::io::{BufRead, Write};
+use std::sync::atomic::Ordering;
+use log::LevelFilter;
+use verilator_debugger::debugger::STDIO_PATH;
+use verilator_debugger::{diff_traces, AnnotationSummary, open_log, Config, DebuggerVarilator, DiffOutcome, DiffRules, Mismatch, Script, SymbolMap, TraceFormat, TraceReader, TraceRecord};
+use clap::CommandFactory;
+use clap_complete::Generator;
+use cli::{AnnotateArgs, Cli, Command, CompletionsArgs, CoverageArgs, DiffArgs, ElfArgs, QueryArgs, ReplArgs, ServeArgs, SourceArgs, StatsArgs, SymbolAtArgs, ToolchainArgs, TraceArgs};
+use repl::Repl;
+use config_file::ConfigFile;
+use failure::{Failure, Reporter};
 
-#[derive(Debug)]
-struct Config{
-    addr2line_path: String,
-    readelf_path: String,
-    elf_file : String,
-    log_file : String,
-    output_file : String,
+fn main() -> std::io::Result<()>{
+    let cli = Cli::parse_args();
+    let report = Reporter { json: cli.errors_json };
+    init_logger(match cli.verbose {
+        _ if cli.quiet => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    });
+    let file = ConfigFile::load(cli.config.as_deref()).unwrap_or_else(|err| {
+        report.fail(Failure::Usage, format!("Problem loading the configuration: {}", err));
+    });
+    match cli.command {
+        Command::Annotate(args) => annotate(*args, &file, report),
+        Command::SymbolAt(args) => symbol_at(args, report),
+        Command::Stats(args) => stats(args, &file, report),
+        Command::Coverage(args) => coverage(args, &file, report),
+        Command::Diff(args) => diff(args, &file, report),
+        Command::Repl(args) => repl(args, &file, report),
+        Command::Query(args) => query(args, &file, report),
+        Command::Serve(args) => serve(args, &file, report),
+        Command::Completions(args) => completions(args),
+    }
 }
 
-#[derive(Debug)]
-struct DebuggerVarilator{
-    config :Config,
-    output: String,
+/**
+ * Print the diagnostics of the debugger to stderr from a level, RUST_LOG can still choose the levels of each module.
+ *
+ * The warnings are prefixed with Warning: and the status messages are printed as they are.
+ */
+fn init_logger(level: LevelFilter){
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|out, record| match record.level() {
+            log::Level::Error => writeln!(out, "Error: {}", record.args()),
+            log::Level::Warn => writeln!(out, "Warning: {}", record.args()),
+            log::Level::Info => writeln!(out, "{}", record.args()),
+            log::Level::Debug => writeln!(out, "Debug: {}", record.args()),
+            log::Level::Trace => writeln!(out, "Trace: {}", record.args()),
+        })
+        .init();
 }
 
-impl DebuggerVarilator {
-    /**
-     * Constructor.
-     * 
-     * @param addr2line_path: Path to the addr2line of the toolchain that built the elf. 
-     * @param elf_file: Path to the elf.
-     * @param log_file: Path to the file containing the log.
-     * @param out_file: Path to the file that will receive the output.
-     */
-    fn new(addr2line_path: &str, elf_file : &str, log_file : &str, out_file : &str) -> DebuggerVarilator{
-       let config = Config{
-            addr2line_path: addr2line_path.to_string(),
-            readelf_path: addr2line_path.to_string().replace("addr2line", "readelf"),
-            elf_file: elf_file.to_string(),
-            log_file: log_file.to_string(),
-            output_file: out_file.to_string()
-        };
-        DebuggerVarilator {
-            config: config,
-            output : "".to_string()
-        }
-    }
+/**
+ * Print what was annotated, even when the output is quiet, it only depends on the log.
+ */
+fn print_summary(summary: &AnnotationSummary, log_file: &str){
+    eprintln!("Annotated {} lines of {}, {} instructions traced, {} in the elfs", summary.lines, log_file, summary.traced,
+        summary.kept);
+}
 
-    /**
-     * Construct the object by parsing the command line arguments.
-     * 
-     * @param addr2line_path: Path to the addr2line of the toolchain that built the elf. 
-     * @param args: A mutable iterator containing the command line arguments.
-     */
+/**
+ * Annotate a log with the options of the command line and the configuration file.
+ */
+fn annotate(mut args: AnnotateArgs, file: &ConfigFile, report: Reporter) -> std::io::Result<()>{
+    args.apply_config(&file.output);
+    args.trace.apply_config(&file.trace);
+    args.toolchain.apply_config(&file.toolchain);
+    args.toolchain.detect_arch(&args.elf.files);
 
-    fn from_args(addr2line_path: &str, mut args: env::Args) -> Result<DebuggerVarilator, String>{
-        let help = format!("\n\tUsage: {} <path/to/elf> <path/to/log> [path/to/output]", args.next().unwrap_or("Debugger".to_string()));
-        
-        let elf_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err(format!("Didn't get a elf_file name{}", help)),
-        };
-        
-        let log_file = match args.next() {
-            Some(arg) => arg,
-            None => return Err(format!("Didn't get the input log file{}", help)),
-        };
-        
-        let output_file = args.next().unwrap_or(format!("parsed_{}", log_file));
-        
-        Ok(DebuggerVarilator::new (
-            addr2line_path,
-            &elf_file,
-            &log_file,
-            &output_file,
-        ))
+    let addr2line_path = toolchain_path(args.toolchain.addr2line_path(), report);
+    // The objdump is only looked for when it disassembles.
+    let objdump_path = match args.disassemble {
+        true => toolchain_path(args.toolchain.objdump_path(), report),
+        false => None,
+    };
+    let config = Config{
+        output_file: args.output_file(),
+        compress: args.compress,
+        split_by: args.split_by,
+        log_file: args.log_file(),
+        addr2line_path,
+        arch: args.toolchain.arch.unwrap_or_default(),
+        elf_files: args.elf.files,
+        load_offsets: args.elf.load_offset,
+        start_addrs: args.start_addr,
+        end_addrs: args.end_addr,
+        keep_external: args.keep_external,
+        trace_format: args.trace.trace_format,
+        layout: args.trace.layout(),
+        pc_regex: args.trace.pc_regex.clone(),
+        pc_signal: args.trace.pc_signal.clone(),
+        format: args.format.unwrap_or_default(),
+        color: args.color.unwrap_or_default(),
+        context: args.context.unwrap_or(if args.tui { tui::SOURCE_CONTEXT } else { 0 }),
+        from_cycle: args.from_cycle,
+        to_cycle: args.to_cycle,
+        from_time: args.from_time,
+        to_time: args.to_time,
+        harts: args.hart,
+        only_paths: args.only_path,
+        exclude_paths: args.exclude_path,
+        path_maps: args.sources.path_map,
+        source_dirs: args.sources.source_dir,
+        functions: args.function,
+        function_context: args.function_context,
+        break_at: args.break_at,
+        watch_data: args.watch_data,
+        break_context: args.break_context,
+        first_hit: args.first_hit,
+        call_stack: args.call_stack,
+        calls_only: args.calls_only,
+        traps: args.traps,
+        csr_names: args.csr_names,
+        gaps: args.gaps,
+        max_gap: args.max_gap,
+        show_writes: args.show_writes,
+        writes_to: args.writes_to,
+        data_symbols: args.data_symbols,
+        disassemble: args.disassemble,
+        objdump_path,
+        follow: args.follow,
+        listen: args.listen.clone(),
+        jobs: args.elf.jobs.map(|jobs| jobs as usize),
+    };
+    let mut dv = DebuggerVarilator::new(config).unwrap_or_else(|err| report.fail_with("Problem loading the elf", &err));
+    if let Some(path) = &args.script {
+        dv.set_script(Script::load(path).unwrap_or_else(|err| report.fail_with("Problem loading the script", &err)));
     }
 
-    /**
-     * Parse a log line to get the address and call the addr2line to return the source file.
-     * 
-     * @param addresses: A list of addresses in hex string format. i.e. 200040f0. 
-     * @return A list of strings with the corresponding addresses.
-     */
-    fn get_src_file(&mut self, addresses: &Vec<&str>) -> Vec<String> {
- 
-        let mut ps = process::Command::new(&self.config.addr2line_path);
-        ps.arg("-e").arg(&self.config.elf_file);
-        for addr in addresses{
-            ps.arg(addr.to_string());
+    if args.tui {
+        if dv.config.log_file == STDIO_PATH {
+            report.fail(Failure::Usage, "The TUI reads the keys from the terminal, give it a log file instead of stdin");
         }
-            
-        let res = ps.output().expect("Failed to execute addr2line");
-
-        String::from_utf8(res.stdout).expect("stdout parsing error").lines().map(|l| l.to_string()).collect()
+        if let Err(err) = tui::browse(dv, args.trace.parser()) {
+            report.fail_with("Problem browsing the log", &err);
+        }
+        return Ok(());
     }
-
-    /**
-     * Parse the output of the addr2line and return the code pointed at it.
-     * 
-     * @param src_info: addr2line output in the format <path/to/source>:<line>. 
-     */
-    fn get_src_location(&mut self, src_info: &str) -> String {
-        let mut it = src_info.split(':');
-        let filename = match it.next() {
-            Some(name) => name,
-            None => return String::from(DEFAULT_ERROR),
-        };
-        let line_number = match it.next() {
-            Some(name) => name,
-            None => return String::from(DEFAULT_ERROR),
-        };
-        let line_number = line_number.trim_end_matches('\n').parse::<usize>();
-
-        if let Ok(number) = line_number {
-            if let Ok(file) = File::open(&filename){
-                for line in io::BufReader::new(file).lines().skip(number - 1){
-                    if let Ok(l) = line {
-                        return "    ".to_owned() + &l + "\n";
-                    }
-                }
-            }
+    if dv.config.follow || dv.config.listen.is_some() {
+        let stop = dv.stop_handle();
+        if let Err(err) = ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed)) {
+            report.fail(Failure::Output, format!("Problem installing the Ctrl-C handler: {}", err));
         }
-        String::from(DEFAULT_ERROR)
     }
+    match dv.run() {
+        Ok(summary) => print_summary(&summary, &dv.config.log_file),
+        Err(err) => report.fail_with("Problem annotating the log", &err),
+    }
+    Ok(())
+}
 
-     /**
-     * Load the log file content filtering out the lines with addresses out of the specified range.
-     * 
-     * @param start_addr: Range start address.
-     * @param end_addr: Range end address.
-     * @return a String with the file content, string error otherwise.
-     */
-    fn get_file_content(&mut self, start_addr:u32, end_addr:u32) -> Result<String, String>{
-        let address_re = Regex::new(r"[\da-fA-F]+\s+[\da-fA-F]+\s+([\da-fA-F]+)\s+[\da-fA-F]+\s+\w+").unwrap();
-        let mut res = String::from("");
-        if let Ok(file) = File::open(&self.config.log_file){
-            for line in io::BufReader::new(file).lines(){
-                if let Ok(l) = line {
-                    if let Some(cap) = address_re.captures(&l) {
-                        let addr = u32::from_str_radix(&cap[1], 16).unwrap();
-                        if start_addr < addr && end_addr > addr{
-                            res += &(l + "\n");
-                        }
-                    }
-                }
-            }
-        }
-        Ok(res)
-   }
+/**
+ * Create the config of the commands that look up the elfs, with the toolchain of the config file and the defaults of
+ * annotate for the rest.
+ */
+fn elf_config(toolchain: &mut ToolchainArgs, elf: ElfArgs, sources: SourceArgs, file: &ConfigFile, report: Reporter) -> Config{
+    toolchain.apply_config(&file.toolchain);
+    toolchain.detect_arch(&elf.files);
+    let mut config = Config::new(toolchain_path(toolchain.addr2line_path(), report), elf.files, elf.load_offset);
+    config.arch = toolchain.arch.unwrap_or_default();
+    config.path_maps = sources.path_map;
+    config.source_dirs = sources.source_dir;
+    config.jobs = elf.jobs.map(|jobs| jobs as usize);
+    config
+}
+
+/**
+ * Create the config of the commands writing a report of a whole log, with the defaults of annotate for the rest.
+ */
+fn report_config(toolchain: &mut ToolchainArgs, trace: &mut TraceArgs, elf: ElfArgs, sources: SourceArgs,
+    file: &ConfigFile, report: Reporter) -> Config{
+    trace.apply_config(&file.trace);
+    let mut config = elf_config(toolchain, elf, sources, file, report);
+    config.trace_format = trace.trace_format;
+    config.layout = trace.layout();
+    config.pc_regex = trace.pc_regex.clone();
+    config.pc_signal = trace.pc_signal.clone();
+    config
+}
+
+/**
+ * Annotate a log into a report instead of its annotated lines.
+ */
+fn write_report(config: Config, report: Reporter) -> std::io::Result<()>{
+    let mut dv = DebuggerVarilator::new(config).unwrap_or_else(|err| report.fail_with("Problem loading the elf", &err));
+    match dv.run() {
+        Ok(summary) => print_summary(&summary, &dv.config.log_file),
+        Err(err) => report.fail_with("Problem annotating the log", &err),
+    }
+    Ok(())
+}
 
+/**
+ * Write the profile of a log in one of the formats of the profilers.
+ */
+fn stats(mut args: StatsArgs, file: &ConfigFile, report: Reporter) -> std::io::Result<()>{
+    let mut config = report_config(&mut args.toolchain, &mut args.trace, args.elf, SourceArgs::default(), file, report);
+    config.log_file = args.log;
+    config.output_file = args.output;
+    config.format = args.format;
+    config.from_cycle = args.from_cycle;
+    config.to_cycle = args.to_cycle;
+    config.harts = args.hart;
+    write_report(config, report)
+}
 
-     /**
-     * Read the elf and return the start address and the size.
-     * 
-     * @return a tuple with the address and size and string error otherwise.
-     */
-   fn get_elf_addr_and_size(&mut self) -> Result<(u32,u32), String>{
-       let res =  match process::Command::new(&self.config.readelf_path)
-        .arg("-l")
-        .arg(&self.config.elf_file)
-        .output(){
-            Ok(res) => res,
-            _ => return Err(String::from("Failed to execute readelf"))
-        };
+/**
+ * Write the coverage of the elfs by a log.
+ */
+fn coverage(mut args: CoverageArgs, file: &ConfigFile, report: Reporter) -> std::io::Result<()>{
+    let mut config = report_config(&mut args.toolchain, &mut args.trace, args.elf, args.sources, file, report);
+    config.log_file = args.log;
+    config.output_file = args.output;
+    config.format = args.format;
+    write_report(config, report)
+}
 
-        let res = match String::from_utf8(res.stdout){
-            Ok(res) => res,
-            _ => return Err(String::from("Failed to execute readelf"))
-        };
+/**
+ * Get the path of an optional tool of the toolchain options, exit if it's the one of the toolchain and the toolchain
+ * isn't found.
+ */
+fn toolchain_path(path: Result<Option<String>, String>, report: Reporter) -> Option<String>{
+    path.unwrap_or_else(|err| {
+        report.fail(Failure::Toolchain, format!("Problem finding the toolchain: {}", err));
+    })
+}
 
-         // Regex to parse the readelf -l output.
-        let entry_point_re = Regex::new(r"Entry point\s0x([\da-fA-F]+)").unwrap();
-        let load_re = Regex::new(r"LOAD\s+0x[\da-fA-F]+\s+0x[\da-fA-F]+\s+0x([\da-fA-F]+)\s+0x([\da-fA-F]+)\s+0x[\da-fA-F]+\s+").unwrap();
-        let mut start_addr = std::u32::MAX;
-        let mut size: u32 = 0;
-        for line in res.lines(){
-            if let Some(cap) = entry_point_re.captures(line) {
-                start_addr = u32::from_str_radix(&cap[1], 16).unwrap(); 
-            }
-            else if let Some(cap) = load_re.captures(line) {
-               let addr:u32 = u32::from_str_radix(&cap[1], 16).unwrap(); 
-               size = u32::from_str_radix(&cap[2], 16).unwrap(); 
-               if addr == start_addr & 0xFFFF0000 {
-                   break;
-               }
-            }
+/**
+ * Print the data symbol of each address, ?? for the addresses that aren't in one.
+ */
+fn symbol_at(args: SymbolAtArgs, report: Reporter) -> std::io::Result<()>{
+    let symbols = SymbolMap::read_data(&args.elf.files, &args.elf.load_offset)
+        .unwrap_or_else(|err| report.fail_with("Problem loading the elf", &err));
+    for addr in args.addrs {
+        match symbols.lookup(addr) {
+            Some((symbol, _)) => println!("0x{:x} {} (0x{:x}, {} bytes)", addr, symbols.symbolize(addr).unwrap_or_default(),
+                symbol.addr, symbol.size),
+            None => println!("0x{:x} ??", addr),
         }
-        Ok((start_addr, size))
-   }
-    
-    /**
-     * Process the log file by iterating through all lines.
-     */
-    fn run (&mut self) -> std::io::Result<()> {
-        println!("Starting ...");
-        
-        let (start_addr, size)  = self.get_elf_addr_and_size().expect("Error to get elf Address");
-        let log_content = self.get_file_content(start_addr, start_addr + size).expect("Error to open the file");
-        // let log_content = fs::read_to_string(&self.config.log_file).expect("Error to open the file");
-        let total = log_content.lines().count();
-        println!("File {} imported successfully", self.config.log_file);
-        println!("Parsing it...");
-        
-        let mut line_list: Vec<&str> = Vec::new();
-        let mut addr_list: Vec<&str> = Vec::new();
-        let mut last_addr: u32 = 0;
-        for (count, line) in log_content.lines().enumerate() {
-            
-            let address = match line.split_whitespace().skip(2).next() {
-                Some(addr) => addr,
-                None => continue
-            };
-            line_list.push(line);
-            addr_list.push(address);
+    }
+    Ok(())
+}
 
-            // If the current address is in the sequency of the last address, then keep pushing in the stack.
-            // Otherwise process the addresses in the stack.
-            let address =  u32::from_str_radix(address, 16).unwrap_or(0);
-            if last_addr.abs_diff(address) <= 4{
-                last_addr = address;
-                continue;
-            }
-            last_addr = address;
-            
-            let src_file_list = self.get_src_file(&addr_list);
-            // Skip this search if the current log line represents the same source line.
-            let mut last_src_location = "";
-            for (src_file, line) in src_file_list.iter().zip(line_list.iter()){
-                
-                if !last_src_location.eq(src_file) {
-                    self.output.push_str("\n");
-                    let src_code = self.get_src_location(&src_file);
-                    self.output.push_str(&src_file);
-                    self.output.push_str("\n");
-                
-                    self.output.push_str(&src_code);
-                }
-                self.output.push_str(line);
-                self.output.push_str("\n");
-                last_src_location = src_file;
-            }
-            
-            addr_list.clear();
-            line_list.clear();
+/**
+ * Open a log to read its records, exit if it can't be read or its format is unknown.
+ */
+fn open_trace(path: &str, format: Option<TraceFormat>, report: Reporter) -> TraceReader<Box<dyn BufRead>>{
+    let reader = open_log(path).and_then(|(log, _)| TraceReader::new(log, format.map(|format| format.parser())));
+    reader.unwrap_or_else(|err| report.fail_with(&format!("Problem reading the log {}", path), &err))
+}
 
-            print!("\rProgress:  {}%", count * 100 / total);
+/**
+ * Print the instruction of a log where the logs diverge with its source, or that the log ended.
+ */
+fn print_side(dv: &mut DebuggerVarilator, path: &str, record: Option<&TraceRecord>){
+    println!("{}:", path);
+    let Some(record) = record else {
+        println!("    the log ends here");
+        return;
+    };
+    println!("  > {}", record.line);
+    let info = dv.get_src_info(record.pc);
+    println!("    {}", info.location.header());
+    for call_site in &info.location.inlined_at {
+        println!("      inlined into {}", call_site.header());
+    }
+    if !info.context.is_empty() {
+        for (number, code) in &info.context {
+            let marker = if Some(*number) == info.location.line { ">" } else { " " };
+            println!("      {} {:>5} | {}", marker, number, code);
         }
-        // Processing has finished, write the result to the output file.
-        fs::write(&self.config.output_file, &self.output)?;
-        println!("\nFinished\nOutput {} generated successfully", self.config.output_file);
+    } else if let Some(code) = info.code.as_deref().map(str::to_string).or_else(|| info.unavailable()) {
+        println!("        {}", code);
+    }
+}
 
-        Ok(())
+/**
+ * Compare the instructions of two logs in the elfs and print where they diverge, exit with the code of a divergence
+ * if they do so it can check an RTL simulation against a golden trace in CI.
+ */
+fn diff(mut args: DiffArgs, file: &ConfigFile, report: Reporter) -> std::io::Result<()>{
+    let mut config = elf_config(&mut args.toolchain, args.elf, args.sources, file, report);
+    config.context = args.context;
+    let mut dv = DebuggerVarilator::new(config).and_then(|mut dv| dv.load_ranges().map(|_| dv))
+        .unwrap_or_else(|err| report.fail_with("Problem loading the elf", &err));
+    let in_elfs = |record: &std::io::Result<TraceRecord>| record.as_ref().map_or(true, |record| dv.in_elfs(record.pc));
+    let left = open_trace(&args.left, args.left_format, report).filter(in_elfs);
+    let right = open_trace(&args.right, args.right_format, report).filter(in_elfs);
+    let rules = DiffRules { writes: args.writes, ignore_registers: args.ignore_register, ignore_csrs: args.ignore_csr };
+    let outcome = diff_traces(left, right, &rules, args.before).unwrap_or_else(|err| {
+        report.fail(Failure::Log, format!("Problem reading the logs: {}", err));
+    });
+    let divergence = match outcome {
+        DiffOutcome::Identical(count) => {
+            println!("The logs are identical, {} instructions", count);
+            return Ok(());
+        },
+        DiffOutcome::Diverged(divergence) => divergence,
+    };
+    let value = |value: Option<u64>| value.map_or(String::from("nothing"), |value| format!("0x{:x}", value));
+    let reason = match &divergence.mismatch {
+        Mismatch::Pc => String::from("the instructions are at different addresses"),
+        Mismatch::Ended => String::from("one log ends"),
+        Mismatch::Write { register, left, right } => format!("{} is written {} in {} and {} in {}", register,
+            value(*left), args.left, value(*right), args.right),
+    };
+    let summary = format!("The logs diverge after {} instructions in common, {}", divergence.matching, reason);
+    println!("{}", summary);
+    if !divergence.before.is_empty() {
+        println!();
+        println!("Last instructions in common, from {}:", args.left);
+        for record in &divergence.before {
+            println!("    {}", record.line);
+        }
     }
+    println!();
+    print_side(&mut dv, &args.left, divergence.left.as_ref());
+    println!();
+    print_side(&mut dv, &args.right, divergence.right.as_ref());
+    report.fail(Failure::Divergence, summary);
 }
 
-fn main() -> std::io::Result<()>{
+/**
+ * Answer the queries typed on stdin about the elfs.
+ */
+fn repl(mut args: ReplArgs, file: &ConfigFile, report: Reporter) -> std::io::Result<()>{
+    let mut config = elf_config(&mut args.toolchain, args.elf, args.sources, file, report);
+    config.context = args.context;
+    let mut repl = DebuggerVarilator::new(config).and_then(|dv| Repl::new(dv, args.log, args.trace_format))
+        .unwrap_or_else(|err| report.fail_with("Problem loading the elf", &err));
+    repl.run(std::io::stdin().lock())
+}
 
-    let mut dv = DebuggerVarilator::from_args("/tools/riscv/bin/riscv32-unknown-elf-addr2line", env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
-    });
+/**
+ * Answer the queries of the command line about the elfs, exit at the first location that isn't found.
+ */
+fn query(mut args: QueryArgs, file: &ConfigFile, report: Reporter) -> std::io::Result<()>{
+    let mut config = elf_config(&mut args.toolchain, args.elf, args.sources, file, report);
+    config.context = args.context;
+    let mut repl = DebuggerVarilator::new(config).and_then(|dv| Repl::new(dv, args.log, args.trace_format))
+        .unwrap_or_else(|err| report.fail_with("Problem loading the elf", &err));
+    for location in &args.locations {
+        if let Err(err) = repl.query(location) {
+            report.fail(Failure::Usage, err);
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Index the log and serve the viewer of the trace.
+ */
+fn serve(mut args: ServeArgs, file: &ConfigFile, report: Reporter) -> std::io::Result<()>{
+    let mut config = elf_config(&mut args.toolchain, args.elf, args.sources, file, report);
+    config.log_file = args.log;
+    let dv = DebuggerVarilator::new(config).unwrap_or_else(|err| report.fail_with("Problem loading the elf", &err));
+    if let Err(err) = serve::serve(dv, args.trace_format.map(|format| format.parser()), &args.address, args.port) {
+        report.fail_with("Problem serving the log", &err);
+    }
+    Ok(())
+}
 
-    dv.run()
+/**
+ * Print the completion script of the command line for a shell.
+ */
+fn completions(args: CompletionsArgs) -> std::io::Result<()>{
+    let mut command = Cli::command();
+    command.set_bin_name(command.get_name().to_string());
+    command.build();
+    // The generators of some shells panic when the output is closed, the script is written at once.
+    let mut script = Vec::new();
+    args.shell.try_generate(&command, &mut script)?;
+    std::io::stdout().write_all(&script)
 }
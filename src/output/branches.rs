@@ -0,0 +1,69 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::isa::{self, Arch, InstrClass};
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * The outcomes of a conditional branch.
+ */
+#[derive(Debug, Default)]
+struct BranchSite{
+    /// The function, file and line of the branch.
+    location: String,
+    taken: u64,
+    not_taken: u64,
+}
+
+/**
+ * Count how many times each conditional branch was taken and not taken and write them, at the end.
+ *
+ * A branch is taken when the next instruction of its hart isn't the one that follows it, so a trap taken right after
+ * a branch counts as taken. The last branch of each hart has no outcome and isn't counted.
+ */
+#[derive(Debug, Default)]
+pub struct BranchesRenderer{
+    sites: BTreeMap<u64, BranchSite>,
+    /// The last branch of each hart and the address of the instruction that follows it, waiting for the next record.
+    pending: HashMap<Option<u32>, (u64, u64)>,
+    arch: Arch,
+}
+
+impl BranchesRenderer {
+    pub fn new(arch: Arch) -> BranchesRenderer{
+        BranchesRenderer { arch, ..BranchesRenderer::default() }
+    }
+}
+
+impl Renderer for BranchesRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, _stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        if let Some((pc, next_pc)) = self.pending.remove(&record.hart) {
+            let site = self.sites.get_mut(&pc).unwrap();
+            if record.pc == next_pc {
+                site.not_taken += 1;
+            } else {
+                site.taken += 1;
+            }
+        }
+        if InstrClass::of(record, self.arch) == InstrClass::Branch {
+            self.sites.entry(record.pc).or_insert_with(|| BranchSite { location: info.location.header(), ..BranchSite::default() });
+            self.pending.insert(record.hart, (record.pc, record.pc + isa::record_size(record, self.arch)));
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        let sites: Vec<(&u64, &BranchSite)> = self.sites.iter().filter(|(_, site)| site.taken + site.not_taken > 0).collect();
+        let never_taken = sites.iter().filter(|(_, site)| site.taken == 0).count();
+        let always_taken = sites.iter().filter(|(_, site)| site.not_taken == 0).count();
+        writeln!(out, "{} branch sites, {} never taken, {} always taken", sites.len(), never_taken, always_taken)?;
+        writeln!(out)?;
+        writeln!(out, "{:>10} {:>12} {:>12}  location", "address", "taken", "not taken")?;
+        for (pc, site) in sites {
+            writeln!(out, "{:>10x} {:>12} {:>12}  {}", pc, site.taken, site.not_taken, site.location)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * Write an outline of the execution, only the entries and the exits of the functions indented by their call depth.
+ *
+ * Each line starts with the cycle, or the time, of the instruction that entered or left the function, and with its
+ * hart when the log has several.
+ */
+#[derive(Debug, Default)]
+pub struct CallsRenderer{
+    /// The call stack of each hart at its last record.
+    stacks: HashMap<Option<u32>, Vec<Frame>>,
+}
+
+impl CallsRenderer {
+    pub fn new() -> CallsRenderer{
+        CallsRenderer::default()
+    }
+}
+
+/**
+ * Write the start of an outline line, the cycle or time of the record, its hart and the indentation of a depth.
+ */
+fn write_prefix(record: &TraceRecord, depth: usize, out: &mut dyn Write) -> io::Result<()>{
+    match record.cycle.or(record.time) {
+        Some(when) => write!(out, "{:>12} ", when)?,
+        None => write!(out, "{:>12} ", "")?,
+    }
+    if let Some(hart) = record.hart {
+        write!(out, "hart {} ", hart)?;
+    }
+    write!(out, "{}", "  ".repeat(depth))
+}
+
+impl Renderer for CallsRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let last = self.stacks.entry(record.hart).or_default();
+        let common = last.iter().zip(stack).take_while(|(a, b)| a == b).count();
+        if common == last.len() && common == stack.len() {
+            return Ok(());
+        }
+        for (depth, frame) in last.iter().enumerate().skip(common).rev() {
+            write_prefix(record, depth, out)?;
+            writeln!(out, "<- {}()", frame.function)?;
+        }
+        let outer = info.location.inlined_at.last().unwrap_or(&info.location);
+        for (depth, frame) in stack.iter().enumerate().skip(common) {
+            write_prefix(record, depth, out)?;
+            if depth + 1 == stack.len() {
+                writeln!(out, "-> {}() at {}", frame.function, outer)?;
+            } else {
+                writeln!(out, "-> {}()", frame.function)?;
+            }
+        }
+        *last = stack.to_vec();
+        Ok(())
+    }
+}
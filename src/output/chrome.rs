@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use serde_json::{json, Value};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+use crate::trap::TrapEvent;
+
+/**
+ * The functions a hart is in, with the timestamp of its last instruction.
+ */
+#[derive(Debug, Default)]
+struct HartTimeline{
+    frames: Vec<Frame>,
+    last: u64,
+    /// The number of instructions, the timestamps of the logs without cycles nor times.
+    count: u64,
+}
+
+/**
+ * Write the calls as the duration events of the Chrome trace event format, a timeline with a track per hart where each
+ * call is a slice nested in the slice of its caller, opened in ui.perfetto.dev or chrome://tracing.
+ *
+ * The timestamps are the cycles, or the times for the logs without cycles, shown as microseconds. The slices begin at
+ * the first instruction of the calls and end at the first instruction after their return. The traps are marked as
+ * instant events.
+ */
+#[derive(Debug, Default)]
+pub struct ChromeTraceRenderer{
+    harts: BTreeMap<Option<u32>, HartTimeline>,
+    count: usize,
+}
+
+impl ChromeTraceRenderer {
+    pub fn new() -> ChromeTraceRenderer{
+        ChromeTraceRenderer::default()
+    }
+
+    fn write_event(&mut self, event: Value, out: &mut dyn Write) -> io::Result<()>{
+        out.write_all(if self.count == 0 { b"\n" } else { b",\n" })?;
+        serde_json::to_writer(&mut *out, &event)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /**
+     * End the slices of the frames of a hart above a depth, from the innermost.
+     */
+    fn end_frames(&mut self, hart: Option<u32>, depth: usize, timestamp: u64, out: &mut dyn Write) -> io::Result<()>{
+        let frames = self.harts.get_mut(&hart).map_or(Vec::new(), |timeline| timeline.frames.split_off(depth));
+        for frame in frames.iter().rev() {
+            self.write_event(json!({ "name": frame.function, "ph": "E", "ts": timestamp, "pid": 0, "tid": hart.unwrap_or(0) }), out)?;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for ChromeTraceRenderer {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        out.write_all(b"{\"traceEvents\":[")
+    }
+
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let tid = record.hart.unwrap_or(0);
+        if !self.harts.contains_key(&record.hart) {
+            self.write_event(json!({ "name": "thread_name", "ph": "M", "pid": 0, "tid": tid, "args": { "name": format!("hart {}", tid) } }), out)?;
+        }
+        let timeline = self.harts.entry(record.hart).or_default();
+        timeline.count += 1;
+        let timestamp = record.cycle.or(record.time).unwrap_or(timeline.count);
+        timeline.last = timestamp;
+        let common = timeline.frames.iter().zip(stack).take_while(|(open, frame)| open == frame).count();
+        self.end_frames(record.hart, common, timestamp, out)?;
+        for frame in &stack[common..] {
+            self.write_event(json!({ "name": frame.function, "ph": "B", "ts": timestamp, "pid": 0, "tid": tid }), out)?;
+        }
+        self.harts.entry(record.hart).or_default().frames.extend_from_slice(&stack[common..]);
+        Ok(())
+    }
+
+    fn trap(&mut self, event: &TrapEvent, record: &TraceRecord, out: &mut dyn Write) -> io::Result<()>{
+        let name = match event {
+            TrapEvent::Entry { cause, from } => format!("Trap entry ({}) from 0x{:x}", cause, from),
+            TrapEvent::Exit { instruction, from } => format!("Trap exit ({}) from 0x{:x}", instruction, from),
+        };
+        // The trap is marked before its record is counted.
+        let count = self.harts.get(&record.hart).map_or(0, |timeline| timeline.count) + 1;
+        let timestamp = record.cycle.or(record.time).unwrap_or(count);
+        self.write_event(json!({ "name": name, "ph": "i", "s": "t", "ts": timestamp, "pid": 0, "tid": record.hart.unwrap_or(0) }), out)
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        // The calls still open end after the last instruction of their hart.
+        let harts: Vec<(Option<u32>, u64)> = self.harts.iter().map(|(hart, timeline)| (*hart, timeline.last + 1)).collect();
+        for (hart, timestamp) in harts {
+            self.end_frames(hart, 0, timestamp, out)?;
+        }
+        out.write_all(b"\n]}\n")
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::elf::ElfFunction;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * Write the share of the instructions of each function of the elfs that the trace executed, at the end.
+ */
+#[derive(Debug, Default)]
+pub struct CoverageRenderer{
+    /// The functions at the addresses they run from.
+    functions: Vec<ElfFunction>,
+    executed: HashSet<u64>,
+}
+
+impl CoverageRenderer {
+    /**
+     * Constructor.
+     *
+     * @param functions: The functions of the elfs, with their addresses moved by the load offsets.
+     */
+    pub fn new(functions: Vec<ElfFunction>) -> CoverageRenderer{
+        CoverageRenderer { functions, executed: HashSet::new() }
+    }
+}
+
+/**
+ * Get the share of a count in a total, 0 when the total is 0.
+ */
+fn percent(count: usize, total: usize) -> f64{
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl Renderer for CoverageRenderer {
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, _stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        self.executed.insert(record.pc);
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        let covered: Vec<usize> = self.functions.iter()
+            .map(|function| function.instructions.iter().filter(|pc| self.executed.contains(pc)).count())
+            .collect();
+        let total: usize = self.functions.iter().map(|function| function.instructions.len()).sum();
+        let executed: usize = covered.iter().sum();
+        let entered = covered.iter().filter(|&&count| count > 0).count();
+        writeln!(out, "Coverage of {} of {} instructions ({:.2}%), {} of {} functions executed", executed, total,
+            percent(executed, total), entered, self.functions.len())?;
+        writeln!(out)?;
+        writeln!(out, "{:>10} {:>8} {:>12} {:>7}  function", "address", "size", "instructions", "%")?;
+        for (function, covered) in self.functions.iter().zip(covered) {
+            let count = function.instructions.len();
+            writeln!(out, "{:>10x} {:>8} {:>12} {:>6.2}%  {}", function.addr, function.size, format!("{}/{}", covered, count),
+                percent(covered, count), function.name)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::csr::Csr;
+use crate::output::{RenderOptions, Renderer};
+use crate::trace::TraceRecord;
+
+/// The columns of the csv, in order.
+const HEADER: [&str; 10] = ["address", "function", "file", "line", "source", "inlined_at", "time", "cycle", "opcode", "instruction"];
+
+/// The columns added after the others when the call stack is requested.
+const CALL_STACK_HEADER: [&str; 2] = ["depth", "callers"];
+
+/// The columns added at the end when the CSR names are requested.
+const CSR_HEADER: [&str; 2] = ["csr", "csr_description"];
+
+/// The column added at the end when the register writes are requested.
+const WRITES_HEADER: [&str; 1] = ["writes"];
+
+/// The column added at the end when the data symbols are requested.
+const DATA_SYMBOL_HEADER: [&str; 1] = ["data_symbol"];
+
+/// The column added at the end when the disassembly is requested.
+const DISASSEMBLY_HEADER: [&str; 1] = ["disassembly"];
+
+/**
+ * Write one csv row per traced instruction, with the source information followed by the log fields.
+ */
+#[derive(Debug, Default)]
+pub struct CsvRenderer{
+    options: RenderOptions,
+}
+
+impl CsvRenderer {
+    /**
+     * Constructor.
+     *
+     * @param options: Whether to add the depth and the callers of the functions, the CSRs, the register writes, the
+     * variables accessed and the disassembly to the rows.
+     */
+    pub fn new(options: RenderOptions) -> CsvRenderer{
+        CsvRenderer { options }
+    }
+}
+
+/**
+ * Quote a field when it contains a separator, a quote or a line break.
+ */
+fn field(value: &str) -> String{
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Renderer for CsvRenderer {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        let mut header = HEADER.to_vec();
+        if self.options.call_stack {
+            header.extend(CALL_STACK_HEADER);
+        }
+        if self.options.csr_names {
+            header.extend(CSR_HEADER);
+        }
+        if self.options.writes {
+            header.extend(WRITES_HEADER);
+        }
+        if self.options.data_symbols.is_some() {
+            header.extend(DATA_SYMBOL_HEADER);
+        }
+        if self.options.disassembly.is_some() {
+            header.extend(DISASSEMBLY_HEADER);
+        }
+        writeln!(out, "{}", header.join(","))
+    }
+
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let mut row = vec![
+            format!("0x{:x}", record.pc),
+            info.location.function.clone().unwrap_or_default(),
+            info.location.file.clone().unwrap_or_default(),
+            info.location.line.map_or(String::new(), |line| line.to_string()),
+            info.code.clone().unwrap_or_default(),
+            info.location.inlined_at.iter().map(|call_site| call_site.header()).collect::<Vec<String>>().join("; "),
+            record.time.map_or(String::new(), |time| time.to_string()),
+            record.cycle.map_or(String::new(), |cycle| cycle.to_string()),
+            record.opcode.clone().unwrap_or_default(),
+            record.instruction.clone().unwrap_or_default(),
+        ];
+        if self.options.call_stack {
+            row.push(stack.len().to_string());
+            row.push(stack.iter().rev().skip(1).map(|frame| frame.function.as_str()).collect::<Vec<&str>>().join("; "));
+        }
+        if self.options.csr_names {
+            let csr = Csr::of(record);
+            row.push(csr.as_ref().map_or(String::new(), |csr| csr.name.clone()));
+            row.push(csr.map_or(String::new(), |csr| csr.description));
+        }
+        if self.options.writes {
+            let writes: Vec<String> = record.register_writes().iter()
+                .map(|write| format!("{}=0x{:x}", write.register, write.value)).collect();
+            row.push(writes.join("; "));
+        }
+        if self.options.data_symbols.is_some() {
+            row.push(self.options.data_symbol(record).unwrap_or_default());
+        }
+        if self.options.disassembly.is_some() {
+            row.push(self.options.disassembly(record).unwrap_or_default().to_string());
+        }
+        let row: Vec<String> = row.iter().map(|value| field(value)).collect();
+        writeln!(out, "{}", row.join(","))
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * Write the dynamic call graph in the Graphviz dot language, with one caller -> callee edge labeled with the number of
+ * calls, at the end.
+ */
+#[derive(Debug, Default)]
+pub struct DotRenderer{
+    /// The depth of the call stack of each hart at its last record.
+    depths: HashMap<Option<u32>, usize>,
+    calls: BTreeMap<(String, String), u64>,
+}
+
+impl DotRenderer {
+    pub fn new() -> DotRenderer{
+        DotRenderer::default()
+    }
+}
+
+/**
+ * Quote a node name, escaping the characters that end the string.
+ */
+fn quote(name: &str) -> String{
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Renderer for DotRenderer {
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        let depth = self.depths.insert(record.hart, stack.len()).unwrap_or(0);
+        if stack.len() > depth && stack.len() > 1 {
+            let caller = stack[stack.len() - 2].function.clone();
+            let callee = stack[stack.len() - 1].function.clone();
+            *self.calls.entry((caller, callee)).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        writeln!(out, "digraph calls {{")?;
+        writeln!(out, "    node [shape=box];")?;
+        for ((caller, callee), count) in &self.calls {
+            writeln!(out, "    {} -> {} [label=\"{}\"];", quote(caller), quote(callee), count)?;
+        }
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * Write the folded stacks of flamegraph.pl and speedscope, one main;uart_init;delay <count> line per call stack.
+ *
+ * The count is the number of cycles spent in each stack when the log has cycles and the number of instructions
+ * otherwise.
+ */
+#[derive(Debug, Default)]
+pub struct FoldedRenderer{
+    stacks: HashMap<String, u64>,
+    /// The stack and the cycle of the last record, waiting for the next cycle to know its cost.
+    last: Option<(String, u64)>,
+}
+
+impl FoldedRenderer {
+    pub fn new() -> FoldedRenderer{
+        FoldedRenderer::default()
+    }
+}
+
+impl Renderer for FoldedRenderer {
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        let stack = stack.iter().map(|frame| frame.function.as_str()).collect::<Vec<&str>>().join(";");
+        match (self.last.take(), record.cycle) {
+            (Some((last_stack, last_cycle)), Some(cycle)) => {
+                *self.stacks.entry(last_stack).or_default() += cycle.saturating_sub(last_cycle);
+            },
+            (_, None) => *self.stacks.entry(stack.clone()).or_default() += 1,
+            _ => (),
+        }
+        self.last = record.cycle.map(|cycle| (stack, cycle));
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        let mut stacks: Vec<(&String, &u64)> = self.stacks.iter().filter(|(_, count)| **count > 0).collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(out, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::path::Path;
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::{RenderOptions, Renderer};
+use crate::symbolizer::SrcLocation;
+use crate::trace::TraceRecord;
+
+/// The width of the column of each hart.
+const COLUMN_WIDTH: usize = 48;
+
+/// The colors of the columns, reused when there are more harts.
+const HART_COLORS: [&str; 6] = ["\x1b[36m", "\x1b[33m", "\x1b[35m", "\x1b[32m", "\x1b[34m", "\x1b[31m"];
+const RESET: &str = "\x1b[0m";
+
+/**
+ * Write the instructions of the harts side by side, one column per hart, so the interactions between them, i.e. the
+ * contention on a spinlock, can be followed.
+ *
+ * Each row is one instruction in the column of its hart, indented by the call depth of the hart and preceded by a
+ * row with the function, file and line of the instruction whenever they change for the hart. A column is added
+ * when a hart shows up, the header is written again then. The records without a hart are of hart 0.
+ */
+#[derive(Debug, Default)]
+pub struct HartsRenderer{
+    options: RenderOptions,
+    harts: BTreeSet<u32>,
+    /// The location of the last record of each hart.
+    locations: HashMap<u32, SrcLocation>,
+}
+
+impl HartsRenderer {
+    /**
+     * Constructor.
+     *
+     * @param options: Whether to color the columns of the harts with ANSI colors.
+     */
+    pub fn new(options: RenderOptions) -> HartsRenderer{
+        HartsRenderer { options, ..HartsRenderer::default() }
+    }
+
+    fn write_header(&self, out: &mut dyn Write) -> io::Result<()>{
+        let columns: Vec<Option<String>> = self.harts.iter().map(|hart| Some(format!("hart {}", hart))).collect();
+        self.write_row(None, &columns, out)?;
+        let rules = vec![Some("-".repeat(COLUMN_WIDTH)); self.harts.len()];
+        self.write_row(None, &rules, out)
+    }
+
+    /**
+     * Write a row, the cells are in the order of the harts and the empty ones are None.
+     */
+    fn write_row(&self, when: Option<u64>, cells: &[Option<String>], out: &mut dyn Write) -> io::Result<()>{
+        let mut row = match when {
+            Some(when) => format!("{:>12}", when),
+            None => format!("{:>12}", ""),
+        };
+        for (column, cell) in cells.iter().enumerate() {
+            let text: String = cell.as_deref().unwrap_or("").chars().take(COLUMN_WIDTH).collect();
+            let padded = format!("{:<width$}", text, width = COLUMN_WIDTH);
+            row.push_str(if column == 0 { "  " } else { " | " });
+            match (self.options.color, cell) {
+                (true, Some(_)) => row.push_str(&format!("{}{}{}", HART_COLORS[column % HART_COLORS.len()], padded, RESET)),
+                _ => row.push_str(&padded),
+            }
+        }
+        writeln!(out, "{}", row.trim_end())
+    }
+
+    /**
+     * Write a cell in the column of a hart.
+     */
+    fn write_cell(&self, hart: u32, when: Option<u64>, cell: String, out: &mut dyn Write) -> io::Result<()>{
+        let column = self.harts.iter().position(|other| *other == hart).unwrap();
+        let mut cells = vec![None; self.harts.len()];
+        cells[column] = Some(cell);
+        self.write_row(when, &cells, out)
+    }
+}
+
+/**
+ * Get the short form of a location for a column, the function with the file name and the line.
+ */
+fn short_location(location: &SrcLocation) -> String{
+    let file = location.file.as_deref().map(|file| Path::new(file).file_name().map_or(file.into(), |name| name.to_string_lossy()));
+    let place = match (file, location.line) {
+        (Some(file), Some(line)) => format!("{}:{}", file, line),
+        (Some(file), None) => format!("{}:?", file),
+        (None, _) => String::from("??"),
+    };
+    match &location.function {
+        Some(function) => format!("{}() {}", function, place),
+        None => place,
+    }
+}
+
+impl Renderer for HartsRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let hart = record.hart.unwrap_or(0);
+        if self.harts.insert(hart) {
+            if self.harts.len() > 1 {
+                writeln!(out)?;
+            }
+            self.write_header(out)?;
+        }
+        let indent = "  ".repeat(stack.len().saturating_sub(1));
+        if self.locations.get(&hart) != Some(&info.location) {
+            self.write_cell(hart, None, format!("{}{}", indent, short_location(&info.location)), out)?;
+            self.locations.insert(hart, info.location.clone());
+        }
+        let instruction = record.instruction.as_deref().or(record.opcode.as_deref()).unwrap_or("");
+        self.write_cell(hart, record.cycle.or(record.time), format!("{}  {:x} {}", indent, record.pc, instruction), out)
+    }
+}
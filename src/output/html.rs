@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::{SrcInfo, EXTERNAL_FUNCTION};
+use crate::csr::Csr;
+use crate::output::{RenderOptions, Renderer};
+use crate::symbolizer::SrcLocation;
+use crate::trace::TraceRecord;
+
+/// The width of the column of the register writes before the log lines.
+const WRITES_WIDTH: usize = 20;
+
+/// The width of the column of the disassembly before the log lines.
+const DISASSEMBLY_WIDTH: usize = 28;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 1em 2em; }
+details { border-left: 3px solid #4a78b5; margin: 0.4em 0; padding-left: 0.6em; }
+summary { cursor: pointer; }
+summary a { color: #4a78b5; font-family: monospace; text-decoration: none; }
+pre { margin: 0.2em 0 0.2em 1.2em; color: #555; }
+code.src { background: #f4f4f4; padding: 0 0.4em; }
+.missing { color: #a00; }
+.inlined { color: #777; font-size: 0.9em; }
+.note { color: #4a78b5; }
+.writes { color: #8959a8; }
+.asm { color: #3e999f; }
+pre.context { background: #f4f4f4; color: #000; padding: 0.3em; }
+.current { background: #fff3b0; }
+.kw { color: #8959a8; font-weight: bold; }
+.num { color: #f5871f; }
+.str { color: #718c00; }
+.com { color: #999; font-style: italic; }
+";
+
+/// The keywords highlighted in the source lines, from C, C++ and Rust.
+const KEYWORDS: [&str; 58] = [
+    "as", "asm", "auto", "break", "case", "char", "const", "continue", "default", "do", "double",
+    "else", "enum", "extern", "false", "float", "fn", "for", "goto", "if", "impl", "int", "let",
+    "long", "loop", "match", "mod", "mut", "pub", "register", "return", "self", "short", "signed",
+    "sizeof", "static", "struct", "switch", "true", "typedef", "union", "unsafe", "unsigned", "use",
+    "void", "volatile", "while", "class", "namespace", "template", "new", "delete", "inline",
+    "bool", "nullptr", "where", "trait", "type",
+];
+
+/**
+ * Write a self-contained html page with one collapsible block per source line and an index of the functions and
+ * source files.
+ */
+#[derive(Debug, Default)]
+pub struct HtmlRenderer{
+    last_location: Option<SrcLocation>,
+    last_depth: usize,
+    options: RenderOptions,
+    blocks: usize,
+    /// The functions in order of appearance with the anchor of their first block, the demangled names can't be ids.
+    functions: Vec<(String, usize)>,
+    /// The source files in order of appearance with the anchor of their first block.
+    files: Vec<(String, usize)>,
+    seen: HashSet<String>,
+}
+
+impl HtmlRenderer {
+    /**
+     * Constructor.
+     *
+     * @param options: Whether to show the callers of the functions in the blocks, the CSRs and the variables accessed
+     * after the instructions and the register writes and the disassembly before them.
+     */
+    pub fn new(options: RenderOptions) -> HtmlRenderer{
+        HtmlRenderer { options, ..HtmlRenderer::default() }
+    }
+}
+
+/**
+ * Escape the characters that have a meaning in html.
+ */
+fn escape(text: &str) -> String{
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/**
+ * Highlight the keywords, numbers, strings and comments of a line of code.
+ *
+ * @param code: The line of code.
+ * @return The escaped html of the line.
+ */
+fn highlight(code: &str) -> String{
+    let chars: Vec<char> = code.chars().collect();
+    let mut res = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            let rest: String = chars[i..].iter().collect();
+            res.push_str(&format!("<span class=\"com\">{}</span>", escape(&rest)));
+            break;
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            let token: String = chars[start..i].iter().collect();
+            res.push_str(&format!("<span class=\"str\">{}</span>", escape(&token)));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            res.push_str(&format!("<span class=\"num\">{}</span>", escape(&token)));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            if KEYWORDS.contains(&token.as_str()) {
+                res.push_str(&format!("<span class=\"kw\">{}</span>", token));
+            } else {
+                res.push_str(&escape(&token));
+            }
+        } else {
+            res.push_str(&escape(&c.to_string()));
+            i += 1;
+        }
+    }
+    res
+}
+
+impl Renderer for HtmlRenderer {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Annotated trace</title>\n<style>{}</style>\n</head>\n<body>", STYLE)?;
+        writeln!(out, "<h1>Annotated trace</h1>\n<p><a href=\"#functions\">Functions</a> <a href=\"#files\">Source files</a></p>")
+    }
+
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let moved = self.options.call_stack && self.last_depth != stack.len();
+        if self.last_location.as_ref() != Some(&info.location) || moved {
+            if self.last_location.is_some() {
+                writeln!(out, "</pre></details>")?;
+            }
+            if let Some(file) = &info.location.file {
+                if self.seen.insert(format!("file:{}", file)) {
+                    self.files.push((file.clone(), self.blocks));
+                }
+            }
+            if let Some(function) = &info.location.function {
+                if self.seen.insert(format!("fn:{}", function)) {
+                    self.functions.push((function.clone(), self.blocks));
+                }
+            }
+            let code = match &info.code {
+                Some(code) => format!("<code class=\"src\">{}</code>", highlight(code.trim())),
+                None => match info.unavailable() {
+                    Some(text) => format!("<span class=\"missing\">{}</span>", escape(&text)),
+                    None => String::from("<span class=\"missing\">Not found</span>"),
+                },
+            };
+            let mut inlined: String = info.location.inlined_at.iter()
+                .map(|call_site| format!(" <span class=\"inlined\">inlined into {}</span>", escape(&call_site.header())))
+                .collect();
+            if self.options.call_stack && stack.len() > 1 {
+                let callers: Vec<String> = stack.iter().rev().skip(1).map(|frame| escape(&frame.function)).collect();
+                inlined.push_str(&format!(" <span class=\"inlined\">called from {}</span>", callers.join(" &lt;- ")));
+            }
+            writeln!(out, "<details open id=\"b{0}\"><summary><a href=\"#b{0}\">{1}</a>{2} {3}</summary>",
+                self.blocks, escape(&if info.external { EXTERNAL_FUNCTION.to_string() } else { info.location.header() }), inlined, code)?;
+            if !info.context.is_empty() {
+                writeln!(out, "<pre class=\"context\">")?;
+                for (number, code) in &info.context {
+                    let line = format!("{:>5} | {}", number, highlight(code));
+                    if Some(*number) == info.location.line {
+                        writeln!(out, "<span class=\"current\">{}</span>", line)?;
+                    } else {
+                        writeln!(out, "{}", line)?;
+                    }
+                }
+                writeln!(out, "</pre>")?;
+            }
+            writeln!(out, "<pre>")?;
+            self.blocks += 1;
+            self.last_location = Some(info.location.clone());
+            self.last_depth = stack.len();
+        }
+        if self.options.writes {
+            let writes: Vec<String> = record.register_writes().iter()
+                .map(|write| format!("{}=0x{:08x}", write.register, write.value)).collect();
+            write!(out, "<span class=\"writes\">{:<width$}</span> ", writes.join(" "), width = WRITES_WIDTH)?;
+        }
+        if self.options.disassembly.is_some() {
+            let disassembly = format!("{:<width$}", self.options.disassembly(record).unwrap_or("??"), width = DISASSEMBLY_WIDTH);
+            write!(out, "<span class=\"asm\">{}</span> ", escape(&disassembly))?;
+        }
+        let mut notes = Vec::new();
+        if let Some(csr) = if self.options.csr_names { Csr::of(record) } else { None } {
+            notes.push(format!("{}: {}", csr.name, csr.description));
+        }
+        notes.extend(self.options.data_symbol(record));
+        notes.extend(record.notes.iter().cloned());
+        match notes.is_empty() {
+            true => writeln!(out, "{}", escape(&record.line)),
+            false => writeln!(out, "{}  <span class=\"note\"># {}</span>", escape(&record.line), escape(&notes.join("; "))),
+        }
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        if self.last_location.is_some() {
+            writeln!(out, "</pre></details>")?;
+        }
+        writeln!(out, "<h2 id=\"functions\">Functions</h2>\n<ul>")?;
+        for (function, block) in &self.functions {
+            writeln!(out, "<li><a href=\"#b{}\">{}</a></li>", block, escape(function))?;
+        }
+        writeln!(out, "</ul>\n<h2 id=\"files\">Source files</h2>\n<ul>")?;
+        for (file, block) in &self.files {
+            writeln!(out, "<li><a href=\"#b{}\">{}</a></li>", block, escape(file))?;
+        }
+        writeln!(out, "</ul>\n</body>\n</html>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functions_link_to_their_first_block(){
+        let mut renderer = HtmlRenderer::new(RenderOptions::default());
+        let mut out = Vec::new();
+        for (pc, function) in [(0x10, "main"), (0x14, "std::vector<int>::push_back(int const&)")] {
+            let record = TraceRecord { line: format!("line 0x{:x}", pc), hart: None, time: None, cycle: None, pc,
+                opcode: None, instruction: None, notes: Vec::new() };
+            let location = SrcLocation { file: Some(String::from("main.cpp")), line: Some(pc as u32),
+                function: Some(function.to_string()), inlined_at: Vec::new() };
+            let info = SrcInfo { location, code: None, context: Vec::new(), symbol: None, external: false };
+            renderer.record(&record, &info, &[], &mut out).unwrap();
+        }
+        renderer.end(&mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<details open id=\"b1\">"));
+        assert!(html.contains("<li><a href=\"#b1\">std::vector&lt;int&gt;::push_back(int const&amp;)</a></li>"));
+        assert!(!html.contains("fn-"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::io::{self, Write};
+use serde::Serialize;
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::csr::Csr;
+use crate::output::{RenderOptions, Renderer};
+use crate::trace::TraceRecord;
+
+/**
+ * Write a json array with one object per traced instruction, or one object per line as json lines.
+ */
+#[derive(Debug, Default)]
+pub struct JsonRenderer{
+    count: usize,
+    options: RenderOptions,
+    /// Write json lines, each object is written on its own line as soon as its record is annotated.
+    lines: bool,
+}
+
+impl JsonRenderer {
+    /**
+     * Constructor.
+     *
+     * @param options: Whether to add the depth and the callers of the functions, the CSRs, the register writes, the
+     * variables accessed and the disassembly to the objects.
+     */
+    pub fn new(options: RenderOptions) -> JsonRenderer{
+        JsonRenderer { count: 0, options, lines: false }
+    }
+
+    /**
+     * Constructor of the json lines, one object per line without the array around them, to pipe into jq or a
+     * dashboard while --follow annotates the log.
+     *
+     * @param options: The options of new.
+     */
+    pub fn lines(options: RenderOptions) -> JsonRenderer{
+        JsonRenderer { count: 0, options, lines: true }
+    }
+}
+
+/**
+ * The json object of a traced instruction.
+ */
+#[derive(Serialize)]
+struct JsonRecord<'a>{
+    time: Option<u64>,
+    cycle: Option<u64>,
+    pc: String,
+    opcode: Option<&'a str>,
+    instruction: Option<&'a str>,
+    function: Option<&'a str>,
+    file: Option<&'a str>,
+    line: Option<u32>,
+    source: Option<&'a str>,
+    /// The call sites the code was inlined into, from the innermost.
+    inlined_at: Vec<JsonCallSite<'a>>,
+    /// The numbered source lines around the executed one, when context lines are requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    context: Vec<JsonContextLine<'a>>,
+    /// The number of functions in the call stack, when the call stack is requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depth: Option<usize>,
+    /// The functions of the call stack that called the function of the instruction, from the innermost.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    callers: Option<Vec<&'a str>>,
+    /// The CSR the instruction accesses, when the CSR names are requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    csr: Option<JsonCsr>,
+    /// The registers written by the instruction, when the writes are requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    writes: Option<Vec<JsonWrite>>,
+    /// The variable the instruction loaded or stored, when the data symbols are requested and it's known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_symbol: Option<String>,
+    /// The instruction as disassembled from the elfs, when the disassembly is requested and it's known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disassembly: Option<&'a str>,
+    /// The notes added by the script.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<&'a str>,
+    /// Set for the instructions out of the elfs, kept with --keep-external.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    external: Option<bool>,
+}
+
+/**
+ * The json object of a register write.
+ */
+#[derive(Serialize)]
+struct JsonWrite{
+    register: String,
+    value: String,
+}
+
+/**
+ * The json object of a CSR.
+ */
+#[derive(Serialize)]
+struct JsonCsr{
+    number: String,
+    name: String,
+    description: String,
+}
+
+/**
+ * The json object of a source line around the executed one.
+ */
+#[derive(Serialize)]
+struct JsonContextLine<'a>{
+    line: u32,
+    source: &'a str,
+}
+
+/**
+ * The json object of a call site an inlined function was inlined into.
+ */
+#[derive(Serialize)]
+struct JsonCallSite<'a>{
+    function: Option<&'a str>,
+    file: Option<&'a str>,
+    line: Option<u32>,
+}
+
+impl Renderer for JsonRenderer {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        match self.lines {
+            true => Ok(()),
+            false => out.write_all(b"["),
+        }
+    }
+
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let json = JsonRecord {
+            time: record.time,
+            cycle: record.cycle,
+            pc: format!("0x{:x}", record.pc),
+            opcode: record.opcode.as_deref(),
+            instruction: record.instruction.as_deref(),
+            function: info.location.function.as_deref(),
+            file: info.location.file.as_deref(),
+            line: info.location.line,
+            source: info.code.as_deref(),
+            inlined_at: info.location.inlined_at.iter().map(|call_site| JsonCallSite {
+                function: call_site.function.as_deref(),
+                file: call_site.file.as_deref(),
+                line: call_site.line,
+            }).collect(),
+            context: info.context.iter().map(|(line, source)| JsonContextLine { line: *line, source }).collect(),
+            depth: Some(stack.len()).filter(|_| self.options.call_stack),
+            callers: Some(stack.iter().rev().skip(1).map(|frame| frame.function.as_str()).collect()).filter(|_| self.options.call_stack),
+            csr: if self.options.csr_names { Csr::of(record) } else { None }.map(|csr| JsonCsr {
+                number: format!("0x{:03x}", csr.number),
+                name: csr.name,
+                description: csr.description,
+            }),
+            writes: Some(record.register_writes()).filter(|_| self.options.writes).map(|writes| writes.into_iter()
+                .map(|write| JsonWrite { register: write.register, value: format!("0x{:x}", write.value) }).collect()),
+            data_symbol: self.options.data_symbol(record),
+            disassembly: self.options.disassembly(record),
+            notes: record.notes.iter().map(String::as_str).collect(),
+            external: Some(true).filter(|_| info.external),
+        };
+        if self.lines {
+            serde_json::to_writer(&mut *out, &json)?;
+            return out.write_all(b"\n");
+        }
+        out.write_all(if self.count == 0 { b"\n" } else { b",\n" })?;
+        serde_json::to_writer(&mut *out, &json)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        match self.lines {
+            true => Ok(()),
+            false => out.write_all(b"\n]\n"),
+        }
+    }
+}
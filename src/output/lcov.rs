@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::elf::ElfFunction;
+use crate::output::Renderer;
+use crate::symbolizer::SrcLocation;
+use crate::trace::TraceRecord;
+
+/**
+ * The coverage of a source file.
+ */
+#[derive(Debug, Default)]
+struct FileCoverage{
+    /// The line and the executions of the functions that start in the file, by name.
+    functions: BTreeMap<String, (u32, u64)>,
+    /// The executions of each line, those of its most executed instruction.
+    lines: BTreeMap<u32, u64>,
+}
+
+/**
+ * Write the line coverage of the trace as an lcov tracefile, the .info files read by genhtml, at the end.
+ *
+ * Every line with code in the functions of the elfs is listed, with the number of times it was executed.
+ */
+#[derive(Debug, Default)]
+pub struct LcovRenderer{
+    /// The functions at the addresses they run from.
+    functions: Vec<ElfFunction>,
+    /// The source location of the instructions of the functions.
+    locations: HashMap<u64, SrcLocation>,
+    executions: HashMap<u64, u64>,
+}
+
+impl LcovRenderer {
+    /**
+     * Constructor.
+     *
+     * @param functions: The functions of the elfs, with their addresses moved by the load offsets.
+     * @param locations: The source location of the instructions of the functions, the instructions without a
+     * known line aren't in the report.
+     */
+    pub fn new(functions: Vec<ElfFunction>, locations: HashMap<u64, SrcLocation>) -> LcovRenderer{
+        LcovRenderer { functions, locations, executions: HashMap::new() }
+    }
+
+    /**
+     * Group the executions by source file.
+     */
+    fn files(&self) -> BTreeMap<&str, FileCoverage>{
+        let mut files: BTreeMap<&str, FileCoverage> = BTreeMap::new();
+        for function in &self.functions {
+            let mut entries = 0;
+            for (i, pc) in function.instructions.iter().enumerate() {
+                let executions = self.executions.get(pc).copied().unwrap_or(0);
+                if i == 0 {
+                    entries = executions;
+                }
+                if let Some((file, line)) = self.locations.get(pc).and_then(file_line) {
+                    let count = files.entry(file).or_default().lines.entry(line).or_default();
+                    *count = executions.max(*count);
+                }
+            }
+            // The first instruction can be inlined code, the function starts at the call site it was inlined into.
+            let outer = function.instructions.first().and_then(|pc| self.locations.get(pc))
+                .and_then(|location| file_line(location.inlined_at.last().unwrap_or(location)));
+            if let Some((file, line)) = outer {
+                files.entry(file).or_default().functions.insert(function.name.clone(), (line, entries));
+            }
+        }
+        files
+    }
+}
+
+/**
+ * Get the file and the line of a location, None if either is unknown.
+ */
+fn file_line(location: &SrcLocation) -> Option<(&str, u32)>{
+    Some((location.file.as_deref()?, location.line.filter(|&line| line > 0)?))
+}
+
+impl Renderer for LcovRenderer {
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, _stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        *self.executions.entry(record.pc).or_default() += 1;
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        for (file, coverage) in self.files() {
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{}", file)?;
+            for (name, (line, _)) in &coverage.functions {
+                writeln!(out, "FN:{},{}", line, name)?;
+            }
+            for (name, (_, entries)) in &coverage.functions {
+                writeln!(out, "FNDA:{},{}", entries, name)?;
+            }
+            writeln!(out, "FNF:{}", coverage.functions.len())?;
+            writeln!(out, "FNH:{}", coverage.functions.values().filter(|(_, entries)| *entries > 0).count())?;
+            for (line, count) in &coverage.lines {
+                writeln!(out, "DA:{},{}", line, count)?;
+            }
+            writeln!(out, "LF:{}", coverage.lines.len())?;
+            writeln!(out, "LH:{}", coverage.lines.values().filter(|count| **count > 0).count())?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
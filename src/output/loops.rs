@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::isa::{self, Arch, InstrClass, Transfer};
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/// The most instructions per iteration of a loop flagged as a busy-wait.
+const BUSY_WAIT_INSTRUCTIONS: f64 = 8.0;
+
+/**
+ * An executed instruction address.
+ */
+#[derive(Debug)]
+struct Instruction{
+    executions: u64,
+    /// The function, file and line of the instruction.
+    location: String,
+    /// True if the instruction only tests or waits, it doesn't store, call or trap.
+    waits: bool,
+}
+
+/**
+ * A loop found in the trace.
+ */
+#[derive(Debug)]
+struct Loop{
+    head: u64,
+    /// The address of the branch or the jump back to the head.
+    tail: u64,
+    /// The instructions executed at the addresses of the loop, those of the functions it calls aren't included.
+    instructions: u64,
+    iterations: u64,
+    busy_wait: bool,
+}
+
+/**
+ * Find the loops of the trace, from the branches and the jumps taken backward, and write those that executed the
+ * most instructions, at the end.
+ *
+ * The instructions of a loop are those executed between its head and its backward branch, so an outer loop includes
+ * its inner loops. A loop that runs few instructions per iteration without storing or calling anything is reported
+ * separately as a busy-wait, i.e. polling a register or a delay loop.
+ */
+#[derive(Debug, Default)]
+pub struct LoopsRenderer{
+    instructions: BTreeMap<u64, Instruction>,
+    /// The head and the tail of the backward transfers taken.
+    back_edges: BTreeSet<(u64, u64)>,
+    /// The address of the last branch or jump of each hart with the address of the instruction that follows it.
+    pending: HashMap<Option<u32>, (u64, u64)>,
+    total: u64,
+    arch: Arch,
+}
+
+impl LoopsRenderer {
+    pub fn new(arch: Arch) -> LoopsRenderer{
+        LoopsRenderer { arch, ..LoopsRenderer::default() }
+    }
+
+    /**
+     * Measure the loops from the executions of their instructions, from the most expensive.
+     */
+    fn loops(&self) -> Vec<Loop>{
+        let mut loops: Vec<Loop> = self.back_edges.iter().map(|&(head, tail)| {
+            let body: Vec<&Instruction> = self.instructions.range(head..=tail).map(|(_, instruction)| instruction).collect();
+            let instructions = body.iter().map(|instruction| instruction.executions).sum();
+            // The head runs once per iteration, whether it was reached by entering the loop or by looping back.
+            let iterations = self.instructions[&head].executions;
+            let busy_wait = body.iter().all(|instruction| instruction.waits)
+                && instructions as f64 / iterations as f64 <= BUSY_WAIT_INSTRUCTIONS;
+            Loop { head, tail, instructions, iterations, busy_wait }
+        }).collect();
+        loops.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.head.cmp(&b.head)));
+        loops
+    }
+
+    /**
+     * Write a table of loops.
+     */
+    fn write_loops(&self, out: &mut dyn Write, loops: &[&Loop]) -> io::Result<()>{
+        writeln!(out, "{:>21} {:>12} {:>14} {:>7}  location", "addresses", "iterations", "per iteration", "%")?;
+        for lp in loops {
+            writeln!(out, "{:>21} {:>12} {:>14.1} {:>6.2}%  {}", format!("{:x}-{:x}", lp.head, lp.tail), lp.iterations,
+                lp.instructions as f64 / lp.iterations as f64, lp.instructions as f64 * 100.0 / self.total as f64,
+                self.instructions[&lp.head].location)?;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for LoopsRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, _stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        self.total += 1;
+        let class = InstrClass::of(record, self.arch);
+        let transfer = Transfer::of(record, self.arch);
+        let instruction = self.instructions.entry(record.pc).or_insert_with(|| Instruction {
+            executions: 0,
+            location: info.location.header(),
+            waits: !matches!(class, InstrClass::Store | InstrClass::System | InstrClass::Other)
+                && !matches!(transfer, Some(Transfer::Call | Transfer::Return)),
+        });
+        instruction.executions += 1;
+        if let Some((pc, next_pc)) = self.pending.remove(&record.hart) {
+            if record.pc <= pc && record.pc != next_pc {
+                self.back_edges.insert((record.pc, pc));
+            }
+        }
+        if class == InstrClass::Branch || transfer == Some(Transfer::Jump) {
+            self.pending.insert(record.hart, (record.pc, record.pc + isa::record_size(record, self.arch)));
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        let loops = self.loops();
+        let (busy_waits, hot): (Vec<&Loop>, Vec<&Loop>) = loops.iter().partition(|lp| lp.busy_wait);
+        writeln!(out, "{} loops in {} instructions", loops.len(), self.total)?;
+        writeln!(out)?;
+        self.write_loops(out, &hot)?;
+        if !busy_waits.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "Busy-wait loops:")?;
+            self.write_loops(out, &busy_waits)?;
+        }
+        Ok(())
+    }
+}
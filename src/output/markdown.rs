@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::breakpoint::{self, BreakpointHit, WatchHit};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::csr::Csr;
+use crate::gap::TraceGap;
+use crate::output::{RenderOptions, Renderer};
+use crate::symbolizer::SrcLocation;
+use crate::trace::TraceRecord;
+use crate::trap::{TrapCause, TrapEvent};
+
+/// The width of the column of the register writes before the log lines.
+const WRITES_WIDTH: usize = 20;
+
+/// The width of the column of the disassembly before the log lines.
+const DISASSEMBLY_WIDTH: usize = 28;
+
+/**
+ * Write a Markdown document to paste into an issue or a review, with a heading each time the trace enters a function
+ * and a fenced block of the source code followed by a fenced block of the log lines for each source line.
+ */
+#[derive(Debug, Default)]
+pub struct MarkdownRenderer{
+    last_location: Option<SrcLocation>,
+    last_depth: usize,
+    /// The function of the last heading, None before the first one and after a banner.
+    last_function: Option<String>,
+    /// Whether the block of the log lines is open.
+    in_block: bool,
+    options: RenderOptions,
+    /// The number of traps entered per cause and of traps exited, with --traps.
+    traps: BTreeMap<TrapCause, u64>,
+    trap_exits: u64,
+}
+
+impl MarkdownRenderer {
+    /**
+     * Constructor.
+     *
+     * @param options: Whether to write the callers of the functions under their location, the CSRs and the variables
+     * accessed after the instructions and the register writes and the disassembly before them.
+     */
+    pub fn new(options: RenderOptions) -> MarkdownRenderer{
+        MarkdownRenderer { options, ..MarkdownRenderer::default() }
+    }
+
+    fn close_block(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        if self.in_block {
+            writeln!(out, "```")?;
+            self.in_block = false;
+        }
+        Ok(())
+    }
+
+    /**
+     * Write the location of a source line, its call sites and callers and its code, and open the block of its log
+     * lines.
+     */
+    fn write_region(&mut self, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        if let Some(external) = info.symbol.as_ref().filter(|_| info.external) {
+            writeln!(out, "\n{}\n\n```text", code_span(external))?;
+            self.in_block = true;
+            return Ok(());
+        }
+        write!(out, "\n{}", code_span(&info.location.to_string()))?;
+        for call_site in &info.location.inlined_at {
+            write!(out, ", inlined into {}", code_span(&call_site.header()))?;
+        }
+        if self.options.call_stack && stack.len() > 1 {
+            let callers: Vec<String> = stack.iter().rev().skip(1).map(|frame| code_span(&format!("{}()", frame.function))).collect();
+            write!(out, ", called from {}", callers.join(" <- "))?;
+        }
+        writeln!(out, "\n")?;
+        let code: Vec<String> = match (&info.code, info.context.is_empty()) {
+            (_, false) => {
+                let width = info.context.last().map_or(0, |(number, _)| number.to_string().len());
+                info.context.iter().map(|(number, code)| {
+                    let marker = if Some(*number) == info.location.line { ">" } else { " " };
+                    format!("{} {:>width$} | {}", marker, number, code)
+                }).collect()
+            },
+            (Some(code), true) => vec![code.trim().to_string()],
+            (None, true) => Vec::new(),
+        };
+        if let (true, Some(symbol)) = (code.is_empty(), &info.symbol) {
+            writeln!(out, "*Source not found*, at {}\n", code_span(symbol))?;
+        } else if code.is_empty() {
+            writeln!(out, "*Source not found*\n")?;
+        } else {
+            let fence = fence(&code);
+            writeln!(out, "{}{}\n{}\n{}\n", fence, language(info.location.file.as_deref()), code.join("\n"), fence)?;
+        }
+        writeln!(out, "```text")?;
+        self.in_block = true;
+        Ok(())
+    }
+
+    /**
+     * Write a banner as a quote between the blocks, the next record starts a new heading and a new block.
+     */
+    fn write_banner(&mut self, banner: &str, out: &mut dyn Write) -> io::Result<()>{
+        self.close_block(out)?;
+        writeln!(out, "\n> **{}**", banner)?;
+        self.last_location = None;
+        self.last_function = None;
+        Ok(())
+    }
+}
+
+/**
+ * Write a text as inline code, delimited by more backticks than it contains in a row.
+ */
+fn code_span(text: &str) -> String{
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", ticks, text, ticks)
+    } else {
+        format!("{}{}{}", ticks, text, ticks)
+    }
+}
+
+/**
+ * Get the fence of a code block, longer than any run of backticks of its lines.
+ */
+fn fence(lines: &[String]) -> String{
+    let longest = lines.iter().map(|line| longest_backtick_run(line)).max().unwrap_or(0);
+    "`".repeat((longest + 1).max(3))
+}
+
+fn longest_backtick_run(text: &str) -> usize{
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/**
+ * Get the language of the code block of a source file, from its extension, for the highlighting of GitHub.
+ */
+fn language(file: Option<&str>) -> &'static str{
+    match file.and_then(|file| Path::new(file).extension()).and_then(|extension| extension.to_str()) {
+        Some("rs") => "rust",
+        Some("c" | "h") => "c",
+        Some("cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx") => "cpp",
+        Some("s" | "S") => "asm",
+        _ => "",
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        writeln!(out, "# Annotated trace")
+    }
+
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let function = info.location.outer_function().unwrap_or("??");
+        if self.last_function.as_deref() != Some(function) {
+            self.close_block(out)?;
+            writeln!(out, "\n## {}", code_span(function))?;
+            self.last_function = Some(function.to_string());
+            self.last_location = None;
+        }
+        let moved = self.options.call_stack && self.last_depth != stack.len();
+        if self.last_location.as_ref() != Some(&info.location) || moved {
+            self.close_block(out)?;
+            self.write_region(info, stack, out)?;
+            self.last_location = Some(info.location.clone());
+            self.last_depth = stack.len();
+        }
+        if self.options.writes {
+            let writes: Vec<String> = record.register_writes().iter()
+                .map(|write| format!("{}=0x{:08x}", write.register, write.value)).collect();
+            write!(out, "{:<width$} ", writes.join(" "), width = WRITES_WIDTH)?;
+        }
+        if self.options.disassembly.is_some() {
+            write!(out, "{:<width$} ", self.options.disassembly(record).unwrap_or("??"), width = DISASSEMBLY_WIDTH)?;
+        }
+        let mut notes = Vec::new();
+        if let Some(csr) = if self.options.csr_names { Csr::of(record) } else { None } {
+            notes.push(format!("{}: {}", csr.name, csr.description));
+        }
+        notes.extend(self.options.data_symbol(record));
+        notes.extend(record.notes.iter().cloned());
+        match notes.is_empty() {
+            true => writeln!(out, "{}", record.line),
+            false => writeln!(out, "{}  # {}", record.line, notes.join("; ")),
+        }
+    }
+
+    fn trap(&mut self, event: &TrapEvent, _record: &TraceRecord, out: &mut dyn Write) -> io::Result<()>{
+        let banner = match event {
+            TrapEvent::Entry { cause, from } => {
+                *self.traps.entry(*cause).or_default() += 1;
+                format!("Trap entry ({}) from 0x{:x}", cause, from)
+            },
+            TrapEvent::Exit { instruction, from } => {
+                self.trap_exits += 1;
+                format!("Trap exit ({}) from 0x{:x}", instruction, from)
+            },
+        };
+        self.write_banner(&banner, out)
+    }
+
+    fn gap(&mut self, gap: &TraceGap, _record: &TraceRecord, out: &mut dyn Write) -> io::Result<()>{
+        self.write_banner(&format!("Gap: {}, trace lines may be missing", gap), out)
+    }
+
+    fn hit(&mut self, hit: &BreakpointHit, record: &TraceRecord, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        self.write_banner(&hit.describe(record), out)?;
+        let frames: Vec<String> = breakpoint::backtrace(record, stack).iter().map(|frame| code_span(frame)).collect();
+        writeln!(out, ">\n> {}", frames.join("<br>"))
+    }
+
+    fn watch(&mut self, hit: &WatchHit, record: &TraceRecord, out: &mut dyn Write) -> io::Result<()>{
+        self.write_banner(&hit.describe(record), out)
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        self.close_block(out)?;
+        if self.traps.is_empty() && self.trap_exits == 0 {
+            return Ok(());
+        }
+        writeln!(out, "\n## Traps\n\n{} entered, {} exited\n\n| Cause | Count |\n| --- | ---: |",
+            self.traps.values().sum::<u64>(), self.trap_exits)?;
+        for (cause, count) in &self.traps {
+            writeln!(out, "| {} | {} |", cause, count)?;
+        }
+        Ok(())
+    }
+}
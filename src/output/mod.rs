@@ -0,0 +1,375 @@
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::breakpoint::{BreakpointHit, WatchHit};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::elf::{Disassembly, SymbolMap};
+use crate::isa::Arch;
+use crate::trace::TraceRecord;
+use crate::gap::TraceGap;
+use crate::trap::TrapEvent;
+
+pub mod branches;
+pub mod calls;
+pub mod chrome;
+pub mod coverage;
+pub mod csv;
+pub mod dot;
+pub mod folded;
+pub mod harts;
+pub mod html;
+pub mod lcov;
+pub mod loops;
+pub mod markdown;
+pub mod json;
+pub mod perf;
+pub mod profile;
+pub mod speedscope;
+pub mod split;
+pub mod stack;
+pub mod stats;
+pub mod text;
+
+/**
+ * The formats the annotated trace can be written in.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat{
+    /// The trace lines interleaved with the source code.
+    #[default]
+    Text,
+    /// An array with one object per traced instruction.
+    Json,
+    /// One json object per traced instruction and per line, written as each instruction is annotated.
+    Jsonl,
+    /// One row per traced instruction.
+    Csv,
+    /// A self-contained page with collapsible source blocks.
+    Html,
+    /// A document with a heading per function and fenced blocks of the source and of the trace lines.
+    Markdown,
+    /// The trace lines under the source locations written as file:line: code, like the diagnostics of a compiler,
+    /// so the editors and the terminals open them on a click.
+    Quickfix,
+    /// The histogram of the classes of the executed instructions, overall and per function.
+    Stats,
+    /// The instructions and the cycles spent in each function, from the most expensive.
+    Profile,
+    /// The profile as a json object.
+    ProfileJson,
+    /// The folded call stacks of flamegraph.pl, with the cycles or the instructions spent in each.
+    Folded,
+    /// The samples of perf script with the call stacks, for the FlameGraph tools and the converters of perf.
+    PerfScript,
+    /// The calls as the slices of a timeline per hart in the Chrome trace event format, for ui.perfetto.dev.
+    ChromeTrace,
+    /// The calls as an evented profile per hart for speedscope.app.
+    Speedscope,
+    /// The graph of the calls between the functions in the Graphviz dot language.
+    Dot,
+    /// The share of the instructions of each function of the elfs that was executed.
+    Coverage,
+    /// The line coverage as an lcov tracefile for genhtml.
+    Lcov,
+    /// How many times each conditional branch was taken and not taken.
+    Branches,
+    /// The maximum stack usage of each hart and the call chain that reached it.
+    Stack,
+    /// The loops that executed the most instructions, with the busy-waits apart.
+    Loops,
+    /// The instructions of the harts side by side, one column per hart.
+    Harts,
+}
+
+impl OutputFormat {
+    /// All the formats.
+    pub const ALL: [OutputFormat; 21] = [OutputFormat::Text, OutputFormat::Json, OutputFormat::Jsonl, OutputFormat::Csv,
+        OutputFormat::Html, OutputFormat::Markdown, OutputFormat::Quickfix, OutputFormat::Stats, OutputFormat::Profile,
+        OutputFormat::ProfileJson, OutputFormat::Folded, OutputFormat::PerfScript, OutputFormat::ChromeTrace, OutputFormat::Speedscope,
+        OutputFormat::Dot, OutputFormat::Coverage, OutputFormat::Lcov, OutputFormat::Branches, OutputFormat::Stack, OutputFormat::Loops, OutputFormat::Harts];
+
+    /**
+     * Get the name of the format, as accepted by from_str.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Html => "html",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Quickfix => "quickfix",
+            OutputFormat::Stats => "stats",
+            OutputFormat::Profile => "profile",
+            OutputFormat::ProfileJson => "profile-json",
+            OutputFormat::Folded => "folded",
+            OutputFormat::PerfScript => "perf-script",
+            OutputFormat::ChromeTrace => "chrome-trace",
+            OutputFormat::Speedscope => "speedscope",
+            OutputFormat::Dot => "dot",
+            OutputFormat::Coverage => "coverage",
+            OutputFormat::Lcov => "lcov",
+            OutputFormat::Branches => "branches",
+            OutputFormat::Stack => "stack",
+            OutputFormat::Loops => "loops",
+            OutputFormat::Harts => "harts",
+        }
+    }
+
+    /**
+     * Get the names of all the formats.
+     */
+    pub fn names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|format| format.name()).collect()
+    }
+
+    /**
+     * Get the extension of the files written in this format, without the dot.
+     */
+    pub fn extension(&self) -> &'static str{
+        match self {
+            OutputFormat::Json | OutputFormat::ProfileJson | OutputFormat::ChromeTrace | OutputFormat::Speedscope => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Html => "html",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Folded => "folded",
+            OutputFormat::PerfScript => "perf",
+            OutputFormat::Dot => "dot",
+            OutputFormat::Lcov => "info",
+            OutputFormat::Text | OutputFormat::Quickfix | OutputFormat::Stats | OutputFormat::Profile | OutputFormat::Coverage
+                | OutputFormat::Branches | OutputFormat::Stack | OutputFormat::Loops | OutputFormat::Harts => "txt",
+        }
+    }
+
+    /**
+     * Create the renderer that writes this format.
+     *
+     * @param options: What the annotated formats show besides the source information.
+     */
+    pub fn renderer(&self, options: RenderOptions) -> Box<dyn Renderer>{
+        match self {
+            OutputFormat::Text => Box::new(text::TextRenderer::new(options)),
+            OutputFormat::Json => Box::new(json::JsonRenderer::new(options)),
+            OutputFormat::Jsonl => Box::new(json::JsonRenderer::lines(options)),
+            OutputFormat::Csv => Box::new(csv::CsvRenderer::new(options)),
+            OutputFormat::Html => Box::new(html::HtmlRenderer::new(options)),
+            OutputFormat::Markdown => Box::new(markdown::MarkdownRenderer::new(options)),
+            OutputFormat::Quickfix => Box::new(text::TextRenderer::clickable(options)),
+            OutputFormat::Stats => Box::new(stats::StatsRenderer::new(options.arch)),
+            OutputFormat::Profile => Box::new(profile::ProfileRenderer::new(false)),
+            OutputFormat::ProfileJson => Box::new(profile::ProfileRenderer::new(true)),
+            OutputFormat::Folded => Box::new(folded::FoldedRenderer::new()),
+            OutputFormat::PerfScript => Box::new(perf::PerfScriptRenderer::new()),
+            OutputFormat::ChromeTrace => Box::new(chrome::ChromeTraceRenderer::new()),
+            OutputFormat::Speedscope => Box::new(speedscope::SpeedscopeRenderer::new()),
+            OutputFormat::Dot => Box::new(dot::DotRenderer::new()),
+            // The functions of the elfs are given by the debugger once it reads them.
+            OutputFormat::Coverage => Box::new(coverage::CoverageRenderer::new(Vec::new())),
+            OutputFormat::Lcov => Box::new(lcov::LcovRenderer::new(Vec::new(), Default::default())),
+            OutputFormat::Branches => Box::new(branches::BranchesRenderer::new(options.arch)),
+            OutputFormat::Stack => Box::new(stack::StackRenderer::new()),
+            OutputFormat::Loops => Box::new(loops::LoopsRenderer::new(options.arch)),
+            OutputFormat::Harts => Box::new(harts::HartsRenderer::new(options)),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+            .ok_or_else(|| format!("Unknown output format {}, expected one of {}", name, Self::names().join(", ")))
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * What the annotated formats, text, quickfix, json, jsonl, csv, html and markdown, show besides the source information.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RenderOptions{
+    /// Highlight the annotations with ANSI colors, only used by the text and the quickfix formats.
+    pub color: bool,
+    /// Show the depth and the callers of the instructions.
+    pub call_stack: bool,
+    /// Show the name and the description of the CSRs the instructions access.
+    pub csr_names: bool,
+    /// Show the registers written by the instructions.
+    pub writes: bool,
+    /// The data symbols of the elfs, to show the variables the loads and the stores access.
+    pub data_symbols: Option<Arc<SymbolMap>>,
+    /// The disassembly of the elfs, to show the executed instructions as objdump decodes them. The instructions of
+    /// the log, or the ones decoded from its words, are shown for the addresses it doesn't have.
+    pub disassembly: Option<Arc<Disassembly>>,
+    /// The architecture of the harts, to decode the instruction words.
+    pub arch: Arch,
+}
+
+impl RenderOptions {
+    /**
+     * Get the variable a record loads or stores, with the data symbols.
+     *
+     * @return The symbol and its offset, i.e. BUFFER+0x10, None if the address isn't known or isn't in a symbol.
+     */
+    pub fn data_symbol(&self, record: &TraceRecord) -> Option<String>{
+        self.data_symbols.as_ref()?.symbolize(record.data_address()?)
+    }
+
+    /**
+     * Get the instruction of a record as disassembled from the elfs, or else as the log has it.
+     *
+     * @return The mnemonic and the operands, None if the disassembly isn't shown or the instruction isn't known.
+     */
+    pub fn disassembly<'a>(&'a self, record: &'a TraceRecord) -> Option<&'a str>{
+        self.disassembly.as_ref()?.get(record.pc).or(record.instruction.as_deref())
+    }
+}
+
+/**
+ * When to highlight the annotations with ANSI colors.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice{
+    /// Only when the output is a terminal and NO_COLOR isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// All the choices.
+    pub const ALL: [ColorChoice; 3] = [ColorChoice::Auto, ColorChoice::Always, ColorChoice::Never];
+
+    /**
+     * Get the name of the choice, as accepted by from_str.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        }
+    }
+
+    /**
+     * Get the names of all the choices.
+     */
+    pub fn names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|choice| choice.name()).collect()
+    }
+
+    /**
+     * Decide whether to use colors.
+     *
+     * @param out: The stream the output is written to.
+     * @return True if the output should be colorized.
+     */
+    pub fn enabled(&self, out: &impl IsTerminal) -> bool{
+        match self {
+            ColorChoice::Auto => out.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|choice| choice.name() == name)
+            .ok_or_else(|| format!("Unknown color choice {}, expected one of {}", name, Self::names().join(", ")))
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * Write the annotated records in one output format.
+ */
+pub trait Renderer {
+    /**
+     * Write what comes before the first record.
+     */
+    fn begin(&mut self, _out: &mut dyn Write) -> io::Result<()>{
+        Ok(())
+    }
+
+    /**
+     * Write one traced instruction with its source information.
+     *
+     * @param record: The instruction parsed from the log.
+     * @param info: The source information of the instruction address.
+     * @param stack: The call stack of the hart from the outermost function, the last frame is the function of the
+     * instruction.
+     * @param out: The output.
+     */
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>;
+
+    /**
+     * Mark a trap entry or exit, called with --traps right before the record that follows the boundary.
+     *
+     * @param event: The boundary.
+     * @param record: The first instruction after the boundary.
+     * @param out: The output.
+     */
+    fn trap(&mut self, _event: &TrapEvent, _record: &TraceRecord, _out: &mut dyn Write) -> io::Result<()>{
+        Ok(())
+    }
+
+    /**
+     * Mark a gap of the trace, called with --gaps right before the first record shown after it.
+     *
+     * @param gap: The gap.
+     * @param record: The first instruction shown after the gap.
+     * @param out: The output.
+     */
+    fn gap(&mut self, _gap: &TraceGap, _record: &TraceRecord, _out: &mut dyn Write) -> io::Result<()>{
+        Ok(())
+    }
+
+    /**
+     * Mark an execution of a breakpoint, called with --break-at right before its first record.
+     *
+     * @param hit: The breakpoint and how many times it was executed.
+     * @param record: The first instruction of the execution.
+     * @param stack: The call stack of the hart at the instruction, from the outermost function.
+     * @param out: The output.
+     */
+    fn hit(&mut self, _hit: &BreakpointHit, _record: &TraceRecord, _stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        Ok(())
+    }
+
+    /**
+     * Mark a load or a store of a watchpoint, called with --watch-data right before the record that accesses it.
+     *
+     * @param hit: The watchpoint, the access and how many times it was accessed.
+     * @param record: The instruction that accesses it.
+     * @param out: The output.
+     */
+    fn watch(&mut self, _hit: &WatchHit, _record: &TraceRecord, _out: &mut dyn Write) -> io::Result<()>{
+        Ok(())
+    }
+
+    /**
+     * Write what comes after the last record.
+     */
+    fn end(&mut self, _out: &mut dyn Write) -> io::Result<()>{
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/// The command of the samples, the simulated program has no process name.
+const COMM: &str = "verilator";
+
+/**
+ * A traced instruction waiting for the next instruction of its hart to know how many cycles it took.
+ */
+#[derive(Debug)]
+struct Sample{
+    cycle: u64,
+    /// The address of each frame of the call stack from the innermost, the instruction then the calls, with its
+    /// function.
+    frames: Vec<(u64, String)>,
+}
+
+/**
+ * Write the instructions as the samples of perf script, so stackcollapse-perf.pl, the FlameGraph tools and the
+ * converters of perf reports read the trace like a profile of a Linux program.
+ *
+ * Each sample has the hart as its thread and cpu, the cycle as its timestamp in microseconds and the call stack as its
+ * callchain. When the log has cycles the event is cycles and the period of a sample is the cycles until the next
+ * instruction of its hart, otherwise the event is instructions with a period of 1.
+ */
+#[derive(Debug, Default)]
+pub struct PerfScriptRenderer{
+    /// The last instruction of each hart, with cycles.
+    pending: BTreeMap<Option<u32>, Sample>,
+}
+
+impl PerfScriptRenderer {
+    pub fn new() -> PerfScriptRenderer{
+        PerfScriptRenderer::default()
+    }
+}
+
+/**
+ * Write a sample, its header line and one line per frame of its callchain.
+ *
+ * @param timestamp: The cycle or the time of the sample, written as microseconds.
+ */
+fn write_sample(hart: Option<u32>, timestamp: u64, period: u64, event: &str, frames: &[(u64, String)], out: &mut dyn Write) -> io::Result<()>{
+    let hart = hart.unwrap_or(0);
+    writeln!(out, "{} {:>5} [{:03}] {:>5}.{:06}: {:>10} {}:", COMM, hart, hart, timestamp / 1_000_000, timestamp % 1_000_000, period, event)?;
+    for (addr, function) in frames {
+        writeln!(out, "\t{:>16x} {} ([unknown])", addr, function)?;
+    }
+    writeln!(out)
+}
+
+impl Renderer for PerfScriptRenderer {
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        // A function was entered from the call in its caller, the callers are sampled at their calls.
+        let addrs = std::iter::once(record.pc).chain(stack.iter().rev().map(|frame| frame.call_pc.unwrap_or(0)));
+        let frames: Vec<(u64, String)> = addrs.zip(stack.iter().rev()).map(|(addr, frame)| (addr, frame.function.clone())).collect();
+        let Some(cycle) = record.cycle else {
+            return write_sample(record.hart, record.time.unwrap_or(0), 1, "instructions", &frames, out);
+        };
+        if let Some(last) = self.pending.insert(record.hart, Sample { cycle, frames }) {
+            let period = cycle.saturating_sub(last.cycle);
+            // The instructions retired in the same cycle as the next one cost nothing.
+            if period > 0 {
+                write_sample(record.hart, last.cycle, period, "cycles", &last.frames, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        // The last instruction of each hart is counted as one cycle, nothing follows it.
+        for (hart, last) in std::mem::take(&mut self.pending) {
+            write_sample(hart, last.cycle, 1, "cycles", &last.frames, out)?;
+        }
+        Ok(())
+    }
+}
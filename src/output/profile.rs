@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use serde::Serialize;
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * The instructions and the cycles spent in a function.
+ */
+#[derive(Debug, Default, Clone, Copy)]
+struct Cost{
+    instructions: u64,
+    cycles: u64,
+}
+
+/**
+ * Write the instructions and the cycles spent in each function, from the most expensive, at the end.
+ *
+ * The cycles of an instruction are the cycles until the next traced one, and the inlined code counts in the function
+ * it was inlined into.
+ */
+#[derive(Debug, Default)]
+pub struct ProfileRenderer{
+    json: bool,
+    functions: HashMap<String, Cost>,
+    /// The function and the cycle of the last record, waiting for the next cycle to know its cost.
+    last: Option<(String, u64)>,
+}
+
+/**
+ * The json object of the whole profile.
+ */
+#[derive(Serialize)]
+struct JsonProfile<'a>{
+    instructions: u64,
+    /// None when the log has no cycles.
+    cycles: Option<u64>,
+    functions: Vec<JsonFunction<'a>>,
+}
+
+/**
+ * The json object of the cost of a function.
+ */
+#[derive(Serialize)]
+struct JsonFunction<'a>{
+    function: &'a str,
+    instructions: u64,
+    instructions_percent: f64,
+    cycles: Option<u64>,
+    cycles_percent: Option<f64>,
+}
+
+impl ProfileRenderer {
+    /**
+     * Constructor.
+     *
+     * @param json: Whether to write the profile as json instead of a text table.
+     */
+    pub fn new(json: bool) -> ProfileRenderer{
+        ProfileRenderer { json, ..ProfileRenderer::default() }
+    }
+
+    /**
+     * Get the functions from the most expensive, by cycles when the log has them or by instructions.
+     */
+    fn sorted(&self) -> Vec<(&String, Cost)>{
+        let mut functions: Vec<(&String, Cost)> = self.functions.iter().map(|(name, cost)| (name, *cost)).collect();
+        functions.sort_by(|(a_name, a), (b_name, b)| (b.cycles, b.instructions).cmp(&(a.cycles, a.instructions))
+            .then(a_name.cmp(b_name)));
+        functions
+    }
+
+    fn write_text(&self, out: &mut dyn Write, total: Cost, has_cycles: bool) -> io::Result<()>{
+        if has_cycles {
+            writeln!(out, "Profile of {} instructions in {} cycles", total.instructions, total.cycles)?;
+            writeln!(out)?;
+            writeln!(out, "{:>12} {:>7} {:>12} {:>7}  function", "instructions", "%", "cycles", "%")?;
+        } else {
+            writeln!(out, "Profile of {} instructions", total.instructions)?;
+            writeln!(out)?;
+            writeln!(out, "{:>12} {:>7}  function", "instructions", "%")?;
+        }
+        for (name, cost) in self.sorted() {
+            write!(out, "{:>12} {:>6.2}%", cost.instructions, percent(cost.instructions, total.instructions))?;
+            if has_cycles {
+                write!(out, " {:>12} {:>6.2}%", cost.cycles, percent(cost.cycles, total.cycles))?;
+            }
+            writeln!(out, "  {}", name)?;
+        }
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut dyn Write, total: Cost, has_cycles: bool) -> io::Result<()>{
+        let profile = JsonProfile {
+            instructions: total.instructions,
+            cycles: Some(total.cycles).filter(|_| has_cycles),
+            functions: self.sorted().into_iter().map(|(name, cost)| JsonFunction {
+                function: name,
+                instructions: cost.instructions,
+                instructions_percent: percent(cost.instructions, total.instructions),
+                cycles: Some(cost.cycles).filter(|_| has_cycles),
+                cycles_percent: Some(percent(cost.cycles, total.cycles)).filter(|_| has_cycles),
+            }).collect(),
+        };
+        serde_json::to_writer_pretty(&mut *out, &profile)?;
+        writeln!(out)
+    }
+}
+
+/**
+ * Get the share of a count in a total, 0 when the total is 0.
+ */
+fn percent(count: u64, total: u64) -> f64{
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl Renderer for ProfileRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, _stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        let function = info.location.outer_function().unwrap_or("??").to_string();
+        if let (Some((last_function, last_cycle)), Some(cycle)) = (self.last.take(), record.cycle) {
+            self.functions.entry(last_function).or_default().cycles += cycle.saturating_sub(last_cycle);
+        }
+        self.functions.entry(function.clone()).or_default().instructions += 1;
+        self.last = record.cycle.map(|cycle| (function, cycle));
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        let total = self.functions.values().fold(Cost::default(), |total, cost| Cost {
+            instructions: total.instructions + cost.instructions,
+            cycles: total.cycles + cost.cycles,
+        });
+        let has_cycles = total.cycles > 0;
+        if self.json {
+            self.write_json(out, total, has_cycles)
+        } else {
+            self.write_text(out, total, has_cycles)
+        }
+    }
+}
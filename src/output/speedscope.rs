@@ -0,0 +1,101 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use serde_json::{json, Value};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * The evented profile of a hart, the open and close events of its calls.
+ */
+#[derive(Debug, Default)]
+struct HartProfile{
+    /// The frames the hart is in, from the outermost.
+    frames: Vec<Frame>,
+    events: Vec<Value>,
+    start: Option<u64>,
+    last: u64,
+    /// The number of instructions, the timestamps of the logs without cycles nor times.
+    count: u64,
+}
+
+impl HartProfile {
+    /**
+     * Close the frames above a depth, from the innermost.
+     *
+     * @param indexes: The index of each function in the shared frames.
+     */
+    fn close(&mut self, depth: usize, at: u64, indexes: &HashMap<String, usize>){
+        for frame in self.frames.split_off(depth).iter().rev() {
+            self.events.push(json!({ "type": "C", "frame": indexes[&frame.function], "at": at }));
+        }
+    }
+}
+
+/**
+ * Write the calls as a speedscope file, with an evented profile per hart, for the time order, left heavy and sandwich
+ * views of speedscope.app.
+ *
+ * The values are the cycles, or the times for the logs without cycles, else the instructions. The profiles are
+ * written at the end, the calls of each hart are kept until then.
+ */
+#[derive(Debug, Default)]
+pub struct SpeedscopeRenderer{
+    /// The functions shared by the profiles, in the order they were first called.
+    functions: Vec<String>,
+    indexes: HashMap<String, usize>,
+    harts: BTreeMap<Option<u32>, HartProfile>,
+}
+
+impl SpeedscopeRenderer {
+    pub fn new() -> SpeedscopeRenderer{
+        SpeedscopeRenderer::default()
+    }
+}
+
+impl Renderer for SpeedscopeRenderer {
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        let profile = self.harts.entry(record.hart).or_default();
+        profile.count += 1;
+        let at = record.cycle.or(record.time).unwrap_or(profile.count);
+        profile.start.get_or_insert(at);
+        profile.last = at;
+        let common = profile.frames.iter().zip(stack).take_while(|(open, frame)| open == frame).count();
+        profile.close(common, at, &self.indexes);
+        for frame in &stack[common..] {
+            let index = *self.indexes.entry(frame.function.clone()).or_insert_with(|| {
+                self.functions.push(frame.function.clone());
+                self.functions.len() - 1
+            });
+            profile.events.push(json!({ "type": "O", "frame": index, "at": at }));
+            profile.frames.push(frame.clone());
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        let mut profiles = Vec::new();
+        for (hart, mut profile) in std::mem::take(&mut self.harts) {
+            // The calls still open end after the last instruction of their hart.
+            let end = profile.last + 1;
+            profile.close(0, end, &self.indexes);
+            profiles.push(json!({
+                "type": "evented",
+                "name": format!("hart {}", hart.unwrap_or(0)),
+                "unit": "none",
+                "startValue": profile.start.unwrap_or(0),
+                "endValue": end,
+                "events": profile.events,
+            }));
+        }
+        let file = json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "exporter": concat!("varilator_debugger ", env!("CARGO_PKG_VERSION")),
+            "shared": { "frames": self.functions.iter().map(|name| json!({ "name": name })).collect::<Vec<Value>>() },
+            "profiles": profiles,
+        });
+        serde_json::to_writer(&mut *out, &file)?;
+        writeln!(out)
+    }
+}
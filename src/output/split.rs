@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::breakpoint::{BreakpointHit, WatchHit};
+use crate::callstack::Frame;
+use crate::compress::{Compression, Encoder};
+use crate::debugger::SrcInfo;
+use crate::gap::TraceGap;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+use crate::trap::TrapEvent;
+
+/// The most files of the functions kept open, the least recently written one is closed to open another.
+const MAX_OPEN_FILES: usize = 64;
+/// The longest name of a file of a function before its extension, the long C++ and Rust names are cut.
+const MAX_FILE_NAME: usize = 100;
+/// The name of the index file in the output directory, no function file gets it.
+pub const INDEX_FILE: &str = "index.txt";
+
+/**
+ * How the annotated output is split into files.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitBy{
+    /// One file per executed function, with the code inlined into it.
+    Function,
+}
+
+impl SplitBy {
+    /// All the splits.
+    pub const ALL: [SplitBy; 1] = [SplitBy::Function];
+
+    /**
+     * Get the name of the split, as accepted by from_str.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            SplitBy::Function => "function",
+        }
+    }
+
+    /**
+     * Get the names of all the splits.
+     */
+    pub fn names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|split| split.name()).collect()
+    }
+}
+
+impl FromStr for SplitBy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|split| split.name() == name)
+            .ok_or_else(|| format!("Unknown split {}, expected one of {}", name, Self::names().join(", ")))
+    }
+}
+
+impl fmt::Display for SplitBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * The file of one function and the renderer writing it.
+ */
+struct Part{
+    function: String,
+    file_name: String,
+    renderer: Box<dyn Renderer>,
+    /// The open file, None once it's closed to open another, it's appended to when it's written again.
+    out: Option<Encoder<BufWriter<File>>>,
+    instructions: u64,
+    /// When the file was last written, to close the least recently written one.
+    last_write: u64,
+}
+
+/**
+ * A boundary waiting for the next record, it goes into the file of the function of the record.
+ */
+enum Pending{
+    Trap(TrapEvent, TraceRecord),
+    Gap(TraceGap, TraceRecord),
+    Hit(BreakpointHit, TraceRecord, Vec<Frame>),
+    Watch(WatchHit, TraceRecord),
+}
+
+/**
+ * Write the records of each function into its own file of a directory with another renderer, and the index of the
+ * files to the output.
+ *
+ * The function of a record is the one its address is in, the code inlined into a function goes into its file. The
+ * index lists the files in the order the functions were first executed.
+ */
+pub struct SplitRenderer{
+    dir: PathBuf,
+    compression: Option<Compression>,
+    /// The extension of the files, of the format of the renderers.
+    extension: String,
+    new_renderer: Box<dyn Fn() -> Box<dyn Renderer>>,
+    parts: Vec<Part>,
+    /// The index of the part of each function.
+    functions: HashMap<String, usize>,
+    file_names: HashSet<String>,
+    open: usize,
+    writes: u64,
+    pending: Vec<Pending>,
+}
+
+impl SplitRenderer {
+    /**
+     * Constructor.
+     *
+     * @param dir: The directory that receives the files, it must exist.
+     * @param compression: The compression of the files, None to write them as is.
+     * @param extension: The extension of the files, without the dot and the one of the compression.
+     * @param new_renderer: Create the renderer of a file.
+     */
+    pub fn new(dir: impl Into<PathBuf>, compression: Option<Compression>, extension: &str, new_renderer: Box<dyn Fn() -> Box<dyn Renderer>>) -> SplitRenderer{
+        SplitRenderer {
+            dir: dir.into(),
+            compression,
+            extension: extension.to_string(),
+            new_renderer,
+            parts: Vec::new(),
+            functions: HashMap::new(),
+            file_names: HashSet::from([INDEX_FILE.to_string()]),
+            open: 0,
+            writes: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /**
+     * Get the part of a function, its file is created the first time.
+     */
+    fn part(&mut self, function: &str) -> io::Result<usize>{
+        if let Some(&index) = self.functions.get(function) {
+            return Ok(index);
+        }
+        let file_name = self.file_name(function);
+        let out = self.open(&file_name, false)?;
+        self.parts.push(Part {
+            function: function.to_string(),
+            file_name,
+            renderer: (self.new_renderer)(),
+            out: Some(out),
+            instructions: 0,
+            last_write: 0,
+        });
+        let index = self.parts.len() - 1;
+        self.functions.insert(function.to_string(), index);
+        let (renderer, out) = self.writer(index)?;
+        renderer.begin(out)?;
+        Ok(index)
+    }
+
+    /**
+     * Choose a file name for a function that no other function has, with the characters that are safe in a path.
+     */
+    fn file_name(&mut self, function: &str) -> String{
+        let mut stem: String = function.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') { c } else { '_' })
+            .take(MAX_FILE_NAME).collect();
+        if stem.is_empty() || stem.starts_with('.') {
+            stem.insert(0, '_');
+        }
+        let suffix = match self.compression {
+            Some(compression) => format!("{}.{}", self.extension, compression.name()),
+            None => self.extension.clone(),
+        };
+        let mut file_name = format!("{}.{}", stem, suffix);
+        let mut count = 1;
+        while !self.file_names.insert(file_name.clone()) {
+            count += 1;
+            file_name = format!("{}_{}.{}", stem, count, suffix);
+        }
+        file_name
+    }
+
+    /**
+     * Open the file of a part, closing the least recently written one when too many are open.
+     *
+     * @param append: Whether to write at the end of the file, it was closed before, instead of creating it.
+     */
+    fn open(&mut self, file_name: &str, append: bool) -> io::Result<Encoder<BufWriter<File>>>{
+        if self.open >= MAX_OPEN_FILES {
+            if let Some(part) = self.parts.iter_mut().filter(|part| part.out.is_some()).min_by_key(|part| part.last_write) {
+                // Each compressed file is a sequence of gzip members or zstd frames, one closed after the other.
+                part.out.take().unwrap().finish()?;
+                self.open -= 1;
+            }
+        }
+        let path = self.dir.join(file_name);
+        let file = match append {
+            true => OpenOptions::new().append(true).open(&path),
+            false => File::create(&path),
+        }.map_err(|err| io::Error::new(err.kind(), format!("{}, {}", path.display(), err)))?;
+        self.open += 1;
+        Ok(Encoder::new(self.compression, BufWriter::new(file)))
+    }
+
+    /**
+     * Get the renderer of a part and the stream of its file, reopened if it was closed.
+     */
+    fn writer(&mut self, index: usize) -> io::Result<(&mut dyn Renderer, &mut dyn Write)>{
+        if self.parts[index].out.is_none() {
+            let file_name = self.parts[index].file_name.clone();
+            let out = self.open(&file_name, true)?;
+            self.parts[index].out = Some(out);
+        }
+        self.writes += 1;
+        let part = &mut self.parts[index];
+        part.last_write = self.writes;
+        Ok((part.renderer.as_mut(), part.out.as_mut().unwrap()))
+    }
+}
+
+impl Renderer for SplitRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        let index = self.part(info.location.outer_function().unwrap_or("??"))?;
+        let pending = std::mem::take(&mut self.pending);
+        let (renderer, out) = self.writer(index)?;
+        for boundary in &pending {
+            match boundary {
+                Pending::Trap(event, record) => renderer.trap(event, record, out)?,
+                Pending::Gap(gap, record) => renderer.gap(gap, record, out)?,
+                Pending::Hit(hit, record, stack) => renderer.hit(hit, record, stack, out)?,
+                Pending::Watch(hit, record) => renderer.watch(hit, record, out)?,
+            }
+        }
+        renderer.record(record, info, stack, out)?;
+        self.parts[index].instructions += 1;
+        Ok(())
+    }
+
+    fn trap(&mut self, event: &TrapEvent, record: &TraceRecord, _out: &mut dyn Write) -> io::Result<()>{
+        self.pending.push(Pending::Trap(event.clone(), record.clone()));
+        Ok(())
+    }
+
+    fn gap(&mut self, gap: &TraceGap, record: &TraceRecord, _out: &mut dyn Write) -> io::Result<()>{
+        self.pending.push(Pending::Gap(gap.clone(), record.clone()));
+        Ok(())
+    }
+
+    fn hit(&mut self, hit: &BreakpointHit, record: &TraceRecord, stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        self.pending.push(Pending::Hit(hit.clone(), record.clone(), stack.to_vec()));
+        Ok(())
+    }
+
+    fn watch(&mut self, hit: &WatchHit, record: &TraceRecord, _out: &mut dyn Write) -> io::Result<()>{
+        self.pending.push(Pending::Watch(hit.clone(), record.clone()));
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        for index in 0..self.parts.len() {
+            let (renderer, file) = self.writer(index)?;
+            renderer.end(file)?;
+            self.parts[index].out.take().unwrap().finish()?;
+            self.open -= 1;
+        }
+        let width = self.parts.iter().map(|part| part.file_name.len()).max().unwrap_or(0).max("File".len());
+        writeln!(out, "{:>12}  {:<width$}  Function", "Instructions", "File")?;
+        for part in &self.parts {
+            writeln!(out, "{:>12}  {:<width$}  {}", part.instructions, part.file_name, part.function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::isa;
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/**
+ * The stack pointer of a hart.
+ */
+#[derive(Debug, Default)]
+struct HartStack{
+    /// The stack pointer, relative to the one the trace started with until the log shows a value of sp.
+    sp: i64,
+    /// The highest stack pointer seen, the usage is how far sp is below it.
+    top: i64,
+    /// True once the log showed a value of sp.
+    absolute: bool,
+    peak: Option<Peak>,
+}
+
+/**
+ * The deepest point of the stack of a hart.
+ */
+#[derive(Debug)]
+struct Peak{
+    usage: u64,
+    /// The cycle, or the time, of the instruction that reached it.
+    when: Option<(&'static str, u64)>,
+    pc: u64,
+    /// The value of sp, None if the log doesn't show it.
+    sp: Option<u64>,
+    /// The functions of the call stack from the outermost one.
+    chain: Vec<String>,
+}
+
+/**
+ * Follow the stack pointer of the harts and write the maximum stack usage with the call chain that produced it, at the
+ * end.
+ *
+ * The writes of sp are used when the log has the register writes, the addi sp,sp,N of the prologues and the
+ * epilogues otherwise. The usage is measured from the highest sp seen, so a switch to another stack, i.e. the stack
+ * of a trap handler or of another task, counts as a usage of the distance between them.
+ */
+#[derive(Debug, Default)]
+pub struct StackRenderer{
+    harts: BTreeMap<Option<u32>, HartStack>,
+    /// The maximum usage reached while each function was running.
+    functions: HashMap<String, u64>,
+}
+
+impl StackRenderer {
+    pub fn new() -> StackRenderer{
+        StackRenderer::default()
+    }
+}
+
+impl Renderer for StackRenderer {
+    fn record(&mut self, record: &TraceRecord, _info: &SrcInfo, stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        let writes = record.register_writes();
+        let hart = self.harts.entry(record.hart).or_default();
+        if writes.is_empty() {
+            let Some(adjustment) = isa::stack_adjustment(record) else {
+                return Ok(());
+            };
+            hart.sp += adjustment;
+        } else {
+            let Some(write) = writes.iter().find(|write| write.register == "x2") else {
+                return Ok(());
+            };
+            if !hart.absolute {
+                // The usage tracked from the relative sp is kept, from the sp before the first adjustment if it's
+                // the first write.
+                let before = write.value as i64 - isa::stack_adjustment(record).unwrap_or(0);
+                hart.top = before + (hart.top - hart.sp);
+                hart.absolute = true;
+            }
+            hart.sp = write.value as i64;
+        }
+        hart.top = hart.top.max(hart.sp);
+        let usage = (hart.top - hart.sp) as u64;
+        if let Some(frame) = stack.last() {
+            let function = self.functions.entry(frame.function.clone()).or_default();
+            *function = usage.max(*function);
+        }
+        if hart.peak.as_ref().is_none_or(|peak| usage > peak.usage) {
+            hart.peak = Some(Peak {
+                usage,
+                when: record.cycle.map(|cycle| ("cycle", cycle)).or(record.time.map(|time| ("time", time))),
+                pc: record.pc,
+                sp: Some(hart.sp as u64).filter(|_| hart.absolute),
+                chain: stack.iter().map(|frame| frame.function.clone()).collect(),
+            });
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        if self.harts.values().all(|hart| hart.peak.is_none()) {
+            return writeln!(out, "The trace doesn't move the stack pointer");
+        }
+        for (hart, stack) in &self.harts {
+            let Some(peak) = &stack.peak else {
+                continue;
+            };
+            if let Some(hart) = hart.filter(|_| self.harts.len() > 1) {
+                write!(out, "hart {}: ", hart)?;
+            }
+            write!(out, "Maximum stack usage of {} bytes at 0x{:x}", peak.usage, peak.pc)?;
+            if let Some((unit, when)) = peak.when {
+                write!(out, ", {} {}", unit, when)?;
+            }
+            if let Some(sp) = peak.sp {
+                write!(out, ", sp 0x{:x}", sp)?;
+            }
+            writeln!(out)?;
+            for (depth, function) in peak.chain.iter().enumerate() {
+                writeln!(out, "{:indent$}{}", "", function, indent = 2 * (depth + 1))?;
+            }
+            writeln!(out)?;
+        }
+        let mut functions: Vec<(&String, &u64)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        writeln!(out, "{:>10}  function", "bytes")?;
+        for (function, usage) in functions {
+            writeln!(out, "{:>10}  {}", usage, function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::isa::{Arch, InstrClass};
+use crate::output::Renderer;
+use crate::trace::TraceRecord;
+
+/// The width of the longest bar of the histogram.
+const BAR_WIDTH: u64 = 40;
+
+/// The number of instructions of each class, indexed by InstrClass::index.
+type ClassCounts = [u64; InstrClass::ALL.len()];
+
+/**
+ * Count the executed instructions of each class and write the instruction mix, overall and per function, at the end.
+ *
+ * When the log has cycles, the IPC and the CPI are written too. The cycles of an instruction are the cycles until the
+ * next one of its hart. The inlined code counts in the function it was inlined into, as in the profile.
+ */
+#[derive(Debug, Default)]
+pub struct StatsRenderer{
+    total: ClassCounts,
+    functions: HashMap<String, ClassCounts>,
+    cycles: u64,
+    function_cycles: HashMap<String, u64>,
+    /// The function and the cycle of the last record of each hart, waiting for the next cycle to know its cost.
+    last: HashMap<Option<u32>, (String, u64)>,
+    arch: Arch,
+}
+
+impl StatsRenderer {
+    pub fn new(arch: Arch) -> StatsRenderer{
+        StatsRenderer { arch, ..StatsRenderer::default() }
+    }
+
+    fn write_histogram(&self, out: &mut dyn Write) -> io::Result<()>{
+        let count: u64 = self.total.iter().sum();
+        let max = self.total.iter().copied().max().unwrap_or(0).max(1);
+        writeln!(out, "Instruction mix of {} instructions", count)?;
+        if self.cycles > 0 {
+            writeln!(out, "{} cycles, IPC {:.3}, CPI {:.3}", self.cycles, ipc(count, self.cycles), ipc(self.cycles, count))?;
+        }
+        writeln!(out)?;
+        writeln!(out, "{:<8} {:>12} {:>7}", "class", "count", "%")?;
+        for class in InstrClass::ALL {
+            let n = self.total[class.index()];
+            let bar = "#".repeat((n * BAR_WIDTH).div_ceil(max) as usize);
+            writeln!(out, "{}", format!("{:<8} {:>12} {:>6.1}% {}", class.name(), n, percent(n, count), bar).trim_end())?;
+        }
+        Ok(())
+    }
+
+    fn write_functions(&self, out: &mut dyn Write) -> io::Result<()>{
+        let mut functions: Vec<(&String, &ClassCounts)> = self.functions.iter().collect();
+        functions.sort_by(|(a_name, a), (b_name, b)| b.iter().sum::<u64>().cmp(&a.iter().sum()).then(a_name.cmp(b_name)));
+        let width = functions.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("function".len());
+        writeln!(out)?;
+        writeln!(out, "Per function")?;
+        writeln!(out)?;
+        let has_cycles = self.cycles > 0;
+        write!(out, "{:<width$} {:>12}", "function", "count")?;
+        if has_cycles {
+            write!(out, " {:>12} {:>7} {:>7}", "cycles", "IPC", "CPI")?;
+        }
+        for class in InstrClass::ALL {
+            write!(out, " {:>8}", class.name())?;
+        }
+        writeln!(out)?;
+        for (name, counts) in functions {
+            let count = counts.iter().sum::<u64>();
+            write!(out, "{:<width$} {:>12}", name, count)?;
+            if has_cycles {
+                let cycles = self.function_cycles.get(name).copied().unwrap_or(0);
+                write!(out, " {:>12} {:>7.3} {:>7.3}", cycles, ipc(count, cycles), ipc(cycles, count))?;
+            }
+            for n in counts {
+                write!(out, " {:>8}", n)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Get the share of a count in a total, 0 when the total is 0.
+ */
+fn percent(count: u64, total: u64) -> f64{
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+/**
+ * Get the ratio of two counts, the instructions per cycle or the cycles per instruction, 0 when the divisor is 0.
+ */
+fn ipc(count: u64, per: u64) -> f64{
+    if per == 0 {
+        0.0
+    } else {
+        count as f64 / per as f64
+    }
+}
+
+impl Renderer for StatsRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, _stack: &[Frame], _out: &mut dyn Write) -> io::Result<()>{
+        let class = InstrClass::of(record, self.arch).index();
+        let function = info.location.outer_function().unwrap_or("??").to_string();
+        self.total[class] += 1;
+        self.functions.entry(function.clone()).or_default()[class] += 1;
+        if let Some(cycle) = record.cycle {
+            if let Some((last_function, last_cycle)) = self.last.insert(record.hart, (function, cycle)) {
+                let cycles = cycle.saturating_sub(last_cycle);
+                self.cycles += cycles;
+                *self.function_cycles.entry(last_function).or_default() += cycles;
+            }
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        self.write_histogram(out)?;
+        self.write_functions(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbolizer::SrcLocation;
+
+    fn location(function: &str) -> SrcLocation{
+        SrcLocation { file: Some(String::from("main.c")), line: Some(3), function: Some(function.to_string()),
+            inlined_at: Vec::new() }
+    }
+
+    #[test]
+    fn inlined_code_counts_in_the_outer_function(){
+        let mut renderer = StatsRenderer::new(Arch::Riscv);
+        let mut inlined = location("add");
+        inlined.inlined_at.push(location("main"));
+        for (pc, location) in [(0x10, location("main")), (0x14, inlined)] {
+            let record = TraceRecord { line: String::new(), hart: None, time: None, cycle: Some(pc), pc, opcode: None,
+                instruction: Some(String::from("addi a0, a0, 1")), notes: Vec::new() };
+            let info = SrcInfo { location, code: None, context: Vec::new(), symbol: None, external: false };
+            renderer.record(&record, &info, &[], &mut io::sink()).unwrap();
+        }
+        assert_eq!(renderer.functions.keys().collect::<Vec<_>>(), ["main"]);
+        assert_eq!(renderer.functions["main"][InstrClass::Alu.index()], 2);
+        assert_eq!(renderer.function_cycles["main"], 4);
+    }
+}
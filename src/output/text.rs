@@ -0,0 +1,347 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use crate::breakpoint::{self, BreakpointHit, WatchHit};
+use crate::callstack::Frame;
+use crate::debugger::SrcInfo;
+use crate::csr::Csr;
+use crate::gap::TraceGap;
+use crate::output::{RenderOptions, Renderer};
+use crate::symbolizer::SrcLocation;
+use crate::trace::TraceRecord;
+use crate::trap::{TrapCause, TrapEvent};
+
+const DEFAULT_ERROR: &str = "    Not found";
+
+/// The width of the column of the register writes before the log lines.
+const WRITES_WIDTH: usize = 20;
+
+/// The width of the column of the disassembly before the log lines.
+const DISASSEMBLY_WIDTH: usize = 28;
+
+// ANSI escape sequences of the colors of the annotations.
+const FILE_COLOR: &str = "\x1b[36m";
+const LINE_COLOR: &str = "\x1b[33m";
+const FUNCTION_COLOR: &str = "\x1b[1;35m";
+const CODE_COLOR: &str = "\x1b[32m";
+const ERROR_COLOR: &str = "\x1b[31m";
+const TRAP_COLOR: &str = "\x1b[1;33m";
+const NOTE_COLOR: &str = "\x1b[34m";
+const RESET: &str = "\x1b[0m";
+
+/**
+ * Write the log lines, preceded by the source location and code whenever it changes.
+ *
+ * The location is written again whenever the hart changes, so each line is under the location of its own instruction
+ * when the instructions of several harts are interleaved. The locations of the logs with hart ids are tagged with the
+ * hart they are of.
+ */
+#[derive(Debug, Default)]
+pub struct TextRenderer{
+    /// The hart, the location and the call depth of the last record, the location is None after a banner.
+    last: Option<(Option<u32>, Option<SrcLocation>, usize)>,
+    options: RenderOptions,
+    /// Write the locations as file:line: code, one per line, for the editors and the terminals to open on a click.
+    clickable: bool,
+    /// The number of traps entered per cause and of traps exited, with --traps.
+    traps: BTreeMap<TrapCause, u64>,
+    trap_exits: u64,
+}
+
+impl TextRenderer {
+    /**
+     * Constructor.
+     *
+     * @param options: Whether to highlight the source locations and code with ANSI colors, to write the callers of the
+     * functions under their location, the CSRs and the variables accessed after the instructions and the register
+     * writes and the disassembly before them.
+     */
+    pub fn new(options: RenderOptions) -> TextRenderer{
+        TextRenderer { options, ..TextRenderer::default() }
+    }
+
+    /**
+     * Constructor of the quickfix format, where each location is a file:line: code line like the diagnostics of a
+     * compiler, read by the quickfix list of vim, the problem matchers of VS Code and the terminals. The context lines
+     * are written as file:line- code like grep does, so only the executed line is an entry.
+     *
+     * @param options: The options of new.
+     */
+    pub fn clickable(options: RenderOptions) -> TextRenderer{
+        TextRenderer { options, clickable: true, ..TextRenderer::default() }
+    }
+
+    fn write_annotation(&self, hart: Option<u32>, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        out.write_all(b"\n")?;
+        if self.clickable {
+            return self.write_clickable(hart, info, stack, out);
+        }
+        if let Some(hart) = hart {
+            write!(out, "hart {}: ", hart)?;
+        }
+        if let Some(external) = info.symbol.as_ref().filter(|_| info.external) {
+            return match self.options.color {
+                true => writeln!(out, "{}{}{}", ERROR_COLOR, external, RESET),
+                false => writeln!(out, "{}", external),
+            };
+        }
+        self.write_header(&info.location, out)?;
+        for call_site in &info.location.inlined_at {
+            out.write_all(b"  inlined into ")?;
+            self.write_header(call_site, out)?;
+        }
+        if self.options.call_stack && stack.len() > 1 {
+            let callers: Vec<String> = stack.iter().rev().skip(1).map(|frame| match self.options.color {
+                true => format!("{}{}(){}", FUNCTION_COLOR, frame.function, RESET),
+                false => format!("{}()", frame.function),
+            }).collect();
+            writeln!(out, "  called from {}", callers.join(" <- "))?;
+        }
+        if !info.context.is_empty() {
+            return self.write_context(info, out);
+        }
+        let missing = info.unavailable().map_or(DEFAULT_ERROR.to_string(), |text| format!("    {}", text));
+        match (&info.code, self.options.color) {
+            (Some(code), false) => writeln!(out, "    {}", code),
+            (None, false) => writeln!(out, "{}", missing),
+            (Some(code), true) => writeln!(out, "    {}{}{}", CODE_COLOR, code, RESET),
+            (None, true) => writeln!(out, "{}{}{}", ERROR_COLOR, missing, RESET),
+        }
+    }
+
+    /**
+     * Write a location as file:line: code, with the call sites it was inlined at and its context lines as file:line-
+     * code around it.
+     */
+    fn write_clickable(&self, hart: Option<u32>, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let hart = hart.map_or(String::new(), |hart| format!("hart {}: ", hart));
+        if let Some(external) = info.symbol.as_ref().filter(|_| info.external) {
+            return writeln!(out, "{}{}", hart, self.paint(ERROR_COLOR, external));
+        }
+        let function = info.location.function.as_deref().map_or(String::new(), |function| format!("{}(): ", function));
+        let current = match &info.code {
+            Some(code) => self.paint(CODE_COLOR, code.trim()),
+            None => self.paint(ERROR_COLOR, &info.unavailable().unwrap_or_else(|| DEFAULT_ERROR.trim().to_string())),
+        };
+        match info.context.is_empty() {
+            true => writeln!(out, "{}: {}{}{}", info.location, hart, function, current)?,
+            false => for (number, code) in &info.context {
+                let file = info.location.file.as_deref().unwrap_or("??");
+                match Some(*number) == info.location.line {
+                    true => writeln!(out, "{}:{}: {}{}{}", file, number, hart, function, current)?,
+                    false => writeln!(out, "{}:{}- {}", file, number, code)?,
+                }
+            },
+        }
+        // Each call site is in the function the previous one was inlined into.
+        let inlined = std::iter::once(&info.location).chain(&info.location.inlined_at).map(|location| location.function.as_deref());
+        for (call_site, inlined) in info.location.inlined_at.iter().zip(inlined) {
+            writeln!(out, "{}: {}() inlined here", call_site, inlined.unwrap_or("??"))?;
+        }
+        if self.options.call_stack && stack.len() > 1 {
+            let callers: Vec<String> = stack.iter().rev().skip(1).map(|frame| format!("{}()", frame.function)).collect();
+            writeln!(out, "  called from {}", callers.join(" <- "))?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Highlight a text with a color when the colors are on.
+     */
+    fn paint(&self, color: &str, text: &str) -> String{
+        match self.options.color {
+            true => format!("{}{}{}", color, text, RESET),
+            false => text.to_string(),
+        }
+    }
+
+    /**
+     * Write the numbered source lines around the executed one, which is marked with >.
+     */
+    fn write_context(&self, info: &SrcInfo, out: &mut dyn Write) -> io::Result<()>{
+        let width = info.context.last().map_or(0, |(number, _)| number.to_string().len());
+        for (number, code) in &info.context {
+            let current = Some(*number) == info.location.line;
+            let marker = if current { "  > " } else { "    " };
+            match (self.options.color, current) {
+                (false, _) => writeln!(out, "{}{:>width$} | {}", marker, number, code)?,
+                (true, true) => writeln!(out, "{}{}{:>width$}{} | {}{}{}", marker, LINE_COLOR, number, RESET, CODE_COLOR, code, RESET)?,
+                (true, false) => writeln!(out, "{}{}{:>width$}{} | {}", marker, LINE_COLOR, number, RESET, code)?,
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Annotate the next record again after a banner, even when it's at the same location as the last one.
+     */
+    fn forget_location(&mut self){
+        if let Some(last) = &mut self.last {
+            last.1 = None;
+        }
+    }
+
+    /**
+     * Write the function, file and line of a location.
+     */
+    fn write_header(&self, location: &SrcLocation, out: &mut dyn Write) -> io::Result<()>{
+        if !self.options.color {
+            return writeln!(out, "{}", location.header());
+        }
+        if let Some(function) = &location.function {
+            write!(out, "{}{}(){} at ", FUNCTION_COLOR, function, RESET)?;
+        }
+        let text = location.to_string();
+        let (file, line) = text.rsplit_once(':').unwrap_or((&text, ""));
+        writeln!(out, "{}{}{}:{}{}{}", FILE_COLOR, file, RESET, LINE_COLOR, line, RESET)
+    }
+}
+
+impl Renderer for TextRenderer {
+    fn record(&mut self, record: &TraceRecord, info: &SrcInfo, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let changed = match &self.last {
+            Some((hart, location, depth)) => *hart != record.hart || location.as_ref() != Some(&info.location)
+                || (self.options.call_stack && *depth != stack.len()),
+            None => true,
+        };
+        if changed {
+            self.last = Some((record.hart, Some(info.location.clone()), stack.len()));
+            self.write_annotation(record.hart, info, stack, out)?;
+        }
+        if self.options.writes {
+            let writes: Vec<String> = record.register_writes().iter()
+                .map(|write| format!("{}=0x{:08x}", write.register, write.value)).collect();
+            write!(out, "{:<width$} ", writes.join(" "), width = WRITES_WIDTH)?;
+        }
+        if self.options.disassembly.is_some() {
+            write!(out, "{:<width$} ", self.options.disassembly(record).unwrap_or("??"), width = DISASSEMBLY_WIDTH)?;
+        }
+        let mut notes = Vec::new();
+        if let Some(csr) = if self.options.csr_names { Csr::of(record) } else { None } {
+            notes.push(format!("{}: {}", csr.name, csr.description));
+        }
+        notes.extend(self.options.data_symbol(record));
+        notes.extend(record.notes.iter().cloned());
+        match (notes.is_empty(), self.options.color) {
+            (true, _) => writeln!(out, "{}", record.line),
+            (false, true) => writeln!(out, "{}  {}# {}{}", record.line, NOTE_COLOR, notes.join("; "), RESET),
+            (false, false) => writeln!(out, "{}  # {}", record.line, notes.join("; ")),
+        }
+    }
+
+    fn trap(&mut self, event: &TrapEvent, _record: &TraceRecord, out: &mut dyn Write) -> io::Result<()>{
+        let banner = match event {
+            TrapEvent::Entry { cause, from } => {
+                *self.traps.entry(*cause).or_default() += 1;
+                format!(">>> TRAP ENTRY ({}) from 0x{:x}", cause, from)
+            },
+            TrapEvent::Exit { instruction, from } => {
+                self.trap_exits += 1;
+                format!("<<< TRAP EXIT ({}) from 0x{:x}", instruction, from)
+            },
+        };
+        match self.options.color {
+            true => writeln!(out, "\n{}{}{}", TRAP_COLOR, banner, RESET)?,
+            false => writeln!(out, "\n{}", banner)?,
+        }
+        // The handler and the code it returns to are annotated again after the banner.
+        self.forget_location();
+        Ok(())
+    }
+
+    fn gap(&mut self, gap: &TraceGap, _record: &TraceRecord, out: &mut dyn Write) -> io::Result<()>{
+        let banner = format!("~~~ GAP: {}, trace lines may be missing", gap);
+        match self.options.color {
+            true => writeln!(out, "\n{}{}{}", TRAP_COLOR, banner, RESET)?,
+            false => writeln!(out, "\n{}", banner)?,
+        }
+        self.forget_location();
+        Ok(())
+    }
+
+    fn hit(&mut self, hit: &BreakpointHit, record: &TraceRecord, stack: &[Frame], out: &mut dyn Write) -> io::Result<()>{
+        let banner = format!("*** {}", hit.describe(record));
+        match self.options.color {
+            true => writeln!(out, "\n{}{}{}", TRAP_COLOR, banner, RESET)?,
+            false => writeln!(out, "\n{}", banner)?,
+        }
+        for frame in breakpoint::backtrace(record, stack) {
+            writeln!(out, "    {}", frame)?;
+        }
+        // The execution is annotated from its first instruction even when the context before it has the same line.
+        self.forget_location();
+        Ok(())
+    }
+
+    fn watch(&mut self, hit: &WatchHit, record: &TraceRecord, out: &mut dyn Write) -> io::Result<()>{
+        let banner = format!("*** {}", hit.describe(record));
+        match self.options.color {
+            true => writeln!(out, "\n{}{}{}", TRAP_COLOR, banner, RESET)?,
+            false => writeln!(out, "\n{}", banner)?,
+        }
+        self.forget_location();
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>{
+        if self.traps.is_empty() && self.trap_exits == 0 {
+            return Ok(());
+        }
+        writeln!(out, "\nTraps: {} entered, {} exited", self.traps.values().sum::<u64>(), self.trap_exits)?;
+        for (cause, count) in &self.traps {
+            writeln!(out, "{:>12}  {}", count, cause)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(hart: Option<u32>, pc: u64) -> TraceRecord{
+        TraceRecord { line: format!("line 0x{:x}", pc), hart, time: None, cycle: None, pc, opcode: None, instruction: None,
+            notes: Vec::new() }
+    }
+
+    fn info(function: &str, line: u32) -> SrcInfo{
+        let location = SrcLocation { file: Some(String::from("main.c")), line: Some(line), function: Some(function.to_string()),
+            inlined_at: Vec::new() };
+        SrcInfo { location, code: Some(format!("{}();", function)), context: Vec::new(), symbol: None, external: false }
+    }
+
+    fn render(records: &[(TraceRecord, SrcInfo)]) -> String{
+        let mut renderer = TextRenderer::new(RenderOptions::default());
+        let mut out = Vec::new();
+        for (record, info) in records {
+            renderer.record(record, info, &[], &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn location_is_written_when_it_changes(){
+        let text = render(&[(record(None, 0x10), info("main", 3)), (record(None, 0x12), info("main", 3)),
+            (record(None, 0x14), info("add", 8))]);
+        assert_eq!(text, "\nmain() at main.c:3\n    main();\nline 0x10\nline 0x12\n\nadd() at main.c:8\n    add();\nline 0x14\n");
+    }
+
+    #[test]
+    fn interleaved_harts_are_under_their_own_location(){
+        let text = render(&[(record(Some(0), 0x10), info("main", 3)), (record(Some(1), 0x20), info("add", 8)),
+            (record(Some(0), 0x12), info("main", 3)), (record(Some(0), 0x14), info("main", 3))]);
+        assert_eq!(text, "\nhart 0: main() at main.c:3\n    main();\nline 0x10\n\
+            \nhart 1: add() at main.c:8\n    add();\nline 0x20\n\
+            \nhart 0: main() at main.c:3\n    main();\nline 0x12\nline 0x14\n");
+    }
+
+    #[test]
+    fn location_is_written_again_after_a_banner(){
+        let mut renderer = TextRenderer::new(RenderOptions::default());
+        let mut out = Vec::new();
+        let first = record(Some(0), 0x10);
+        renderer.record(&first, &info("main", 3), &[], &mut out).unwrap();
+        renderer.gap(&TraceGap { hart: Some(0), counter: "cycle", from: 1, to: 5000 }, &first, &mut out).unwrap();
+        renderer.record(&record(Some(0), 0x12), &info("main", 3), &[], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().matches("hart 0: main() at main.c:3").count(), 2);
+    }
+}
@@ -0,0 +1,386 @@
+use std::io::BufRead;
+use std::str::FromStr;
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use crate::callstack::CallStack;
+use crate::compress::open_log;
+use crate::config::Config;
+use crate::debugger::DebuggerVarilator;
+use crate::error::Error;
+use crate::source::PathMap;
+use crate::symbolizer::{SrcLocation, Symbolizer};
+use crate::trace::{LayoutParser, RegexParser, TraceField, TraceFormat, TraceParser, TraceReader, TraceRecord};
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        match err {
+            Error::Io { .. } | Error::Output(_) => PyOSError::new_err(err.to_string()),
+            Error::Log(_) | Error::Config(_) => PyValueError::new_err(err.to_string()),
+            Error::Tool { .. } | Error::Elf { .. } => PyRuntimeError::new_err(err.to_string()),
+        }
+    }
+}
+
+/**
+ * Create the parser of the options given to Python, like the command line does.
+ *
+ * @param format: The name of a trace format, i.e. ibex.
+ * @param layout: The comma separated fields of the columns, i.e. time,cycle,pc,opcode,instruction.
+ * @param pc_regex: A regex whose first capture group is the address of the instruction.
+ * @return The parser, None to detect the format when none is given, ValueError if an option is invalid.
+ */
+fn parser(format: Option<&str>, layout: Option<&str>, pc_regex: Option<&str>) -> PyResult<Option<Box<dyn TraceParser>>>{
+    Ok(match (pc_regex, layout, format) {
+        (Some(pattern), _, _) => Some(Box::new(RegexParser::new(pattern)?)),
+        (None, Some(layout), _) => Some(Box::new(LayoutParser::new(TraceField::parse_layout(layout).map_err(PyValueError::new_err)?))),
+        (None, None, Some(format)) => Some(TraceFormat::from_str(format).map_err(PyValueError::new_err)?.parser()),
+        (None, None, None) => None,
+    })
+}
+
+/**
+ * Extract one path or a list of paths.
+ */
+fn paths(value: &Bound<'_, PyAny>) -> PyResult<Vec<String>>{
+    match value.extract::<String>() {
+        Ok(path) => Ok(vec![path]),
+        Err(_) => value.extract(),
+    }
+}
+
+/**
+ * An executed instruction of the trace.
+ */
+#[pyclass(name = "TraceRecord", frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+struct PyTraceRecord{
+    record: TraceRecord,
+}
+
+#[pymethods]
+impl PyTraceRecord {
+    /// The log line the record was parsed from.
+    #[getter]
+    fn line(&self) -> &str {
+        &self.record.line
+    }
+
+    /// The hart that executed the instruction, None when the log doesn't tell.
+    #[getter]
+    fn hart(&self) -> Option<u32> {
+        self.record.hart
+    }
+
+    #[getter]
+    fn time(&self) -> Option<u64> {
+        self.record.time
+    }
+
+    #[getter]
+    fn cycle(&self) -> Option<u64> {
+        self.record.cycle
+    }
+
+    /// The address of the instruction.
+    #[getter]
+    fn pc(&self) -> u64 {
+        self.record.pc
+    }
+
+    /// The instruction word in hex as printed in the log.
+    #[getter]
+    fn opcode(&self) -> Option<&str> {
+        self.record.opcode.as_deref()
+    }
+
+    /// The decoded instruction, i.e. c.addi x2,x2,-16.
+    #[getter]
+    fn instruction(&self) -> Option<&str> {
+        self.record.instruction.as_deref()
+    }
+
+    /// The registers the instruction wrote, as (register, value) pairs.
+    fn register_writes(&self) -> Vec<(String, u64)> {
+        self.record.register_writes().into_iter().map(|write| (write.register, write.value)).collect()
+    }
+
+    /// The address of the memory accessed by the instruction, None if the log doesn't show it.
+    fn data_address(&self) -> Option<u64> {
+        self.record.data_address()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TraceRecord(pc=0x{:x}, instruction={:?})", self.record.pc, self.record.instruction.as_deref().unwrap_or("??"))
+    }
+}
+
+impl From<TraceRecord> for PyTraceRecord {
+    fn from(record: TraceRecord) -> PyTraceRecord {
+        PyTraceRecord { record }
+    }
+}
+
+/**
+ * The place in the source code an address comes from.
+ */
+#[pyclass(name = "SrcLocation", frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+struct PySrcLocation{
+    location: SrcLocation,
+}
+
+#[pymethods]
+impl PySrcLocation {
+    #[getter]
+    fn file(&self) -> Option<&str> {
+        self.location.file.as_deref()
+    }
+
+    #[getter]
+    fn line(&self) -> Option<u32> {
+        self.location.line
+    }
+
+    /// The function the address belongs to.
+    #[getter]
+    fn function(&self) -> Option<&str> {
+        self.location.function.as_deref()
+    }
+
+    /// The call sites the code was inlined into, from the innermost to the outermost.
+    #[getter]
+    fn inlined_at(&self) -> Vec<PySrcLocation> {
+        self.location.inlined_at.iter().cloned().map(PySrcLocation::from).collect()
+    }
+
+    /// The function whose code contains the address, the function the inlined code was inlined into.
+    fn outer_function(&self) -> Option<&str> {
+        self.location.outer_function()
+    }
+
+    /// The location with its function in front, i.e. uart_init() at drivers/uart.c:42.
+    fn header(&self) -> String {
+        self.location.header()
+    }
+
+    fn __str__(&self) -> String {
+        self.location.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SrcLocation({:?})", self.location.header())
+    }
+}
+
+impl From<SrcLocation> for PySrcLocation {
+    fn from(location: SrcLocation) -> PySrcLocation {
+        PySrcLocation { location }
+    }
+}
+
+/**
+ * An instruction of the trace with the source it was compiled from, as the annotation shows it.
+ */
+#[pyclass(name = "AnnotatedRecord", frozen, skip_from_py_object, get_all)]
+#[derive(Debug, Clone)]
+struct PyAnnotatedRecord{
+    record: PyTraceRecord,
+    location: PySrcLocation,
+    /// The line of code, None if the source isn't available.
+    code: Option<String>,
+    /// The numbered lines around the line of code, with the context lines of the annotator.
+    context: Vec<(u32, String)>,
+    /// The function symbol and the offset of the address when the code isn't available, i.e. uart_init+0x24.
+    symbol: Option<String>,
+    /// The address is out of the elfs, kept with keep_external.
+    external: bool,
+    /// The functions of the call stack, from the outermost to the function of the instruction.
+    stack: Vec<String>,
+}
+
+#[pymethods]
+impl PyAnnotatedRecord {
+    fn __repr__(&self) -> String {
+        format!("AnnotatedRecord(pc=0x{:x}, location={:?})", self.record.record.pc, self.location.location.header())
+    }
+}
+
+/**
+ * A parser of the log lines, of a built-in format, of a field layout or of a pc regex.
+ */
+#[pyclass(name = "Parser")]
+struct PyParser{
+    parser: Box<dyn TraceParser>,
+}
+
+#[pymethods]
+impl PyParser {
+    #[new]
+    #[pyo3(signature = (format = None, layout = None, pc_regex = None))]
+    fn new(format: Option<&str>, layout: Option<&str>, pc_regex: Option<&str>) -> PyResult<PyParser> {
+        let parser = parser(format, layout, pc_regex)?.unwrap_or_else(|| TraceFormat::default().parser());
+        Ok(PyParser { parser })
+    }
+
+    /// Parse a log line, the lines of the stateful formats like qemu must be given in order.
+    ///
+    /// Returns the records traced by the line, empty if it traces no instruction.
+    fn parse(&mut self, line: &str) -> Vec<PyTraceRecord> {
+        let mut records = Vec::new();
+        self.parser.parse_into(line, &mut records);
+        records.into_iter().map(PyTraceRecord::from).collect()
+    }
+}
+
+/**
+ * The records of a log, read one by one.
+ */
+#[pyclass(name = "TraceReader", unsendable)]
+struct PyTraceReader{
+    path: String,
+    reader: TraceReader<Box<dyn BufRead>>,
+}
+
+#[pymethods]
+impl PyTraceReader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<PyTraceRecord>> {
+        match self.reader.next().transpose() {
+            Ok(record) => Ok(record.map(PyTraceRecord::from)),
+            Err(err) => Err(Error::io(&self.path, err).into()),
+        }
+    }
+}
+
+/**
+ * The symbolizer of an elf, from its DWARF debug info or from addr2line.
+ */
+#[pyclass(name = "Symbolizer", unsendable)]
+struct PySymbolizer{
+    symbolizer: Symbolizer,
+}
+
+#[pymethods]
+impl PySymbolizer {
+    #[new]
+    #[pyo3(signature = (elf, addr2line = None))]
+    fn new(elf: &str, addr2line: Option<&str>) -> PyResult<PySymbolizer> {
+        Ok(PySymbolizer { symbolizer: Symbolizer::new(addr2line, elf)? })
+    }
+
+    /// Get the source location of an address of the elf.
+    fn locate(&mut self, addr: u64) -> PySrcLocation {
+        self.symbolizer.locate(addr).into()
+    }
+
+    /// Get the source locations of addresses of the elf, in their order.
+    fn locate_all(&mut self, addrs: Vec<u64>) -> Vec<PySrcLocation> {
+        self.symbolizer.locate_all(&addrs).into_iter().map(PySrcLocation::from).collect()
+    }
+}
+
+/**
+ * The annotated records of a log, read one by one, the records out of the elfs are skipped.
+ */
+#[pyclass(name = "Annotator", unsendable)]
+struct PyAnnotator{
+    dv: DebuggerVarilator,
+    reader: TraceReader<Box<dyn BufRead>>,
+    call_stack: CallStack,
+}
+
+#[pymethods]
+impl PyAnnotator {
+    /// Open a log to annotate with the source code of the elfs that produced it.
+    ///
+    /// elf is the path of an elf or a list of them, the format of the log is detected unless format, layout or
+    /// pc_regex is given, and the other options are the ones of the annotate command.
+    #[new]
+    #[pyo3(signature = (elf, log, *, format = None, layout = None, pc_regex = None, load_offset = Vec::new(),
+        toolchain_prefix = None, addr2line = None, context = 0, path_map = Vec::new(), source_dir = Vec::new(),
+        keep_external = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(elf: &Bound<'_, PyAny>, log: &str, format: Option<&str>, layout: Option<&str>, pc_regex: Option<&str>,
+        load_offset: Vec<i64>, toolchain_prefix: Option<&str>, addr2line: Option<String>, context: u32,
+        path_map: Vec<String>, source_dir: Vec<String>, keep_external: bool) -> PyResult<PyAnnotator> {
+        let mut config = Config::for_elfs(toolchain_prefix, addr2line, paths(elf)?, load_offset)?;
+        config.log_file = log.to_string();
+        config.context = context;
+        config.path_maps = path_map.iter().map(|rule| PathMap::from_str(rule)).collect::<Result<_, String>>()
+            .map_err(PyValueError::new_err)?;
+        config.source_dirs = source_dir;
+        config.keep_external = keep_external;
+        let mut dv = DebuggerVarilator::new(config)?;
+        dv.load_ranges()?;
+        let (input, _) = open_log(log)?;
+        let reader = TraceReader::new(input, parser(format, layout, pc_regex)?)?;
+        let call_stack = CallStack::new(dv.config.arch);
+        Ok(PyAnnotator { dv, reader, call_stack })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<PyAnnotatedRecord>> {
+        let Some(record) = self.dv.next_record(&mut self.reader)? else {
+            return Ok(None);
+        };
+        let info = self.dv.get_src_info(record.pc).clone();
+        let function = info.location.outer_function().unwrap_or("??");
+        let stack = self.call_stack.update(&record, function).iter().map(|frame| frame.function.clone()).collect();
+        Ok(Some(PyAnnotatedRecord {
+            record: record.into(),
+            location: info.location.into(),
+            code: info.code,
+            context: info.context,
+            symbol: info.symbol,
+            external: info.external,
+            stack,
+        }))
+    }
+}
+
+/**
+ * Get the names of the built-in trace formats.
+ */
+#[pyfunction]
+fn formats() -> Vec<&'static str>{
+    TraceFormat::names()
+}
+
+/**
+ * Guess the format of a log from its first lines, a few hundred are enough.
+ *
+ * @return The name of the format, None if no line traces an instruction in any format.
+ */
+#[pyfunction]
+fn detect_format(lines: Vec<String>) -> Option<&'static str>{
+    TraceFormat::detect(&lines).map(|format| format.name())
+}
+
+/**
+ * Read the records of a log, gzip and zstd compressed logs are decompressed.
+ *
+ * The format is detected from the first lines unless format, layout or pc_regex is given.
+ */
+#[pyfunction]
+#[pyo3(signature = (path, format = None, layout = None, pc_regex = None))]
+fn read_log(path: &str, format: Option<&str>, layout: Option<&str>, pc_regex: Option<&str>) -> PyResult<PyTraceReader>{
+    let (input, _) = open_log(path)?;
+    let reader = TraceReader::new(input, parser(format, layout, pc_regex)?)?;
+    Ok(PyTraceReader { path: path.to_string(), reader })
+}
+
+/**
+ * The parsers, the symbolizer and the annotation of the command line for the Python notebooks and analyses.
+ */
+#[pymodule(name = "varilator_debugger")]
+mod module {
+    #[pymodule_export]
+    use super::{detect_format, formats, read_log, PyAnnotatedRecord, PyAnnotator, PyParser, PySrcLocation, PySymbolizer,
+        PyTraceReader, PyTraceRecord};
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use verilator_debugger::{open_log, Breakpoint, DebuggerVarilator, Error, ElfFunction, SrcInfo, SrcLocation, TraceFormat, TraceReader};
+use crate::cli::parse_addr;
+
+/// The most cycles listed for the executions of a location, the others are only counted.
+const LISTED_EXECUTIONS: usize = 20;
+
+const HELP: &str = "\
+addr ADDR       the source and the function of an address, i.e. addr 0x20000514
+func NAME       the addresses and the source of a function, i.e. func uart_init
+line FILE:LINE  the addresses of a line of code, i.e. line main.c:42
+log PATH        the log whose executions of the locations are shown
+help            this help
+quit            leave, like Ctrl-D";
+
+/**
+ * Answer the queries about the addresses, the functions and the lines of code of the elfs, typed one per line, with
+ * the elfs loaded once.
+ *
+ * The cycles at which the location executed are shown when a log is given, the log is read at each query so it can
+ * still be written by the simulation.
+ */
+pub struct Repl{
+    dv: DebuggerVarilator,
+    /// The functions of the elfs, at the addresses they run from.
+    functions: Vec<ElfFunction>,
+    log: Option<String>,
+    /// The format of the log, None to detect it.
+    format: Option<TraceFormat>,
+}
+
+impl Repl {
+    /**
+     * Constructor.
+     *
+     * @param dv: The debugger, with the elfs.
+     * @param log: The log whose executions are shown, None to only show the source.
+     * @param format: The format of the log, None to detect it.
+     * @return The REPL, error if the elfs can't be read.
+     */
+    pub fn new(mut dv: DebuggerVarilator, log: Option<String>, format: Option<TraceFormat>) -> Result<Repl, Error>{
+        dv.load_ranges()?;
+        let functions = dv.elf_functions()?;
+        Ok(Repl { dv, functions, log, format })
+    }
+
+    /**
+     * Answer the queries of the input until it ends or quit is typed, the prompt is only shown on a terminal.
+     */
+    pub fn run(&mut self, input: impl BufRead) -> io::Result<()>{
+        let prompt = io::stdin().is_terminal();
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                print!("> ");
+                io::stdout().flush()?;
+            }
+            let Some(line) = lines.next().transpose()? else {
+                return Ok(());
+            };
+            let mut words = line.split_whitespace();
+            let (command, argument) = (words.next().unwrap_or(""), words.collect::<Vec<&str>>().join(" "));
+            let result = match command {
+                "" => Ok(()),
+                "addr" => self.addr(&argument),
+                "func" => self.func(&argument),
+                "line" => self.line(&argument),
+                "log" if argument.is_empty() => Err(String::from("Expected the path of the log, i.e. log trace.log")),
+                "log" => {
+                    self.log = Some(argument);
+                    Ok(())
+                },
+                "help" => {
+                    println!("{}", HELP);
+                    Ok(())
+                },
+                "quit" | "exit" => return Ok(()),
+                _ => Err(format!("Unknown command {}, type help for the commands", command)),
+            };
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+    }
+
+    /**
+     * Answer the query of a location given on the command line, like its addr, func or line command.
+     *
+     * @return String error if the location isn't in the elfs or the log can't be read.
+     */
+    pub fn query(&mut self, location: &Breakpoint) -> Result<(), String>{
+        let argument = location.to_string();
+        match location {
+            Breakpoint::Addr(_) => self.addr(&argument),
+            Breakpoint::Function(_) => self.func(&argument),
+            Breakpoint::Line { .. } => self.line(&argument),
+        }
+    }
+
+    fn addr(&mut self, argument: &str) -> Result<(), String>{
+        let addr = parse_addr(argument)?;
+        let info = self.dv.get_src_info(addr).clone();
+        println!("0x{:x} {}", addr, info.location.header());
+        print_source(&info);
+        self.print_executions(&HashSet::from([addr]), "Executed")
+    }
+
+    fn func(&mut self, argument: &str) -> Result<(), String>{
+        let matches: Vec<ElfFunction> = self.functions.iter().filter(|function| {
+            function.name == argument || function.name.rsplit("::").next() == Some(argument)
+        }).cloned().collect();
+        if matches.is_empty() {
+            return Err(format!("No function {} in the elfs", argument));
+        }
+        for function in matches {
+            let info = self.dv.get_src_info(function.addr).clone();
+            println!("{} 0x{:x}-0x{:x}, {} bytes, at {}", function.name, function.addr, function.addr + function.size,
+                function.size, info.location);
+            print_source(&info);
+            self.print_executions(&HashSet::from([function.addr]), "Called")?;
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, argument: &str) -> Result<(), String>{
+        let (file, line) = argument.rsplit_once(':').and_then(|(file, line)| Some((file, line.parse::<u32>().ok()?)))
+            .ok_or_else(|| format!("Invalid line {}, expected i.e. main.c:42", argument))?;
+        // The code of the line may also have been inlined into other functions, its call sites match too.
+        let matches = |location: &SrcLocation| std::iter::once(location).chain(&location.inlined_at).any(|frame| {
+            frame.line == Some(line) && frame.file.as_deref().is_some_and(|path| Path::new(path).ends_with(file))
+        });
+        let instructions: Vec<u64> = self.functions.iter().flat_map(|function| function.instructions.iter().copied()).collect();
+        let mut functions: Vec<(String, Vec<u64>)> = Vec::new();
+        let mut source = None;
+        for pc in instructions {
+            let info = self.dv.get_src_info(pc);
+            if !matches(&info.location) {
+                continue;
+            }
+            let function = info.location.outer_function().unwrap_or("??").to_string();
+            if source.is_none() && info.location.line == Some(line) {
+                source = Some(pc);
+            }
+            match functions.iter_mut().find(|(name, _)| *name == function) {
+                Some((_, addrs)) => addrs.push(pc),
+                None => functions.push((function, vec![pc])),
+            }
+        }
+        if functions.is_empty() {
+            return Err(format!("No instruction of the elfs is at {}", argument));
+        }
+        for (function, addrs) in &functions {
+            let addrs: Vec<String> = addrs.iter().map(|addr| format!("0x{:x}", addr)).collect();
+            println!("{} in {}: {}", argument, function, addrs.join(" "));
+        }
+        if let Some(pc) = source {
+            let info = self.dv.get_src_info(pc);
+            print_source(info);
+        }
+        let addrs = functions.into_iter().flat_map(|(_, addrs)| addrs).collect();
+        self.print_executions(&addrs, "Executed")
+    }
+
+    /**
+     * Print the cycles of the log at which a location executed, nothing when there is no log.
+     *
+     * @param addrs: The addresses of the location. A run of instructions in them is one execution, a new one starts
+     * when the log jumps back into them.
+     * @param verb: What an execution is, i.e. Called for a function.
+     */
+    fn print_executions(&self, addrs: &HashSet<u64>, verb: &str) -> Result<(), String>{
+        let Some(path) = &self.log else {
+            return Ok(());
+        };
+        let (log, _) = open_log(path).map_err(|err| err.to_string())?;
+        let reader = TraceReader::new(log, self.format.map(|format| format.parser())).map_err(|err| err.to_string())?;
+        let mut last: HashMap<Option<u32>, u64> = HashMap::new();
+        let mut executions = Vec::new();
+        let mut count = 0;
+        let mut unit = "cycle";
+        for record in reader {
+            let record = record.map_err(|err| format!("Failed to read the log {}: {}", path, err))?;
+            let previous = last.insert(record.hart, record.pc);
+            if !addrs.contains(&record.pc) || previous.is_some_and(|previous| addrs.contains(&previous) && previous < record.pc) {
+                continue;
+            }
+            count += 1;
+            if executions.len() < LISTED_EXECUTIONS {
+                if record.cycle.is_none() && record.time.is_some() {
+                    unit = "time";
+                }
+                executions.extend(record.cycle.or(record.time));
+            }
+        }
+        let times = if count == 1 { "time" } else { "times" };
+        if count == 0 {
+            println!("Not executed in {}", path);
+        } else if executions.is_empty() {
+            println!("{} {} {} in {}", verb, count, times, path);
+        } else {
+            let listed: Vec<String> = executions.iter().map(u64::to_string).collect();
+            let more = if count > executions.len() { format!(" and {} more", count - executions.len()) } else { String::new() };
+            let unit = if listed.len() > 1 { format!("{}s", unit) } else { unit.to_string() };
+            println!("{} {} {} in {}, at {} {}{}", verb, count, times, path, unit, listed.join(", "), more);
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Print the call sites a location was inlined into and its code, or the context lines around it, or its function
+ * symbol when the code isn't available.
+ */
+fn print_source(info: &SrcInfo){
+    for call_site in &info.location.inlined_at {
+        println!("  inlined into {}", call_site.header());
+    }
+    if !info.context.is_empty() {
+        for (number, code) in &info.context {
+            let marker = if Some(*number) == info.location.line { ">" } else { " " };
+            println!("    {} {:>5} | {}", marker, number, code);
+        }
+    } else if let Some(code) = info.code.as_deref().map(str::to_string).or_else(|| info.unavailable()) {
+        println!("    {}", code);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use crate::debugger::{AnnotationSummary, SrcInfo};
+use crate::error::{Error, Result};
+use crate::trace::TraceRecord;
+
+/**
+ * What the script tells to do with an instruction.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict{
+    /// Show the instruction with the notes, none to show it as is.
+    Show(Vec<String>),
+    /// Hide the instruction from the output.
+    Hide,
+}
+
+/**
+ * A rhai script with the callbacks run along the annotation, to add notes to the instructions, count them or hide
+ * them. The script may define:
+ *
+ * - init(), returning the state of the script, `this` in the other callbacks, an empty map by default.
+ * - on_record(record), called with each instruction that passes the filters, a map of its pc, hart, time, cycle,
+ *   opcode, instruction, line, function, file, source_line and source. It returns false to hide the instruction, a
+ *   string or an array of strings to add them as notes, or nothing to show it as is.
+ * - on_end(summary), called at the end with the lines, traced and kept counts, the string it returns is printed.
+ *
+ * The print of the script writes to stderr, so it doesn't mix with the output.
+ */
+pub struct Script{
+    path: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    on_record: bool,
+    on_end: bool,
+}
+
+impl Script {
+    /**
+     * Load a script, its top level statements are run and then its init.
+     *
+     * @param path: The path of the script.
+     * @return The script, error if it can't be read, doesn't compile or its init fails.
+     */
+    pub fn load(path: &str) -> Result<Script>{
+        let source = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        let mut engine = Engine::new();
+        engine.on_print(|text| eprintln!("{}", text));
+        engine.on_debug(|text, _, position| eprintln!("{} {}", position, text));
+        let ast = engine.compile(&source).map_err(|err| Error::Config(format!("The script {} doesn't compile: {}", path, err)))?;
+        let has = |name: &str, params: usize| ast.iter_functions().any(|function| function.name == name && function.params.len() == params);
+        let (init, on_record, on_end) = (has("init", 0), has("on_record", 1), has("on_end", 1));
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast).map_err(|err| Error::Config(format!("The script {} failed: {}", path, err)))?;
+        let state = match init {
+            true => engine.call_fn_with_options(CallFnOptions::new().eval_ast(false), &mut scope, &ast, "init", ())
+                .map_err(|err| Error::Config(format!("The init of the script {} failed: {}", path, err)))?,
+            false => Dynamic::from_map(Map::new()),
+        };
+        Ok(Script { path: path.to_string(), engine, ast, scope, state, on_record, on_end })
+    }
+
+    /**
+     * Run on_record on an instruction.
+     *
+     * @param record: The instruction.
+     * @param info: The source information of its address.
+     * @return What to do with the instruction, string error if on_record fails or returns something else than a
+     * bool, a string, an array of strings or nothing.
+     */
+    pub fn on_record(&mut self, record: &TraceRecord, info: &SrcInfo) -> std::result::Result<Verdict, String>{
+        if !self.on_record {
+            return Ok(Verdict::Show(Vec::new()));
+        }
+        let optional = |value: Option<u64>| value.map_or(Dynamic::UNIT, |value| Dynamic::from_int(value as i64));
+        let text = |value: Option<&str>| value.map_or(Dynamic::UNIT, |value| Dynamic::from(value.to_string()));
+        let mut map = Map::new();
+        map.insert("pc".into(), Dynamic::from_int(record.pc as i64));
+        map.insert("hart".into(), optional(record.hart.map(u64::from)));
+        map.insert("time".into(), optional(record.time));
+        map.insert("cycle".into(), optional(record.cycle));
+        map.insert("opcode".into(), text(record.opcode.as_deref()));
+        map.insert("instruction".into(), text(record.instruction.as_deref()));
+        map.insert("line".into(), Dynamic::from(record.line.clone()));
+        map.insert("function".into(), text(info.location.outer_function()));
+        map.insert("file".into(), text(info.location.file.as_deref()));
+        map.insert("source_line".into(), optional(info.location.line.map(u64::from)));
+        map.insert("source".into(), text(info.code.as_deref()));
+        let verdict = self.call("on_record", Dynamic::from_map(map))?;
+        if let Ok(show) = verdict.as_bool() {
+            return Ok(if show { Verdict::Show(Vec::new()) } else { Verdict::Hide });
+        }
+        if verdict.is_unit() {
+            return Ok(Verdict::Show(Vec::new()));
+        }
+        if verdict.is_string() {
+            return Ok(Verdict::Show(vec![verdict.to_string()]));
+        }
+        match verdict.try_cast::<Array>() {
+            Some(notes) => Ok(Verdict::Show(notes.iter().map(Dynamic::to_string).collect())),
+            None => Err(String::from("on_record returned neither a bool, a string, an array nor nothing")),
+        }
+    }
+
+    /**
+     * Run on_end at the end of the annotation.
+     *
+     * @param summary: What the annotation went through.
+     * @return The string on_end returned, None if it returned nothing or isn't defined, string error if it fails.
+     */
+    pub fn on_end(&mut self, summary: &AnnotationSummary) -> std::result::Result<Option<String>, String>{
+        if !self.on_end {
+            return Ok(None);
+        }
+        let mut map = Map::new();
+        map.insert("lines".into(), Dynamic::from_int(summary.lines as i64));
+        map.insert("traced".into(), Dynamic::from_int(summary.traced as i64));
+        map.insert("kept".into(), Dynamic::from_int(summary.kept as i64));
+        let result = self.call("on_end", Dynamic::from_map(map))?;
+        Ok(Some(result.to_string()).filter(|_| !result.is_unit()))
+    }
+
+    /**
+     * Call a callback with the state of the script as `this`.
+     */
+    fn call(&mut self, name: &str, argument: Dynamic) -> std::result::Result<Dynamic, String>{
+        let options = CallFnOptions::new().eval_ast(false).rewind_scope(true).bind_this_ptr(&mut self.state);
+        self.engine.call_fn_with_options(options, &mut self.scope, &self.ast, name, (argument,))
+            .map_err(|err| format!("{} of the script {} failed: {}", name, self.path, err))
+    }
+}
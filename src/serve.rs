@@ -0,0 +1,445 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use regex::Regex;
+use serde_json::{json, Value};
+use verilator_debugger::{open_log, CallStack, DebuggerVarilator, Error, TraceParser, TraceReader, TraceRecord};
+
+/// The records sent when a page doesn't ask for a count, and the most sent in a page.
+const PAGE_RECORDS: usize = 200;
+const MAX_PAGE_RECORDS: usize = 5000;
+
+/// How long a connection may take to send its request, so a stalled client doesn't hold the server.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The page of the viewer, it fetches the trace from the api of the server.
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>varilator_debugger</title>
+<style>
+body { font-family: sans-serif; margin: 0; display: flex; flex-direction: column; height: 100vh; }
+header { padding: 8px; background: #24292e; color: #fff; display: flex; gap: 8px; align-items: center; }
+header input { flex: 1; font-family: monospace; }
+#status { color: #f97583; }
+main { flex: 1; display: flex; min-height: 0; }
+nav, section { overflow: auto; border-right: 1px solid #ccc; }
+nav { width: 22%; }
+#trace { width: 43%; }
+#source { flex: 1; }
+h3 { margin: 8px; font-size: 14px; }
+table { border-collapse: collapse; font-family: monospace; font-size: 12px; width: 100%; }
+td { padding: 1px 6px; white-space: pre; }
+#functions tr, #files tr, #records tr { cursor: pointer; }
+#functions tr:hover, #files tr:hover, #records tr:hover { background: #f1f8ff; }
+#lines td:first-child, #lines td:nth-child(2) { text-align: right; color: #6a737d; }
+tr.current { outline: 2px solid #0366d6; }
+button { margin: 8px; }
+</style>
+</head>
+<body>
+<header>
+<b id="title"></b>
+<input id="q" placeholder="Search the log lines and the locations, a regex, press Enter">
+<select id="function"><option value="">All the functions</option></select>
+<span id="status"></span>
+</header>
+<main>
+<nav>
+<h3>Functions: instructions, calls</h3><table id="functions"></table>
+<h3>Sources: lines executed</h3><table id="files"></table>
+</nav>
+<section id="trace"><table id="records"></table><button id="more" hidden>More</button></section>
+<section id="source"><h3 id="file">Select an instruction or a source</h3><table id="lines"></table></section>
+</main>
+<script>
+const $ = id => document.getElementById(id);
+let next = 0;
+
+function row(table, cells, onclick) {
+  const tr = table.insertRow();
+  for (const cell of cells) {
+    tr.insertCell().textContent = cell;
+  }
+  if (onclick) {
+    tr.onclick = onclick;
+  }
+  return tr;
+}
+
+async function get(path, params) {
+  const response = await fetch(path + '?' + new URLSearchParams(params));
+  const body = await response.json();
+  if (!response.ok) {
+    throw new Error(body.error);
+  }
+  return body;
+}
+
+async function guarded(call) {
+  try {
+    await call();
+    $('status').textContent = '';
+  } catch (err) {
+    $('status').textContent = err.message;
+  }
+}
+
+async function summary() {
+  const summary = await get('/api/summary', {});
+  $('title').textContent = summary.log + ': ' + summary.records + ' instructions';
+  for (const f of summary.functions) {
+    row($('functions'), [f.name, f.instructions, f.calls], () => {
+      $('function').value = f.name;
+      guarded(() => records(true));
+    });
+    const option = document.createElement('option');
+    option.value = option.textContent = f.name;
+    $('function').append(option);
+  }
+  for (const f of summary.files) {
+    row($('files'), [f.file, f.lines], () => guarded(() => source(f.file)));
+  }
+}
+
+async function records(reset) {
+  if (reset) {
+    $('records').replaceChildren();
+    next = 0;
+  }
+  const page = await get('/api/records', {from: next, q: $('q').value, function: $('function').value});
+  for (const r of page.records) {
+    row($('records'), [r.index, r.cycle ?? '', '  '.repeat(r.depth) + r.function, r.location, r.line],
+      () => guarded(() => source(r.file, r.source_line)));
+  }
+  next = page.next;
+  $('more').hidden = next === null;
+}
+
+async function source(file, line) {
+  if (!file) {
+    throw new Error('The source of this instruction is unknown');
+  }
+  const source = await get('/api/source', {file});
+  $('file').textContent = file;
+  const table = $('lines');
+  table.replaceChildren();
+  const max = source.lines.reduce((max, [, , hits]) => Math.max(max, hits), 1);
+  for (const [number, code, hits] of source.lines) {
+    const tr = row(table, [number, hits || '', code]);
+    if (hits) {
+      tr.style.background = `rgba(255, 99, 71, ${0.1 + 0.6 * Math.log(1 + hits) / Math.log(1 + max)})`;
+    }
+    if (number === line) {
+      tr.className = 'current';
+      tr.scrollIntoView({block: 'center'});
+    }
+  }
+}
+
+$('q').onchange = $('function').onchange = () => guarded(() => records(true));
+$('more').onclick = () => guarded(() => records(false));
+guarded(async () => {
+  await summary();
+  await records(true);
+});
+</script>
+</body>
+</html>
+"#;
+
+/**
+ * The instructions executed in a function.
+ */
+struct FunctionStats{
+    name: String,
+    instructions: u64,
+    /// The times the function was called, the stack of a hart grew into it.
+    calls: u64,
+}
+
+/**
+ * The records of a log with their function and depth, the instructions per function and the executions of each source
+ * line, read once so the pages are answered without reading the log again.
+ */
+pub struct TraceIndex{
+    dv: DebuggerVarilator,
+    records: Vec<TraceRecord>,
+    /// The index in the functions of the function of each record, and the depth of its call stack.
+    record_functions: Vec<usize>,
+    depths: Vec<usize>,
+    functions: Vec<FunctionStats>,
+    /// The executions of the instructions of each line, per source file of the debug info.
+    coverage: BTreeMap<String, BTreeMap<u32, u64>>,
+}
+
+/**
+ * The answer to a request, with its status and the type of its body.
+ */
+struct Response{
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(value: Value) -> Response{
+        Response { status: "200 OK", content_type: "application/json", body: value.to_string().into_bytes() }
+    }
+
+    fn error(status: &'static str, message: impl Into<String>) -> Response{
+        Response { status, ..Response::json(json!({ "error": message.into() })) }
+    }
+}
+
+impl TraceIndex {
+    /**
+     * Read the log of the debugger and index its records in the elfs.
+     *
+     * @param dv: The debugger, with the elfs and the log.
+     * @param parser: The parser of the log, None to detect the format.
+     * @return The index, error if the elfs or the log can't be read.
+     */
+    pub fn new(mut dv: DebuggerVarilator, parser: Option<Box<dyn TraceParser>>) -> Result<TraceIndex, Error>{
+        dv.load_ranges()?;
+        let (log, _) = open_log(&dv.config.log_file)?;
+        let mut reader = TraceReader::new(log, parser)?;
+        let mut index = TraceIndex {
+            dv,
+            records: Vec::new(),
+            record_functions: Vec::new(),
+            depths: Vec::new(),
+            functions: Vec::new(),
+            coverage: BTreeMap::new(),
+        };
+        let mut function_indexes: HashMap<String, usize> = HashMap::new();
+        let mut call_stack = CallStack::new(index.dv.config.arch);
+        let mut last_depths: HashMap<Option<u32>, usize> = HashMap::new();
+        while let Some(record) = index.dv.next_record(&mut reader)? {
+            let info = index.dv.get_src_info(record.pc);
+            let function = info.location.outer_function().unwrap_or("??").to_string();
+            if let (Some(file), Some(line)) = (&info.location.file, info.location.line) {
+                *index.coverage.entry(file.clone()).or_default().entry(line).or_default() += 1;
+            }
+            let depth = call_stack.update(&record, &function).len();
+            let called = depth > last_depths.insert(record.hart, depth).unwrap_or(depth);
+            let position = *function_indexes.entry(function.clone()).or_insert_with(|| {
+                index.functions.push(FunctionStats { name: function, instructions: 0, calls: 0 });
+                index.functions.len() - 1
+            });
+            let stats = &mut index.functions[position];
+            stats.instructions += 1;
+            stats.calls += u64::from(called);
+            index.record_functions.push(position);
+            index.depths.push(depth);
+            index.records.push(record);
+        }
+        Ok(index)
+    }
+
+    /**
+     * Answer a request of the page or of its api.
+     *
+     * @param path: The path of the request, i.e. /api/records.
+     * @param params: The parameters of its query.
+     */
+    fn respond(&mut self, path: &str, params: &HashMap<String, String>) -> Response{
+        match path {
+            "/" | "/index.html" => Response { status: "200 OK", content_type: "text/html; charset=utf-8", body: PAGE.as_bytes().to_vec() },
+            "/api/summary" => self.summary(),
+            "/api/records" => self.page(params),
+            "/api/source" => self.source(params),
+            _ => Response::error("404 Not Found", format!("Unknown path {}", path)),
+        }
+    }
+
+    /**
+     * The number of records, the functions from the one with the most instructions and the sources with the number
+     * of lines executed.
+     */
+    fn summary(&self) -> Response{
+        let mut functions: Vec<&FunctionStats> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.instructions.cmp(&a.instructions).then_with(|| a.name.cmp(&b.name)));
+        Response::json(json!({
+            "log": self.dv.config.log_file,
+            "records": self.records.len(),
+            "functions": functions.iter().map(|stats| json!({
+                "name": stats.name,
+                "instructions": stats.instructions,
+                "calls": stats.calls,
+            })).collect::<Vec<Value>>(),
+            "files": self.coverage.iter().map(|(file, lines)| json!({ "file": file, "lines": lines.len() }))
+                .collect::<Vec<Value>>(),
+        }))
+    }
+
+    /**
+     * A page of the records from an index, of a function and matching a regex on their line or their location.
+     *
+     * @param params: from, the index of the first record to look at, count, the most records sent, function and q,
+     * the regex.
+     * @return The records with their location, and the index to ask the next page from, null at the end.
+     */
+    fn page(&mut self, params: &HashMap<String, String>) -> Response{
+        let number = |name: &str, default: usize| params.get(name).filter(|value| !value.is_empty())
+            .map_or(Ok(default), |value| value.parse::<usize>().map_err(|_| format!("Invalid {} {}", name, value)));
+        let (from, count) = match (number("from", 0), number("count", PAGE_RECORDS)) {
+            (Ok(from), Ok(count)) => (from, count.clamp(1, MAX_PAGE_RECORDS)),
+            (Err(err), _) | (_, Err(err)) => return Response::error("400 Bad Request", err),
+        };
+        let regex = match params.get("q").filter(|q| !q.is_empty()).map(|q| Regex::new(q)).transpose() {
+            Ok(regex) => regex,
+            Err(err) => return Response::error("400 Bad Request", format!("Invalid regex: {}", err)),
+        };
+        let function = params.get("function").filter(|name| !name.is_empty())
+            .map(|name| self.functions.iter().position(|stats| stats.name == *name));
+        if function == Some(None) {
+            return Response::error("404 Not Found", "Unknown function");
+        }
+        let mut records = Vec::new();
+        let mut next = None;
+        for index in from..self.records.len() {
+            if function.is_some_and(|function| function != Some(self.record_functions[index])) {
+                continue;
+            }
+            let record = &self.records[index];
+            let info = self.dv.get_src_info(record.pc);
+            let location = match (&info.symbol, info.external) {
+                (Some(symbol), true) => symbol.clone(),
+                _ => info.location.header(),
+            };
+            if regex.as_ref().is_some_and(|regex| !regex.is_match(&record.line) && !regex.is_match(&location)) {
+                continue;
+            }
+            if records.len() == count {
+                next = Some(index);
+                break;
+            }
+            records.push(json!({
+                "index": index,
+                "pc": format!("0x{:x}", record.pc),
+                "cycle": record.cycle,
+                "line": record.line,
+                "function": self.functions[self.record_functions[index]].name,
+                "depth": self.depths[index],
+                "location": location,
+                "file": info.location.file,
+                "source_line": info.location.line,
+            }));
+        }
+        Response::json(json!({ "records": records, "next": next }))
+    }
+
+    /**
+     * The lines of a source file with the number of instructions executed for each.
+     *
+     * @param params: file, the path of the file in the debug info, only the files of the executed instructions are
+     * served.
+     */
+    fn source(&self, params: &HashMap<String, String>) -> Response{
+        let Some(file) = params.get("file") else {
+            return Response::error("400 Bad Request", "No file given");
+        };
+        // Any other path could read the files of the machine, i.e. /etc/passwd.
+        let Some(hits) = self.coverage.get(file) else {
+            return Response::error("404 Not Found", format!("Unknown source {}", file));
+        };
+        let Some(lines) = self.dv.source_lines(file) else {
+            return Response::error("404 Not Found", format!("Can't read the source {}", file));
+        };
+        let lines: Vec<Value> = lines.iter().zip(1u32..).map(|(code, number)| {
+            json!([number, code, hits.get(&number).copied().unwrap_or(0)])
+        }).collect();
+        Response::json(json!({ "file": file, "lines": lines }))
+    }
+
+    /**
+     * Read a request from a connection and write its answer, only GET is supported.
+     */
+    fn handle(&mut self, stream: TcpStream) -> io::Result<()>{
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // The headers aren't used, they are read so the client sees the answer.
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+            header.clear();
+        }
+        let mut words = request.split_whitespace();
+        let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or("/"));
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let response = match method {
+            "GET" => self.respond(&percent_decode(path), &parse_query(query)),
+            _ => Response::error("405 Method Not Allowed", format!("Unsupported method {}", method)),
+        };
+        let mut stream = &stream;
+        write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status, response.content_type, response.body.len())?;
+        stream.write_all(&response.body)?;
+        stream.flush()
+    }
+}
+
+/**
+ * Get the parameters of the query of a URL, i.e. from=200&q=uart_.
+ */
+fn parse_query(query: &str) -> HashMap<String, String>{
+    query.split('&').filter(|param| !param.is_empty()).map(|param| {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        (percent_decode(name), percent_decode(value))
+    }).collect()
+}
+
+/**
+ * Decode the %XX escapes and the + of a part of a URL.
+ */
+fn percent_decode(text: &str) -> String{
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            },
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/**
+ * Index the log of the debugger and serve the viewer until the process is stopped, the requests are answered one at a
+ * time.
+ *
+ * @param dv: The debugger, with the elfs and the log to serve.
+ * @param parser: The parser of the log, None to detect the format.
+ * @param address: The address to listen on, i.e. 127.0.0.1.
+ * @param port: The port to listen on.
+ * @return Error if the elfs or the log can't be read or the address can't be listened on.
+ */
+pub fn serve(dv: DebuggerVarilator, parser: Option<Box<dyn TraceParser>>, address: &str, port: u16) -> Result<(), Error>{
+    log::info!("Indexing {}...", dv.config.log_file);
+    let mut index = TraceIndex::new(dv, parser)?;
+    let listener = TcpListener::bind((address, port)).map_err(Error::Output)?;
+    if listener.local_addr().is_ok_and(|local| !local.ip().is_loopback()) {
+        log::warn!("The log and its sources are served to the network on {}, the default 127.0.0.1 only serves this machine", address);
+    }
+    log::info!("Serving {} instructions on http://{}:{}/, press Ctrl-C to stop", index.records.len(), address, port);
+    for stream in listener.incoming() {
+        // A client that goes away only loses its own answer.
+        if let Err(err) = stream.and_then(|stream| index.handle(stream)) {
+            log::debug!("Problem answering a request: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use crate::vfs;
+
+/**
+ * A rule replacing the directory of the source paths of the debug info, i.e. the checkout of a build server, with
+ * the directory where the sources are found locally. It's written FROM=TO, i.e. /builder/src=/home/me/src.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathMap{
+    /// The directory of the debug info, without the trailing slash.
+    pub from: String,
+    /// The local directory replacing it.
+    pub to: String,
+}
+
+impl PathMap {
+    /**
+     * Replace the directory of a path if the path is in it.
+     *
+     * @param path: The path of a source file of the debug info.
+     * @return The local path, None if the path isn't in the directory of the rule.
+     */
+    pub fn apply(&self, path: &str) -> Option<String>{
+        let rest = path.strip_prefix(self.from.as_str())?;
+        // The rule of /src doesn't map /srcx/main.c, only the paths of the directory itself.
+        if !rest.is_empty() && !rest.starts_with('/') && !self.from.ends_with('/') {
+            return None;
+        }
+        Some(match rest.trim_start_matches('/') {
+            "" => self.to.clone(),
+            rest => format!("{}/{}", self.to.trim_end_matches('/'), rest),
+        })
+    }
+}
+
+impl FromStr for PathMap {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let Some((from, to)) = rule.split_once('=') else {
+            return Err(format!("Invalid path map {}, expected FROM=TO, i.e. /builder/src=/home/me/src", rule));
+        };
+        if from.is_empty() || to.is_empty() {
+            return Err(format!("Invalid path map {}, both directories are needed", rule));
+        }
+        let from = match from.trim_end_matches('/') {
+            "" => "/",
+            from => from,
+        };
+        Ok(PathMap { from: from.to_string(), to: to.to_string() })
+    }
+}
+
+impl fmt::Display for PathMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.from, self.to)
+    }
+}
+
+/**
+ * Get the local path of a source file of the debug info.
+ *
+ * @param path_maps: The rules, the first one whose directory contains the path is applied.
+ * @param path: The path of the debug info.
+ * @return The path given by the rule, the path itself when no rule applies.
+ */
+pub fn remap<'a>(path_maps: &[PathMap], path: &'a str) -> Cow<'a, str>{
+    path_maps.iter().find_map(|path_map| path_map.apply(path)).map_or(Cow::Borrowed(path), Cow::Owned)
+}
+
+/**
+ * The directories searched for the source files that aren't at the path of the debug info, like the dir command of
+ * GDB.
+ */
+#[derive(Debug, Default)]
+pub struct SourceDirs{
+    dirs: Vec<PathBuf>,
+    /// The files under the directories by name, in the order of the directories, listed the first time a file isn't
+    /// found by its suffixes.
+    files: OnceLock<HashMap<OsString, Vec<PathBuf>>>,
+}
+
+impl SourceDirs {
+    /**
+     * Constructor.
+     *
+     * @param dirs: The directories, in the order they are searched.
+     */
+    pub fn new(dirs: &[String]) -> SourceDirs{
+        SourceDirs { dirs: dirs.iter().map(PathBuf::from).collect(), files: OnceLock::new() }
+    }
+
+    /**
+     * Find a source file in the directories.
+     *
+     * The path is first looked for in each directory by its suffixes, the longest first, i.e. src/main.rs and then
+     * main.rs for /builder/src/main.rs. Otherwise the file of the same name anywhere under the directories with the
+     * most trailing directories in common with the path is used. The files added in memory under the directories are
+     * found too.
+     *
+     * @param path: The path of the debug info.
+     * @return The path of the file found, None if there are no directories or none has the file.
+     */
+    pub fn find(&self, path: &str) -> Option<PathBuf>{
+        let components: Vec<&OsStr> = Path::new(path).components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            }).collect();
+        let name = *components.last()?;
+        let found = self.dirs.iter().find_map(|dir| (0..components.len())
+            .map(|start| components[start..].iter().fold(dir.clone(), |path, component| path.join(component)))
+            .find(|candidate| vfs::is_file(candidate)));
+        if found.is_some() {
+            return found;
+        }
+        let common = |candidate: &PathBuf| candidate.components().rev().zip(components.iter().rev())
+            .take_while(|(component, name)| component.as_os_str() == **name).count();
+        // The first of the files with the most directories in common, max_by_key keeps the last one.
+        self.files().get(name)?.iter().rev().max_by_key(|candidate| common(candidate)).cloned()
+    }
+
+    fn files(&self) -> &HashMap<OsString, Vec<PathBuf>>{
+        self.files.get_or_init(|| {
+            let mut files: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+            for dir in &self.dirs {
+                list_files(dir, &mut files);
+                for path in vfs::list(dir) {
+                    if let Some(name) = path.file_name() {
+                        files.entry(name.to_os_string()).or_default().push(path.clone());
+                    }
+                }
+            }
+            files
+        })
+    }
+}
+
+/**
+ * Add the files under a directory to the files by name, in the order of their names. The hidden directories, i.e.
+ * .git, and the links to directories aren't entered.
+ */
+fn list_files(dir: &Path, files: &mut HashMap<OsString, Vec<PathBuf>>){
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::debug!("Can't list the source directory {}: {}", dir.display(), err);
+            return;
+        },
+    };
+    let mut entries: Vec<fs::DirEntry> = entries.map_while(Result::ok).collect();
+    entries.sort_by_key(fs::DirEntry::file_name);
+    for entry in entries {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                list_files(&path, files);
+            }
+        } else if path.is_file() {
+            files.entry(entry.file_name()).or_default().push(path);
+        }
+    }
+}
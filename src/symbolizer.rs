@@ -0,0 +1,437 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::thread;
+use std::sync::{Arc, Mutex, OnceLock};
+use addr2line::{Context, FrameIter, Loader};
+use gimli::{EndianArcSlice, RunTimeEndian};
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use crate::error::{Error, Result};
+use crate::vfs;
+
+/**
+ * The place in the source code an address comes from.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SrcLocation{
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// The function the address belongs to.
+    pub function: Option<String>,
+    /// When the code was inlined, the call sites it was inlined into from the innermost to the outermost, each with
+    /// the function that contains the call.
+    pub inlined_at: Vec<SrcLocation>,
+}
+
+impl SrcLocation {
+    /**
+     * Parse one line of addr2line output.
+     *
+     * @param output: A line in the format <path/to/source>:<line>, with ?? and ? or 0 for the unknown parts.
+     */
+    pub fn parse(output: &str) -> SrcLocation{
+        let (file, line) = output.rsplit_once(':').unwrap_or((output, ""));
+        let digits: String = line.chars().take_while(|c| c.is_ascii_digit()).collect();
+        SrcLocation {
+            file: Some(file.to_string()).filter(|file| file != "??"),
+            line: digits.parse().ok().filter(|line| *line != 0),
+            function: None,
+            inlined_at: Vec::new(),
+        }
+    }
+
+    /**
+     * Format the location with its function in front, i.e. uart_init() at drivers/uart.c:42.
+     */
+    pub fn header(&self) -> String{
+        match &self.function {
+            Some(function) => format!("{}() at {}", function, self),
+            None => self.to_string(),
+        }
+    }
+
+    /**
+     * Get the function whose code contains the address, the function the inlined code was inlined into.
+     */
+    pub fn outer_function(&self) -> Option<&str>{
+        match self.inlined_at.last() {
+            Some(call_site) => call_site.function.as_deref(),
+            None => self.function.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for SrcLocation {
+    /// Format the file and line like addr2line does.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}", file, line),
+            (Some(file), None) => write!(f, "{}:?", file),
+            (None, Some(line)) => write!(f, "??:{}", line),
+            (None, None) => write!(f, "??:0"),
+        }
+    }
+}
+
+/// The address queried after each address to mark the end of the variable number of inlined frames.
+const SENTINEL: u64 = 0;
+
+/**
+ * A long lived addr2line process that receives the addresses through its stdin.
+ */
+pub struct Addr2line{
+    child: process::Child,
+    stdin: process::ChildStdin,
+    stdout: io::BufReader<process::ChildStdout>,
+}
+
+impl Addr2line {
+    /**
+     * Spawn the addr2line process.
+     *
+     * @param addr2line_path: Path to the addr2line of the toolchain that built the elf.
+     * @param elf_file: Path to the elf.
+     * @return The running process, error otherwise.
+     */
+    pub fn spawn(addr2line_path: &str, elf_file: &str) -> Result<Addr2line>{
+        let mut child = match process::Command::new(addr2line_path)
+            .arg("-f")
+            .arg("-i")
+            .arg("-a")
+            .arg("-e")
+            .arg(elf_file)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .spawn(){
+                Ok(child) => child,
+                Err(err) => return Err(Error::tool(addr2line_path, err.to_string())),
+            };
+        let stdin = child.stdin.take().unwrap();
+        let stdout = io::BufReader::new(child.stdout.take().unwrap());
+        Ok(Addr2line { child, stdin, stdout })
+    }
+
+    /**
+     * Write one address to the process and read back its answer.
+     *
+     * @param addr: The address to be looked up.
+     * @return The function and source location output lines of each frame from the innermost, error otherwise.
+     */
+    pub fn locate(&mut self, addr: u64) -> Result<Vec<(String, String)>>{
+        Ok(self.locate_all(&[addr])?.pop().unwrap_or_default())
+    }
+
+    /**
+     * Write a batch of addresses to the process and read back their answers, the addresses are written by another
+     * thread while the answers are read so the pipes don't fill up and the process doesn't wait for each round trip.
+     *
+     * Each address is followed by the sentinel so the end of its frames is found by the echo of the sentinel address.
+     *
+     * @param addrs: The addresses to be looked up.
+     * @return The function and source location output lines of each frame from the innermost, for each address in
+     * order, error otherwise.
+     */
+    pub fn locate_all(&mut self, addrs: &[u64]) -> Result<Vec<Vec<(String, String)>>>{
+        let Addr2line { stdin, stdout, .. } = self;
+        thread::scope(|scope| {
+            let writer = scope.spawn(move || -> io::Result<()> {
+                let mut input = io::BufWriter::new(stdin);
+                for addr in addrs {
+                    writeln!(input, "{:x}\n{:x}", addr, SENTINEL)?;
+                }
+                input.flush()
+            });
+            let mut answers = Vec::with_capacity(addrs.len());
+            for &addr in addrs {
+                // Skip the frames of the previous sentinel.
+                while Self::echo(&Self::read_line(stdout)?) != Some(addr) {}
+                let mut frames = Vec::new();
+                loop {
+                    let function = Self::read_line(stdout)?;
+                    if Self::echo(&function) == Some(SENTINEL) {
+                        break;
+                    }
+                    let location = Self::read_line(stdout)?;
+                    frames.push((function, location));
+                }
+                answers.push(frames);
+            }
+            match writer.join() {
+                Ok(Ok(())) => Ok(answers),
+                _ => Err(Error::tool("addr2line", "it exited unexpectedly")),
+            }
+        })
+    }
+
+    fn read_line(stdout: &mut io::BufReader<process::ChildStdout>) -> Result<String>{
+        let mut line = String::new();
+        if stdout.read_line(&mut line).unwrap_or(0) == 0 {
+            return Err(Error::tool("addr2line", "it exited unexpectedly"));
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    /**
+     * Parse the line addr2line -a prints before the frames of an address.
+     */
+    fn echo(line: &str) -> Option<u64>{
+        u64::from_str_radix(line.strip_prefix("0x")?, 16).ok()
+    }
+}
+
+impl Drop for Addr2line {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/**
+ * The DWARF info of an elf read from its content, for the elfs added in memory that can't be loaded from a path.
+ */
+pub struct DwarfInfo{
+    context: Context<EndianArcSlice<RunTimeEndian>>,
+    /// The symbols of the elf by address, the function of the addresses without debug info.
+    symbols: Vec<(u64, String)>,
+}
+
+impl DwarfInfo {
+    /**
+     * Read the DWARF info and the symbols of an elf.
+     *
+     * @param elf_file: The path of the elf, for the errors.
+     * @param data: The content of the elf.
+     * @return The debug info, error if the content isn't an object file or its DWARF info can't be read.
+     */
+    pub fn parse(elf_file: &str, data: &[u8]) -> Result<DwarfInfo>{
+        let file = object::File::parse(data).map_err(|err| Error::elf(elf_file, err.to_string()))?;
+        let endian = if file.is_little_endian() { RunTimeEndian::Little } else { RunTimeEndian::Big };
+        // The elfs are linked, their debug info has no relocations to apply. The missing sections are empty.
+        let dwarf = gimli::Dwarf::load(|id| -> std::result::Result<_, gimli::Error> {
+            let data = file.section_by_name(id.name()).and_then(|section| section.uncompressed_data().ok()).unwrap_or_default();
+            Ok(EndianArcSlice::new(Arc::from(&*data), endian))
+        }).map_err(|err| Error::elf(elf_file, format!("failed to load the debug info, {}", err)))?;
+        let context = Context::from_dwarf(dwarf).map_err(|err| Error::elf(elf_file, format!("failed to load the debug info, {}", err)))?;
+        let mut symbols: Vec<(u64, String)> = file.symbols()
+            .filter(|symbol| symbol.is_definition() && !matches!(symbol.kind(), SymbolKind::Section | SymbolKind::File))
+            .filter_map(|symbol| Some((symbol.address(), symbol.name().ok()?.to_string())))
+            .collect();
+        symbols.sort_by_key(|(addr, _)| *addr);
+        Ok(DwarfInfo { context, symbols })
+    }
+
+    /**
+     * Find the symbol at or before an address, like the symbol map of the loader.
+     */
+    fn find_symbol(&self, probe: u64) -> Option<&str>{
+        let index = self.symbols.partition_point(|(addr, _)| *addr <= probe).checked_sub(1)?;
+        Some(&self.symbols[index].1)
+    }
+}
+
+/**
+ * The backend used to resolve the addresses to source locations.
+ */
+pub enum Symbolizer{
+    /// Reads the DWARF info of the elf in-process.
+    Native(Box<Loader>),
+    /// Reads the DWARF info of an elf added in memory, see vfs::insert.
+    Memory(Box<DwarfInfo>),
+    /// Queries an addr2line process of the toolchain.
+    External(Addr2line),
+}
+
+impl Symbolizer {
+    /**
+     * Create the symbolizer of an elf.
+     *
+     * @param addr2line_path: Optional path to an addr2line to use instead of the built-in DWARF reader.
+     * @param elf_file: Path to the elf, the elfs added in memory are read by the built-in DWARF reader.
+     * @return The symbolizer, or an error if the elf debug info can't be loaded.
+     */
+    pub fn new(addr2line_path: Option<&str>, elf_file: &str) -> Result<Symbolizer>{
+        if let Some(data) = vfs::get(elf_file) {
+            return Ok(Symbolizer::Memory(Box::new(DwarfInfo::parse(elf_file, &data)?)));
+        }
+        match addr2line_path {
+            Some(path) => Ok(Symbolizer::External(Addr2line::spawn(path, elf_file)?)),
+            None => match Loader::new(elf_file) {
+                Ok(loader) => Ok(Symbolizer::Native(Box::new(loader))),
+                Err(err) => Err(Error::elf(elf_file, format!("failed to load the debug info, {}", err))),
+            },
+        }
+    }
+
+    /**
+     * Resolve an address.
+     *
+     * @param addr: The address to be looked up.
+     * @return The source location, with None in the parts that are unknown.
+     */
+    pub fn locate(&mut self, addr: u64) -> SrcLocation {
+        match self {
+            Symbolizer::Native(loader) => Self::read_location(addr, loader.find_location(addr), loader.find_frames(addr),
+                |probe| loader.find_symbol(probe)),
+            Symbolizer::Memory(info) => Self::read_location(addr, info.context.find_location(addr),
+                info.context.find_frames(addr).skip_all_loads(), |probe| info.find_symbol(probe)),
+            Symbolizer::External(_) => self.locate_all(&[addr]).pop().unwrap_or_default(),
+        }
+    }
+
+    /**
+     * Resolve a batch of addresses, an addr2line process receives them all at once.
+     *
+     * @param addrs: The addresses to be looked up.
+     * @return The source location of each address in order, with None in the parts that are unknown.
+     */
+    pub fn locate_all(&mut self, addrs: &[u64]) -> Vec<SrcLocation> {
+        match self {
+            Symbolizer::Native(_) | Symbolizer::Memory(_) => addrs.iter().map(|&addr| self.locate(addr)).collect(),
+            Symbolizer::External(addr2line) => match addr2line.locate_all(addrs) {
+                Ok(answers) => answers.into_iter().map(Self::parse_frames).collect(),
+                Err(err) => {
+                    log::warn!("the sources of {} addresses are unknown, {}", addrs.len(), err);
+                    vec![SrcLocation::default(); addrs.len()]
+                },
+            },
+        }
+    }
+}
+
+impl Symbolizer {
+    /**
+     * Build the location of an address from the frames addr2line printed for it.
+     *
+     * @param frames: The function and source location output lines of each frame from the innermost.
+     */
+    fn parse_frames(frames: Vec<(String, String)>) -> SrcLocation{
+        let mut frames = frames.into_iter().map(|(function, output)| SrcLocation {
+            function: Some(function).filter(|function| function != "??" && !is_label(function)).as_deref().map(demangle),
+            ..SrcLocation::parse(&output)
+        });
+        let mut location = frames.next().unwrap_or_default();
+        location.inlined_at = frames.collect();
+        location
+    }
+
+    /**
+     * Build the location of an address from what the DWARF reader found for it.
+     *
+     * @param addr: The address looked up.
+     * @param location: The line of the address.
+     * @param frames: The frames of the address.
+     * @param find_symbol: Find the symbol at or before an address, the function when the address has no frame.
+     */
+    fn read_location<'a, R: gimli::Reader>(addr: u64, location: std::result::Result<Option<addr2line::Location>, impl fmt::Display>,
+        frames: std::result::Result<FrameIter<R>, impl fmt::Display>, find_symbol: impl FnOnce(u64) -> Option<&'a str>) -> SrcLocation{
+        let mut location = match location {
+            Ok(Some(loc)) => SrcLocation {
+                file: loc.file.map(|file| file.to_string()),
+                line: loc.line,
+                ..SrcLocation::default()
+            },
+            Ok(None) => SrcLocation::default(),
+            Err(err) => {
+                log::debug!("Can't read the line of the address 0x{:x}, {}", addr, err);
+                SrcLocation::default()
+            },
+        };
+        let mut frames = Self::find_frames(frames).into_iter();
+        // The ARM function symbols have the Thumb bit set, the instructions are at even addresses so the
+        // symbol that holds the address plus one is the function on both architectures.
+        location.function = frames.next().and_then(|frame| frame.function)
+            .or_else(|| find_symbol(addr | 1).filter(|name| !is_label(name)).map(demangle));
+        location.inlined_at = frames.collect();
+        location
+    }
+
+    /**
+     * Read the frames of an address found in the DWARF info.
+     *
+     * @param frames: The frames of the address.
+     * @return The demangled function and location of each frame from the innermost, empty if the address isn't in a function.
+     */
+    fn find_frames<R: gimli::Reader>(frames: std::result::Result<FrameIter<R>, impl fmt::Display>) -> Vec<SrcLocation>{
+        let mut res = Vec::new();
+        if let Ok(mut frames) = frames {
+            while let Ok(Some(frame)) = frames.next() {
+                res.push(SrcLocation {
+                    file: frame.location.as_ref().and_then(|loc| loc.file).map(str::to_string),
+                    line: frame.location.as_ref().and_then(|loc| loc.line),
+                    function: frame.function.and_then(|function| function.demangle().ok().map(|name| name.into_owned())),
+                    inlined_at: Vec::new(),
+                });
+            }
+        }
+        res
+    }
+}
+
+/**
+ * Demangle a C++ or Rust symbol, the names that aren't mangled are returned as they are.
+ */
+pub(crate) fn demangle(name: &str) -> String{
+    addr2line::demangle_auto(Cow::Borrowed(name), None).into_owned()
+}
+
+/**
+ * Check if a symbol is an assembler local label or a mapping symbol, i.e. .Lpcrel_hi0 or $x, rather than a function.
+ */
+fn is_label(name: &str) -> bool{
+    name.starts_with(".L") || name.starts_with('$')
+}
+
+/**
+ * One symbolizer per worker thread, each created the first time its thread needs it.
+ */
+pub struct SymbolizerPool{
+    addr2line_path: Option<String>,
+    elf_file: String,
+    workers: Vec<OnceLock<Option<Mutex<Symbolizer>>>>,
+}
+
+impl SymbolizerPool {
+    /**
+     * Constructor.
+     *
+     * @param addr2line_path: Optional path to an addr2line to use instead of the built-in DWARF reader.
+     * @param elf_file: Path to the elf.
+     * @param size: The number of worker threads.
+     */
+    pub fn new(addr2line_path: Option<&str>, elf_file: &str, size: usize) -> SymbolizerPool{
+        SymbolizerPool {
+            addr2line_path: addr2line_path.map(str::to_string),
+            elf_file: elf_file.to_string(),
+            workers: (0..size.max(1)).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    /**
+     * Resolve an address with the symbolizer of the current rayon worker thread.
+     *
+     * @param addr: The address to be looked up.
+     * @return The source location, with None in the parts that are unknown.
+     */
+    pub fn locate(&self, addr: u64) -> SrcLocation {
+        self.locate_all(&[addr]).pop().unwrap_or_default()
+    }
+
+    /**
+     * Resolve a batch of addresses with the symbolizer of the current rayon worker thread.
+     *
+     * @param addrs: The addresses to be looked up.
+     * @return The source location of each address in order, with None in the parts that are unknown.
+     */
+    pub fn locate_all(&self, addrs: &[u64]) -> Vec<SrcLocation> {
+        let index = rayon::current_thread_index().unwrap_or(0) % self.workers.len();
+        let worker = self.workers[index].get_or_init(|| {
+            Symbolizer::new(self.addr2line_path.as_deref(), &self.elf_file).map(Mutex::new)
+                .inspect_err(|err| log::warn!("a worker can't symbolize the addresses, {}", err)).ok()
+        });
+        match worker {
+            Some(symbolizer) => symbolizer.lock().unwrap().locate_all(addrs),
+            None => vec![SrcLocation::default(); addrs.len()],
+        }
+    }
+}
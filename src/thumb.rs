@@ -0,0 +1,228 @@
+use crate::isa::{InstrClass, Transfer};
+use crate::trace::TraceRecord;
+
+/// The condition suffixes of the conditional branches, i.e. beq or bhi.w.
+const CONDITIONS: [&str; 16] = ["eq", "ne", "cs", "hs", "cc", "lo", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le"];
+
+/// The number of the link register.
+const LR: u16 = 14;
+/// The number of the program counter.
+const PC: u16 = 15;
+
+/**
+ * Get the size of a Thumb instruction from its first half word.
+ *
+ * @param first_half: The half word at the address of the instruction.
+ * @return 4 for the 32 bit instructions, 2 for the others.
+ */
+pub fn instruction_size(first_half: u16) -> u64{
+    match first_half >> 11 {
+        0b11101..=0b11111 => 4,
+        _ => 2,
+    }
+}
+
+/**
+ * Split the instruction word of a traced instruction into its half words.
+ *
+ * The 32 bit instructions are written as objdump shows them, the first half word in the high bits, i.e. f000f802
+ * for a bl.
+ *
+ * @return The first half word and the second one of the 32 bit instructions, None if the log has no word.
+ */
+fn halves(record: &TraceRecord) -> Option<(u16, Option<u16>)>{
+    let word = u32::from_str_radix(record.opcode.as_deref()?.trim().trim_start_matches("0x"), 16).ok()?;
+    Some(match word > 0xffff {
+        true => ((word >> 16) as u16, Some(word as u16)),
+        false => (word as u16, None),
+    })
+}
+
+/**
+ * Split the decoded instruction of a traced instruction into its mnemonic, without the .w or .n width, and its
+ * operands.
+ */
+fn mnemonic(record: &TraceRecord) -> Option<(String, Vec<String>)>{
+    let instruction = record.instruction.as_deref()?.trim().to_lowercase();
+    let (mnemonic, operands) = instruction.split_once(char::is_whitespace).unwrap_or((&instruction, ""));
+    let mnemonic = mnemonic.strip_suffix(".w").or(mnemonic.strip_suffix(".n")).unwrap_or(mnemonic).to_string();
+    let operands = operands.split(|c: char| !c.is_ascii_alphanumeric()).filter(|operand| !operand.is_empty())
+        .map(str::to_string).collect();
+    Some((mnemonic, operands))
+}
+
+/**
+ * Classify a traced Thumb instruction.
+ *
+ * The instruction word is decoded when the log has it, the mnemonic of the decoded instruction is used otherwise.
+ * The returns are jumps and the accesses to the special registers, msr and mrs, are CSR accesses.
+ *
+ * @param record: The traced instruction.
+ * @return The class, Other if neither the opcode nor the mnemonic is known.
+ */
+pub fn class(record: &TraceRecord) -> InstrClass{
+    match halves(record) {
+        Some((first, Some(second))) => decode_wide(first, second),
+        Some((first, None)) => decode(first),
+        None => mnemonic(record).map_or(InstrClass::Other, |(mnemonic, operands)| from_mnemonic(&mnemonic, &operands)),
+    }
+}
+
+/**
+ * Classify a 16 bit instruction.
+ */
+fn decode(half: u16) -> InstrClass{
+    match half >> 10 {
+        0b000000..=0b010000 => InstrClass::Alu,
+        // bx and blx, and the mov to pc.
+        0b010001 if (half >> 8) & 0b11 == 0b11 || ((half >> 8) & 0b11 == 0b10 && ((half & 0x7) | ((half >> 4) & 0x8)) == PC) => InstrClass::Jump,
+        0b010001 => InstrClass::Alu,
+        0b010010 | 0b010011 => InstrClass::Load,
+        0b010100..=0b010111 if (half >> 9) & 0b111 <= 0b010 => InstrClass::Store,
+        0b010100..=0b010111 => InstrClass::Load,
+        // The loads and the stores of an immediate offset, of the words, the bytes, the half words and from sp.
+        0b011000..=0b100111 if half & 0x0800 != 0 => InstrClass::Load,
+        0b011000..=0b100111 => InstrClass::Store,
+        0b101000..=0b101011 => InstrClass::Alu,
+        0b101100..=0b101111 => match (half >> 8) & 0xf {
+            0b0001 | 0b0011 | 0b1001 | 0b1011 => InstrClass::Branch,
+            0b0100 | 0b0101 => InstrClass::Store,
+            0b1101 => InstrClass::Jump,
+            0b1100 => InstrClass::Load,
+            0b0110 | 0b1110 => InstrClass::System,
+            0b1111 if half & 0xf == 0 => InstrClass::System,
+            _ => InstrClass::Alu,
+        },
+        0b110000 | 0b110001 => InstrClass::Store,
+        0b110010 | 0b110011 => InstrClass::Load,
+        0b110100..=0b110111 => match (half >> 8) & 0xf {
+            0xe => InstrClass::Other,
+            0xf => InstrClass::System,
+            _ => InstrClass::Branch,
+        },
+        0b111000 | 0b111001 => InstrClass::Jump,
+        _ => InstrClass::Other,
+    }
+}
+
+/**
+ * Classify a 32 bit instruction.
+ */
+fn decode_wide(first: u16, second: u16) -> InstrClass{
+    let load = first & 0x10 != 0;
+    match (first >> 11, (first >> 9) & 0b11) {
+        // tbb and tbh.
+        (0b11101, 0b00) if first & 0xfff0 == 0xe8d0 && second & 0xffe0 == 0xf000 => InstrClass::Jump,
+        // The loads of multiple registers with pc, the pop.w of the epilogues.
+        (0b11101, 0b00) if load && first & 0x40 == 0 && second & 0x8000 != 0 => InstrClass::Jump,
+        (0b11101, 0b00) if load => InstrClass::Load,
+        (0b11101, 0b00) => InstrClass::Store,
+        (0b11101, 0b01) => InstrClass::Alu,
+        (0b11110, _) if second & 0x8000 == 0 => InstrClass::Alu,
+        (0b11110, _) if second & 0x5000 != 0 => InstrClass::Jump,
+        (0b11110, _) if (first >> 7) & 0b111 != 0b111 => InstrClass::Branch,
+        // msr and mrs, the hints and the barriers.
+        (0b11110, _) if matches!((first >> 4) & 0x7e, 0x38 | 0x3e) => InstrClass::Csr,
+        (0b11110, _) => InstrClass::System,
+        (0b11111, 0b00) if load && second >> 12 == PC => InstrClass::Jump,
+        (0b11111, 0b00) if load => InstrClass::Load,
+        (0b11111, 0b00) if first & 0x100 == 0 => InstrClass::Store,
+        (0b11111, 0b00) => InstrClass::Other,
+        (0b11111, 0b01) => InstrClass::Alu,
+        // The coprocessor and floating point loads and stores, vldr, vstr, vpush and vpop.
+        (_, 0b10) if load => InstrClass::Load,
+        (_, 0b10) => InstrClass::Store,
+        _ => InstrClass::Alu,
+    }
+}
+
+/**
+ * Classify an instruction by its mnemonic, i.e. ldr or bne.
+ */
+fn from_mnemonic(mnemonic: &str, operands: &[String]) -> InstrClass{
+    if transfer_of_mnemonic(mnemonic, operands).is_some() {
+        return InstrClass::Jump;
+    }
+    let starts = |prefixes: &[&str]| prefixes.iter().any(|prefix| mnemonic.starts_with(prefix));
+    match mnemonic {
+        "cbz" | "cbnz" => InstrClass::Branch,
+        "msr" | "mrs" => InstrClass::Csr,
+        "svc" | "bkpt" | "wfi" | "wfe" | "sev" | "yield" | "cpsie" | "cpsid" | "dmb" | "dsb" | "isb" | "udf" => InstrClass::System,
+        _ if mnemonic.strip_prefix('b').is_some_and(|condition| CONDITIONS.contains(&condition)) => InstrClass::Branch,
+        _ if starts(&["ldr", "ldm", "lda", "pop", "vldr", "vldm", "vpop"]) => InstrClass::Load,
+        _ if starts(&["str", "stm", "stl", "push", "vstr", "vstm", "vpush"]) => InstrClass::Store,
+        _ if mnemonic.chars().all(|c| c.is_ascii_alphanumeric() || c == '.') && !mnemonic.is_empty() => InstrClass::Alu,
+        _ => InstrClass::Other,
+    }
+}
+
+/**
+ * Classify the unconditional jumps of a traced Thumb instruction.
+ *
+ * The calls are bl and blx, the returns are bx lr, the mov pc, lr and the pops and the loads of pc from the stack.
+ *
+ * @param record: The traced instruction.
+ * @return The transfer, None if the instruction isn't a jump.
+ */
+pub fn transfer(record: &TraceRecord) -> Option<Transfer>{
+    match halves(record) {
+        Some((first, Some(second))) => transfer_wide(first, second),
+        Some((first, None)) => match first {
+            _ if first & 0xff87 == 0x4780 => Some(Transfer::Call),
+            _ if first & 0xff87 == 0x4700 && (first >> 3) & 0xf == LR => Some(Transfer::Return),
+            _ if first & 0xff87 == 0x4700 => Some(Transfer::Jump),
+            0x46f7 => Some(Transfer::Return),
+            _ if first & 0xff87 == 0x4687 => Some(Transfer::Jump),
+            _ if first & 0xff00 == 0xbd00 => Some(Transfer::Return),
+            _ if first >> 11 == 0b11100 => Some(Transfer::Jump),
+            _ => None,
+        },
+        None => mnemonic(record).and_then(|(mnemonic, operands)| transfer_of_mnemonic(&mnemonic, &operands)),
+    }
+}
+
+fn transfer_wide(first: u16, second: u16) -> Option<Transfer>{
+    match (first, second) {
+        _ if first >> 11 == 0b11110 && second & 0xc000 == 0xc000 => Some(Transfer::Call),
+        _ if first >> 11 == 0b11110 && second & 0xd000 == 0x9000 => Some(Transfer::Jump),
+        // pop.w with pc and ldr.w pc, [sp], #4.
+        (0xe8bd, _) if second & 0x8000 != 0 => Some(Transfer::Return),
+        (0xf85d, _) if second >> 12 == PC => Some(Transfer::Return),
+        _ if first & 0xfff0 == 0xe8d0 && second & 0xffe0 == 0xf000 => Some(Transfer::Jump),
+        _ => None,
+    }
+}
+
+fn transfer_of_mnemonic(mnemonic: &str, operands: &[String]) -> Option<Transfer>{
+    let has = |register: &str| operands.iter().any(|operand| operand == register);
+    let first = operands.first().map(String::as_str);
+    match mnemonic {
+        "bl" | "blx" => Some(Transfer::Call),
+        "bx" if first == Some("lr") => Some(Transfer::Return),
+        "b" | "bx" | "tbb" | "tbh" => Some(Transfer::Jump),
+        "pop" if has("pc") => Some(Transfer::Return),
+        "ldm" | "ldmia" | "ldmfd" | "ldr" if has("pc") && has("sp") => Some(Transfer::Return),
+        "ldr" if first == Some("pc") => Some(Transfer::Jump),
+        "mov" if first == Some("pc") && operands.get(1).is_some_and(|operand| operand == "lr") => Some(Transfer::Return),
+        "mov" if first == Some("pc") => Some(Transfer::Jump),
+        _ => None,
+    }
+}
+
+/**
+ * Get the size of a traced Thumb instruction, from its opcode or else from its mnemonic.
+ *
+ * @param record: The traced instruction.
+ * @return 4 for the 32 bit instructions, the bl and the .w mnemonics, 2 for the others.
+ */
+pub fn record_size(record: &TraceRecord) -> u64{
+    match (halves(record), record.instruction.as_deref()) {
+        (Some((_, Some(_))), _) => 4,
+        (Some((first, None)), _) => instruction_size(first),
+        (None, Some(insn)) => {
+            let mnemonic = insn.split_whitespace().next().unwrap_or("").to_lowercase();
+            if mnemonic == "bl" || mnemonic.ends_with(".w") { 4 } else { 2 }
+        },
+        (None, None) => 2,
+    }
+}
@@ -0,0 +1,118 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use crate::isa::Arch;
+
+/// The triples probed when the RISC-V toolchain isn't configured.
+pub const RISCV_TRIPLES: [&str; 2] = ["riscv32-unknown-elf", "riscv64-unknown-elf"];
+
+/// The triples probed when the ARM toolchain isn't configured.
+pub const ARM_TRIPLES: [&str; 1] = ["arm-none-eabi"];
+
+/// The common install locations of the RISC-V toolchains probed after the PATH.
+pub const RISCV_INSTALL_DIRS: [&str; 5] = [
+    "/tools/riscv/bin",
+    "/opt/riscv/bin",
+    "/opt/riscv32/bin",
+    "/opt/riscv64/bin",
+    "/opt/lowrisc-toolchain/bin",
+];
+
+/// The common install locations of the ARM toolchains probed after the PATH.
+pub const ARM_INSTALL_DIRS: [&str; 4] = [
+    "/opt/arm-none-eabi/bin",
+    "/opt/gcc-arm-none-eabi/bin",
+    "/opt/arm-gnu-toolchain/bin",
+    "/usr/local/gcc-arm-none-eabi/bin",
+];
+
+/**
+ * Get the triples probed when the toolchain of an architecture isn't configured.
+ */
+pub fn default_triples(arch: Arch) -> &'static [&'static str]{
+    match arch {
+        Arch::Riscv => &RISCV_TRIPLES,
+        Arch::Arm => &ARM_TRIPLES,
+    }
+}
+
+/**
+ * Get the common install locations of the toolchains of an architecture.
+ */
+pub fn install_dirs(arch: Arch) -> &'static [&'static str]{
+    match arch {
+        Arch::Riscv => &RISCV_INSTALL_DIRS,
+        Arch::Arm => &ARM_INSTALL_DIRS,
+    }
+}
+
+/// The tools that must be present for a toolchain to be picked.
+const REQUIRED_TOOLS: [&str; 2] = ["addr2line", "objdump"];
+
+/**
+ * The binutils of the toolchain that built the elf, i.e. riscv32-unknown-elf- or arm-none-eabi-.
+ */
+#[derive(Debug, Clone)]
+pub struct Toolchain{
+    /// Everything before the tool name, i.e. /opt/riscv/bin/riscv32-unknown-elf-.
+    pub prefix: String,
+}
+
+impl Toolchain {
+    /**
+     * Constructor.
+     *
+     * @param prefix: The path and triple of the tools, i.e. /opt/riscv/bin/riscv32-unknown-elf-.
+     */
+    pub fn new(prefix: &str) -> Toolchain{
+        Toolchain { prefix: prefix.to_string() }
+    }
+
+    /**
+     * Look for a toolchain in the PATH, in $RISCV/bin for RISC-V and in the common install locations.
+     *
+     * @param triple: The triple of the toolchain, by default the riscv32 and riscv64 elf triples are probed for
+     * RISC-V and arm-none-eabi for ARM.
+     * @param arch: The architecture of the elfs.
+     * @return The first toolchain that has both addr2line and objdump, None if there is none.
+     */
+    pub fn discover(triple: Option<&str>, arch: Arch) -> Option<Toolchain>{
+        let triples = match triple {
+            Some(triple) => vec![triple],
+            None => default_triples(arch).to_vec(),
+        };
+        Self::search_dirs(arch).iter().find_map(|dir| Self::find_in(dir, &triples))
+    }
+
+    /**
+     * Look for a toolchain in a directory.
+     *
+     * @param dir: The directory of the binutils, i.e. /opt/riscv/bin.
+     * @param triples: The triples of the toolchain, probed in order.
+     * @return The toolchain of the first triple whose addr2line and objdump are in the directory, None if there is none.
+     */
+    pub fn find_in(dir: &Path, triples: &[&str]) -> Option<Toolchain>{
+        triples.iter().map(|triple| Toolchain::new(&dir.join(format!("{}-", triple)).to_string_lossy()))
+            .find(|toolchain| REQUIRED_TOOLS.iter().all(|tool| Path::new(&toolchain.tool(tool)).is_file()))
+    }
+
+    /**
+     * Get the directories probed by discover for an architecture, in order.
+     */
+    pub fn search_dirs(arch: Arch) -> Vec<PathBuf>{
+        let mut dirs: Vec<PathBuf> = env::var_os("PATH").map_or(Vec::new(), |path| env::split_paths(&path).collect());
+        if let Some(riscv) = env::var_os("RISCV").filter(|_| arch == Arch::Riscv) {
+            dirs.push(PathBuf::from(riscv).join("bin"));
+        }
+        dirs.extend(install_dirs(arch).iter().map(PathBuf::from));
+        dirs
+    }
+
+    /**
+     * Get the path of a tool of the toolchain.
+     *
+     * @param name: The tool name without prefix, i.e. objdump.
+     */
+    pub fn tool(&self, name: &str) -> String{
+        format!("{}{}", self.prefix, name)
+    }
+}
@@ -0,0 +1,970 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use regex::Regex;
+use crate::error::Error;
+use crate::isa::{self, InstrClass};
+
+/// The number of lines the trace format is detected from.
+pub const DETECT_LINES: usize = 500;
+
+/**
+ * One executed instruction of the trace.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord{
+    /// The log line the record was parsed from.
+    pub line: String,
+    /// The hart that executed the instruction, None when the log doesn't tell.
+    pub hart: Option<u32>,
+    pub time: Option<u64>,
+    pub cycle: Option<u64>,
+    /// Address of the instruction.
+    pub pc: u64,
+    /// The instruction word in hex as printed in the log.
+    pub opcode: Option<String>,
+    /// The decoded instruction, i.e. c.addi x2,x2,-16.
+    pub instruction: Option<String>,
+    /// The notes added to the instruction by the script, shown after the other notes.
+    pub notes: Vec<String>,
+}
+
+/**
+ * A register written by an instruction, as shown by the logs with write-backs.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterWrite{
+    /// The architectural name, i.e. x5.
+    pub register: String,
+    pub value: u64,
+}
+
+/**
+ * A load or a store of an instruction, as shown by the logs with data addresses.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataAccess{
+    /// Load or Store.
+    pub kind: InstrClass,
+    pub addr: u64,
+    /// The value loaded or stored, None when the log doesn't show it.
+    pub value: Option<u64>,
+}
+
+impl TraceRecord {
+    /**
+     * Get the registers the instruction wrote from the write-backs of its log line, in the x5=0x2000001c form of
+     * Ibex or the x5 0x2000001c form of the Spike commit log.
+     *
+     * @return The writes in the order of the line, empty when the log has no write-backs.
+     */
+    pub fn register_writes(&self) -> Vec<RegisterWrite>{
+        let write = |register: &str, value: &str| Some(RegisterWrite {
+            register: isa::register_name(register)?,
+            value: u64::from_str_radix(value.strip_prefix("0x")?, 16).ok()?,
+        });
+        let tokens: Vec<&str> = self.line.split_whitespace().collect();
+        let mut writes = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            if let Some(written) = tokens[i].split_once('=').and_then(|(register, value)| write(register, value)) {
+                writes.push(written);
+            } else if let Some(written) = tokens.get(i + 1).and_then(|value| write(tokens[i], value)) {
+                writes.push(written);
+                i += 1;
+            }
+            i += 1;
+        }
+        writes
+    }
+
+    /**
+     * Get the address of the data the instruction loaded or stored, in the PA:0x10000ffc form of Ibex or the
+     * mem 0x10000ffc form of the Spike commit log.
+     *
+     * @return The address, None when the instruction doesn't access memory or the log doesn't show it.
+     */
+    pub fn data_address(&self) -> Option<u64>{
+        let tokens: Vec<&str> = self.line.split_whitespace().collect();
+        tokens.iter().enumerate().find_map(|(i, token)| match token.strip_prefix("PA:") {
+            Some(addr) => Some(addr),
+            None if *token == "mem" => tokens.get(i + 1).copied(),
+            None => None,
+        }).and_then(|addr| u64::from_str_radix(addr.strip_prefix("0x")?, 16).ok())
+    }
+
+    /**
+     * Get the load or the store of the instruction, in the PA:0x10000ffc store:0x20000018 form of Ibex or the
+     * mem 0x10000ffc form of the Spike commit log, followed by the value for the stores.
+     *
+     * @return The access, None when the instruction doesn't access memory or the log doesn't show it.
+     */
+    pub fn data_access(&self) -> Option<DataAccess>{
+        let hex = |token: &str| u64::from_str_radix(token.strip_prefix("0x")?, 16).ok();
+        let tokens: Vec<&str> = self.line.split_whitespace().collect();
+        if let Some(i) = tokens.iter().position(|token| token.starts_with("PA:")) {
+            let addr = hex(&tokens[i]["PA:".len()..])?;
+            return tokens[i + 1..].iter().find_map(|token| match token.split_once(':') {
+                Some(("load", value)) => Some(DataAccess { kind: InstrClass::Load, addr, value: hex(value) }),
+                Some(("store", value)) => Some(DataAccess { kind: InstrClass::Store, addr, value: hex(value) }),
+                _ => None,
+            });
+        }
+        let i = tokens.iter().position(|token| *token == "mem")?;
+        let addr = hex(tokens.get(i + 1)?)?;
+        // The value loaded is the one written to the destination register.
+        Some(match tokens.get(i + 2).and_then(|value| hex(value)) {
+            Some(value) => DataAccess { kind: InstrClass::Store, addr, value: Some(value) },
+            None => DataAccess { kind: InstrClass::Load, addr, value: self.register_writes().first().map(|write| write.value) },
+        })
+    }
+}
+
+/**
+ * The meaning of a column of the log lines.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceField{
+    /// The hart or core id, the digits at the end of the column, i.e. 1 or core1.
+    Hart,
+    Time,
+    Cycle,
+    /// The address of the instruction in hex, with or without 0x.
+    Pc,
+    Opcode,
+    /// The decoded instruction, when it's the last field it takes the remaining columns.
+    Instruction,
+    /// A column that isn't used.
+    Skip,
+}
+
+impl TraceField {
+    /// All the fields.
+    pub const ALL: [TraceField; 7] = [TraceField::Hart, TraceField::Time, TraceField::Cycle, TraceField::Pc, TraceField::Opcode,
+        TraceField::Instruction, TraceField::Skip];
+
+    /**
+     * Get the name of the field, as accepted by from_str.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            TraceField::Hart => "hart",
+            TraceField::Time => "time",
+            TraceField::Cycle => "cycle",
+            TraceField::Pc => "pc",
+            TraceField::Opcode => "opcode",
+            TraceField::Instruction => "instruction",
+            TraceField::Skip => "skip",
+        }
+    }
+
+    /**
+     * Get the names of all the fields.
+     */
+    pub fn names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|field| field.name()).collect()
+    }
+
+    /**
+     * Parse a comma separated field layout, i.e. time,cycle,pc,opcode,instruction.
+     *
+     * @param layout: The names of the fields in the order of the columns.
+     * @return The fields, string error if a name is unknown or there is no pc.
+     */
+    pub fn parse_layout(layout: &str) -> Result<TraceLayout, String>{
+        let fields = layout.split(',').map(|name| name.trim().parse()).collect::<Result<Vec<TraceField>, String>>()?;
+        if !fields.contains(&TraceField::Pc) {
+            return Err(format!("The field layout {} has no pc column", layout));
+        }
+        Ok(fields)
+    }
+
+    /**
+     * Get the layout of logs that only need the address, which is in the given column.
+     *
+     * @param column: The number of the column starting at 1.
+     */
+    pub fn pc_column(column: usize) -> TraceLayout{
+        let mut layout = vec![TraceField::Skip; column.saturating_sub(1)];
+        layout.push(TraceField::Pc);
+        layout
+    }
+}
+
+impl FromStr for TraceField {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|field| field.name() == name)
+            .ok_or_else(|| format!("Unknown trace field {}, expected one of {}", name, Self::names().join(", ")))
+    }
+}
+
+impl fmt::Display for TraceField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The fields of the columns of the log lines in order.
+pub type TraceLayout = Vec<TraceField>;
+
+/**
+ * The trace formats of the cores and simulators that have a built-in parser.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat{
+    /// <time> <cycle> <pc> <insn> <decoded instruction> ...
+    #[default]
+    Default,
+    /// The trace_core_*.log of the lowRISC Ibex tracer: Time, Cycle, PC, Insn, Decoded instruction and Register and
+    /// memory contents separated by tabs.
+    Ibex,
+    /// The log of the Spike ISA simulator, either the instruction log of -l, core 0: 0x80000134 (0x00a12423) sw ...,
+    /// or the commit log of --log-commits, core 0: 3 0x80000134 (0x00a12423) x8 0x...
+    Spike,
+    /// The log of qemu -d exec,in_asm, the disassembly of each translation block is replayed every time the block is
+    /// executed. Add nochain to -d so the chained blocks are logged too.
+    Qemu,
+    /// A csv with a header naming the columns, the address is in the pc or address column and the time, cycle, opcode
+    /// and instruction columns are also used.
+    Csv,
+}
+
+impl TraceFormat {
+    /// All the formats.
+    pub const ALL: [TraceFormat; 5] = [TraceFormat::Default, TraceFormat::Ibex, TraceFormat::Spike, TraceFormat::Qemu,
+        TraceFormat::Csv];
+
+    /**
+     * Get the name of the format, as accepted by from_str.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            TraceFormat::Default => "default",
+            TraceFormat::Ibex => "ibex",
+            TraceFormat::Spike => "spike",
+            TraceFormat::Qemu => "qemu",
+            TraceFormat::Csv => "csv",
+        }
+    }
+
+    /**
+     * Get the names of all the formats.
+     */
+    pub fn names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|format| format.name()).collect()
+    }
+
+    /**
+     * Create the parser of this format.
+     */
+    pub fn parser(&self) -> Box<dyn TraceParser>{
+        match self {
+            TraceFormat::Default => Box::new(DefaultParser::new()),
+            TraceFormat::Ibex => Box::new(LayoutParser::new(vec![TraceField::Time, TraceField::Cycle, TraceField::Pc,
+                TraceField::Opcode, TraceField::Instruction, TraceField::Skip])),
+            TraceFormat::Spike => Box::new(SpikeParser::new()),
+            TraceFormat::Qemu => Box::new(QemuParser::new()),
+            TraceFormat::Csv => Box::new(CsvParser::new()),
+        }
+    }
+
+    /**
+     * Guess the format of a log from its first lines.
+     *
+     * Each format is scored with the number of lines its parser extracts records from, the ibex format only counts
+     * the tab separated lines. On a tie the most specific format wins.
+     *
+     * @param lines: The first lines of the log, a few hundred are enough.
+     * @return The format, None if no line traces an instruction in any format.
+     */
+    pub fn detect(lines: &[impl AsRef<str>]) -> Option<TraceFormat>{
+        let mut best: Option<(TraceFormat, usize)> = None;
+        // From the most specific to the most generic.
+        for format in [TraceFormat::Csv, TraceFormat::Qemu, TraceFormat::Spike, TraceFormat::Ibex, TraceFormat::Default] {
+            let mut parser = format.parser();
+            let mut records = Vec::new();
+            let score = lines.iter().map(AsRef::as_ref).filter(|line| {
+                records.clear();
+                parser.parse_into(line, &mut records);
+                !records.is_empty() && (format != TraceFormat::Ibex || line.matches('\t').count() >= 4)
+            }).count();
+            if score > best.map_or(0, |(_, best)| best) {
+                best = Some((format, score));
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+            .ok_or_else(|| format!("Unknown trace format {}, expected one of {}", name, Self::names().join(", ")))
+    }
+}
+
+impl fmt::Display for TraceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * Extract the executed instructions from the lines of a trace log.
+ *
+ * Implement it to annotate the logs of a tracer that has no built-in parser, see DebuggerVarilator::set_parser.
+ */
+pub trait TraceParser: fmt::Debug + Send + Sync{
+    /**
+     * Parse a log line.
+     *
+     * @param line: A line of the log.
+     * @return The record, or None if the line doesn't trace an instruction or the parser is stateful.
+     */
+    fn parse(&self, line: &str) -> Option<TraceRecord>;
+
+    /**
+     * Parse a log line to get the address of the executed instruction.
+     *
+     * @param line: A line of the log.
+     * @return The address, or None if the line doesn't trace an instruction.
+     */
+    fn parse_pc(&self, line: &str) -> Option<u64>{
+        self.parse(line).map(|record| record.pc)
+    }
+
+    /**
+     * Check if the records of a line depend on the previous lines, i.e. they can't be parsed in parallel.
+     */
+    fn is_stateful(&self) -> bool{
+        false
+    }
+
+    /**
+     * Parse a log line in a stateful way, lines can trace any number of instructions.
+     *
+     * @param line: A line of the log, they must be given in order.
+     * @param records: Receives the records traced by the line.
+     */
+    fn parse_into(&mut self, line: &str, records: &mut Vec<TraceRecord>){
+        records.extend(self.parse(line));
+    }
+}
+
+/**
+ * Read the records of a log one by one, in a given format or in the one detected from its first lines.
+ */
+#[derive(Debug)]
+pub struct TraceReader<R>{
+    input: R,
+    parser: Box<dyn TraceParser>,
+    /// The lines read to detect the format, parsed first.
+    lines: VecDeque<String>,
+    records: VecDeque<TraceRecord>,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    /**
+     * Constructor.
+     *
+     * @param input: The log.
+     * @param parser: The parser of the log, None to detect the format.
+     * @return The reader, error if the log can't be read or no line of the first ones traces an instruction in a
+     * known format.
+     */
+    pub fn new(mut input: R, parser: Option<Box<dyn TraceParser>>) -> Result<TraceReader<R>, Error>{
+        let mut lines = VecDeque::new();
+        let parser = match parser {
+            Some(parser) => parser,
+            None => {
+                let mut line = String::new();
+                while lines.len() < DETECT_LINES {
+                    line.clear();
+                    if input.read_line(&mut line).map_err(|err| Error::Log(format!("Failed to read the log: {}", err)))? == 0 {
+                        break;
+                    }
+                    lines.push_back(line.trim_end_matches(['\n', '\r']).to_string());
+                }
+                let detected: Vec<String> = lines.iter().cloned().collect();
+                TraceFormat::detect(&detected)
+                    .ok_or_else(|| Error::Log(String::from("No line of the log traces an instruction in a known format")))?.parser()
+            },
+        };
+        Ok(TraceReader { input, parser, lines, records: VecDeque::new() })
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item>{
+        let mut records = Vec::new();
+        let mut line = String::new();
+        while self.records.is_empty() {
+            let next = match self.lines.pop_front() {
+                Some(next) => next,
+                None => {
+                    line.clear();
+                    match self.input.read_line(&mut line) {
+                        Ok(0) => return None,
+                        Ok(_) => line.trim_end_matches(['\n', '\r']).to_string(),
+                        Err(err) => return Some(Err(err)),
+                    }
+                },
+            };
+            self.parser.parse_into(&next, &mut records);
+            self.records.extend(records.drain(..));
+        }
+        self.records.pop_front().map(Ok)
+    }
+}
+
+/**
+ * The parser of the default log format: <time> <cycle> <pc> <insn> <decoded instruction> ...
+ */
+#[derive(Debug)]
+pub struct DefaultParser{
+    regex: Regex,
+}
+
+impl DefaultParser {
+    /**
+     * Constructor.
+     */
+    pub fn new() -> DefaultParser{
+        DefaultParser {
+            regex: Regex::new(r"([\da-fA-F]+)\s+([\da-fA-F]+)\s+([\da-fA-F]+)\s+([\da-fA-F]+)\s+(\w[^\t]*)").unwrap(),
+        }
+    }
+}
+
+impl Default for DefaultParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceParser for DefaultParser {
+    fn parse(&self, line: &str) -> Option<TraceRecord>{
+        let cap = self.regex.captures(line)?;
+        Some(TraceRecord {
+            line: line.to_string(),
+            hart: None,
+            time: cap[1].parse().ok(),
+            cycle: cap[2].parse().ok(),
+            pc: u64::from_str_radix(&cap[3], 16).ok()?,
+            opcode: Some(cap[4].to_string()),
+            instruction: Some(cap[5].trim_end().to_string()),
+            notes: Vec::new(),
+        })
+    }
+
+    fn parse_pc(&self, line: &str) -> Option<u64>{
+        let cap = self.regex.captures(line)?;
+        u64::from_str_radix(&cap[3], 16).ok()
+    }
+}
+
+/**
+ * The parser of the logs whose lines have the fields in another order.
+ *
+ * The columns are separated by tabs when the line has any, otherwise by whitespace.
+ */
+#[derive(Debug)]
+pub struct LayoutParser{
+    layout: TraceLayout,
+}
+
+impl LayoutParser {
+    /**
+     * Constructor.
+     *
+     * @param layout: The fields of the columns in order, the lines without a pc are ignored.
+     */
+    pub fn new(layout: TraceLayout) -> LayoutParser{
+        LayoutParser { layout }
+    }
+}
+
+impl TraceParser for LayoutParser {
+    fn parse(&self, line: &str) -> Option<TraceRecord>{
+        let tabbed = line.contains('\t');
+        let columns: Vec<&str> = if tabbed {
+            line.split('\t').map(str::trim).collect()
+        } else {
+            line.split_whitespace().collect()
+        };
+        let mut record = TraceRecord {
+            line: line.to_string(),
+            hart: None,
+            time: None,
+            cycle: None,
+            pc: 0,
+            opcode: None,
+            instruction: None,
+            notes: Vec::new(),
+        };
+        let mut pc = None;
+        for (i, field) in self.layout.iter().enumerate() {
+            let column = columns.get(i).copied().unwrap_or("");
+            match field {
+                TraceField::Hart => record.hart = column.trim_start_matches(|c: char| !c.is_ascii_digit()).parse().ok(),
+                TraceField::Time => record.time = column.parse().ok(),
+                TraceField::Cycle => record.cycle = column.parse().ok(),
+                TraceField::Pc => pc = parse_hex(column),
+                TraceField::Opcode => record.opcode = Some(column.to_string()).filter(|opcode| !opcode.is_empty()),
+                TraceField::Instruction if i + 1 == self.layout.len() && !tabbed => {
+                    record.instruction = Some(columns.get(i..).unwrap_or_default().join(" ")).filter(|insn| !insn.is_empty());
+                },
+                TraceField::Instruction => record.instruction = Some(column.to_string()).filter(|insn| !insn.is_empty()),
+                TraceField::Skip => (),
+            }
+        }
+        record.pc = pc?;
+        Some(record)
+    }
+}
+
+/**
+ * The parser of the logs whose address is extracted by a regex, i.e. pc=0x([0-9a-f]+).
+ *
+ * The address is the group named pc, or the first group when there is none. The optional groups named hart, time,
+ * cycle, opcode and instruction fill the other fields.
+ */
+#[derive(Debug)]
+pub struct RegexParser{
+    regex: Regex,
+}
+
+impl RegexParser {
+    /**
+     * Constructor.
+     *
+     * @param pattern: The regex, the lines that don't match it are ignored.
+     * @return The parser, error if the regex is invalid or has no group.
+     */
+    pub fn new(pattern: &str) -> Result<RegexParser, Error>{
+        let regex = Regex::new(pattern).map_err(|err| Error::Config(format!("Invalid pc regex {}: {}", pattern, err)))?;
+        if regex.captures_len() < 2 {
+            return Err(Error::Config(format!("The pc regex {} has no capture group", pattern)));
+        }
+        Ok(RegexParser { regex })
+    }
+}
+
+impl TraceParser for RegexParser {
+    fn parse(&self, line: &str) -> Option<TraceRecord>{
+        let cap = self.regex.captures(line)?;
+        let group = |name: &str| cap.name(name).map(|value| value.as_str().trim());
+        let pc = group("pc").or_else(|| cap.get(1).map(|value| value.as_str().trim()))?;
+        Some(TraceRecord {
+            line: line.to_string(),
+            hart: group("hart").and_then(|hart| hart.parse().ok()),
+            time: group("time").and_then(|time| time.parse().ok()),
+            cycle: group("cycle").and_then(|cycle| cycle.parse().ok()),
+            pc: parse_hex(pc)?,
+            opcode: group("opcode").map(str::to_string),
+            instruction: group("instruction").map(str::to_string),
+            notes: Vec::new(),
+        })
+    }
+}
+
+/**
+ * The parser of the Spike logs: core <hart>: [<privilege>] 0x<pc> (0x<insn>) <decoded instruction or commits>
+ */
+#[derive(Debug)]
+pub struct SpikeParser{
+    regex: Regex,
+}
+
+impl SpikeParser {
+    /**
+     * Constructor.
+     */
+    pub fn new() -> SpikeParser{
+        SpikeParser {
+            regex: Regex::new(r"core\s+(\d+):\s+(\d\s+)?0x([\da-fA-F]+)\s+\(0x([\da-fA-F]+)\)\s*(.*)").unwrap(),
+        }
+    }
+}
+
+impl Default for SpikeParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceParser for SpikeParser {
+    fn parse(&self, line: &str) -> Option<TraceRecord>{
+        let cap = self.regex.captures(line)?;
+        // The commit log has the privilege level and the register writes instead of the decoded instruction.
+        let commit = cap.get(2).is_some();
+        Some(TraceRecord {
+            line: line.to_string(),
+            hart: cap[1].parse().ok(),
+            time: None,
+            cycle: None,
+            pc: u64::from_str_radix(&cap[3], 16).ok()?,
+            opcode: Some(cap[4].to_string()),
+            instruction: Some(cap[5].trim_end().to_string()).filter(|insn| !commit && !insn.is_empty()),
+            notes: Vec::new(),
+        })
+    }
+}
+
+/**
+ * The parser of the qemu -d exec,in_asm logs, it keeps the translation blocks read from the in_asm sections.
+ */
+#[derive(Debug)]
+pub struct QemuParser{
+    insn_re: Regex,
+    exec_re: Regex,
+    /// The instructions of each block by start address.
+    blocks: HashMap<u64, Vec<TraceRecord>>,
+    /// The block being read, after an IN: line.
+    current: Option<Vec<TraceRecord>>,
+}
+
+impl QemuParser {
+    /**
+     * Constructor.
+     */
+    pub fn new() -> QemuParser{
+        QemuParser {
+            // 0x20000000:  10001137          lui                     sp,65537
+            insn_re: Regex::new(r"^0x([\da-fA-F]+):\s+(?:([\da-fA-F]{4,8})\s+)?(.*)$").unwrap(),
+            // Trace 0: 0x7f2c0c000100 [00000000/0000000020000000/00000000/ff020000] main, the number is the cpu.
+            exec_re: Regex::new(r"^Trace (\d+): 0x[\da-fA-F]+ \[[\da-fA-F]+/([\da-fA-F]+)/").unwrap(),
+            blocks: HashMap::new(),
+            current: None,
+        }
+    }
+
+    fn end_block(&mut self){
+        if let Some(block) = self.current.take() {
+            if let Some(first) = block.first() {
+                self.blocks.insert(first.pc, block);
+            }
+        }
+    }
+}
+
+impl Default for QemuParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceParser for QemuParser {
+    fn parse(&self, _line: &str) -> Option<TraceRecord>{
+        None
+    }
+
+    fn is_stateful(&self) -> bool{
+        true
+    }
+
+    fn parse_into(&mut self, line: &str, records: &mut Vec<TraceRecord>){
+        if line.starts_with("IN:") {
+            self.end_block();
+            self.current = Some(Vec::new());
+        } else if let (Some(block), Some(cap)) = (self.current.as_mut(), self.insn_re.captures(line)) {
+            if let Ok(pc) = u64::from_str_radix(&cap[1], 16) {
+                block.push(TraceRecord {
+                    line: line.to_string(),
+                    hart: None,
+                    time: None,
+                    cycle: None,
+                    pc,
+                    opcode: cap.get(2).map(|opcode| opcode.as_str().to_string()),
+                    instruction: Some(cap[3].split_whitespace().collect::<Vec<&str>>().join(" ")).filter(|insn| !insn.is_empty()),
+                    notes: Vec::new(),
+                });
+            }
+        } else if let Some(cap) = self.exec_re.captures(line) {
+            self.end_block();
+            let Ok(pc) = u64::from_str_radix(&cap[2], 16) else {
+                return;
+            };
+            let hart = cap[1].parse().ok();
+            match self.blocks.get(&pc) {
+                Some(block) => records.extend(block.iter().map(|record| TraceRecord { hart, ..record.clone() })),
+                // Without in_asm only the start of the blocks is known.
+                None => records.push(TraceRecord {
+                    line: line.to_string(),
+                    hart,
+                    time: None,
+                    cycle: None,
+                    pc,
+                    opcode: None,
+                    instruction: None,
+                    notes: Vec::new(),
+                }),
+            }
+        } else {
+            self.end_block();
+        }
+    }
+}
+
+/**
+ * The index of the columns of a csv log.
+ */
+#[derive(Debug)]
+struct CsvColumns{
+    pc: Option<usize>,
+    hart: Option<usize>,
+    time: Option<usize>,
+    cycle: Option<usize>,
+    opcode: Option<usize>,
+    instruction: Option<usize>,
+}
+
+impl CsvColumns {
+    fn new(header: &str) -> CsvColumns{
+        let names: Vec<String> = split_csv(header).iter().map(|name| name.trim().to_lowercase()).collect();
+        let find = |candidates: &[&str]| names.iter().position(|name| candidates.contains(&name.as_str()));
+        CsvColumns {
+            pc: find(&["pc", "address", "addr"]),
+            hart: find(&["hart", "core", "hartid"]),
+            time: find(&["time"]),
+            cycle: find(&["cycle"]),
+            opcode: find(&["opcode", "insn"]),
+            instruction: find(&["instruction", "decoded instruction", "decoded"]),
+        }
+    }
+
+    fn parse(&self, line: &str) -> Option<TraceRecord>{
+        let fields = split_csv(line);
+        let field = |index: Option<usize>| index.and_then(|index| fields.get(index)).map(|value| value.trim())
+            .filter(|value| !value.is_empty());
+        Some(TraceRecord {
+            line: line.to_string(),
+            hart: field(self.hart).and_then(|hart| hart.parse().ok()),
+            time: field(self.time).and_then(|time| time.parse().ok()),
+            cycle: field(self.cycle).and_then(|cycle| cycle.parse().ok()),
+            pc: parse_hex(field(self.pc)?)?,
+            opcode: field(self.opcode).map(str::to_string),
+            instruction: field(self.instruction).map(str::to_string),
+            notes: Vec::new(),
+        })
+    }
+}
+
+/**
+ * The parser of the csv logs, the columns are named by the header.
+ */
+#[derive(Debug, Default)]
+pub struct CsvParser{
+    /// None until the header is read.
+    columns: Option<CsvColumns>,
+}
+
+impl CsvParser {
+    /**
+     * Constructor.
+     */
+    pub fn new() -> CsvParser{
+        CsvParser { columns: None }
+    }
+}
+
+impl TraceParser for CsvParser {
+    fn parse(&self, _line: &str) -> Option<TraceRecord>{
+        None
+    }
+
+    fn is_stateful(&self) -> bool{
+        true
+    }
+
+    fn parse_into(&mut self, line: &str, records: &mut Vec<TraceRecord>){
+        match &self.columns {
+            Some(columns) => records.extend(columns.parse(line)),
+            None => self.columns = Some(CsvColumns::new(line)),
+        }
+    }
+}
+
+/**
+ * Split a csv line in its fields, removing the quotes.
+ */
+fn split_csv(line: &str) -> Vec<String>{
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                fields.last_mut().unwrap().push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/**
+ * Parse an address in hex, with or without 0x.
+ */
+fn parse_hex(value: &str) -> Option<u64>{
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IBEX: &str = "            130\t        13\t20000088\t    1141\tc.addi x2,x2,-16              \tx2=0x10000fe0";
+    const IBEX_STORE: &str = "            140\t        14\t2000008a\t    c606\tc.swsp x1,12(x2)              \t x1:0x200000d2 PA:0x10000fec store:0x200000d2";
+    const SPIKE: &str = "core   0: 0x0000000020000004 (0x00000297) auipc x5,0x0";
+    const SPIKE_COMMIT: &str = "core   1: 3 0x20000044 (0x0445a603) x12 0x00000007 mem 0x10000044";
+
+    fn parse_all(mut parser: Box<dyn TraceParser>, lines: &[&str]) -> Vec<TraceRecord>{
+        let mut records = Vec::new();
+        for line in lines {
+            parser.parse_into(line, &mut records);
+        }
+        records
+    }
+
+    #[test]
+    fn default_parser(){
+        let record = DefaultParser::new().parse("10 1 20000000 10001137 lui x2,0x10001").unwrap();
+        assert_eq!((record.time, record.cycle, record.pc), (Some(10), Some(1), 0x20000000));
+        assert_eq!((record.opcode.as_deref(), record.instruction.as_deref()), (Some("10001137"), Some("lui x2,0x10001")));
+        assert_eq!(DefaultParser::new().parse("Time Cycle PC Insn"), None);
+    }
+
+    #[test]
+    fn ibex_parser(){
+        let record = TraceFormat::Ibex.parser().parse(IBEX).unwrap();
+        assert_eq!((record.time, record.cycle, record.pc, record.hart), (Some(130), Some(13), 0x20000088, None));
+        assert_eq!((record.opcode.as_deref(), record.instruction.as_deref()), (Some("1141"), Some("c.addi x2,x2,-16")));
+        assert_eq!(record.register_writes(), [RegisterWrite { register: String::from("x2"), value: 0x10000fe0 }]);
+        assert_eq!(TraceFormat::Ibex.parser().parse("Time\tCycle\tPC\tInsn\tDecoded instruction"), None);
+    }
+
+    #[test]
+    fn ibex_data_access(){
+        let record = TraceFormat::Ibex.parser().parse(IBEX_STORE).unwrap();
+        assert_eq!(record.data_address(), Some(0x10000fec));
+        assert_eq!(record.data_access(), Some(DataAccess { kind: InstrClass::Store, addr: 0x10000fec, value: Some(0x200000d2) }));
+        assert_eq!(TraceFormat::Ibex.parser().parse(IBEX).unwrap().data_access(), None);
+    }
+
+    #[test]
+    fn spike_parser(){
+        let record = SpikeParser::new().parse(SPIKE).unwrap();
+        assert_eq!((record.hart, record.pc), (Some(0), 0x20000004));
+        assert_eq!((record.opcode.as_deref(), record.instruction.as_deref()), (Some("00000297"), Some("auipc x5,0x0")));
+        assert_eq!(SpikeParser::new().parse("core   0: >>>>  main"), None);
+    }
+
+    #[test]
+    fn spike_commit_log(){
+        let record = SpikeParser::new().parse(SPIKE_COMMIT).unwrap();
+        assert_eq!((record.hart, record.pc, record.instruction.as_deref()), (Some(1), 0x20000044, None));
+        assert_eq!(record.register_writes(), [RegisterWrite { register: String::from("x12"), value: 7 }]);
+        // The value of a load is the one written to its destination register.
+        assert_eq!(record.data_access(), Some(DataAccess { kind: InstrClass::Load, addr: 0x10000044, value: Some(7) }));
+        let store = SpikeParser::new().parse("core   0: 3 0x20000096 (0x00b50023) mem 0x10000000 0x03").unwrap();
+        assert_eq!(store.data_access(), Some(DataAccess { kind: InstrClass::Store, addr: 0x10000000, value: Some(3) }));
+    }
+
+    #[test]
+    fn qemu_parser_replays_the_blocks(){
+        let records = parse_all(TraceFormat::Qemu.parser(), &[
+            "IN: main",
+            "0x20000000:  10001137          lui                     x2,0x10001",
+            "0x20000004:  00000297          auipc                   x5,0x0",
+            "",
+            "Trace 0: 0x7f2c0c000000 [00000000/0000000020000000/00000000/ff020000] main",
+            "Trace 1: 0x7f2c0c000000 [00000000/0000000020000000/00000000/ff020000] main",
+            "Trace 0: 0x7f2c0c0000c6 [00000000/00000000200000c6/00000000/ff020000] delay",
+        ]);
+        let executed: Vec<(Option<u32>, u64)> = records.iter().map(|record| (record.hart, record.pc)).collect();
+        assert_eq!(executed, [(Some(0), 0x20000000), (Some(0), 0x20000004), (Some(1), 0x20000000), (Some(1), 0x20000004),
+            (Some(0), 0x200000c6)]);
+        assert_eq!(records[1].instruction.as_deref(), Some("auipc x5,0x0"));
+        // The block without in_asm is only known by its start.
+        assert_eq!(records[4].instruction, None);
+    }
+
+    #[test]
+    fn csv_parser_reads_the_header(){
+        let records = parse_all(TraceFormat::Csv.parser(), &[
+            "Cycle,Hart,PC,Opcode,Instruction",
+            "1,0,0x20000000,10001137,\"lui x2,0x10001\"",
+            "2,1,20000004,00000297,\"auipc x5,0x0\"",
+            "3,0,,,",
+        ]);
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].cycle, records[0].hart, records[0].pc), (Some(1), Some(0), 0x20000000));
+        assert_eq!(records[0].instruction.as_deref(), Some("lui x2,0x10001"));
+        assert_eq!((records[1].hart, records[1].pc), (Some(1), 0x20000004));
+    }
+
+    #[test]
+    fn layout_parser(){
+        let layout = TraceField::parse_layout("hart,cycle,pc,instruction").unwrap();
+        let record = LayoutParser::new(layout).parse("core1 42 0x80000000 addi a0, a0, 1").unwrap();
+        assert_eq!((record.hart, record.cycle, record.pc), (Some(1), Some(42), 0x80000000));
+        assert_eq!(record.instruction.as_deref(), Some("addi a0, a0, 1"));
+        let record = LayoutParser::new(TraceField::pc_column(2)).parse("x 1c y").unwrap();
+        assert_eq!(record.pc, 0x1c);
+        assert!(TraceField::parse_layout("time,cycle").is_err());
+        assert!(TraceField::parse_layout("pc,width").is_err());
+    }
+
+    #[test]
+    fn regex_parser(){
+        let parser = RegexParser::new(r"hart=(?P<hart>\d+) pc=(?P<pc>[0-9a-f]+) (?P<instruction>.*)").unwrap();
+        let record = parser.parse("hart=2 pc=80000010 jal ra, 0x100").unwrap();
+        assert_eq!((record.hart, record.pc, record.instruction.as_deref()), (Some(2), 0x80000010, Some("jal ra, 0x100")));
+        let record = RegexParser::new(r"@([0-9a-f]+)").unwrap().parse("exec @1234").unwrap();
+        assert_eq!(record.pc, 0x1234);
+        assert!(RegexParser::new(r"pc=[0-9a-f]+").is_err());
+        assert!(RegexParser::new(r"pc=(").is_err());
+    }
+
+    #[test]
+    fn detect_formats(){
+        assert_eq!(TraceFormat::detect(&["Time\tCycle\tPC\tInsn\tDecoded instruction\tRegister and memory contents", IBEX,
+            IBEX_STORE]), Some(TraceFormat::Ibex));
+        assert_eq!(TraceFormat::detect(&[SPIKE, "core   0: >>>>  main", SPIKE]), Some(TraceFormat::Spike));
+        assert_eq!(TraceFormat::detect(&[SPIKE_COMMIT]), Some(TraceFormat::Spike));
+        assert_eq!(TraceFormat::detect(&["IN: main", "0x20000000:  10001137          lui x2,0x10001", "",
+            "Trace 0: 0x7f2c0c000000 [00000000/0000000020000000/00000000/ff020000] main"]), Some(TraceFormat::Qemu));
+        assert_eq!(TraceFormat::detect(&["cycle,pc,instruction", "1,20000000,lui", "2,20000004,auipc"]), Some(TraceFormat::Csv));
+        assert_eq!(TraceFormat::detect(&["10 1 20000000 10001137 lui x2,0x10001"]), Some(TraceFormat::Default));
+        assert_eq!(TraceFormat::detect(&["booting", "hello world"]), None);
+    }
+
+    #[test]
+    fn reader_detects_the_format(){
+        let log = format!("{}\ncore   0: >>>>  main\n{}\n", SPIKE, SPIKE_COMMIT);
+        let pcs: Vec<u64> = TraceReader::new(log.as_bytes(), None).unwrap().map(|record| record.unwrap().pc).collect();
+        assert_eq!(pcs, [0x20000004, 0x20000044]);
+        assert!(TraceReader::new("booting\n".as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn format_names(){
+        for format in TraceFormat::ALL {
+            assert_eq!(format.name().parse::<TraceFormat>(), Ok(format));
+        }
+        assert!("verilator".parse::<TraceFormat>().is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::isa::{self, Arch, InstrClass};
+use crate::trace::TraceRecord;
+
+/**
+ * Why a trap was taken, as far as the trace tells.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TrapCause{
+    Ecall,
+    Ebreak,
+    /// The program counter jumped after an instruction that doesn't transfer control, an interrupt or an exception
+    /// of the instruction that wasn't retired.
+    Asynchronous,
+}
+
+impl TrapCause {
+    /**
+     * Get the name of the cause shown in the banners and the summary.
+     */
+    pub fn name(&self) -> &'static str{
+        match self {
+            TrapCause::Ecall => "ecall",
+            TrapCause::Ebreak => "ebreak",
+            TrapCause::Asynchronous => "interrupt or exception",
+        }
+    }
+}
+
+impl fmt::Display for TrapCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * A boundary between the program and a trap handler.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrapEvent{
+    /// The record is the first instruction of a trap handler.
+    Entry{
+        cause: TrapCause,
+        /// The address of the last instruction before the trap.
+        from: u64,
+    },
+    /// The record is the first instruction after a return from a trap.
+    Exit{
+        /// The return instruction, i.e. mret.
+        instruction: &'static str,
+        from: u64,
+    },
+}
+
+/**
+ * How an instruction moves the program counter.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow{
+    Sequential,
+    /// A branch or a jump, the next instruction can be anywhere.
+    Transfer,
+    Ecall,
+    Ebreak,
+    TrapReturn(&'static str),
+}
+
+impl Flow {
+    fn of(record: &TraceRecord) -> Flow{
+        let word = record.opcode.as_deref().and_then(|opcode| u32::from_str_radix(opcode.trim().trim_start_matches("0x"), 16).ok());
+        let mnemonic = record.instruction.as_deref().and_then(|insn| insn.split_whitespace().next()).unwrap_or("");
+        match (word, mnemonic) {
+            (Some(0x0000_0073), _) | (None, "ecall") => Flow::Ecall,
+            (Some(0x0010_0073), _) | (Some(0x9002), _) | (None, "ebreak" | "c.ebreak") => Flow::Ebreak,
+            (Some(0x3020_0073), _) | (None, "mret") => Flow::TrapReturn("mret"),
+            (Some(0x1020_0073), _) | (None, "sret") => Flow::TrapReturn("sret"),
+            (Some(0x0020_0073), _) | (None, "uret") => Flow::TrapReturn("uret"),
+            (Some(0x7b20_0073), _) | (None, "dret") => Flow::TrapReturn("dret"),
+            _ => match InstrClass::of(record, Arch::Riscv) {
+                InstrClass::Branch | InstrClass::Jump => Flow::Transfer,
+                _ => Flow::Sequential,
+            },
+        }
+    }
+}
+
+/**
+ * Find the trap entries and exits of the RISC-V harts from the control flow of the trace.
+ *
+ * A trap is entered after an ecall or an ebreak, or when the program counter jumps after an instruction that doesn't
+ * transfer control. It's left after an mret, sret, uret or dret.
+ */
+#[derive(Debug, Default)]
+pub struct TrapDetector{
+    /// The flow, the address and the address of the next instruction of the last record of each hart.
+    harts: HashMap<Option<u32>, (Flow, u64, u64)>,
+}
+
+impl TrapDetector {
+    /**
+     * Constructor.
+     */
+    pub fn new() -> TrapDetector{
+        TrapDetector::default()
+    }
+
+    /**
+     * Move the hart of a record to the record, the records must be given in the order of the trace.
+     *
+     * @param record: The traced instruction.
+     * @return The boundary the record is right after, None if it continues the flow of its hart.
+     */
+    pub fn update(&mut self, record: &TraceRecord) -> Option<TrapEvent>{
+        let flow = Flow::of(record);
+        let next_pc = record.pc + isa::record_size(record, Arch::Riscv);
+        let (last_flow, last_pc, last_next_pc) = self.harts.insert(record.hart, (flow, record.pc, next_pc))?;
+        match last_flow {
+            Flow::TrapReturn(instruction) => Some(TrapEvent::Exit { instruction, from: last_pc }),
+            Flow::Ecall => Some(TrapEvent::Entry { cause: TrapCause::Ecall, from: last_pc }),
+            Flow::Ebreak => Some(TrapEvent::Entry { cause: TrapCause::Ebreak, from: last_pc }),
+            Flow::Sequential if record.pc != last_next_pc => Some(TrapEvent::Entry { cause: TrapCause::Asynchronous, from: last_pc }),
+            _ => None,
+        }
+    }
+}
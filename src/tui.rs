@@ -0,0 +1,434 @@
+use std::io::{self, BufRead, IsTerminal};
+use std::rc::Rc;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{DefaultTerminal, Frame as TerminalFrame};
+use regex::Regex;
+use verilator_debugger::{open_log, CallStack, DebuggerVarilator, Error, Frame, TraceParser, TraceReader, TraceRecord};
+
+/// The source lines shown before and after the executed one when --context isn't given.
+pub const SOURCE_CONTEXT: u32 = 5;
+
+/// The width of the function column of the trace pane.
+const FUNCTION_WIDTH: usize = 24;
+
+/**
+ * Browse the annotated instructions of a log in the terminal, with a pane of the trace, a pane of the source of the
+ * selected instruction and a pane of its call stack.
+ *
+ * The records are read from the log as the view moves forward, so a multi-gigabyte log opens at once and only the
+ * part that was browsed is kept in memory.
+ *
+ * Besides the keys that move the selection, /regex searches the lines and the locations of the instructions, n and N
+ * go to the next and the previous match, g CYCLE goes to the first instruction from a cycle, or from a time for the
+ * logs without cycles, and :addr 0x1234 goes to the first execution of an address.
+ */
+pub struct TraceBrowser<R: BufRead>{
+    dv: DebuggerVarilator,
+    reader: TraceReader<R>,
+    /// True once the reader reached the end of the log.
+    ended: bool,
+    /// The records read so far, in the elfs and of the --hart.
+    records: Vec<TraceRecord>,
+    /// The call stack of each record, shared by the records where it doesn't change.
+    stacks: Vec<Rc<[Frame]>>,
+    call_stack: CallStack,
+    /// The index of the selected record and the one of the first record of the trace pane.
+    selected: usize,
+    top: usize,
+    /// The command being typed, with the key that started it.
+    prompt: Option<String>,
+    /// The message shown in the status line until the next key, i.e. that a search found nothing.
+    message: Option<String>,
+    /// The last regex searched, for n and N.
+    search: Option<Regex>,
+    /// The rows of the trace pane when it was last drawn, for PgUp and PgDn.
+    page: usize,
+}
+
+/**
+ * Open the log of the debugger and browse it until q is pressed.
+ *
+ * @param dv: The debugger, with the elfs and the log to browse.
+ * @param parser: The parser of the log, None to detect the format.
+ * @return Error if the terminal, the elfs or the log can't be used.
+ */
+pub fn browse(mut dv: DebuggerVarilator, parser: Option<Box<dyn TraceParser>>) -> Result<(), Error>{
+    if !io::stdout().is_terminal() {
+        return Err(Error::Config(String::from("The TUI needs a terminal")));
+    }
+    dv.load_ranges()?;
+    let path = dv.config.log_file.clone();
+    let (log, _) = open_log(&path)?;
+    let reader = TraceReader::new(log, parser)?;
+    let mut browser = TraceBrowser::new(dv, reader);
+    let mut terminal = ratatui::try_init().map_err(Error::Output)?;
+    let result = browser.run(&mut terminal);
+    // The terminal is restored even when the log can't be read.
+    ratatui::try_restore().map_err(Error::Output)?;
+    result.map_err(|err| Error::io(&path, err))
+}
+
+impl<R: BufRead> TraceBrowser<R> {
+    /**
+     * Constructor.
+     *
+     * @param dv: The debugger, its ranges must be loaded.
+     * @param reader: The records of the log.
+     */
+    pub fn new(dv: DebuggerVarilator, reader: TraceReader<R>) -> TraceBrowser<R>{
+        let arch = dv.config.arch;
+        TraceBrowser {
+            dv,
+            reader,
+            ended: false,
+            records: Vec::new(),
+            stacks: Vec::new(),
+            call_stack: CallStack::new(arch),
+            selected: 0,
+            top: 0,
+            prompt: None,
+            message: None,
+            search: None,
+            page: 1,
+        }
+    }
+
+    /**
+     * Read the log until there are this many records, or until it ends.
+     */
+    fn load(&mut self, count: usize) -> io::Result<()>{
+        while self.records.len() < count && !self.ended {
+            let Some(mut record) = self.reader.next().transpose()? else {
+                self.ended = true;
+                break;
+            };
+            record.pc = self.dv.config.arch.code_addr(record.pc);
+            let hart = record.hart.unwrap_or(0);
+            if !(self.dv.in_elfs(record.pc) || self.dv.config.keep_external) || !(self.dv.config.harts.is_empty() || self.dv.config.harts.contains(&hart)) {
+                continue;
+            }
+            let function = self.dv.get_src_info(record.pc).location.outer_function().unwrap_or("??").to_string();
+            let frames = self.call_stack.update(&record, &function);
+            let stack = match self.stacks.last() {
+                Some(last) if **last == *frames => Rc::clone(last),
+                _ => Rc::from(frames),
+            };
+            self.stacks.push(stack);
+            self.records.push(record);
+        }
+        Ok(())
+    }
+
+    /**
+     * Select a record, the last one read if the log has fewer records.
+     */
+    fn select(&mut self, index: usize) -> io::Result<()>{
+        self.load(index.saturating_add(1))?;
+        self.selected = index.min(self.records.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /**
+     * Browse until q, Escape or Ctrl-C is pressed.
+     */
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()>{
+        self.select(0)?;
+        loop {
+            self.draw(terminal)?;
+            let key = read_key()?;
+            let page = self.page.max(1);
+            self.message = None;
+            if cancels(&key) {
+                return Ok(());
+            }
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char(start @ ('/' | 'g' | ':')) => {
+                    if let Some(command) = self.read_command(terminal, start)? {
+                        self.execute(terminal, start, &command)?;
+                    }
+                },
+                KeyCode::Char('n') => self.search_next(terminal, true)?,
+                KeyCode::Char('N') => self.search_next(terminal, false)?,
+                KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1)?,
+                KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1))?,
+                KeyCode::PageDown | KeyCode::Char(' ') => self.select(self.selected + page)?,
+                KeyCode::PageUp => self.select(self.selected.saturating_sub(page))?,
+                KeyCode::Home => self.select(0)?,
+                KeyCode::End => self.select(usize::MAX)?,
+                _ => (),
+            }
+        }
+    }
+
+    /**
+     * Read a command in the status line until Enter is pressed.
+     *
+     * @param start: The key that started the command.
+     * @return The command without the key, None if it was cancelled with Escape.
+     */
+    fn read_command(&mut self, terminal: &mut DefaultTerminal, start: char) -> io::Result<Option<String>>{
+        let mut command = String::new();
+        loop {
+            self.prompt = Some(match start {
+                'g' => format!("g {}", command),
+                _ => format!("{}{}", start, command),
+            });
+            self.draw(terminal)?;
+            let key = read_key()?;
+            if cancels(&key) {
+                self.prompt = None;
+                return Ok(None);
+            }
+            match key.code {
+                KeyCode::Enter => break,
+                KeyCode::Backspace => {
+                    command.pop();
+                },
+                KeyCode::Char(c) => command.push(c),
+                _ => (),
+            }
+        }
+        self.prompt = None;
+        Ok(Some(command.trim().to_string()))
+    }
+
+    /**
+     * Run a command, the message of the status line tells what didn't work.
+     */
+    fn execute(&mut self, terminal: &mut DefaultTerminal, start: char, command: &str) -> io::Result<()>{
+        match start {
+            '/' => match Regex::new(command) {
+                Ok(regex) => {
+                    self.search = Some(regex);
+                    self.search_next(terminal, true)?;
+                },
+                // The errors of the regex crate point at the pattern over several lines, the last one tells what's wrong.
+                Err(err) => self.message = Some(format!("Invalid regex, {}", err.to_string().lines().last().unwrap_or(""))),
+            },
+            'g' => match command.parse::<u64>() {
+                Ok(cycle) => {
+                    self.show_progress(terminal, &format!("Going to cycle {}...", cycle))?;
+                    let found = self.find(0, |_, record| record.cycle.or(record.time).is_some_and(|when| when >= cycle))?;
+                    self.go_to(found, format!("No instruction from cycle {}", cycle));
+                },
+                Err(_) => self.message = Some(format!("Invalid cycle {}, expected i.e. g 1234", command)),
+            },
+            _ => {
+                let addr = match command.split_whitespace().collect::<Vec<&str>>()[..] {
+                    ["addr", addr] => u64::from_str_radix(addr.trim_start_matches("0x").trim_start_matches("0X"), 16).ok(),
+                    _ => None,
+                };
+                let Some(addr) = addr else {
+                    self.message = Some(format!("Unknown command {}, expected i.e. :addr 0x1234", command));
+                    return Ok(());
+                };
+                self.show_progress(terminal, &format!("Going to 0x{:x}...", addr))?;
+                let found = self.find(0, |_, record| record.pc == addr)?;
+                self.go_to(found, format!("0x{:x} isn't executed", addr));
+            },
+        }
+        Ok(())
+    }
+
+    /**
+     * Go to the next or the previous match of the last search, the lines and the locations of the instructions are
+     * searched.
+     *
+     * @param forward: True to search after the selected record, false to search before it.
+     */
+    fn search_next(&mut self, terminal: &mut DefaultTerminal, forward: bool) -> io::Result<()>{
+        let Some(regex) = self.search.clone() else {
+            self.message = Some(String::from("No search yet, search with /regex"));
+            return Ok(());
+        };
+        let matches = |dv: &mut DebuggerVarilator, record: &TraceRecord| {
+            regex.is_match(&record.line) || regex.is_match(&dv.get_src_info(record.pc).location.header())
+        };
+        let found = match forward {
+            true => {
+                self.show_progress(terminal, &format!("Searching {}...", regex))?;
+                self.find(self.selected + 1, matches)?
+            },
+            false => (0..self.selected).rev().find(|&index| matches(&mut self.dv, &self.records[index])),
+        };
+        self.go_to(found, format!("{} not found", regex));
+        Ok(())
+    }
+
+    /**
+     * Find the first record from an index that matches, reading the log as far as needed.
+     *
+     * @return The index of the record, None if no record matches until the end of the log.
+     */
+    fn find(&mut self, from: usize, mut matches: impl FnMut(&mut DebuggerVarilator, &TraceRecord) -> bool) -> io::Result<Option<usize>>{
+        let mut index = from;
+        loop {
+            self.load(index + 1)?;
+            let Some(record) = self.records.get(index) else {
+                return Ok(None);
+            };
+            if matches(&mut self.dv, record) {
+                return Ok(Some(index));
+            }
+            index += 1;
+        }
+    }
+
+    /**
+     * Select the record found, or show why nothing was found.
+     */
+    fn go_to(&mut self, found: Option<usize>, not_found: String){
+        match found {
+            Some(index) => self.selected = index,
+            None => self.message = Some(not_found),
+        }
+    }
+
+    /**
+     * Show a message in the status line while the log is read, a search may read a large part of it.
+     */
+    fn show_progress(&mut self, terminal: &mut DefaultTerminal, message: &str) -> io::Result<()>{
+        self.message = Some(message.to_string());
+        self.draw(terminal)?;
+        self.message = None;
+        Ok(())
+    }
+
+    /**
+     * Get the number of rows of the trace, the source and the call stack panes, leaving the rows of their titles and
+     * of the status line.
+     */
+    fn pane_rows(height: u16) -> (usize, usize, usize){
+        let rows = (height as usize).saturating_sub(3);
+        let trace = rows / 2;
+        let stack = (rows - trace) / 3;
+        (trace, rows - trace - stack, stack)
+    }
+
+    /**
+     * Draw the panes, ratatui only writes the cells that changed so it doesn't flicker.
+     */
+    fn draw(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()>{
+        terminal.draw(|frame| self.render(frame))?;
+        Ok(())
+    }
+
+    fn render(&mut self, frame: &mut TerminalFrame){
+        let (trace_rows, source_rows, stack_rows) = Self::pane_rows(frame.area().height);
+        let [trace_area, source_area, stack_area, status_area] = Layout::vertical([
+            Constraint::Length(trace_rows as u16),
+            Constraint::Length(source_rows as u16 + 1),
+            Constraint::Length(stack_rows as u16 + 1),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        self.page = trace_rows;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + trace_rows {
+            self.top = self.selected + 1 - trace_rows.max(1);
+        }
+        let rows: Vec<Line> = (self.top..self.top + trace_rows).map_while(|index| {
+            let record = self.records.get(index)?;
+            let function = self.stacks[index].last().map_or("??", |frame| frame.function.as_str());
+            let row = format!("{} {}", column(function, FUNCTION_WIDTH), record.line.replace('\t', "  "));
+            Some(match index == self.selected {
+                // The selected row is highlighted up to the edge of the pane.
+                true => Line::styled(format!("{:<width$}", row, width = trace_area.width as usize), Style::new().reversed()),
+                false => Line::from(row),
+            })
+        }).collect();
+        frame.render_widget(Paragraph::new(rows), trace_area);
+
+        let (source, stack) = match self.records.get(self.selected) {
+            Some(record) => (self.source_lines(record.pc), Rc::clone(&self.stacks[self.selected])),
+            None => ((String::from("source"), Vec::new()), Rc::from(Vec::new())),
+        };
+        let lines: Vec<Line> = source.1.into_iter().map(Line::from).collect();
+        frame.render_widget(Paragraph::new(lines).block(pane(&source.0)), source_area);
+
+        let mut frames: Vec<String> = stack.iter().rev().enumerate().map(|(depth, frame)| match frame.call_pc {
+            Some(call_pc) => format!("#{:<3} {}, called from 0x{:x}", depth, frame.function, call_pc),
+            None => format!("#{:<3} {}", depth, frame.function),
+        }).collect();
+        if frames.len() > stack_rows && stack_rows > 0 {
+            let hidden = frames.len() - stack_rows + 1;
+            frames.truncate(stack_rows - 1);
+            frames.push(format!("     ... {} more", hidden));
+        }
+        let lines: Vec<Line> = frames.into_iter().map(Line::from).collect();
+        frame.render_widget(Paragraph::new(lines).block(pane(&format!("call stack, depth {}", stack.len()))), stack_area);
+
+        let position = match self.ended {
+            true => format!("{}/{}", self.selected + 1, self.records.len()),
+            false => format!("{}/{}+", self.selected + 1, self.records.len()),
+        };
+        let status = match (&self.prompt, &self.message) {
+            (Some(prompt), _) => prompt.clone(),
+            (None, Some(message)) => format!(" {}  {}", position, message),
+            (None, None) => format!(" {}  ↑↓ move  PgUp PgDn page  Home End first last  /regex n N search  g cycle  :addr 0x..  q quit", position),
+        };
+        frame.render_widget(Paragraph::new(status).style(Style::new().reversed()), status_area);
+    }
+
+    /**
+     * Get the title of the source pane and its lines for an address, the context lines around the executed one.
+     */
+    fn source_lines(&mut self, pc: u64) -> (String, Vec<String>){
+        let info = self.dv.get_src_info(pc);
+        let mut title = info.location.header();
+        for call_site in &info.location.inlined_at {
+            title.push_str(&format!(", inlined into {}", call_site.header()));
+        }
+        let lines = if !info.context.is_empty() {
+            info.context.iter().map(|(number, code)| {
+                let marker = if Some(*number) == info.location.line { ">" } else { " " };
+                format!("{} {:>5} | {}", marker, number, code.replace('\t', "    "))
+            }).collect()
+        } else {
+            vec![info.code.clone().or_else(|| info.unavailable()).unwrap_or_else(|| String::from("The source isn't available"))]
+        };
+        (title, lines)
+    }
+}
+
+/**
+ * Get the block of a pane, its title on a rule.
+ */
+fn pane(title: &str) -> Block<'_>{
+    Block::new().borders(Borders::TOP).title(format!(" {} ", title)).title_style(Style::new().bold())
+}
+
+/**
+ * Pad a text to the width of a column, or cut it with a ~ when it's longer.
+ */
+fn column(text: &str, width: usize) -> String{
+    match text.chars().count() > width {
+        true => text.chars().take(width.saturating_sub(1)).chain(std::iter::once('~')).collect(),
+        false => format!("{:<width$}", text),
+    }
+}
+
+/**
+ * Wait for a key to be pressed, the releases and the other events are skipped.
+ */
+fn read_key() -> io::Result<KeyEvent>{
+    loop {
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                return Ok(key);
+            }
+        }
+    }
+}
+
+/**
+ * Check whether a key cancels, Escape or Ctrl-C, the terminal is in raw mode so Ctrl-C is a key and not a signal.
+ */
+fn cancels(key: &KeyEvent) -> bool{
+    key.code == KeyCode::Esc || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL))
+}
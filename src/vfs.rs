@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The files added in memory, read instead of the files of the same path on the disk.
+static FILES: Mutex<BTreeMap<PathBuf, Arc<[u8]>>> = Mutex::new(BTreeMap::new());
+
+/**
+ * Add a file in memory, the elfs and the sources are read from the memory first. There is no file system in the
+ * browser, the page adds the files the user opened.
+ *
+ * @param path: The path the file is read from, i.e. the path of the elf or the path of a source in the debug info.
+ * @param data: The content of the file.
+ */
+pub fn insert(path: &str, data: impl Into<Arc<[u8]>>){
+    FILES.lock().unwrap().insert(PathBuf::from(path), data.into());
+}
+
+/**
+ * Remove the files added in memory.
+ */
+pub fn clear(){
+    FILES.lock().unwrap().clear();
+}
+
+/**
+ * Get a file added in memory.
+ *
+ * @return The content of the file, None if the file wasn't added.
+ */
+pub fn get(path: impl AsRef<Path>) -> Option<Arc<[u8]>>{
+    FILES.lock().unwrap().get(path.as_ref()).cloned()
+}
+
+/**
+ * Read a file from the memory, or else from the disk.
+ *
+ * @return The content of the file, error if it isn't in the memory and can't be read from the disk.
+ */
+pub fn read(path: impl AsRef<Path>) -> io::Result<Arc<[u8]>>{
+    match get(&path) {
+        Some(data) => Ok(data),
+        None => fs::read(path).map(Arc::from),
+    }
+}
+
+/**
+ * Check if a file is in the memory or on the disk.
+ */
+pub fn is_file(path: impl AsRef<Path>) -> bool{
+    get(&path).is_some() || path.as_ref().is_file()
+}
+
+/**
+ * Get the paths of the files added in memory under a directory, in order.
+ */
+pub fn list(dir: &Path) -> Vec<PathBuf>{
+    FILES.lock().unwrap().keys().filter(|path| path.starts_with(dir)).cloned().collect()
+}
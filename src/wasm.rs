@@ -0,0 +1,50 @@
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use crate::config::Config;
+use crate::debugger::DebuggerVarilator;
+use crate::output::OutputFormat;
+use crate::vfs;
+
+/**
+ * Add a file the user opened in the page, an elf or a source, under the path the annotation reads it from.
+ *
+ * @param path: The path of the elf given to annotate, or of a source, i.e. its path in the debug info or a path
+ * under one of the source directories given to annotate.
+ * @param data: The content of the file.
+ */
+#[wasm_bindgen(js_name = addFile)]
+pub fn add_file(path: &str, data: &[u8]){
+    vfs::insert(path, data);
+}
+
+/**
+ * Remove the files added, i.e. before the user opens another firmware.
+ */
+#[wasm_bindgen(js_name = clearFiles)]
+pub fn clear_files(){
+    vfs::clear();
+}
+
+/**
+ * Annotate a log with the source code of the elfs added with addFile, nothing leaves the page.
+ *
+ * @param elf_files: The paths the elfs were added under.
+ * @param log: The content of the log, its format is detected from its first lines.
+ * @param format: The output format, as the --format of the command line, i.e. html or json.
+ * @param context: The number of source lines shown around each line.
+ * @param source_dirs: The directories the sources were added under, searched like --source-dir for the sources that
+ * aren't at the path of the debug info.
+ * @return The annotated log, error if an elf can't be read, the format is unknown or the log isn't UTF-8.
+ */
+#[wasm_bindgen]
+pub fn annotate(elf_files: Vec<String>, log: &[u8], format: &str, context: u32, source_dirs: Vec<String>) -> Result<String, JsError>{
+    let mut config = Config::for_elfs(None, None, elf_files, Vec::new()).map_err(|err| JsError::new(&err.to_string()))?;
+    config.format = OutputFormat::from_str(format).map_err(|err| JsError::new(&err))?;
+    config.context = context;
+    config.source_dirs = source_dirs;
+    config.log_file = String::from("log");
+    let mut output = Vec::new();
+    DebuggerVarilator::new(config).and_then(|mut dv| dv.annotate_bytes(log, &mut output))
+        .map_err(|err| JsError::new(&err.to_string()))?;
+    String::from_utf8(output).map_err(|err| JsError::new(&err.to_string()))
+}
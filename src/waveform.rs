@@ -0,0 +1,395 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use fst_reader::{FstFilter, FstHierarchyEntry, FstReader, FstSignalHandle, FstSignalValue};
+use crate::compress::{is_stream, open_log};
+use crate::error::{Error, Result};
+use crate::trace::TraceField;
+
+/// The columns of the lines synthesized from a waveform, read with a LayoutParser.
+pub const LAYOUT: [TraceField; 2] = [TraceField::Time, TraceField::Pc];
+
+/// The bytes of lines synthesized at a time.
+const FILL_BYTES: usize = 64 * 1024;
+
+/// The changes of the pc read ahead of the annotation from an FST.
+const FST_CHANGES: usize = 64 * 1024;
+
+/// The time of a change of a signal and its new value, None for a value with x or z bits.
+pub type SignalChange = (u64, Option<u64>);
+
+/**
+ * The changes of the value of a signal of a waveform dump, in the order of the time.
+ */
+pub trait SignalChanges {
+    /**
+     * Read the next change of the signal.
+     *
+     * @return The change, None at the end of the dump.
+     */
+    fn next_change(&mut self) -> io::Result<Option<SignalChange>>;
+}
+
+/**
+ * The log synthesized from the changes of the pc signal of a waveform, a time<TAB>pc line for each new value the pc
+ * holds at the end of a time step, read like the log of a tracer.
+ *
+ * An instruction that executes again right after itself, i.e. a branch to itself, doesn't change the pc and is only
+ * seen once. The values with x or z bits are skipped.
+ */
+pub struct WaveformTrace<S: SignalChanges>{
+    changes: S,
+    /// The lines synthesized and not read yet, from the position.
+    buffer: Vec<u8>,
+    position: usize,
+    /// The last change of the current time step, the pc may change again within the step.
+    pending: Option<SignalChange>,
+    /// The value of the last step, a step that keeps it isn't an instruction.
+    last: Option<u64>,
+    ended: bool,
+}
+
+impl<S: SignalChanges> WaveformTrace<S> {
+    /**
+     * Constructor.
+     *
+     * @param changes: The changes of the pc signal.
+     */
+    pub fn new(changes: S) -> WaveformTrace<S>{
+        WaveformTrace { changes, buffer: Vec::new(), position: 0, pending: None, last: None, ended: false }
+    }
+
+    /**
+     * Write the line of the value of the pc at the end of a time step, if it's a new value.
+     */
+    fn settle(&mut self, time: u64, value: Option<u64>){
+        if let Some(pc) = value.filter(|_| value != self.last) {
+            // Writing to a vector doesn't fail.
+            let _ = writeln!(self.buffer, "{}\t{:x}", time, pc);
+        }
+        self.last = value;
+    }
+
+    /**
+     * Synthesize the lines of the next changes of the pc.
+     */
+    fn fill(&mut self) -> io::Result<()>{
+        self.buffer.clear();
+        self.position = 0;
+        while self.buffer.len() < FILL_BYTES && !self.ended {
+            match self.changes.next_change()? {
+                Some((time, value)) => {
+                    if let Some((step, pc)) = self.pending.filter(|(step, _)| *step != time) {
+                        self.settle(step, pc);
+                    }
+                    self.pending = Some((time, value));
+                },
+                None => {
+                    self.ended = true;
+                    if let Some((step, pc)) = self.pending.take() {
+                        self.settle(step, pc);
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: SignalChanges> Read for WaveformTrace<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl<S: SignalChanges> BufRead for WaveformTrace<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]>{
+        while self.position == self.buffer.len() && !self.ended {
+            self.fill()?;
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize){
+        self.position = (self.position + amount).min(self.buffer.len());
+    }
+}
+
+/**
+ * The search of a signal in the hierarchy of a dump, as its scopes and its variables are read.
+ */
+struct SignalSearch<'a, I>{
+    /// The path searched, and its last name.
+    signal: &'a str,
+    name: &'a str,
+    scopes: Vec<String>,
+    /// The path and the identifier of the variables whose path ends with the one searched.
+    found: Vec<(String, I)>,
+    /// The paths of the other variables with the name searched, for the error.
+    names: Vec<String>,
+}
+
+impl<'a, I: Ord + Clone> SignalSearch<'a, I> {
+    /**
+     * Constructor.
+     *
+     * @param signal: The path of the signal, i.e. top.core.pc_id. The scopes above the path can be left out, i.e.
+     * TOP.
+     */
+    fn new(signal: &'a str) -> SignalSearch<'a, I>{
+        let name = signal.rsplit('.').next().unwrap_or(signal);
+        SignalSearch { signal, name, scopes: Vec::new(), found: Vec::new(), names: Vec::new() }
+    }
+
+    fn scope(&mut self, name: &str){
+        self.scopes.push(name.to_string());
+    }
+
+    fn upscope(&mut self){
+        self.scopes.pop();
+    }
+
+    /**
+     * Look at a variable of the current scope.
+     *
+     * @param reference: The name of the variable, with its bit range if any.
+     * @param id: The identifier of its values in the dump, the aliases of a signal have the same.
+     */
+    fn var(&mut self, reference: &str, id: I){
+        let reference = reference.split('[').next().unwrap_or(reference).trim();
+        let path = self.scopes.iter().map(String::as_str).chain([reference]).collect::<Vec<&str>>().join(".");
+        if path == self.signal || path.ends_with(&format!(".{}", self.signal)) {
+            self.found.push((path, id));
+        } else if reference == self.name {
+            self.names.push(path);
+        }
+    }
+
+    /**
+     * Get the identifier of the signal.
+     *
+     * @return The identifier, error if no variable or variables of several signals have the path.
+     */
+    fn finish(mut self) -> io::Result<I>{
+        self.found.sort_by(|a, b| a.1.cmp(&b.1));
+        self.found.dedup_by(|a, b| a.1 == b.1);
+        match self.found.as_slice() {
+            [(_, id)] => Ok(id.clone()),
+            [] if self.names.is_empty() => Err(invalid(format!("The waveform has no signal {}", self.signal))),
+            [] => Err(invalid(format!("The waveform has no signal {}, the signals named {} are {}", self.signal, self.name,
+                self.names.join(", ")))),
+            found => Err(invalid(format!("The signal {} is ambiguous, give the path of one of {}", self.signal,
+                found.iter().map(|(path, _)| path.as_str()).collect::<Vec<&str>>().join(", ")))),
+        }
+    }
+}
+
+/**
+ * The changes of a signal of a VCD dump, i.e. of a Verilator simulation run with --trace.
+ */
+pub struct VcdSignal<R: BufRead>{
+    reader: R,
+    /// The tokens of the line being read.
+    tokens: VecDeque<String>,
+    /// The identifier code of the signal in the value changes.
+    id: String,
+    time: u64,
+}
+
+impl<R: BufRead> VcdSignal<R> {
+    /**
+     * Read the header of a dump and find a signal.
+     *
+     * @param reader: The dump.
+     * @param signal: The path of the signal in the hierarchy of the dump, i.e. top.core.pc_id. The scopes above the
+     * path can be left out, i.e. TOP.
+     * @return The signal before its first change, error if the dump can't be read or doesn't have the signal.
+     */
+    pub fn new(reader: R, signal: &str) -> io::Result<VcdSignal<R>>{
+        let mut vcd = VcdSignal { reader, tokens: VecDeque::new(), id: String::new(), time: 0 };
+        let mut search = SignalSearch::new(signal);
+        loop {
+            let Some(command) = vcd.next_token()? else {
+                return Err(invalid("The waveform ends before the end of its definitions, it isn't a VCD"));
+            };
+            let words = vcd.command_words()?;
+            match (command.as_str(), words.as_slice()) {
+                ("$scope", [_, scope, ..]) => search.scope(scope),
+                ("$upscope", _) => search.upscope(),
+                // The bit range is either its own word or part of the reference.
+                ("$var", [_, _, id, reference, ..]) => search.var(reference, id.clone()),
+                ("$enddefinitions", _) => break,
+                _ => (),
+            }
+        }
+        vcd.id = search.finish()?;
+        Ok(vcd)
+    }
+
+    fn next_token(&mut self) -> io::Result<Option<String>>{
+        let mut line = String::new();
+        while self.tokens.is_empty() {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.tokens.extend(line.split_whitespace().map(str::to_string));
+        }
+        Ok(self.tokens.pop_front())
+    }
+
+    /**
+     * Read the words of a command until its $end.
+     */
+    fn command_words(&mut self) -> io::Result<Vec<String>>{
+        let mut words = Vec::new();
+        while let Some(word) = self.next_token()? {
+            if word == "$end" {
+                break;
+            }
+            words.push(word);
+        }
+        Ok(words)
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error{
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/**
+ * Read the value of a vector, the low 64 bits of the wider ones.
+ *
+ * @return The value, None if it has x or z bits.
+ */
+fn vector_value(bits: &[u8]) -> Option<u64>{
+    if !bits.iter().all(|bit| *bit == b'0' || *bit == b'1') {
+        return None;
+    }
+    let low = &bits[bits.len().saturating_sub(64)..];
+    Some(low.iter().fold(0, |value, bit| value << 1 | u64::from(bit - b'0')))
+}
+
+impl<R: BufRead> SignalChanges for VcdSignal<R> {
+    fn next_change(&mut self) -> io::Result<Option<SignalChange>>{
+        while let Some(token) = self.next_token()? {
+            let value = match token.as_bytes()[0] {
+                b'#' => {
+                    self.time = token[1..].parse().map_err(|_| invalid(format!("Invalid time {}", token)))?;
+                    continue;
+                },
+                b'b' | b'B' => match self.next_token()? {
+                    Some(id) if id == self.id => vector_value(&token.as_bytes()[1..]),
+                    _ => continue,
+                },
+                // The reals and the strings aren't a pc.
+                b'r' | b'R' | b's' | b'S' => {
+                    self.next_token()?;
+                    continue;
+                },
+                b'0' | b'1' if token[1..] == self.id => Some(u64::from(token.as_bytes()[0] - b'0')),
+                b'x' | b'X' | b'z' | b'Z' if token[1..] == self.id => None,
+                b'$' if token == "$comment" => {
+                    self.command_words()?;
+                    continue;
+                },
+                // The other commands, i.e. $dumpvars, are the changes up to their $end.
+                _ => continue,
+            };
+            return Ok(Some((self.time, value)));
+        }
+        Ok(None)
+    }
+}
+
+/**
+ * The changes of a signal of an FST dump, i.e. of a Verilator simulation run with --trace-fst.
+ *
+ * The value changes of an FST are read with a callback, they are read on a thread of their own and handed over as the
+ * log is annotated, so the changes of a long simulation aren't all kept in memory.
+ */
+pub struct FstSignal{
+    /// The changes read, None once the dump is read to its end.
+    changes: Receiver<io::Result<Option<SignalChange>>>,
+    ended: bool,
+}
+
+impl FstSignal {
+    /**
+     * Read the hierarchy of a dump, find a signal and start reading its changes.
+     *
+     * @param input: The dump.
+     * @param signal: The path of the signal in the hierarchy of the dump, as for VcdSignal.
+     * @return The signal, error if the dump can't be read or doesn't have the signal.
+     */
+    pub fn new<R: BufRead + Seek + Send + 'static>(input: R, signal: &str) -> io::Result<FstSignal>{
+        let mut reader = FstReader::open(input).map_err(invalid_fst)?;
+        let mut search = SignalSearch::new(signal);
+        reader.read_hierarchy(|entry| match entry {
+            FstHierarchyEntry::Scope { name, .. } => search.scope(&name),
+            FstHierarchyEntry::UpScope => search.upscope(),
+            FstHierarchyEntry::Var { name, handle, .. } => search.var(&name, handle.get_index()),
+            _ => (),
+        }).map_err(invalid_fst)?;
+        let index = search.finish()?;
+        let (sender, changes) = mpsc::sync_channel(FST_CHANGES);
+        thread::spawn(move || {
+            let filter = FstFilter::filter_signals(vec![FstSignalHandle::from_index(index)]);
+            let read = reader.read_signals(&filter, |time, _, value| {
+                let value = match value {
+                    FstSignalValue::String(bits) => vector_value(bits),
+                    FstSignalValue::Real(_) => None,
+                };
+                // The reading stops when the annotation stops reading the changes.
+                sender.send(Ok(Some((time, value)))).map_err(|_| ())
+            });
+            let _ = match read {
+                Ok(()) => sender.send(Ok(None)),
+                Err(fst_reader::ReadSignalsError::ReadError(err)) => sender.send(Err(invalid_fst(err))),
+                Err(fst_reader::ReadSignalsError::CallbackError(())) => Ok(()),
+            };
+        });
+        Ok(FstSignal { changes, ended: false })
+    }
+}
+
+fn invalid_fst(err: fst_reader::ReaderError) -> io::Error{
+    invalid(format!("Invalid FST: {}", err))
+}
+
+impl SignalChanges for FstSignal {
+    fn next_change(&mut self) -> io::Result<Option<SignalChange>>{
+        if self.ended {
+            return Ok(None);
+        }
+        let change = self.changes.recv().unwrap_or_else(|_| Err(invalid("The reading of the FST stopped, it may be truncated")));
+        self.ended = !matches!(change, Ok(Some(_)));
+        change
+    }
+}
+
+/**
+ * Open a waveform dump as the log of the changes of its pc signal, an FST or else a VCD, plain or compressed.
+ *
+ * @param path: The path of the dump, - for stdin, which is read as a VCD like the fifos.
+ * @param pc_signal: The path of the pc signal in the hierarchy of the dump, i.e. top.core.pc_id.
+ * @return The lines of the log, error if the dump can't be read or doesn't have the signal.
+ */
+pub fn open(path: &str, pc_signal: &str) -> Result<Box<dyn BufRead>>{
+    if !is_stream(path) {
+        let mut dump = File::open(path).map(BufReader::new).map_err(|err| Error::io(path, err))?;
+        if fst_reader::is_fst_file(&mut dump) {
+            let signal = FstSignal::new(dump, pc_signal).map_err(|err| Error::io(path, err))?;
+            return Ok(Box::new(WaveformTrace::new(signal)));
+        }
+    }
+    let (dump, _) = open_log(path)?;
+    let signal = VcdSignal::new(dump, pc_signal).map_err(|err| Error::io(path, err))?;
+    Ok(Box::new(WaveformTrace::new(signal)))
+}